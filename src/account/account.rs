@@ -1,11 +1,15 @@
 use super::wallet::Wallet;
 use crate::{
-    store::{Storage, StorageKind},
+    store::{schema, Cursor, Metric, Page, ScanOptions, Storage, StorageBatch, StorageKind},
+    util::error::{AccountError, StorageError},
     vault::Crypto,
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 
+/// Meta key of the secret custody balance keys are derived from.
+const CUSTODY_SECRET_KEY: &[u8] = b"custody_secret";
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum BalanceType {
     Binary(Vec<u8>),
@@ -33,6 +37,23 @@ pub struct Account {
     pub address: String,
     pub balance: BalanceType,
     pub timestamp: u64,
+    /// Number of transactions sent from this account that made it into the active chain.
+    pub nonce: u64,
+}
+
+/// Account records as written before schema version 2, without a nonce.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct LegacyAccount {
+    address: String,
+    balance: BalanceType,
+    timestamp: u64,
+}
+
+/// The consensus-relevant part of an account: its decrypted balance and next nonce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct AccountState {
+    pub balance: f64,
+    pub nonce: u64,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -42,7 +63,7 @@ struct AccountIndex {
 }
 
 impl BalanceType {
-    pub fn to_vec(&self) -> Result<Vec<u8>, String> {
+    pub fn to_vec(&self) -> Result<Vec<u8>, AccountError> {
         match self {
             BalanceType::Binary(data) => Ok(data.clone()),
             BalanceType::Text(text) => {
@@ -58,11 +79,8 @@ impl BalanceType {
 }
 
 impl Account {
-    fn ledger() -> Storage {
-        Storage::init().unwrap()
-    }
-
-    pub fn new() -> Result<AccountWithPrivateKey, String> {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(store: &Storage) -> Result<AccountWithPrivateKey, AccountError> {
         let wallet = Wallet::new();
         let private_key = wallet.private_key.clone();
         let public_key = wallet.public_key.clone();
@@ -87,6 +105,7 @@ impl Account {
             address: wallet.address.clone(),
             balance: balance_type,
             timestamp,
+            nonce: 0,
         };
 
         let account_index = AccountIndex {
@@ -94,149 +113,333 @@ impl Account {
             public_key: public_key.clone(),
         };
 
-        let store = Self::ledger();
-        let key: Vec<u8> = bincode::serialize(&public_key).map_err(|e| e.to_string())?;
-        let value: Vec<u8> = bincode::serialize(&account).map_err(|e| e.to_string())?;
-        store.put(StorageKind::Account.name(), &key, &value, true)?;
-
-        let address_key: Vec<u8> =
-            bincode::serialize(&account_index.address).map_err(|e| e.to_string())?;
-        let address_value: Vec<u8> =
-            bincode::serialize(&account_index.public_key).map_err(|e| e.to_string())?;
-        store.put(
-            StorageKind::Index.name(),
-            &address_key,
-            &address_value,
-            true,
-        )?;
+        let key: Vec<u8> = bincode::serialize(&public_key)?;
+        let value: Vec<u8> = schema::encode_record(&account)?;
+        let address_key: Vec<u8> = bincode::serialize(&account_index.address)?;
+        let address_value: Vec<u8> = bincode::serialize(&account_index.public_key)?;
+
+        let mut batch = StorageBatch::new();
+        batch
+            .insert(StorageKind::Account.name(), &key, &value)
+            .insert(StorageKind::Index.name(), &address_key, &address_value)
+            .record_metric(Metric::AccountsCreated, 1.0, timestamp)?;
+        store.write(batch)?;
 
         Ok(account_with_public_key)
     }
 
-    pub fn get_account_index(address: String) -> Result<String, String> {
-        let store = Self::ledger();
-
-        let key: Vec<u8> = bincode::serialize(&address).map_err(|e| e.to_string())?;
+    pub fn get_account_index(store: &Storage, address: String) -> Result<String, AccountError> {
+        let key: Vec<u8> = bincode::serialize(&address)?;
         let value = store.get(StorageKind::Index.name(), &key)?;
 
-        let value: String = bincode::deserialize(&value).map_err(|e| e.to_string())?;
+        let value: String = bincode::deserialize(&value)?;
         Ok(value)
     }
 
-    pub fn get_account(address: String) -> Result<Account, String> {
-        let store = Self::ledger();
-
-        let key: Vec<u8> = bincode::serialize(&address).map_err(|e| e.to_string())?;
+    pub fn get_account(store: &Storage, address: String) -> Result<Account, AccountError> {
+        Wallet::check_address(&address)?;
+        let key: Vec<u8> = bincode::serialize(&address)?;
         let account_key = store.get(StorageKind::Index.name(), &key)?;
-        let account_key: String = bincode::deserialize(&account_key).map_err(|e| e.to_string())?;
+        let account_key: String = bincode::deserialize(&account_key)?;
 
-        let wallet_address = Wallet::verify_address(&address);
-        if wallet_address.is_ok() {
-            let store = Self::ledger();
-            let key: Vec<u8> = bincode::serialize(&account_key).map_err(|e| e.to_string())?;
-            let account = store.get(StorageKind::Account.name(), &key)?;
-            let account: Account = bincode::deserialize(&account).map_err(|e| e.to_string())?;
+        let key: Vec<u8> = bincode::serialize(&account_key)?;
+        let account = store.get(StorageKind::Account.name(), &key)?;
+        let account: Account = schema::decode_record(&account)?;
 
-            let account_balance = Account {
-                address: account.address,
-                balance: BalanceType::Text("Encrypted provide private_key to decrypt".to_string()),
-                timestamp: account.timestamp,
-            };
+        let account_balance = Account {
+            address: account.address,
+            balance: BalanceType::Text("Encrypted provide private_key to decrypt".to_string()),
+            timestamp: account.timestamp,
+            nonce: account.nonce,
+        };
 
-            Ok(account_balance)
-        } else {
-            Err("Not a valid wallet address".to_string())
-        }
+        Ok(account_balance)
     }
 
-    pub fn get_account_details(private_key: String) -> Result<Account, String> {
+    pub fn get_account_details(
+        store: &Storage,
+        private_key: String,
+    ) -> Result<Account, AccountError> {
         let public_key = Wallet::verify(&private_key)?;
 
-        let store = Self::ledger();
-        let key: Vec<u8> = bincode::serialize(&public_key).map_err(|e| e.to_string())?;
+        let key: Vec<u8> = bincode::serialize(&public_key)?;
         let account = store.get(StorageKind::Account.name(), &key)?;
-        let account: Account = bincode::deserialize(&account).map_err(|e| e.to_string())?;
+        let account: Account = schema::decode_record(&account)?;
         let balance_bytes = match account.balance {
             BalanceType::Binary(data) => data,
-            _ => return Err("Balance type is not binary".to_string()),
+            _ => return Err(AccountError::InvalidBalanceType),
         };
 
         let _key = public_key.clone();
         let decrypted_data = Crypto::decrypt(balance_bytes, &_key)?;
         let balance = String::from_utf8_lossy(&decrypted_data.data).to_string();
-        let balance = balance.parse::<f64>().unwrap();
+        let balance = balance
+            .parse::<f64>()
+            .map_err(|_| AccountError::InvalidBalance(balance))?;
         let account_details = Account {
             address: account.address,
             balance: BalanceType::Decimal(balance),
             timestamp: account.timestamp,
+            nonce: account.nonce,
         };
 
         Ok(account_details)
     }
 
-    pub fn get_balance(address: String, private_key: String) -> Result<Balance, String> {
-        let wallet_address = Wallet::verify_address(&address);
-        let public_key = Wallet::verify(&private_key)?;
+    pub fn get_balance(
+        store: &Storage,
+        address: String,
+        private_key: String,
+    ) -> Result<Balance, AccountError> {
+        let (public_key, blinding) = Wallet::opening(&private_key)?;
+        if !Wallet::open_address(&address, &public_key, &blinding)? {
+            return Err(AccountError::NotOwner(address));
+        }
 
-        if wallet_address.is_ok() {
-            let store = Self::ledger();
-            let key: Vec<u8> = bincode::serialize(&public_key).map_err(|e| e.to_string())?;
-            let account = store.get(StorageKind::Account.name(), &key)?;
-            let account: Account = bincode::deserialize(&account).map_err(|e| e.to_string())?;
+        let balance_key = Self::balance_key_of(store, &address)?;
+        let key: Vec<u8> = bincode::serialize(&balance_key)?;
+        let account = store.get(StorageKind::Account.name(), &key)?;
+        let account: Account = schema::decode_record(&account)?;
 
-            let balance_bytes = match account.balance {
-                BalanceType::Binary(data) => data,
-                _ => return Err("Balance type is not binary".to_string()),
-            };
+        let balance_bytes = match account.balance {
+            BalanceType::Binary(data) => data,
+            _ => return Err(AccountError::InvalidBalanceType),
+        };
 
-            let _key = public_key.clone();
-            let decrypted_data = Crypto::decrypt(balance_bytes, &_key)?;
-            let balance = String::from_utf8_lossy(&decrypted_data.data).to_string();
-            let balance = balance.parse::<f64>().unwrap();
-            let account_balance = Balance {
-                address: account.address,
-                balance,
-            };
+        let decrypted_data = Crypto::decrypt(balance_bytes, &balance_key)?;
+        let balance = String::from_utf8_lossy(&decrypted_data.data).to_string();
+        let balance = balance
+            .parse::<f64>()
+            .map_err(|_| AccountError::InvalidBalance(balance))?;
+        let account_balance = Balance {
+            address: account.address,
+            balance,
+        };
 
-            Ok(account_balance)
-        } else {
-            Err("Not a valid wallet address".to_string())
-        }
+        Ok(account_balance)
+    }
+
+    /// Key the balance of `address` is encrypted with on this node, as registered in the
+    /// account index: its public key for accounts created or allocated here, or else a
+    /// custody key only this node holds.
+    pub fn balance_key_of(store: &Storage, address: &str) -> Result<String, AccountError> {
+        Self::get_account_index(store, address.to_string())
+    }
+
+    /// Balance key for an account first paid by a block. The address doesn't reveal the
+    /// public key, so the key is derived from a secret kept in this node's database.
+    fn custody_key(store: &Storage, address: &str) -> Result<String, AccountError> {
+        let meta = StorageKind::Meta.name();
+        let secret = match store.get(meta, CUSTODY_SECRET_KEY) {
+            Ok(secret) => secret,
+            Err(StorageError::NotFound) => {
+                let mut batch = StorageBatch::new();
+                batch.insert(meta, CUSTODY_SECRET_KEY, &rand::random::<[u8; 32]>());
+                match store.write(batch) {
+                    Ok(()) | Err(StorageError::RecordExists) => {}
+                    Err(e) => return Err(e.into()),
+                }
+                store.get(meta, CUSTODY_SECRET_KEY)?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let secret: [u8; 32] = secret.try_into().map_err(|_| StorageError::InvalidRecord)?;
+        Ok(blake3::keyed_hash(&secret, address.as_bytes())
+            .to_hex()
+            .to_string())
+    }
+
+    /// Current balance and nonce of `address`, decrypted node-side through the account's
+    /// balance key. A valid address no block has paid yet holds nothing.
+    pub fn state_of(store: &Storage, address: &str) -> Result<AccountState, AccountError> {
+        Wallet::check_address(address)?;
+        let balance_key = match Self::balance_key_of(store, address) {
+            Ok(balance_key) => balance_key,
+            Err(AccountError::Storage(StorageError::NotFound)) => {
+                return Ok(AccountState::default())
+            }
+            Err(e) => return Err(e),
+        };
+        let key: Vec<u8> = bincode::serialize(&balance_key)?;
+        let account = match store.get(StorageKind::Account.name(), &key) {
+            Ok(account) => account,
+            Err(StorageError::NotFound) => return Ok(AccountState::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let account: Account = schema::decode_record(&account)?;
+
+        let balance_bytes = match account.balance {
+            BalanceType::Binary(data) => data,
+            _ => return Err(AccountError::InvalidBalanceType),
+        };
+        let decrypted_data = Crypto::decrypt(balance_bytes, &balance_key)?;
+        let balance = String::from_utf8_lossy(&decrypted_data.data).to_string();
+        let balance = balance
+            .parse::<f64>()
+            .map_err(|_| AccountError::InvalidBalance(balance))?;
+
+        Ok(AccountState {
+            balance,
+            nonce: account.nonce,
+        })
     }
 
-    pub fn get_accounts(page: usize, limit: usize) -> Result<Vec<Account>, String> {
-        let start = if page > 1 { (page - 1) * limit } else { 0 };
-        let store = Self::ledger();
-        let results = match store.batch_get(StorageKind::Account.name(), start, limit) {
-            Ok(results) => results,
-            Err(e) => {
-                eprintln!("Error performing batch get: {}", e);
-                return Err(e);
+    pub fn balance_of(store: &Storage, address: &str) -> Result<f64, AccountError> {
+        Ok(Self::state_of(store, address)?.balance)
+    }
+
+    /// Stages `state` as the new encrypted balance and nonce of `address` into `batch`. An
+    /// account this node hasn't seen yet, first paid by a block, is registered on the way.
+    pub fn stage_state(
+        store: &Storage,
+        batch: &mut StorageBatch,
+        address: &str,
+        state: AccountState,
+    ) -> Result<(), AccountError> {
+        let balance_key = match Self::balance_key_of(store, address) {
+            Err(AccountError::Storage(StorageError::NotFound)) => {
+                Self::custody_key(store, address)?
+            }
+            result => result?,
+        };
+        let key: Vec<u8> = bincode::serialize(&balance_key)?;
+        let mut account: Account = match store.get(StorageKind::Account.name(), &key) {
+            Ok(account) => schema::decode_record(&account)?,
+            Err(StorageError::NotFound) => {
+                let timestamp = Utc::now().timestamp() as u64;
+                batch
+                    .put(
+                        StorageKind::Index.name(),
+                        &bincode::serialize(address)?,
+                        &bincode::serialize(&balance_key)?,
+                    )
+                    .record_metric(Metric::AccountsCreated, 1.0, timestamp)?;
+                Account {
+                    address: address.to_string(),
+                    balance: BalanceType::Binary(Vec::new()),
+                    timestamp,
+                    nonce: 0,
+                }
             }
+            Err(e) => return Err(e.into()),
+        };
+
+        let balance = state.balance.to_string();
+        let encrypted_balance = Crypto::encrypt(balance.into_bytes(), Some(balance_key))?;
+        account.balance = BalanceType::Binary(encrypted_balance.data);
+        account.nonce = state.nonce;
+
+        batch.put(
+            StorageKind::Account.name(),
+            &key,
+            &schema::encode_record(&account)?,
+        );
+        Ok(())
+    }
+
+    /// Stages an account funded by the genesis block. Only its public key is known, so no
+    /// private key is returned, and an account this node already holds takes the allocated
+    /// balance.
+    pub fn stage_genesis(
+        store: &Storage,
+        batch: &mut StorageBatch,
+        address: &str,
+        public_key: &str,
+        balance: f64,
+        timestamp: u64,
+    ) -> Result<(), AccountError> {
+        let encrypted_balance = Crypto::encrypt(
+            balance.to_string().into_bytes(),
+            Some(public_key.to_string()),
+        )?;
+        let account = Account {
+            address: address.to_string(),
+            balance: BalanceType::Binary(encrypted_balance.data),
+            timestamp,
+            nonce: 0,
         };
 
+        let key = bincode::serialize(public_key)?;
+        if !store.exists(StorageKind::Account.name(), &key)? {
+            batch.record_metric(Metric::AccountsCreated, 1.0, timestamp)?;
+        }
+        batch
+            .put(
+                StorageKind::Account.name(),
+                &key,
+                &schema::encode_record(&account)?,
+            )
+            .put(
+                StorageKind::Index.name(),
+                &bincode::serialize(address)?,
+                &bincode::serialize(public_key)?,
+            );
+        Ok(())
+    }
+
+    pub fn get_accounts(
+        store: &Storage,
+        cursor: Option<Cursor>,
+        limit: usize,
+    ) -> Result<Page<Account>, AccountError> {
+        let options = ScanOptions::new(limit).after(cursor);
+        let results = store.scan(StorageKind::Account.name(), &options)?;
+
         let mut results_vec = vec![];
-        for (_key, value) in results.iter() {
-            let data: Account = bincode::deserialize(value).map_err(|e| e.to_string())?;
+        for (_key, value) in results.items.iter() {
+            let data: Account = schema::decode_record(value)?;
             let data: Account = Account {
                 address: data.address,
                 balance: BalanceType::Text("Encrypted provide private_key to decrypt".to_string()),
                 timestamp: data.timestamp,
+                nonce: data.nonce,
             };
             results_vec.push(data);
         }
 
-        Ok(results_vec)
+        Ok(Page {
+            items: results_vec,
+            next_cursor: results.next_cursor,
+        })
     }
 
-    pub fn total_accounts() -> Result<i64, String> {
-        let store = Self::ledger();
-        let total = store.get(
-            StorageKind::Analytics.name(),
-            StorageKind::Account.name().as_bytes(),
-        )?;
-        let accounts: i64 = bincode::deserialize(&total).map_err(|e| e.to_string())?;
+    /// Number of accounts created, from the analytics counter rather than a scan.
+    pub fn total_accounts(store: &Storage) -> Result<i64, AccountError> {
+        let total = store.get_metric_total(Metric::AccountsCreated)?;
+
+        Ok(total as i64)
+    }
+}
 
-        Ok(accounts)
+/// Schema migration 2: rewrites account records with a zero nonce.
+pub(crate) fn add_account_nonces(
+    store: &Storage,
+    batch: &mut StorageBatch,
+) -> Result<usize, StorageError> {
+    let cf = StorageKind::Account.name();
+    let mut records = 0;
+    let mut cursor = None;
+    loop {
+        let page = store.scan(cf, &ScanOptions::new(1_000).after(cursor))?;
+        for (key, value) in page.items {
+            // Older payloads are a strict prefix of the new layout, so a current record can
+            // also decode as a legacy one; try the current layout first.
+            if schema::decode_legacy_record::<Account>(&value).is_ok() {
+                continue;
+            }
+            let legacy: LegacyAccount = schema::decode_legacy_record(&value)?;
+            let account = Account {
+                address: legacy.address,
+                balance: legacy.balance,
+                timestamp: legacy.timestamp,
+                nonce: 0,
+            };
+            batch.put(cf, &key, &schema::encode_record(&account)?);
+            records += 1;
+        }
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            return Ok(records);
+        }
     }
 }
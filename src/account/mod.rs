@@ -1,4 +1,4 @@
 pub mod account;
 pub mod wallet;
 
-pub use account::{Account, AccountWithPrivateKey, Balance};
+pub use account::{Account, AccountState, AccountWithPrivateKey, Balance};
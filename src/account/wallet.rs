@@ -1,13 +1,26 @@
+use crate::util::error::{AccountError, AddressError, CryptoError};
 use crate::vault::KeyPair;
 use bs58::{decode, encode};
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
 use serde::{Deserialize, Serialize};
 
+/// Domain of the blinding a wallet derives from its private key.
+const BLINDING_CONTEXT: &str = "valtoria 2025 address blinding v1";
+/// Domain of the scalar that hides the public key behind the address.
+const ADDRESS_CONTEXT: &str = "valtoria 2025 address v1";
+/// Compressed point followed by a 4 byte checksum.
+const ADDRESS_LEN: usize = 36;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Wallet {
     pub private_key: String,
     pub public_key: String,
     pub address: String,
+    /// Opens the address to the public key, see `open_address`. Derived from the private key,
+    /// so it never needs to be stored.
+    pub blinding: String,
 }
 
 impl Wallet {
@@ -16,20 +29,23 @@ impl Wallet {
 
         let private_key = key.private_key;
         let public_key = key.public_key;
-        let address = Self::generate_address(&public_key);
+        let blinding = Self::blinding(&private_key);
+        let address = Self::generate_address(&public_key, &blinding);
 
         Wallet {
             private_key: hex::encode(private_key.to_bytes()),
             public_key: hex::encode(public_key.compress().to_bytes()),
             address,
+            blinding: hex::encode(blinding),
         }
     }
 
-    pub fn generate_address(public_key: &RistrettoPoint) -> String {
-        let key = KeyPair::generate();
-        let one_time_public_key = key.public_key;
-
-        let stealth_address_point = one_time_public_key + public_key;
+    /// The address is the public key offset by `H(public_key, blinding)·G`, a one-time key
+    /// only the owner can derive. The address alone reveals nothing about the public key,
+    /// which also keys the encrypted balance.
+    pub fn generate_address(public_key: &RistrettoPoint, blinding: &[u8; 32]) -> String {
+        let one_time_key = Self::one_time_scalar(public_key, blinding) * RISTRETTO_BASEPOINT_POINT;
+        let stealth_address_point = one_time_key + public_key;
 
         let mut address_bytes = Vec::new();
         address_bytes.extend_from_slice(&stealth_address_point.compress().to_bytes());
@@ -40,6 +56,19 @@ impl Wallet {
         encode(address_bytes).into_string()
     }
 
+    fn blinding(private_key: &Scalar) -> [u8; 32] {
+        blake3::derive_key(BLINDING_CONTEXT, private_key.as_bytes())
+    }
+
+    fn one_time_scalar(public_key: &RistrettoPoint, blinding: &[u8; 32]) -> Scalar {
+        let mut hasher = blake3::Hasher::new_derive_key(ADDRESS_CONTEXT);
+        hasher.update(public_key.compress().as_bytes());
+        hasher.update(blinding);
+        let mut wide = [0u8; 64];
+        hasher.finalize_xof().fill(&mut wide);
+        Scalar::from_bytes_mod_order_wide(&wide)
+    }
+
     fn calculate_checksum(data: &[u8]) -> [u8; 4] {
         let hash = blake3::hash(data);
         let mut checksum = [0u8; 4];
@@ -47,12 +76,12 @@ impl Wallet {
         checksum
     }
 
-    pub fn verify_address(address: &str) -> Result<bool, &'static str> {
+    pub fn verify_address(address: &str) -> Result<bool, AccountError> {
         let decoded = decode(address)
             .into_vec()
-            .map_err(|_| "Invalid Base58 encoding")?;
+            .map_err(|_| AddressError::Encoding)?;
         if decoded.len() < 4 {
-            return Err("Invalid address length");
+            return Err(AddressError::Length.into());
         }
 
         let (address_bytes, checksum) = decoded.split_at(decoded.len() - 4);
@@ -62,9 +91,67 @@ impl Wallet {
         Ok(checksum == expected_checksum)
     }
 
-    pub fn verify(private_key: &str) -> Result<String, &'static str> {
+    /// Checks `address` is well formed: Base58, the right length, a matching checksum and a
+    /// valid point.
+    pub fn check_address(address: &str) -> Result<(), AccountError> {
+        let decoded = decode(address)
+            .into_vec()
+            .map_err(|_| AddressError::Encoding)?;
+        if decoded.len() != ADDRESS_LEN {
+            return Err(AddressError::Length.into());
+        }
+        if !Self::verify_address(address)? {
+            return Err(AddressError::Checksum.into());
+        }
+        let point = CompressedRistretto::from_slice(&decoded[..ADDRESS_LEN - 4])
+            .map_err(|_| AddressError::Length)?;
+        if point.decompress().is_none() {
+            return Err(AddressError::NotAPoint.into());
+        }
+        Ok(())
+    }
+
+    /// Whether `address` belongs to the hex `public_key`, as shown by its hex `blinding`.
+    /// Finding another key and blinding for the same address takes a discrete log.
+    pub fn open_address(
+        address: &str,
+        public_key: &str,
+        blinding: &str,
+    ) -> Result<bool, AccountError> {
+        Self::check_address(address)?;
+        let public_key = hex::decode(public_key)
+            .ok()
+            .and_then(|bytes| CompressedRistretto::from_slice(&bytes).ok())
+            .and_then(|point| point.decompress());
+        let blinding = hex::decode(blinding)
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+        match (public_key, blinding) {
+            (Some(public_key), Some(blinding)) => {
+                Ok(Self::generate_address(&public_key, &blinding) == address)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Hex public key and blinding of the wallet holding `private_key`.
+    pub fn opening(private_key: &str) -> Result<(String, String), CryptoError> {
+        let key = KeyPair::from_private_key(private_key)?;
+        Ok((
+            hex::encode(key.public_key.compress().to_bytes()),
+            hex::encode(Self::blinding(&key.private_key)),
+        ))
+    }
+
+    pub fn verify(private_key: &str) -> Result<String, CryptoError> {
         let public_key = KeyPair::verify(private_key)?;
 
         Ok(public_key)
     }
 }
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
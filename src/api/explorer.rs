@@ -0,0 +1,227 @@
+//! Read-only HTTP API for block explorers. Nothing it serves needs or reveals a private key:
+//!
+//! - `GET /blocks/latest` and `GET /blocks/{height or hash}`: a header and the metadata of its
+//!   transactions
+//! - `GET /transactions/{id}`: id, fee, size, timestamp, status and block height
+//! - `GET /accounts/{address}`: whether the account exists and when it was created
+//! - `GET /stats`: chain height, supply and the all-time analytics totals
+//! - `GET /stats/{metric}?bucket=hour|day&from=&to=`: one metric over time, in unix seconds
+//!
+//! Errors come back as `{"error": "..."}` with a 4xx or 5xx status.
+
+use crate::account::wallet::Wallet;
+use crate::account::Account;
+use crate::api::http::{self, HttpServer};
+use crate::api::view::{AccountView, BlockSummary, TransactionMetadata};
+use crate::config;
+use crate::net::Node;
+use crate::store::{Bucket, Metric};
+use crate::tx::Transaction;
+use crate::util::error::{AccountError, ApiError, StorageError, TransactionError};
+use chrono::Utc;
+use log::debug;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use tiny_http::{Method, Request};
+
+/// Span of `/stats/{metric}` when `from` is omitted.
+const DEFAULT_SERIES_SECONDS: u64 = 30 * 86_400;
+
+#[derive(Debug, Clone)]
+pub struct ExplorerConfig {
+    pub listen: SocketAddr,
+}
+
+impl Default for ExplorerConfig {
+    fn default() -> Self {
+        ExplorerConfig {
+            listen: SocketAddr::from(([127, 0, 0, 1], config::EXPLORER_PORT)),
+        }
+    }
+}
+
+pub struct ExplorerServer {
+    http: HttpServer,
+}
+
+impl ExplorerServer {
+    /// Serves `node` on `config.listen`, which may be a public address since nothing here is
+    /// private.
+    pub fn start(node: Node, config: ExplorerConfig) -> Result<ExplorerServer, ApiError> {
+        let http = HttpServer::start(config.listen, move |request| serve(&node, request))?;
+        Ok(ExplorerServer { http })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.http.local_addr()
+    }
+
+    pub fn shutdown(&self) {
+        self.http.shutdown();
+    }
+}
+
+/// An error answer: an HTTP status and a message.
+struct Failure(u16, String);
+
+impl Failure {
+    fn bad_request(message: impl Display) -> Self {
+        Failure(400, message.to_string())
+    }
+
+    fn not_found(message: impl Display) -> Self {
+        Failure(404, message.to_string())
+    }
+
+    fn internal(error: impl Display) -> Self {
+        Failure(500, error.to_string())
+    }
+}
+
+fn serve(node: &Node, request: Request) {
+    let response = match respond(node, &request) {
+        Ok(body) => http::json_response(200, &body),
+        Err(Failure(status, message)) => http::json_response(status, &json!({ "error": message })),
+    };
+    if let Err(e) = request.respond(response) {
+        debug!("Could not answer an explorer request: {}", e);
+    }
+}
+
+fn respond(node: &Node, request: &Request) -> Result<Value, Failure> {
+    if *request.method() != Method::Get {
+        return Err(Failure(405, "Only GET is supported".to_string()));
+    }
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path, parse_query(query)),
+        None => (request.url(), HashMap::new()),
+    };
+    let segments: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    match segments.as_slice() {
+        ["blocks", "latest"] => {
+            let chain = node.chain().map_err(Failure::internal)?;
+            Ok(json!(BlockSummary::from(chain.tip())))
+        }
+        ["blocks", id] => block(node, id),
+        ["transactions", id] => transaction(node, id),
+        ["accounts", address] => account(node, address),
+        ["stats"] => stats(node),
+        ["stats", metric] => series(node, metric, &query),
+        _ => Err(Failure::not_found(format!("No route for {}", path))),
+    }
+}
+
+fn parse_query(query: &str) -> HashMap<&str, &str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect()
+}
+
+/// A block by height when `id` is a number, else by hash.
+fn block(node: &Node, id: &str) -> Result<Value, Failure> {
+    let chain = node.chain().map_err(Failure::internal)?;
+    let block = match id.parse::<u64>() {
+        Ok(height) => chain.block_at(height).cloned(),
+        Err(_) => chain.get_block(id).ok(),
+    };
+    match block {
+        Some(block) => Ok(json!(BlockSummary::from(&block))),
+        None => Err(Failure::not_found(format!("Block {} not found", id))),
+    }
+}
+
+/// Looks on the active chain, then in the mempool, then among the stored records.
+fn transaction(node: &Node, id: &str) -> Result<Value, Failure> {
+    {
+        let chain = node.chain().map_err(Failure::internal)?;
+        if let Some((block, tx)) = chain.find_transaction(id) {
+            return Ok(json!(TransactionMetadata::of(tx, Some(block))));
+        }
+    }
+    if let Some(tx) = node.mempool().map_err(Failure::internal)?.get(id) {
+        return Ok(json!(TransactionMetadata::of(&tx, None)));
+    }
+    match Transaction::get_record(node.store(), id) {
+        Ok(record) => Ok(json!(TransactionMetadata::from(&record))),
+        Err(TransactionError::Storage(StorageError::NotFound)) => {
+            Err(Failure::not_found(format!("Transaction {} not found", id)))
+        }
+        Err(e) => Err(Failure::internal(e)),
+    }
+}
+
+fn account(node: &Node, address: &str) -> Result<Value, Failure> {
+    Wallet::check_address(address).map_err(Failure::bad_request)?;
+    let view = match Account::get_account(node.store(), address.to_string()) {
+        Ok(account) => AccountView::from(&account),
+        Err(AccountError::Storage(StorageError::NotFound)) => AccountView {
+            address: address.to_string(),
+            exists: false,
+            created_at: None,
+            nonce: None,
+            balance: None,
+        },
+        Err(e) => return Err(Failure::internal(e)),
+    };
+    Ok(json!(view))
+}
+
+fn stats(node: &Node) -> Result<Value, Failure> {
+    let store = node.store();
+    let accounts = Account::total_accounts(store).map_err(Failure::internal)?;
+    let mut totals = serde_json::Map::new();
+    for metric in Metric::all() {
+        let total = store.get_metric_total(metric).map_err(Failure::internal)?;
+        totals.insert(metric.name().to_string(), json!(total));
+    }
+    let chain = node.chain().map_err(Failure::internal)?;
+    Ok(json!({
+        "height": chain.height(),
+        "supply": chain.supply(),
+        "max_supply": config::MAX_SUPPLY,
+        "difficulty": chain.tip().header.difficulty,
+        "accounts": accounts,
+        "totals": totals,
+    }))
+}
+
+fn series(node: &Node, metric: &str, query: &HashMap<&str, &str>) -> Result<Value, Failure> {
+    let metric = Metric::all()
+        .into_iter()
+        .find(|known| known.name() == metric)
+        .ok_or_else(|| Failure::not_found(format!("Unknown metric {}", metric)))?;
+    let bucket = match query.get("bucket").copied().unwrap_or("day") {
+        "hour" => Bucket::Hour,
+        "day" => Bucket::Day,
+        other => return Err(Failure::bad_request(format!("Unknown bucket {}", other))),
+    };
+    let number = |name: &str, default: u64| match query.get(name) {
+        Some(value) => value
+            .parse::<u64>()
+            .map_err(|_| Failure::bad_request(format!("{} must be unix seconds", name))),
+        None => Ok(default),
+    };
+    let to = number("to", Utc::now().timestamp() as u64)?;
+    let from = number("from", to.saturating_sub(DEFAULT_SERIES_SECONDS))?;
+    if from > to {
+        return Err(Failure::bad_request("from is after to"));
+    }
+    let points = node
+        .store()
+        .get_analytics_series(metric, bucket, from, to)
+        .map_err(Failure::internal)?;
+    let points: Vec<Value> = points
+        .into_iter()
+        .map(|point| json!({ "bucket_start": point.bucket_start, "value": point.value }))
+        .collect();
+    Ok(json!({
+        "metric": metric.name(),
+        "bucket": bucket.name(),
+        "from": from,
+        "to": to,
+        "points": points,
+    }))
+}
@@ -0,0 +1,101 @@
+use crate::util::error::ApiError;
+use serde::Serialize;
+use std::io::{Cursor, Read};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Request, Response, Server};
+
+const WORKERS: usize = 4;
+
+/// A blocking HTTP/1.1 server handing each request to `handler` on one of a few worker
+/// threads.
+pub struct HttpServer {
+    server: Arc<Server>,
+    local_addr: SocketAddr,
+    running: Arc<AtomicBool>,
+}
+
+impl HttpServer {
+    pub fn start<F>(listen: SocketAddr, handler: F) -> Result<HttpServer, ApiError>
+    where
+        F: Fn(Request) + Send + Sync + 'static,
+    {
+        let server = Arc::new(Server::http(listen).map_err(|e| ApiError::Http(e.to_string()))?);
+        let local_addr = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| ApiError::Http("not listening on an IP address".to_string()))?;
+        let running = Arc::new(AtomicBool::new(true));
+        let handler = Arc::new(handler);
+        for _ in 0..WORKERS {
+            let server = server.clone();
+            let running = running.clone();
+            let handler = handler.clone();
+            thread::spawn(move || {
+                while let Ok(request) = server.recv() {
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    handler(request);
+                }
+            });
+        }
+        Ok(HttpServer {
+            server,
+            local_addr,
+            running,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops the workers once they finish the request at hand.
+    pub fn shutdown(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        for _ in 0..WORKERS {
+            self.server.unblock();
+        }
+    }
+}
+
+pub type JsonResponse = Response<Cursor<Vec<u8>>>;
+
+pub fn json_response(status: u16, body: &impl Serialize) -> JsonResponse {
+    let body = serde_json::to_vec(body).expect("API responses serialize to JSON");
+    Response::from_data(body)
+        .with_status_code(status)
+        .with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                .expect("the content type header is valid"),
+        )
+}
+
+pub fn empty_response(status: u16) -> JsonResponse {
+    Response::from_data(Vec::new()).with_status_code(status)
+}
+
+/// Value of the request header `name`, if present.
+pub fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Reads the request body, or `None` if it is longer than `max` bytes.
+pub fn read_body(request: &mut Request, max: usize) -> std::io::Result<Option<Vec<u8>>> {
+    if request.body_length().is_some_and(|len| len > max) {
+        return Ok(None);
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(max as u64 + 1)
+        .read_to_end(&mut body)?;
+    Ok((body.len() <= max).then_some(body))
+}
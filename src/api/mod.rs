@@ -0,0 +1,12 @@
+pub mod explorer;
+pub mod http;
+pub mod rpc;
+pub mod subscriptions;
+pub mod view;
+
+pub use explorer::{ExplorerConfig, ExplorerServer};
+pub use rpc::{RpcConfig, RpcError, RpcServer};
+pub use subscriptions::{SubscriptionConfig, SubscriptionServer};
+pub use view::{
+    AccountView, BlockSummary, BlockView, HeaderView, TransactionMetadata, TransactionView,
+};
@@ -0,0 +1,452 @@
+//! JSON-RPC 2.0 over HTTP `POST` on a loopback address. Every request carries one of the
+//! configured tokens as `Authorization: Bearer <token>`.
+//!
+//! | Method            | Params                  | Method-specific errors                        |
+//! |-------------------|-------------------------|-----------------------------------------------|
+//! | `account_create`  |                         |                                               |
+//! | `account_balance` | `address`               | `INVALID_ADDRESS`                             |
+//! | `tx_submit`       | `transaction` (hex)     | `MALFORMED_TRANSACTION`, `TRANSACTION_REJECTED` |
+//! | `tx_get`          | `id`                    | `TRANSACTION_NOT_FOUND`                       |
+//! | `block_get`       | `hash` or `height`      | `BLOCK_NOT_FOUND`                             |
+//! | `header_get`      | `hash` or `height`      | `BLOCK_NOT_FOUND`                             |
+//! | `chain_info`      |                         |                                               |
+//!
+//! Params may be given by name or by position, in the order listed.
+
+use crate::account::wallet::Wallet;
+use crate::account::Account;
+use crate::api::http::{self, HttpServer, JsonResponse};
+use crate::api::view::{BlockView, HeaderView, TransactionView};
+use crate::chain::Block;
+use crate::config;
+use crate::net::Node;
+use crate::tx::{Transaction, TransactionStatus};
+use crate::util::codec::Decode;
+use crate::util::error::{ApiError, NetworkError};
+use log::debug;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tiny_http::{Method, Request};
+
+/// Largest request body accepted, which leaves room for a batch of full transactions.
+const MAX_REQUEST_BYTES: usize = 1024 * 1024;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The request carried no token, or an unknown one.
+pub const UNAUTHORIZED: i64 = -32001;
+pub const INVALID_ADDRESS: i64 = -32010;
+/// The transaction isn't valid hex or doesn't decode.
+pub const MALFORMED_TRANSACTION: i64 = -32020;
+/// The mempool refused the transaction; `data.reason` says why.
+pub const TRANSACTION_REJECTED: i64 = -32021;
+pub const TRANSACTION_NOT_FOUND: i64 = -32022;
+pub const BLOCK_NOT_FOUND: i64 = -32030;
+
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    /// Must be a loopback address: tokens travel in the clear.
+    pub listen: SocketAddr,
+    /// Bearer tokens accepted; at least one is required.
+    pub tokens: Vec<String>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            listen: SocketAddr::from(([127, 0, 0, 1], config::RPC_PORT)),
+            tokens: Vec::new(),
+        }
+    }
+}
+
+/// The JSON-RPC error object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Display) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    fn internal(error: impl Display) -> Self {
+        RpcError::new(INTERNAL_ERROR, error)
+    }
+}
+
+impl From<NetworkError> for RpcError {
+    fn from(error: NetworkError) -> Self {
+        RpcError::internal(error)
+    }
+}
+
+pub struct RpcServer {
+    http: HttpServer,
+}
+
+impl RpcServer {
+    /// Serves `node` on `config.listen` until `shutdown` is called.
+    pub fn start(node: Node, config: RpcConfig) -> Result<RpcServer, ApiError> {
+        if !config.listen.ip().is_loopback() {
+            return Err(ApiError::NotLoopback(config.listen));
+        }
+        if config.tokens.is_empty() {
+            return Err(ApiError::NoTokens);
+        }
+        let tokens = Arc::new(hash_tokens(&config.tokens));
+        let http = HttpServer::start(config.listen, move |request| serve(&node, &tokens, request))?;
+        Ok(RpcServer { http })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.http.local_addr()
+    }
+
+    pub fn shutdown(&self) {
+        self.http.shutdown();
+    }
+}
+
+/// Writes a fresh random token to `dir/RPC_COOKIE_FILE`, readable only by the owner, for
+/// local tools to pick up when no token is configured.
+pub fn write_cookie(dir: &Path) -> io::Result<String> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let path = dir.join(config::RPC_COOKIE_FILE);
+    fs::create_dir_all(dir)?;
+    fs::write(&path, &token)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(token)
+}
+
+/// Tokens are compared through their hashes, whose equality check runs in constant time.
+pub(crate) fn hash_tokens(tokens: &[String]) -> Vec<blake3::Hash> {
+    tokens
+        .iter()
+        .map(|token| blake3::hash(token.as_bytes()))
+        .collect()
+}
+
+pub(crate) fn is_authorized(tokens: &[blake3::Hash], token: &str) -> bool {
+    tokens.contains(&blake3::hash(token.trim().as_bytes()))
+}
+
+fn serve(node: &Node, tokens: &[blake3::Hash], mut request: Request) {
+    let response = respond(node, tokens, &mut request);
+    if let Err(e) = request.respond(response) {
+        debug!("Could not answer an RPC request: {}", e);
+    }
+}
+
+fn respond(node: &Node, tokens: &[blake3::Hash], request: &mut Request) -> JsonResponse {
+    if *request.method() != Method::Post {
+        return error_response(405, RpcError::new(INVALID_REQUEST, "Use POST"));
+    }
+    let authorized = http::header(request, "Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| is_authorized(tokens, token));
+    if !authorized {
+        return error_response(401, RpcError::new(UNAUTHORIZED, "Unauthorized"));
+    }
+    let body = match http::read_body(request, MAX_REQUEST_BYTES) {
+        Ok(Some(body)) => body,
+        Ok(None) => {
+            return error_response(413, RpcError::new(INVALID_REQUEST, "Request too large"))
+        }
+        Err(e) => return error_response(400, RpcError::new(PARSE_ERROR, e)),
+    };
+    let body: Value = match serde_json::from_slice(&body) {
+        Ok(body) => body,
+        Err(e) => return error_response(200, RpcError::new(PARSE_ERROR, e)),
+    };
+
+    let reply = match body {
+        Value::Array(calls) if calls.is_empty() => Some(failure(
+            Value::Null,
+            RpcError::new(INVALID_REQUEST, "Empty batch"),
+        )),
+        Value::Array(calls) => {
+            let replies: Vec<Value> = calls
+                .into_iter()
+                .filter_map(|call| handle(node, call))
+                .collect();
+            (!replies.is_empty()).then_some(Value::Array(replies))
+        }
+        call => handle(node, call),
+    };
+    match reply {
+        Some(reply) => http::json_response(200, &reply),
+        // Only notifications: nothing to return.
+        None => http::empty_response(204),
+    }
+}
+
+fn error_response(status: u16, error: RpcError) -> JsonResponse {
+    http::json_response(status, &failure(Value::Null, error))
+}
+
+pub(crate) fn success(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "result": result, "id": id })
+}
+
+pub(crate) fn failure(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "error": error, "id": id })
+}
+
+/// Runs one call, returning its response unless it is a notification.
+fn handle(node: &Node, call: Value) -> Option<Value> {
+    let call = match Call::parse(call) {
+        Ok(call) => call,
+        Err(reply) => return Some(reply),
+    };
+    let result = dispatch(node, &call.method, &call.params);
+    call.reply(result)
+}
+
+/// A request object, checked against the JSON-RPC 2.0 envelope.
+pub(crate) struct Call {
+    /// `None` for notifications, which get no response.
+    pub id: Option<Value>,
+    pub method: String,
+    pub params: Params,
+}
+
+impl Call {
+    /// Parses `call`, or returns the error response for an invalid one.
+    pub fn parse(call: Value) -> Result<Call, Value> {
+        let Value::Object(mut call) = call else {
+            return Err(failure(
+                Value::Null,
+                RpcError::new(INVALID_REQUEST, "Expected a request object"),
+            ));
+        };
+        let id = call.remove("id");
+        let id_value = id.clone().unwrap_or(Value::Null);
+        if call.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+            return Err(failure(
+                id_value,
+                RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""),
+            ));
+        }
+        let Some(Value::String(method)) = call.remove("method") else {
+            return Err(failure(
+                id_value,
+                RpcError::new(INVALID_REQUEST, "method must be a string"),
+            ));
+        };
+        let params = match call.remove("params") {
+            None | Some(Value::Null) => Params::Named(Map::new()),
+            Some(Value::Object(named)) => Params::Named(named),
+            Some(Value::Array(positional)) => Params::Positional(positional),
+            Some(_) => {
+                return Err(failure(
+                    id_value,
+                    RpcError::new(INVALID_REQUEST, "params must be an object or an array"),
+                ))
+            }
+        };
+        Ok(Call { id, method, params })
+    }
+
+    /// The response carrying `result`, unless the call is a notification.
+    pub fn reply(self, result: Result<Value, RpcError>) -> Option<Value> {
+        self.id.map(|id| match result {
+            Ok(result) => success(id, result),
+            Err(error) => failure(id, error),
+        })
+    }
+}
+
+pub(crate) enum Params {
+    Named(Map<String, Value>),
+    Positional(Vec<Value>),
+}
+
+impl Params {
+    pub fn get(&self, position: usize, name: &str) -> Option<&Value> {
+        match self {
+            Params::Named(named) => named.get(name),
+            Params::Positional(positional) => positional.get(position),
+        }
+    }
+
+    pub fn string(&self, position: usize, name: &str) -> Result<&str, RpcError> {
+        self.get(position, name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("{} must be a string", name)))
+    }
+
+    /// A list of strings, empty when the param is absent.
+    pub fn strings(&self, position: usize, name: &str) -> Result<Vec<String>, RpcError> {
+        let invalid = || {
+            RpcError::new(
+                INVALID_PARAMS,
+                format!("{} must be a list of strings", name),
+            )
+        };
+        match self.get(position, name) {
+            None | Some(Value::Null) => Ok(Vec::new()),
+            Some(Value::Array(values)) => values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string).ok_or_else(invalid))
+                .collect(),
+            Some(_) => Err(invalid()),
+        }
+    }
+}
+
+fn dispatch(node: &Node, method: &str, params: &Params) -> Result<Value, RpcError> {
+    match method {
+        "account_create" => account_create(node),
+        "account_balance" => account_balance(node, params),
+        "tx_submit" => tx_submit(node, params),
+        "tx_get" => tx_get(node, params),
+        "block_get" => Ok(json!(BlockView::from(&find_block(node, params)?))),
+        "header_get" => Ok(json!(HeaderView::from(&find_block(node, params)?.header))),
+        "chain_info" => chain_info(node),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {}", method),
+        )),
+    }
+}
+
+fn account_create(node: &Node) -> Result<Value, RpcError> {
+    let account = Account::new(node.store()).map_err(RpcError::internal)?;
+    Ok(json!({
+        "address": account.address,
+        "public_key": account.public_key,
+        "private_key": account.private_key,
+        "timestamp": account.timestamp,
+    }))
+}
+
+fn account_balance(node: &Node, params: &Params) -> Result<Value, RpcError> {
+    let address = params.string(0, "address")?;
+    Wallet::check_address(address).map_err(|e| RpcError::new(INVALID_ADDRESS, e))?;
+    let state = Account::state_of(node.store(), address).map_err(RpcError::internal)?;
+    Ok(json!({
+        "address": address,
+        "balance": state.balance,
+        "nonce": state.nonce,
+    }))
+}
+
+fn tx_submit(node: &Node, params: &Params) -> Result<Value, RpcError> {
+    let encoded = params.string(0, "transaction")?;
+    let tx = hex::decode(encoded)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| Transaction::decode(&bytes).map_err(|e| e.to_string()))
+        .map_err(|e| RpcError::new(MALFORMED_TRANSACTION, e))?;
+    let id = tx.id().to_string();
+    match node.submit_transaction(tx) {
+        Ok(()) => Ok(json!({ "id": id, "status": TransactionStatus::Pending.as_str() })),
+        Err(NetworkError::Mempool(e)) => Err(RpcError {
+            code: TRANSACTION_REJECTED,
+            message: e.to_string(),
+            data: Some(json!({ "reason": e.to_string() })),
+        }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn tx_get(node: &Node, params: &Params) -> Result<Value, RpcError> {
+    let id = params.string(0, "id")?;
+    {
+        let chain = node.chain()?;
+        if let Some((block, tx)) = chain.find_transaction(id) {
+            return Ok(json!({
+                "transaction": TransactionView::from(tx),
+                "status": TransactionStatus::Completed.as_str(),
+                "block_hash": block.header.hash,
+                "height": block.header.index,
+                "confirmations": chain.height() - block.header.index + 1,
+            }));
+        }
+    }
+    match node.mempool()?.get(id) {
+        Some(tx) => Ok(json!({
+            "transaction": TransactionView::from(&tx),
+            "status": TransactionStatus::Pending.as_str(),
+            "block_hash": null,
+            "height": null,
+            "confirmations": 0,
+        })),
+        None => Err(RpcError::new(
+            TRANSACTION_NOT_FOUND,
+            format!("Transaction {} not found", id),
+        )),
+    }
+}
+
+/// The block named by a `hash` string or a `height` number: by name, or as the one
+/// positional param.
+fn find_block(node: &Node, params: &Params) -> Result<Block, RpcError> {
+    let chain = node.chain()?;
+    let not_found =
+        |what: &dyn Display| RpcError::new(BLOCK_NOT_FOUND, format!("Block {} not found", what));
+    let selector = match params {
+        Params::Named(named) => named.get("hash").or_else(|| named.get("height")),
+        Params::Positional(positional) => positional.first(),
+    };
+    match selector {
+        Some(Value::String(hash)) => chain.get_block(hash).map_err(|_| not_found(hash)),
+        Some(Value::Number(height)) => {
+            let height = height
+                .as_u64()
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "height must be a whole number"))?;
+            chain
+                .block_at(height)
+                .cloned()
+                .ok_or_else(|| not_found(&height))
+        }
+        _ => Err(RpcError::new(
+            INVALID_PARAMS,
+            "Expected a block hash or height",
+        )),
+    }
+}
+
+fn chain_info(node: &Node) -> Result<Value, RpcError> {
+    let peers = node.peers().len();
+    let syncing = node.is_syncing()?;
+    let chain = node.chain()?;
+    let mempool_size = node.mempool()?.len();
+    let tip = &chain.tip().header;
+    Ok(json!({
+        "network_id": node.network_id(),
+        "genesis_hash": node.genesis_hash(),
+        "height": tip.index,
+        "tip_hash": tip.hash,
+        "difficulty": tip.difficulty,
+        "best_header_height": chain.best_header().height,
+        "supply": chain.supply(),
+        "syncing": syncing,
+        "peers": peers,
+        "mempool_size": mempool_size,
+    }))
+}
@@ -0,0 +1,406 @@
+//! JSON-RPC 2.0 subscriptions over WebSocket on a loopback address. The upgrade request
+//! carries one of the configured tokens, as `Authorization: Bearer <token>` or, for browsers,
+//! as a `token` query parameter.
+//!
+//! | Method        | Params                 | Result             |
+//! |---------------|------------------------|--------------------|
+//! | `subscribe`   | `topic`, then a filter | a subscription id  |
+//! | `unsubscribe` | `subscription`         | `true`             |
+//!
+//! | Topic      | Filter                  | Each notification                      |
+//! |------------|-------------------------|----------------------------------------|
+//! | `headers`  |                         | a header, as `header_get` returns it   |
+//! | `mempool`  |                         | a transaction admitted to the mempool  |
+//! | `statuses` | `ids`, all when omitted | `id`, `status` and `height`            |
+//! | `payments` | `addresses`, required   | `address`, `id`, `amount` and `height` |
+//!
+//! Notifications are sent as
+//! `{"jsonrpc": "2.0", "method": "subscription", "params": {"subscription": .., "topic": .., "result": ..}}`.
+//! A client that falls `EVENT_QUEUE_CAPACITY` events behind is disconnected.
+
+use crate::account::wallet::Wallet;
+use crate::api::rpc::{self, Call, Params, RpcError, INVALID_ADDRESS, INVALID_PARAMS};
+use crate::api::view::{HeaderView, TransactionView};
+use crate::config;
+use crate::events::Event;
+use crate::net::Node;
+use crate::util::error::ApiError;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tungstenite::http::StatusCode;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{Message, WebSocket};
+
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a connection waits for a client message before forwarding queued events.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Subscriptions one connection may hold.
+const MAX_SUBSCRIPTIONS: usize = 32;
+/// Addresses or ids one subscription may filter on.
+const MAX_FILTER_ENTRIES: usize = 1_000;
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionConfig {
+    /// Must be a loopback address: tokens travel in the clear.
+    pub listen: SocketAddr,
+    /// Bearer tokens accepted; at least one is required.
+    pub tokens: Vec<String>,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        SubscriptionConfig {
+            listen: SocketAddr::from(([127, 0, 0, 1], config::WS_PORT)),
+            tokens: Vec::new(),
+        }
+    }
+}
+
+struct Shared {
+    node: Node,
+    tokens: Vec<blake3::Hash>,
+    connections: AtomicUsize,
+    running: AtomicBool,
+}
+
+pub struct SubscriptionServer {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+}
+
+impl SubscriptionServer {
+    /// Serves the events of `node` on `config.listen` until `shutdown` is called.
+    pub fn start(node: Node, config: SubscriptionConfig) -> Result<SubscriptionServer, ApiError> {
+        if !config.listen.ip().is_loopback() {
+            return Err(ApiError::NotLoopback(config.listen));
+        }
+        if config.tokens.is_empty() {
+            return Err(ApiError::NoTokens);
+        }
+        let listener = TcpListener::bind(config.listen)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            node,
+            tokens: rpc::hash_tokens(&config.tokens),
+            connections: AtomicUsize::new(0),
+            running: AtomicBool::new(true),
+        });
+        let acceptor = shared.clone();
+        thread::spawn(move || accept_loop(&acceptor, listener));
+        Ok(SubscriptionServer { shared, local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting connections and closes the open ones.
+    pub fn shutdown(&self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+    }
+}
+
+fn accept_loop(shared: &Arc<Shared>, listener: TcpListener) {
+    while shared.running.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                // Reserve a slot in one step so concurrent accepts can't overshoot the limit.
+                let reserved =
+                    shared
+                        .connections
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+                            (open < config::MAX_WS_CONNECTIONS).then_some(open + 1)
+                        });
+                if reserved.is_err() {
+                    debug!("Refusing subscriber {}: too many connections", addr);
+                    continue;
+                }
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve(&shared, stream) {
+                        debug!("Subscriber {} disconnected: {}", addr, e);
+                    }
+                    shared.connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+            Err(e) => warn!("Error accepting subscriber: {}", e),
+        }
+    }
+}
+
+/// What a subscription forwards.
+enum Topic {
+    Headers,
+    Mempool,
+    /// Status changes of these ids, or of every transaction when `None`.
+    Statuses(Option<HashSet<String>>),
+    Payments(HashSet<String>),
+}
+
+impl Topic {
+    fn name(&self) -> &str {
+        match self {
+            Topic::Headers => "headers",
+            Topic::Mempool => "mempool",
+            Topic::Statuses(_) => "statuses",
+            Topic::Payments(_) => "payments",
+        }
+    }
+
+    fn parse(params: &Params) -> Result<Topic, RpcError> {
+        let topic = params.string(0, "topic")?;
+        let filter = |name: &str| {
+            let entries = params.strings(1, name)?;
+            if entries.len() > MAX_FILTER_ENTRIES {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("At most {} {} per subscription", MAX_FILTER_ENTRIES, name),
+                ));
+            }
+            Ok(entries)
+        };
+        match topic {
+            "headers" => Ok(Topic::Headers),
+            "mempool" => Ok(Topic::Mempool),
+            "statuses" => {
+                let ids = filter("ids")?;
+                Ok(Topic::Statuses(
+                    (!ids.is_empty()).then(|| ids.into_iter().collect()),
+                ))
+            }
+            "payments" => {
+                let addresses = filter("addresses")?;
+                if addresses.is_empty() {
+                    return Err(RpcError::new(
+                        INVALID_PARAMS,
+                        "payments needs at least one address",
+                    ));
+                }
+                for address in &addresses {
+                    Wallet::check_address(address)
+                        .map_err(|e| RpcError::new(INVALID_ADDRESS, e))?;
+                }
+                Ok(Topic::Payments(addresses.into_iter().collect()))
+            }
+            _ => Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("Unknown topic {}", topic),
+            )),
+        }
+    }
+
+    /// The notification result for `event`, if this topic forwards it.
+    fn render(&self, event: &Event) -> Option<Value> {
+        match (self, event) {
+            (Topic::Headers, Event::NewHeader(header)) => Some(json!(HeaderView::from(header))),
+            (Topic::Mempool, Event::TransactionAdmitted(tx)) => {
+                Some(json!(TransactionView::from(tx)))
+            }
+            (Topic::Statuses(ids), Event::StatusChanged { id, status, height })
+                if ids.as_ref().is_none_or(|ids| ids.contains(id)) =>
+            {
+                Some(json!({ "id": id, "status": status.as_str(), "height": height }))
+            }
+            (
+                Topic::Payments(addresses),
+                Event::Payment {
+                    address,
+                    id,
+                    amount,
+                    height,
+                },
+            ) if addresses.contains(address) => Some(json!({
+                "address": address,
+                "id": id,
+                "amount": amount,
+                "height": height,
+            })),
+            _ => None,
+        }
+    }
+}
+
+struct Connection {
+    socket: WebSocket<TcpStream>,
+    subscriptions: HashMap<String, Topic>,
+    next_id: u64,
+}
+
+fn serve(shared: &Shared, stream: TcpStream) -> Result<(), tungstenite::Error> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let socket = tungstenite::accept_hdr(stream, Authorize(&shared.tokens))
+        .map_err(|e| tungstenite::Error::Io(io::Error::other(e.to_string())))?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(EVENT_POLL_INTERVAL))?;
+
+    // Subscribed before the first request is read, so no event after it is missed.
+    let events = shared.node.events().subscribe();
+    let mut connection = Connection {
+        socket,
+        subscriptions: HashMap::new(),
+        next_id: 0,
+    };
+    connection.run(shared, &events)
+}
+
+/// Accepts the upgrade request when it carries a known token.
+struct Authorize<'a>(&'a [blake3::Hash]);
+
+impl Callback for Authorize<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let header = request
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let query = request.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+        });
+        if header
+            .or(query)
+            .is_some_and(|token| rpc::is_authorized(self.0, token))
+        {
+            return Ok(response);
+        }
+        let mut response = ErrorResponse::new(Some("Unauthorized".to_string()));
+        *response.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(response)
+    }
+}
+
+impl Connection {
+    fn run(&mut self, shared: &Shared, events: &Receiver<Event>) -> Result<(), tungstenite::Error> {
+        loop {
+            if !shared.running.load(Ordering::SeqCst) {
+                return self.close(CloseCode::Away, "Shutting down");
+            }
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    if let Some(reply) = self.handle(text.as_str()) {
+                        self.socket.send(Message::text(reply.to_string()))?;
+                    }
+                }
+                Ok(Message::Binary(_)) => {
+                    return self.close(CloseCode::Unsupported, "Only text messages are accepted")
+                }
+                // Pings are answered by the socket itself; a close is answered by the next
+                // read, which then reports the connection closed.
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            loop {
+                match events.try_recv() {
+                    Ok(event) => self.forward(&event)?,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        return self.close(CloseCode::Again, "Too far behind on events")
+                    }
+                }
+            }
+            match self.socket.flush() {
+                Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {}
+                result => result?,
+            }
+        }
+    }
+
+    fn close(&mut self, code: CloseCode, reason: &str) -> Result<(), tungstenite::Error> {
+        self.socket.close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        }))?;
+        self.socket.flush()
+    }
+
+    fn forward(&mut self, event: &Event) -> Result<(), tungstenite::Error> {
+        for (id, topic) in &self.subscriptions {
+            if let Some(result) = topic.render(event) {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "subscription",
+                    "params": { "subscription": id, "topic": topic.name(), "result": result },
+                });
+                self.socket.write(Message::text(notification.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs one request, returning its response unless it is a notification. Batches aren't
+    /// supported over WebSocket.
+    fn handle(&mut self, text: &str) -> Option<Value> {
+        let call = match serde_json::from_str(text) {
+            Ok(call) => call,
+            Err(e) => {
+                return Some(rpc::failure(
+                    Value::Null,
+                    RpcError::new(rpc::PARSE_ERROR, e),
+                ))
+            }
+        };
+        let call = match Call::parse(call) {
+            Ok(call) => call,
+            Err(reply) => return Some(reply),
+        };
+        let result = match call.method.as_str() {
+            "subscribe" => self.subscribe(&call.params),
+            "unsubscribe" => self.unsubscribe(&call.params),
+            method => Err(RpcError::new(
+                rpc::METHOD_NOT_FOUND,
+                format!("Unknown method {}", method),
+            )),
+        };
+        call.reply(result)
+    }
+
+    fn subscribe(&mut self, params: &Params) -> Result<Value, RpcError> {
+        if self.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return Err(RpcError::new(
+                rpc::INVALID_REQUEST,
+                format!("At most {} subscriptions per connection", MAX_SUBSCRIPTIONS),
+            ));
+        }
+        let topic = Topic::parse(params)?;
+        self.next_id += 1;
+        let id = format!("{:x}", self.next_id);
+        self.subscriptions.insert(id.clone(), topic);
+        Ok(json!(id))
+    }
+
+    fn unsubscribe(&mut self, params: &Params) -> Result<Value, RpcError> {
+        let id = params.string(0, "subscription")?;
+        match self.subscriptions.remove(id) {
+            Some(_) => Ok(json!(true)),
+            None => Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("Unknown subscription {}", id),
+            )),
+        }
+    }
+}
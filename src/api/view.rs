@@ -0,0 +1,206 @@
+use crate::account::account::BalanceType;
+use crate::account::Account;
+use crate::chain::{Block, BlockHeader};
+use crate::tx::transaction::EncryptedTransaction;
+use crate::tx::{Transaction, TransactionStatus};
+use crate::util::codec::Encode;
+use serde::{Deserialize, Serialize};
+
+/// The public fields of a block header, as the APIs return them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeaderView {
+    pub hash: String,
+    pub height: u64,
+    pub prev_hash: String,
+    pub timestamp: i64,
+    pub difficulty: u64,
+    pub nonce: u64,
+    pub merkle_root: String,
+    pub state_root: String,
+    pub size: u64,
+    pub version: u64,
+}
+
+/// A transaction without its signature or any decryption key. Encrypted transactions only
+/// show their id and fee.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionView {
+    pub id: String,
+    /// `transfer`, `coinbase` or `encrypted`.
+    pub kind: String,
+    pub sender: Option<String>,
+    pub receiver: Option<String>,
+    pub amount: Option<f64>,
+    pub fee: f64,
+    pub nonce: Option<u64>,
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockView {
+    #[serde(flatten)]
+    pub header: HeaderView,
+    pub transactions: Vec<TransactionView>,
+}
+
+/// What anyone may learn about a transaction: no parties, amounts, signature or keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionMetadata {
+    pub id: String,
+    pub fee: f64,
+    /// Encoded size in bytes.
+    pub size: u64,
+    pub timestamp: u64,
+    pub status: String,
+    pub block_height: Option<u64>,
+    pub block_hash: Option<String>,
+}
+
+/// A block header with the metadata of its transactions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockSummary {
+    #[serde(flatten)]
+    pub header: HeaderView,
+    pub transaction_count: usize,
+    pub transactions: Vec<TransactionMetadata>,
+}
+
+/// An account as shown without its key: whether it exists and since when. The balance stays
+/// encrypted, as `Account::get_account` reports it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountView {
+    pub address: String,
+    pub exists: bool,
+    pub created_at: Option<u64>,
+    pub nonce: Option<u64>,
+    pub balance: Option<String>,
+}
+
+impl From<&BlockHeader> for HeaderView {
+    fn from(header: &BlockHeader) -> Self {
+        HeaderView {
+            hash: header.hash.clone(),
+            height: header.index,
+            prev_hash: header.prev_hash.clone(),
+            timestamp: header.timestamp,
+            difficulty: header.difficulty,
+            nonce: header.nonce,
+            merkle_root: header.merkle_root.clone(),
+            state_root: header.state_root.clone(),
+            size: header.block_size,
+            version: header.version,
+        }
+    }
+}
+
+impl From<&Account> for AccountView {
+    fn from(account: &Account) -> Self {
+        AccountView {
+            address: account.address.clone(),
+            exists: true,
+            created_at: Some(account.timestamp),
+            nonce: Some(account.nonce),
+            balance: match &account.balance {
+                BalanceType::Text(redacted) => Some(redacted.clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl From<&Transaction> for TransactionView {
+    fn from(tx: &Transaction) -> Self {
+        let mut view = TransactionView {
+            id: tx.id().to_string(),
+            kind: String::new(),
+            sender: None,
+            receiver: None,
+            amount: None,
+            fee: tx.fee(),
+            nonce: None,
+            timestamp: None,
+        };
+        match tx {
+            Transaction::Plain(tx) => {
+                view.kind = "transfer".to_string();
+                view.sender = Some(tx.sender().to_string());
+                view.receiver = Some(tx.receiver().to_string());
+                view.amount = Some(tx.amount());
+                view.nonce = Some(tx.nonce());
+                view.timestamp = Some(tx.timestamp());
+            }
+            Transaction::Coinbase(tx) => {
+                view.kind = "coinbase".to_string();
+                view.receiver = Some(tx.receiver().to_string());
+                view.amount = Some(tx.amount());
+            }
+            Transaction::Encrypted(_) => view.kind = "encrypted".to_string(),
+        }
+        view
+    }
+}
+
+impl From<&Block> for BlockView {
+    fn from(block: &Block) -> Self {
+        BlockView {
+            header: HeaderView::from(&block.header),
+            transactions: block
+                .transactions
+                .iter()
+                .map(TransactionView::from)
+                .collect(),
+        }
+    }
+}
+
+impl TransactionMetadata {
+    /// Metadata of a transaction in `block`, or of a pending one when there is no block.
+    pub fn of(tx: &Transaction, block: Option<&Block>) -> Self {
+        let timestamp = match tx {
+            Transaction::Plain(tx) => tx.timestamp(),
+            Transaction::Encrypted(tx) => tx.timestamp(),
+            Transaction::Coinbase(_) => block.map_or(0, |block| block.header.timestamp as u64),
+        };
+        let status = match block {
+            Some(_) => TransactionStatus::Completed,
+            None => TransactionStatus::Pending,
+        };
+        TransactionMetadata {
+            id: tx.id().to_string(),
+            fee: tx.fee(),
+            size: tx.encode().len() as u64,
+            timestamp,
+            status: status.as_str().to_string(),
+            block_height: block.map(|block| block.header.index),
+            block_hash: block.map(|block| block.header.hash.clone()),
+        }
+    }
+}
+
+impl From<&EncryptedTransaction> for TransactionMetadata {
+    fn from(tx: &EncryptedTransaction) -> Self {
+        TransactionMetadata {
+            id: tx.id().to_string(),
+            fee: tx.fee(),
+            size: tx.size() as u64,
+            timestamp: tx.timestamp(),
+            status: tx.status().to_string(),
+            block_height: None,
+            block_hash: None,
+        }
+    }
+}
+
+impl From<&Block> for BlockSummary {
+    fn from(block: &Block) -> Self {
+        BlockSummary {
+            header: HeaderView::from(&block.header),
+            transaction_count: block.transactions.len(),
+            transactions: block
+                .transactions
+                .iter()
+                .map(|tx| TransactionMetadata::of(tx, Some(block)))
+                .collect(),
+        }
+    }
+}
@@ -1,12 +1,20 @@
+use crate::consensus::pow;
 use crate::tx::Transaction;
+use crate::util::codec::{Decode, Decoder, Encode, Encoder};
+use crate::util::error::CodecError;
 use blake3::Hasher;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockHeader {
     pub index: u64,
     pub timestamp: i64,
     pub data: String,
     pub prev_hash: String,
+    pub merkle_root: String,
+    /// Root of the account state tree after this block is connected.
+    pub state_root: String,
     pub hash: String,
     pub nonce: u64,
     pub difficulty: u64,
@@ -14,45 +22,194 @@ pub struct BlockHeader {
     pub version: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     pub header: BlockHeader,
     pub transactions: Vec<Transaction>,
 }
 
+impl BlockHeader {
+    /// Layout version written by this node. Headers of any other version are refused, both
+    /// when decoding and when validating.
+    pub const VERSION: u64 = 2;
+
+    /// Expected number of hashes needed to find this block, which is its difficulty.
+    pub fn work(&self) -> u128 {
+        self.difficulty.into()
+    }
+
+    /// Canonical encoding of every field but the hash, which is what the hash is taken over.
+    pub fn preimage(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.encode_preimage(&mut encoder);
+        encoder.into_bytes()
+    }
+
+    pub fn calculate_hash(&self) -> String {
+        blake3::hash(&self.preimage()).to_hex().to_string()
+    }
+
+    pub fn meets_difficulty(&self) -> bool {
+        pow::meets_target(&self.hash, self.difficulty)
+    }
+
+    fn encode_preimage(&self, encoder: &mut Encoder) {
+        encoder
+            .u64(self.version)
+            .u64(self.index)
+            .i64(self.timestamp)
+            .str(&self.data)
+            .str(&self.prev_hash)
+            .str(&self.merkle_root)
+            .str(&self.state_root)
+            .u64(self.nonce)
+            .u64(self.difficulty)
+            .u64(self.block_size);
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        self.encode_preimage(encoder);
+        encoder.str(&self.hash);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError> {
+        let version = decoder.u64()?;
+        if version != Self::VERSION {
+            return Err(CodecError::UnsupportedVersion {
+                kind: "block header",
+                version,
+            });
+        }
+
+        Ok(BlockHeader {
+            version,
+            index: decoder.u64()?,
+            timestamp: decoder.i64()?,
+            data: decoder.string()?,
+            prev_hash: decoder.string()?,
+            merkle_root: decoder.string()?,
+            state_root: decoder.string()?,
+            nonce: decoder.u64()?,
+            difficulty: decoder.u64()?,
+            block_size: decoder.u64()?,
+            hash: decoder.string()?,
+        })
+    }
+}
+
+/// The header followed by the transaction list: a `u32` count, then each transaction as
+/// length-prefixed bytes so a reader can skip ones it doesn't need.
+impl Encode for Block {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        encoder.value(&self.header);
+        encode_transactions(encoder, &self.transactions);
+    }
+}
+
+impl Decode for Block {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError> {
+        let header = decoder.value()?;
+        let count = decoder.u32()?;
+        let transactions = (0..count)
+            .map(|_| Transaction::decode(decoder.bytes()?))
+            .collect::<Result<_, _>>()?;
+        Ok(Block {
+            header,
+            transactions,
+        })
+    }
+}
+
 impl Block {
-    fn new(index: u64, transactions: Vec<Transaction>, prev_hash: String) -> Self {
-        let timestamp = Utc::now();
-        let hash = Block::calculate_hash(index, &timestamp, &transactions, &prev_hash);
-        Block {
-            index,
-            timestamp,
+    /// A block over `transactions` with an empty state root; the chain fills in the root of
+    /// the state the block leads to.
+    pub fn new(
+        index: u64,
+        prev_hash: String,
+        difficulty: u64,
+        transactions: Vec<Transaction>,
+    ) -> Self {
+        let mut block = Block {
+            header: BlockHeader {
+                index,
+                timestamp: Utc::now().timestamp(),
+                data: "New Block".to_string(),
+                prev_hash,
+                merkle_root: calculate_merkle_root(&transactions),
+                state_root: "0".repeat(64),
+                hash: String::new(),
+                nonce: 0,
+                difficulty,
+                block_size: transactions_size(&transactions),
+                version: BlockHeader::VERSION,
+            },
             transactions,
-            prev_hash,
-            hash,
-        }
+        };
+        block.header.hash = block.calculate_hash();
+        block
     }
 
     pub fn calculate_hash(&self) -> String {
-        let mut hasher = Hasher::new();
+        self.header.calculate_hash()
+    }
 
-        hasher.update(self.header.prev_hash.as_bytes());
-        hasher.update(self.header.timestamp.to_be_bytes());
-        hasher.update(self.header.nonce.to_be_bytes());
-        hasher.update(self.header.version.to_be_bytes());
+    pub fn meets_difficulty(&self) -> bool {
+        self.header.meets_difficulty()
+    }
+
+    /// Searches nonces until the block hash meets its difficulty.
+    pub fn mine(&mut self) {
+        loop {
+            self.header.hash = self.calculate_hash();
+            if self.meets_difficulty() {
+                return;
+            }
+            self.header.nonce = self.header.nonce.wrapping_add(1);
+        }
+    }
+}
 
-        format!("{:x}", hasher.finalize())
+fn encode_transactions(encoder: &mut Encoder, transactions: &[Transaction]) {
+    let count = u32::try_from(transactions.len()).expect("block exceeds u32::MAX transactions");
+    encoder.u32(count);
+    for tx in transactions {
+        encoder.bytes(&tx.encode());
     }
 }
 
-// fn calculate_merkle_root(transactions: &[Transaction]) -> String {
-//     let mut hasher = Sha256::new();
+/// Encoded size of the transaction list, as declared in `BlockHeader::block_size`.
+pub fn transactions_size(transactions: &[Transaction]) -> u64 {
+    let mut encoder = Encoder::new();
+    encode_transactions(&mut encoder, transactions);
+    encoder.into_bytes().len() as u64
+}
 
-//     for tx in transactions {
-//         hasher.update(tx.sender.as_bytes());
-//         hasher.update(tx.receiver.as_bytes());
-//         hasher.update(tx.amount.to_be_bytes());
-//     }
+/// Binary blake3 Merkle tree over the encoded transactions; an odd node is paired with
+/// itself. A block without transactions has an all-zero root.
+pub fn calculate_merkle_root(transactions: &[Transaction]) -> String {
+    if transactions.is_empty() {
+        return "0".repeat(64);
+    }
 
-//     format!("{:x}", hasher.finalize())
-// }
+    let mut level: Vec<_> = transactions
+        .iter()
+        .map(|tx| blake3::hash(&tx.encode()))
+        .collect();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut hasher = Hasher::new();
+                hasher.update(pair[0].as_bytes());
+                hasher.update(pair.get(1).unwrap_or(&pair[0]).as_bytes());
+                hasher.finalize()
+            })
+            .collect();
+    }
+
+    level[0].to_hex().to_string()
+}
@@ -1,75 +1,730 @@
-use crate::chain::{Block, BlockHeader};
-use crate::tx::Transaction;
+use crate::account::Account;
+use crate::chain::emission::capped_subsidy;
+use crate::chain::state::{BlockUndo, ChainState};
+use crate::chain::state_tree::{self, StateTree};
+use crate::chain::validation::{
+    median_time_past, reject, validate_body, validate_coinbase, validate_header,
+};
+use crate::chain::{Block, BlockHeader, GenesisConfig, StateProof};
+use crate::config;
+use crate::consensus::pow::{self, BlockSample};
+use crate::events::{Event, EventBus};
+use crate::store::{schema, ScanOptions, Storage, StorageBatch, StorageKind};
+use crate::tx::{Transaction, TransactionStatus};
+use crate::util::codec::{Decode, Encode};
+use crate::util::error::{BlockRejection, ChainError, StorageError};
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+const TIP_KEY: &[u8] = b"tip";
+const INDEX_PREFIX: &str = "index:";
+
+/// Where a block sits in the block tree, including side chains.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockIndex {
+    pub hash: String,
+    pub prev_hash: String,
+    pub height: u64,
+    pub timestamp: i64,
+    pub difficulty: u64,
+    pub cumulative_work: u128,
+    /// Coins issued on this branch up to and including this block.
+    pub supply: f64,
+}
 
 #[derive(Debug, Clone)]
+pub enum BlockOutcome {
+    /// The block extended the active chain.
+    Extended,
+    /// The block made a side chain the one with most work; `disconnected` holds the blocks
+    /// taken off the old chain, tip first, whose transactions are pending again.
+    Reorganized {
+        depth: u64,
+        disconnected: Vec<Block>,
+    },
+    /// The block was stored on a side chain with less cumulative work than the active one.
+    SideChain,
+}
+
+/// The active chain is kept in `blocks`; every known block, including side chains, is
+/// persisted in the chain column family and indexed in `index`.
+///
+/// Headers validated ahead of their bodies, as during sync, are kept in memory in `headers`
+/// until their blocks are added. Their `supply` is the parent's, since it depends on the body.
+#[derive(Clone)]
 pub struct Blockchain {
     pub blocks: Vec<Block>,
+    index: HashMap<String, BlockIndex>,
+    headers: HashMap<String, BlockIndex>,
+    /// Active-chain height of every transaction in `blocks`, by id.
+    transactions: HashMap<String, u64>,
+    /// The header-only entry with most cumulative work, if any was added.
+    best_header: Option<String>,
+    /// Account state as of the active tip.
+    state_tree: StateTree,
+    max_reorg_depth: u64,
+    store: Storage,
+    events: EventBus,
 }
 
 impl Blockchain {
-    pub fn new() -> Self {
-        let now = Utc::now().timestamp();
+    /// Loads the chain from `store`, writing the genesis block described by `genesis` on
+    /// first use. A store whose genesis block differs belongs to another network and is
+    /// refused.
+    pub fn open(store: &Storage, genesis: &GenesisConfig) -> Result<Self, ChainError> {
+        let mut blockchain = Blockchain {
+            blocks: Vec::new(),
+            index: HashMap::new(),
+            headers: HashMap::new(),
+            transactions: HashMap::new(),
+            best_header: None,
+            state_tree: StateTree::default(),
+            max_reorg_depth: config::MAX_REORG_DEPTH,
+            store: store.clone(),
+            events: EventBus::new(),
+        };
 
-        let genesis_block = Block {
-            header: BlockHeader {
-                index: 0,
-                timestamp: now as i64,
-                data: "Genesis Block".to_string(),
-                prev_hash: "0".repeat(64),
-                hash: String::new(),
-                nonce: 0,
-                difficulty: 4,
-                block_size: 0,
-                version: 1,
-            },
-            transactions: vec![],
+        let cf = StorageKind::Chain.name();
+        let tip: String = match store.get(cf, TIP_KEY) {
+            Ok(tip) => bincode::deserialize(&tip)?,
+            Err(StorageError::NotFound) => {
+                blockchain.write_genesis(genesis)?;
+                return Ok(blockchain);
+            }
+            Err(e) => return Err(e.into()),
         };
 
-        let mut blockchain = Blockchain {
-            blocks: vec![genesis_block],
+        let mut cursor = None;
+        loop {
+            let options = ScanOptions::new(1_000)
+                .prefix(INDEX_PREFIX.as_bytes())
+                .after(cursor);
+            let page = store.scan(cf, &options)?;
+            for (_, value) in page.items {
+                let entry: BlockIndex = schema::decode_record(&value)?;
+                blockchain.index.insert(entry.hash.clone(), entry);
+            }
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        let expected = genesis.block()?.header.hash;
+        let found: String = bincode::deserialize(&store.get(cf, &height_key(0))?)?;
+        if found != expected {
+            return Err(ChainError::GenesisMismatch { expected, found });
+        }
+
+        let tip_height = blockchain.entry(&tip)?.height;
+        for height in 0..=tip_height {
+            let hash: String = bincode::deserialize(&store.get(cf, &height_key(height))?)?;
+            let block = blockchain.get_block(&hash)?;
+            blockchain.index_transactions(&block, true);
+            blockchain.blocks.push(block);
+        }
+        blockchain.state_tree = StateTree::new(&state_tree::load(store)?);
+
+        Ok(blockchain)
+    }
+
+    pub fn max_reorg_depth(mut self, depth: u64) -> Self {
+        self.max_reorg_depth = depth;
+        self
+    }
+
+    /// Publishes changes to the active chain on `events` instead of a bus of its own.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    fn write_genesis(&mut self, config: &GenesisConfig) -> Result<(), ChainError> {
+        let genesis = config.block()?;
+        let entry = BlockIndex {
+            hash: genesis.header.hash.clone(),
+            prev_hash: genesis.header.prev_hash.clone(),
+            height: 0,
+            timestamp: genesis.header.timestamp,
+            difficulty: genesis.header.difficulty,
+            cumulative_work: genesis.header.work(),
+            supply: config.supply(),
         };
 
-        blockchain.blocks[0].header.hash = blockchain.blocks[0].calculate_hash();
-        blockchain
+        let cf = StorageKind::Chain.name();
+        let mut batch = StorageBatch::new();
+        batch
+            .insert(cf, &block_key(&entry.hash), &genesis.encode())
+            .insert(cf, &index_key(&entry.hash), &schema::encode_record(&entry)?)
+            .put(cf, &height_key(0), &bincode::serialize(&entry.hash)?)
+            .put(cf, TIP_KEY, &bincode::serialize(&entry.hash)?);
+        for allocation in &config.allocations {
+            Account::stage_genesis(
+                &self.store,
+                &mut batch,
+                &allocation.address,
+                &allocation.public_key,
+                allocation.amount,
+                config.timestamp as u64,
+            )?;
+        }
+        for (address, state) in config.accounts() {
+            state_tree::stage(&mut batch, &address, state)?;
+        }
+        self.store.write(batch)?;
+        self.state_tree = StateTree::new(&config.accounts());
+
+        self.index.insert(entry.hash.clone(), entry);
+        self.index_transactions(&genesis, true);
+        self.blocks.push(genesis);
+        Ok(())
+    }
+
+    /// Announces a switch of the active chain: the transfers of `disconnected` blocks are
+    /// pending again, then each `connected` block brings its header, completed transfers and
+    /// payments.
+    fn publish(&self, disconnected: &[Block], connected: &[Block]) {
+        for block in disconnected {
+            for tx in &block.transactions {
+                if let Transaction::Plain(tx) = tx {
+                    self.events.publish(Event::StatusChanged {
+                        id: tx.id().to_string(),
+                        status: TransactionStatus::Pending,
+                        height: None,
+                    });
+                }
+            }
+        }
+        for block in connected {
+            let height = block.header.index;
+            self.events.publish(Event::NewHeader(block.header.clone()));
+            for tx in &block.transactions {
+                let (receiver, amount) = match tx {
+                    Transaction::Plain(tx) => {
+                        self.events.publish(Event::StatusChanged {
+                            id: tx.id().to_string(),
+                            status: TransactionStatus::Completed,
+                            height: Some(height),
+                        });
+                        (tx.receiver(), tx.amount())
+                    }
+                    Transaction::Coinbase(tx) => (tx.receiver(), tx.amount()),
+                    Transaction::Encrypted(_) => continue,
+                };
+                self.events.publish(Event::Payment {
+                    address: receiver.to_string(),
+                    id: tx.id().to_string(),
+                    amount,
+                    height,
+                });
+            }
+        }
+    }
+
+    fn index_transactions(&mut self, block: &Block, active: bool) {
+        for tx in &block.transactions {
+            if active {
+                self.transactions
+                    .insert(tx.id().to_string(), block.header.index);
+            } else {
+                self.transactions.remove(tx.id());
+            }
+        }
+    }
+
+    pub fn tip(&self) -> &Block {
+        self.blocks
+            .last()
+            .expect("the active chain always holds the genesis block")
+    }
+
+    pub fn height(&self) -> u64 {
+        self.tip().header.index
+    }
+
+    pub fn tip_entry(&self) -> &BlockIndex {
+        &self.index[&self.tip().header.hash]
+    }
+
+    /// Whether the block itself, not only its header, is known.
+    pub fn contains(&self, hash: &str) -> bool {
+        self.index.contains_key(hash)
+    }
+
+    pub fn contains_header(&self, hash: &str) -> bool {
+        self.index.contains_key(hash) || self.headers.contains_key(hash)
     }
 
-    pub fn add_block(&mut self, transactions: Vec<Transaction>) {
+    /// Index entry of a known block or of a header validated ahead of its body.
+    fn lookup(&self, hash: &str) -> Option<&BlockIndex> {
+        self.index.get(hash).or_else(|| self.headers.get(hash))
+    }
+
+    /// The header with most cumulative work, whose block may still be missing. Syncing is
+    /// done once it is the tip.
+    pub fn best_header(&self) -> &BlockIndex {
+        let tip = self.tip_entry();
+        match self
+            .best_header
+            .as_deref()
+            .and_then(|hash| self.lookup(hash))
+        {
+            Some(best) if best.cumulative_work > tip.cumulative_work => best,
+            _ => tip,
+        }
+    }
+
+    /// Validates `headers`, each of which must extend a known block or an earlier header,
+    /// and remembers them so their blocks can be fetched. Headers already known are skipped;
+    /// returns how many were new.
+    pub fn add_headers(&mut self, headers: &[BlockHeader]) -> Result<usize, ChainError> {
         let now = Utc::now().timestamp();
+        let mut added = 0;
+        for header in headers {
+            if self.contains_header(&header.hash) {
+                continue;
+            }
+            let parent = self
+                .lookup(&header.prev_hash)
+                .ok_or_else(|| ChainError::UnknownParent(header.prev_hash.clone()))?;
+            validate_header(
+                header,
+                parent,
+                &self.ancestor_timestamps(parent),
+                self.next_difficulty(parent),
+                now,
+            )?;
 
-        let prev_block = self.blocks.last().unwrap();
-        let new_block = Block {
-            header: BlockHeader {
-                index: prev_block.header.index + 1,
-                timestamp: now as i64,
-                data: "New Block".to_string(),
-                prev_hash: prev_block.header.hash.clone(),
-                hash: String::new(),
-                nonce: 0,
-                difficulty: 4,
-                block_size: 0,
-                version: 1,
-            },
-            transactions,
+            let entry = BlockIndex {
+                hash: header.hash.clone(),
+                prev_hash: header.prev_hash.clone(),
+                height: header.index,
+                timestamp: header.timestamp,
+                difficulty: header.difficulty,
+                supply: parent.supply,
+                cumulative_work: parent.cumulative_work.saturating_add(header.work()),
+            };
+            if entry.cumulative_work > self.best_header().cumulative_work {
+                self.best_header = Some(entry.hash.clone());
+            }
+            self.headers.insert(entry.hash.clone(), entry);
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Forgets the header `hash` and every header built on it, after its block turned out to
+    /// be invalid.
+    pub fn discard_header(&mut self, hash: &str) {
+        let mut discarded = vec![hash.to_string()];
+        while let Some(hash) = discarded.pop() {
+            if self.headers.remove(&hash).is_none() {
+                continue;
+            }
+            discarded.extend(
+                self.headers
+                    .values()
+                    .filter(|entry| entry.prev_hash == hash)
+                    .map(|entry| entry.hash.clone()),
+            );
+        }
+        self.best_header = self
+            .headers
+            .values()
+            .max_by_key(|entry| entry.cumulative_work)
+            .map(|entry| entry.hash.clone());
+    }
+
+    /// Headers between the active chain and the best header whose blocks are still missing,
+    /// oldest first.
+    pub fn missing_blocks(&self) -> Vec<BlockIndex> {
+        let mut missing = Vec::new();
+        let mut current = self.best_header();
+        while let Some(entry) = self.headers.get(&current.hash) {
+            missing.push(entry.clone());
+            match self.lookup(&entry.prev_hash) {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        missing.reverse();
+        missing
+    }
+
+    /// Hashes identifying the best header chain to a peer: the last ten headers, then
+    /// exponentially sparser ones, ending with genesis.
+    pub fn locator(&self) -> Vec<String> {
+        let mut locator = Vec::new();
+        let mut step = 1;
+        let mut current = Some(self.best_header());
+        while let Some(entry) = current {
+            locator.push(entry.hash.clone());
+            if entry.height == 0 {
+                break;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            let target = entry.height.saturating_sub(step);
+            let mut ancestor = entry;
+            while ancestor.height > target {
+                match self.lookup(&ancestor.prev_hash) {
+                    Some(parent) => ancestor = parent,
+                    None => break,
+                }
+            }
+            current = (ancestor.hash != entry.hash).then_some(ancestor);
+        }
+        locator
+    }
+
+    /// Active-chain headers after the first `locator` hash on the active chain, up to `max`.
+    /// Starts after genesis when none of the hashes is known.
+    pub fn headers_after(&self, locator: &[String], max: usize) -> Vec<BlockHeader> {
+        let start = locator
+            .iter()
+            .filter_map(|hash| self.index.get(hash))
+            .find(|entry| self.is_active(entry))
+            .map_or(0, |entry| entry.height as usize);
+        self.blocks[start + 1..]
+            .iter()
+            .take(max)
+            .map(|block| block.header.clone())
+            .collect()
+    }
+
+    pub fn entry(&self, hash: &str) -> Result<&BlockIndex, ChainError> {
+        self.index
+            .get(hash)
+            .ok_or_else(|| ChainError::UnknownBlock(hash.to_string()))
+    }
+
+    /// Any known block, on the active chain or a side chain.
+    pub fn get_block(&self, hash: &str) -> Result<Block, ChainError> {
+        match self.store.get(StorageKind::Chain.name(), &block_key(hash)) {
+            Ok(value) => Ok(Block::decode(&value)?),
+            Err(StorageError::NotFound) => Err(ChainError::UnknownBlock(hash.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// A transaction on the active chain and the block that holds it.
+    pub fn find_transaction(&self, id: &str) -> Option<(&Block, &Transaction)> {
+        let block = self.blocks.get(*self.transactions.get(id)? as usize)?;
+        let tx = block.transactions.iter().find(|tx| tx.id() == id)?;
+        Some((block, tx))
+    }
+
+    /// Active-chain transactions paying or paid by `address`, newest first.
+    pub fn transactions_of(&self, address: &str) -> Vec<(&Block, &Transaction)> {
+        self.blocks
+            .iter()
+            .rev()
+            .flat_map(|block| block.transactions.iter().rev().map(move |tx| (block, tx)))
+            .filter(|(_, tx)| match tx {
+                Transaction::Plain(tx) => tx.sender() == address || tx.receiver() == address,
+                Transaction::Coinbase(tx) => tx.receiver() == address,
+                Transaction::Encrypted(_) => false,
+            })
+            .collect()
+    }
+
+    /// The active block at `height`.
+    pub fn block_at(&self, height: u64) -> Option<&Block> {
+        self.blocks.get(usize::try_from(height).ok()?)
+    }
+
+    pub fn is_active(&self, entry: &BlockIndex) -> bool {
+        self.blocks
+            .get(entry.height as usize)
+            .is_some_and(|block| block.header.hash == entry.hash)
+    }
+
+    /// Coins issued on the active chain so far.
+    pub fn supply(&self) -> f64 {
+        self.tip_entry().supply
+    }
+
+    /// A block template on top of the current tip, still to be mined, whose coinbase pays the
+    /// subsidy and the transactions' fees to `miner`.
+    pub fn next_block(
+        &self,
+        miner: &str,
+        transactions: Vec<Transaction>,
+    ) -> Result<Block, ChainError> {
+        let tip = &self.tip().header;
+        let height = tip.index + 1;
+        let fees: f64 = transactions.iter().map(Transaction::fee).sum();
+        let reward = capped_subsidy(height, self.supply()) + fees;
+        let transactions =
+            std::iter::once(Transaction::coinbase(miner.to_string(), reward, height))
+                .chain(transactions)
+                .collect();
+
+        let difficulty = self.next_difficulty(self.tip_entry());
+        let mut block = Block::new(height, tip.hash.clone(), difficulty, transactions);
+        let mut state = ChainState::new(&self.store, &self.state_tree);
+        state.connect(&block)?;
+        block.header.state_root = state.state_root();
+        // Keep the template valid even when blocks arrive faster than the clock ticks.
+        let median = median_time_past(&self.ancestor_timestamps(self.tip_entry()));
+        if let Some(median) = median {
+            block.header.timestamp = block.header.timestamp.max(median + 1);
+        }
+        block.header.hash = block.calculate_hash();
+        Ok(block)
+    }
+
+    /// Difficulty required of a block on top of `parent`, from the LWMA over the last
+    /// `DIFFICULTY_WINDOW` blocks of its branch.
+    pub fn next_difficulty(&self, parent: &BlockIndex) -> u64 {
+        let mut samples = Vec::with_capacity(config::DIFFICULTY_WINDOW + 1);
+        let mut current = Some(parent);
+        while let Some(entry) = current {
+            if samples.len() == config::DIFFICULTY_WINDOW + 1 {
+                break;
+            }
+            samples.push(BlockSample {
+                timestamp: entry.timestamp,
+                difficulty: entry.difficulty,
+            });
+            current = self.lookup(&entry.prev_hash);
+        }
+        samples.reverse();
+        pow::next_difficulty(&samples)
+    }
+
+    /// Timestamps of `entry` and its ancestors, up to `MEDIAN_TIME_SPAN` blocks.
+    fn ancestor_timestamps(&self, entry: &BlockIndex) -> Vec<i64> {
+        let mut timestamps = Vec::with_capacity(config::MEDIAN_TIME_SPAN);
+        let mut current = Some(entry);
+        while let Some(entry) = current {
+            if timestamps.len() == config::MEDIAN_TIME_SPAN {
+                break;
+            }
+            timestamps.push(entry.timestamp);
+            current = self.lookup(&entry.prev_hash);
+        }
+        timestamps
+    }
+
+    /// Adds a block anywhere in the block tree and switches to the chain with the most
+    /// cumulative work, rolling account state back to the fork point and forward along the new
+    /// branch. The whole switch is written in one batch, so an invalid branch changes nothing.
+    pub fn add_block(&mut self, block: Block) -> Result<BlockOutcome, ChainError> {
+        let hash = block.header.hash.clone();
+        if self.contains(&hash) {
+            return Err(ChainError::DuplicateBlock(hash));
+        }
+        let parent = self
+            .index
+            .get(&block.header.prev_hash)
+            .ok_or_else(|| ChainError::UnknownParent(block.header.prev_hash.clone()))?;
+        let now = Utc::now().timestamp();
+        validate_header(
+            &block.header,
+            parent,
+            &self.ancestor_timestamps(parent),
+            self.next_difficulty(parent),
+            now,
+        )?;
+        validate_body(&block)?;
+        let supply = validate_coinbase(&block, parent)?;
+
+        let entry = BlockIndex {
+            hash: hash.clone(),
+            prev_hash: block.header.prev_hash.clone(),
+            height: block.header.index,
+            timestamp: block.header.timestamp,
+            difficulty: block.header.difficulty,
+            supply,
+            cumulative_work: parent.cumulative_work.saturating_add(block.header.work()),
         };
 
-        new_block.header.hash = new_block.calculate_hash();
-        self.blocks.push(new_block);
+        let cf = StorageKind::Chain.name();
+        let mut batch = StorageBatch::new();
+        batch.insert(cf, &block_key(&hash), &block.encode()).insert(
+            cf,
+            &index_key(&hash),
+            &schema::encode_record(&entry)?,
+        );
+
+        let tip = self.tip_entry().clone();
+        if entry.cumulative_work <= tip.cumulative_work {
+            self.store.write(batch)?;
+            self.headers.remove(&hash);
+            self.index.insert(hash, entry);
+            return Ok(BlockOutcome::SideChain);
+        }
+
+        // Walk back from the new block to the first ancestor on the active chain.
+        let mut branch = vec![block];
+        let mut fork = self.entry(&entry.prev_hash)?.clone();
+        while !self.is_active(&fork) {
+            branch.push(self.get_block(&fork.hash)?);
+            fork = self.entry(&fork.prev_hash)?.clone();
+        }
+        branch.reverse();
+
+        let depth = tip.height - fork.height;
+        if depth > self.max_reorg_depth {
+            return Err(ChainError::ReorgTooDeep {
+                depth,
+                max: self.max_reorg_depth,
+            });
+        }
+
+        let mut state = ChainState::new(&self.store, &self.state_tree);
+        for old in self.blocks[fork.height as usize + 1..].iter().rev() {
+            let undo_key = undo_key(&old.header.hash);
+            let undo: BlockUndo = schema::decode_record(&self.store.get(cf, &undo_key)?)?;
+            state.disconnect(old, undo);
+            batch
+                .delete(cf, &undo_key)
+                .delete(cf, &height_key(old.header.index));
+        }
+        // Transactions already on the kept part of the active chain, or earlier in the
+        // branch, can't be mined again.
+        let mut branch_ids = HashSet::new();
+        for new in &branch {
+            for tx in &new.transactions {
+                let on_chain = self
+                    .transactions
+                    .get(tx.id())
+                    .is_some_and(|height| *height <= fork.height);
+                if on_chain || !branch_ids.insert(tx.id().to_string()) {
+                    return Err(reject(
+                        new,
+                        BlockRejection::DuplicateTransaction(tx.id().to_string()),
+                    ));
+                }
+            }
+            let undo = state.connect(new)?;
+            let expected = state.state_root();
+            if new.header.state_root != expected {
+                return Err(reject(
+                    new,
+                    BlockRejection::StateRootMismatch {
+                        expected,
+                        found: new.header.state_root.clone(),
+                    },
+                ));
+            }
+            batch
+                .put(
+                    cf,
+                    &undo_key(&new.header.hash),
+                    &schema::encode_record(&undo)?,
+                )
+                .put(
+                    cf,
+                    &height_key(new.header.index),
+                    &bincode::serialize(&new.header.hash)?,
+                );
+        }
+        batch.put(cf, TIP_KEY, &bincode::serialize(&hash)?);
+        let touched = state.accounts().clone();
+        state.flush(&mut batch)?;
+        self.store.write(batch)?;
+        self.state_tree.apply(&touched);
+
+        self.headers.remove(&hash);
+        self.index.insert(hash, entry);
+        let mut disconnected = self.blocks.split_off(fork.height as usize + 1);
+        disconnected.reverse();
+        for old in &disconnected {
+            self.index_transactions(old, false);
+        }
+        for new in &branch {
+            self.index_transactions(new, true);
+        }
+        self.publish(&disconnected, &branch);
+        self.blocks.extend(branch);
+
+        if depth == 0 {
+            Ok(BlockOutcome::Extended)
+        } else {
+            Ok(BlockOutcome::Reorganized {
+                depth,
+                disconnected,
+            })
+        }
+    }
+
+    /// Proves the state of `address` after the active block at `height`, to be checked
+    /// against that block's `state_root`. Returns `None` when the account held nothing then.
+    pub fn prove_account(
+        &self,
+        address: &str,
+        height: u64,
+    ) -> Result<Option<StateProof>, ChainError> {
+        if height > self.height() {
+            return Err(ChainError::UnknownBlock(height.to_string()));
+        }
+
+        // Roll the tip state back through the undo records of every later block.
+        let cf = StorageKind::Chain.name();
+        let mut accounts = state_tree::load(&self.store)?;
+        for block in self.blocks[height as usize + 1..].iter().rev() {
+            let undo: BlockUndo =
+                schema::decode_record(&self.store.get(cf, &undo_key(&block.header.hash))?)?;
+            accounts.extend(undo.accounts);
+        }
+        Ok(state_tree::prove(&accounts, address))
     }
 
     pub fn is_chain_valid(&self) -> bool {
+        self.validate_chain().is_ok()
+    }
+
+    /// Re-runs the header, body and coinbase checks over the active chain. Balances and nonces were
+    /// checked when each block was connected and aren't replayed.
+    pub fn validate_chain(&self) -> Result<(), ChainError> {
+        let now = Utc::now().timestamp();
         for i in 1..self.blocks.len() {
             let current_block = &self.blocks[i];
             let prev_block = &self.blocks[i - 1];
 
-            if current_block.header.hash != current_block.calculate_hash() {
-                return false;
-            }
-
             if current_block.header.prev_hash != prev_block.header.hash {
-                return false;
+                return Err(ChainError::InvalidPrevHash(current_block.header.index));
             }
+            let parent = self.entry(&prev_block.header.hash)?;
+            validate_header(
+                &current_block.header,
+                parent,
+                &self.ancestor_timestamps(parent),
+                self.next_difficulty(parent),
+                now,
+            )?;
+            validate_body(current_block)?;
+            validate_coinbase(current_block, parent)?;
         }
-        true
+        Ok(())
     }
 }
+
+/// Blocks are stored in their canonical encoding, which carries its own header version, so
+/// they aren't wrapped in a schema envelope.
+fn block_key(hash: &str) -> Vec<u8> {
+    format!("block:{}", hash).into_bytes()
+}
+
+fn index_key(hash: &str) -> Vec<u8> {
+    format!("{}{}", INDEX_PREFIX, hash).into_bytes()
+}
+
+fn undo_key(hash: &str) -> Vec<u8> {
+    format!("undo:{}", hash).into_bytes()
+}
+
+/// Active-chain height to block hash, big-endian so heights sort numerically.
+fn height_key(height: u64) -> Vec<u8> {
+    let mut key = b"height:".to_vec();
+    key.extend_from_slice(&height.to_be_bytes());
+    key
+}
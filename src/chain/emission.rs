@@ -0,0 +1,20 @@
+use crate::config;
+
+/// Subsidy scheduled for a block at `height`: `INITIAL_BLOCK_REWARD` halved every
+/// `HALVING_INTERVAL` blocks. The schedule sums to just under `MAX_SUPPLY`.
+pub fn block_subsidy(height: u64) -> f64 {
+    if height == 0 {
+        return 0.0;
+    }
+    let halvings = (height - 1) / config::HALVING_INTERVAL;
+    if halvings >= 64 {
+        return 0.0;
+    }
+    config::INITIAL_BLOCK_REWARD / (1u64 << halvings) as f64
+}
+
+/// Scheduled subsidy, cut off so total issuance never passes `MAX_SUPPLY`.
+pub fn capped_subsidy(height: u64, issued: f64) -> f64 {
+    let remaining = (config::MAX_SUPPLY as f64 - issued).max(0.0);
+    block_subsidy(height).min(remaining)
+}
@@ -0,0 +1,172 @@
+use crate::account::wallet::Wallet;
+use crate::account::AccountState;
+use crate::chain::block::{calculate_merkle_root, transactions_size};
+use crate::chain::{state_tree, Block, BlockHeader};
+use crate::config;
+use crate::tx::Transaction;
+use crate::util::error::ChainError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The genesis specification every node of a network must share, embedded in the binary.
+const DEFAULT_GENESIS: &str = include_str!("../../genesis.json");
+
+/// Coins credited to an account by the genesis block. The public key is needed because the
+/// address alone is not enough to register the account and encrypt its balance, and the
+/// blinding shows the key owns the address.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisAllocation {
+    pub address: String,
+    pub public_key: String,
+    pub blinding: String,
+    pub amount: f64,
+}
+
+/// Everything the genesis block is derived from. Two nodes loading the same configuration
+/// build byte-identical genesis blocks and the same initial balances.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisConfig {
+    pub network_id: String,
+    pub timestamp: i64,
+    pub difficulty: u64,
+    pub message: String,
+    #[serde(default)]
+    pub allocations: Vec<GenesisAllocation>,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        Self::parse(DEFAULT_GENESIS).expect("the embedded genesis configuration is valid")
+    }
+}
+
+impl GenesisConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ChainError> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|e| {
+            ChainError::InvalidGenesis(format!("cannot read {}: {e}", path.display()))
+        })?;
+        Self::parse(&json)
+    }
+
+    pub fn parse(json: &str) -> Result<Self, ChainError> {
+        let config: GenesisConfig =
+            serde_json::from_str(json).map_err(|e| ChainError::InvalidGenesis(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ChainError> {
+        let invalid = |reason: String| Err(ChainError::InvalidGenesis(reason));
+
+        if self.network_id.is_empty() {
+            return invalid("network id is empty".to_string());
+        }
+        if self.difficulty < config::MIN_DIFFICULTY {
+            return invalid(format!(
+                "difficulty {} is below the minimum of {}",
+                self.difficulty,
+                config::MIN_DIFFICULTY
+            ));
+        }
+        if self.timestamp < 0 {
+            return invalid(format!("negative timestamp {}", self.timestamp));
+        }
+
+        let mut total = 0.0;
+        for (i, allocation) in self.allocations.iter().enumerate() {
+            let opened = Wallet::open_address(
+                &allocation.address,
+                &allocation.public_key,
+                &allocation.blinding,
+            );
+            match opened {
+                Ok(true) => {}
+                Ok(false) => {
+                    return invalid(format!(
+                        "public key {} does not own {}",
+                        allocation.public_key, allocation.address
+                    ))
+                }
+                Err(e) => return invalid(format!("invalid address {}: {e}", allocation.address)),
+            }
+            let valid_amount = allocation.amount.is_finite() && allocation.amount > 0.0;
+            if !valid_amount {
+                return invalid(format!(
+                    "invalid amount {} for {}",
+                    allocation.amount, allocation.address
+                ));
+            }
+            if self.allocations[..i]
+                .iter()
+                .any(|earlier| earlier.address == allocation.address)
+            {
+                return invalid(format!("{} is allocated twice", allocation.address));
+            }
+            total += allocation.amount;
+        }
+        if total > config::MAX_SUPPLY as f64 {
+            return invalid(format!(
+                "allocations of {total} exceed the maximum supply of {}",
+                config::MAX_SUPPLY
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Coins in circulation once the genesis block is connected.
+    pub fn supply(&self) -> f64 {
+        self.allocations.iter().map(|a| a.amount).sum()
+    }
+
+    /// Account states the genesis block starts the chain with.
+    pub fn accounts(&self) -> HashMap<String, AccountState> {
+        self.allocations
+            .iter()
+            .map(|allocation| {
+                let state = AccountState {
+                    balance: allocation.amount,
+                    nonce: 0,
+                };
+                (allocation.address.clone(), state)
+            })
+            .collect()
+    }
+
+    /// Builds the genesis block. Allocations become height-0 coinbase transactions so the
+    /// merkle root commits to them, and the previous hash commits to the network id so that
+    /// networks sharing everything else still have distinct genesis blocks.
+    pub fn block(&self) -> Result<Block, ChainError> {
+        let transactions: Vec<Transaction> = self
+            .allocations
+            .iter()
+            .map(|allocation| {
+                Transaction::coinbase(allocation.address.clone(), allocation.amount, 0)
+            })
+            .collect();
+
+        let mut genesis = Block {
+            header: BlockHeader {
+                index: 0,
+                timestamp: self.timestamp,
+                data: self.message.clone(),
+                prev_hash: blake3::hash(self.network_id.as_bytes())
+                    .to_hex()
+                    .to_string(),
+                merkle_root: calculate_merkle_root(&transactions),
+                state_root: state_tree::root(&self.accounts()),
+                hash: String::new(),
+                nonce: 0,
+                difficulty: self.difficulty,
+                block_size: transactions_size(&transactions),
+                version: BlockHeader::VERSION,
+            },
+            transactions,
+        };
+        genesis.header.hash = genesis.calculate_hash();
+        Ok(genesis)
+    }
+}
@@ -1,5 +1,13 @@
 pub mod block;
 pub mod chain;
+pub mod emission;
+pub mod genesis;
+pub mod state;
+pub mod state_tree;
+pub mod validation;
 
 pub use block::{Block, BlockHeader};
-pub use chain::Blockchain;
+pub use chain::{BlockIndex, BlockOutcome, Blockchain};
+pub use genesis::{GenesisAllocation, GenesisConfig};
+pub use state::{BlockUndo, ChainState};
+pub use state_tree::StateProof;
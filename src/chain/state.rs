@@ -0,0 +1,188 @@
+use crate::account::{Account, AccountState};
+use crate::chain::state_tree::{self, StateTree};
+use crate::chain::validation::reject;
+use crate::chain::Block;
+use crate::store::{Storage, StorageBatch};
+use crate::tx::{Transaction, TransactionStatus};
+use crate::util::error::{AccountError, BlockRejection, ChainError, StorageError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// State of every account a block touched, as it was before the block was connected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockUndo {
+    pub accounts: Vec<(String, AccountState)>,
+}
+
+/// Account balances, nonces and transaction statuses as they evolve while blocks are
+/// disconnected and connected, kept in memory until the whole reorganization is known to be
+/// valid.
+pub struct ChainState<'a> {
+    store: &'a Storage,
+    tree: &'a StateTree,
+    accounts: HashMap<String, AccountState>,
+    statuses: Vec<(String, TransactionStatus)>,
+}
+
+impl<'a> ChainState<'a> {
+    pub fn new(store: &'a Storage, tree: &'a StateTree) -> Self {
+        ChainState {
+            store,
+            tree,
+            accounts: HashMap::new(),
+            statuses: Vec::new(),
+        }
+    }
+
+    pub fn account(&mut self, address: &str) -> Result<Option<AccountState>, ChainError> {
+        if let Some(state) = self.accounts.get(address) {
+            return Ok(Some(*state));
+        }
+        match Account::state_of(self.store, address) {
+            Ok(state) => {
+                self.accounts.insert(address.to_string(), state);
+                Ok(Some(state))
+            }
+            Err(
+                AccountError::InvalidAddress(_) | AccountError::Storage(StorageError::NotFound),
+            ) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Applies the block's transfers and returns what's needed to undo them. Expects a body
+    /// that already passed `validate_body`.
+    pub fn connect(&mut self, block: &Block) -> Result<BlockUndo, ChainError> {
+        let mut undo = BlockUndo::default();
+
+        for tx in &block.transactions {
+            let tx = match tx {
+                Transaction::Plain(plain) => plain,
+                Transaction::Coinbase(coinbase) => {
+                    let receiver = self.known_account(block, tx.id(), coinbase.receiver())?;
+                    remember(&mut undo, coinbase.receiver(), receiver);
+                    self.accounts.insert(
+                        coinbase.receiver().to_string(),
+                        AccountState {
+                            balance: receiver.balance + coinbase.amount(),
+                            ..receiver
+                        },
+                    );
+                    continue;
+                }
+                Transaction::Encrypted(_) => {
+                    return Err(reject(
+                        block,
+                        BlockRejection::EncryptedTransaction(tx.id().to_string()),
+                    ))
+                }
+            };
+
+            let sender = self.known_account(block, tx.id(), tx.sender())?;
+            let receiver = self.known_account(block, tx.id(), tx.receiver())?;
+            if tx.nonce() != sender.nonce {
+                return Err(reject(
+                    block,
+                    BlockRejection::InvalidNonce {
+                        id: tx.id().to_string(),
+                        expected: sender.nonce,
+                        found: tx.nonce(),
+                    },
+                ));
+            }
+            let required = tx.amount() + tx.fee();
+            if sender.balance < required {
+                return Err(reject(
+                    block,
+                    BlockRejection::InsufficientFunds {
+                        id: tx.id().to_string(),
+                        available: sender.balance,
+                        required,
+                    },
+                ));
+            }
+
+            remember(&mut undo, tx.sender(), sender);
+            remember(&mut undo, tx.receiver(), receiver);
+            self.accounts.insert(
+                tx.sender().to_string(),
+                AccountState {
+                    balance: sender.balance - required,
+                    nonce: sender.nonce + 1,
+                },
+            );
+            // Re-read so a transfer to oneself only loses the fee.
+            let receiver = self.accounts[tx.receiver()];
+            self.accounts.insert(
+                tx.receiver().to_string(),
+                AccountState {
+                    balance: receiver.balance + tx.amount(),
+                    ..receiver
+                },
+            );
+
+            self.statuses
+                .push((tx.id().to_string(), TransactionStatus::Completed));
+        }
+
+        Ok(undo)
+    }
+
+    /// Restores the accounts saved by `connect` and returns the block's transactions to
+    /// pending.
+    pub fn disconnect(&mut self, block: &Block, undo: BlockUndo) {
+        for (address, state) in undo.accounts {
+            self.accounts.insert(address, state);
+        }
+        for tx in &block.transactions {
+            self.statuses
+                .push((tx.id().to_string(), TransactionStatus::Pending));
+        }
+    }
+
+    /// Root of the account state tree as of the last connected block.
+    pub fn state_root(&self) -> String {
+        self.tree.root_with(&self.accounts)
+    }
+
+    /// Accounts changed since the state was created, to commit to the state tree once the
+    /// changes are written.
+    pub fn accounts(&self) -> &HashMap<String, AccountState> {
+        &self.accounts
+    }
+
+    /// Stages every touched account, its state tree leaf and status change into `batch`.
+    pub fn flush(self, batch: &mut StorageBatch) -> Result<(), ChainError> {
+        for (address, state) in &self.accounts {
+            Account::stage_state(self.store, batch, address, *state)?;
+            state_tree::stage(batch, address, *state)?;
+        }
+        for (tx_id, status) in self.statuses {
+            Transaction::stage_status(self.store, batch, &tx_id, status)?;
+        }
+        Ok(())
+    }
+
+    fn known_account(
+        &mut self,
+        block: &Block,
+        tx_id: &str,
+        address: &str,
+    ) -> Result<AccountState, ChainError> {
+        self.account(address)?.ok_or_else(|| {
+            reject(
+                block,
+                BlockRejection::UnknownAccount {
+                    id: tx_id.to_string(),
+                    address: address.to_string(),
+                },
+            )
+        })
+    }
+}
+
+fn remember(undo: &mut BlockUndo, address: &str, state: AccountState) {
+    if !undo.accounts.iter().any(|(touched, _)| touched == address) {
+        undo.accounts.push((address.to_string(), state));
+    }
+}
@@ -0,0 +1,319 @@
+use crate::account::AccountState;
+use crate::store::{schema, ScanOptions, Storage, StorageBatch, StorageKind};
+use crate::util::error::ChainError;
+use blake3::Hasher;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const LEAF_PREFIX: &str = "state:";
+const LEAF_TAG: u8 = 0;
+const NODE_TAG: u8 = 1;
+const EMPTY: [u8; 32] = [0; 32];
+
+/// One account as committed to by the state tree, kept in the chain column family next to
+/// the blocks so it moves with them on reorganizations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StateLeaf {
+    address: String,
+    state: AccountState,
+}
+
+/// Proof that `address` held `state` under a block's `state_root`. Siblings run from the
+/// root down to the subtree holding only this account.
+///
+/// Balance records are encrypted under a fresh nonce on every node, so the tree commits to
+/// the balance and nonce they decrypt to rather than to the ciphertext; anyone with the
+/// account's public key can decrypt its record and check it against the proof.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateProof {
+    pub address: String,
+    pub state: AccountState,
+    pub siblings: Vec<String>,
+}
+
+impl StateProof {
+    pub fn verify(&self, state_root: &str) -> bool {
+        let key = leaf_key(&self.address);
+        let mut hash = leaf_hash(&key, &self.state);
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            let sibling = match hex::decode(sibling)
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            {
+                Some(sibling) => sibling,
+                None => return false,
+            };
+            hash = if bit(&key, depth) {
+                node_hash(&sibling, &hash)
+            } else {
+                node_hash(&hash, &sibling)
+            };
+        }
+        hex::encode(hash) == state_root
+    }
+}
+
+/// Every account with a balance or a nonce, as of the active tip. Accounts still in their
+/// initial state are left out, so accounts created locally and never used don't make one
+/// node's state differ from another's.
+pub fn load(store: &Storage) -> Result<HashMap<String, AccountState>, ChainError> {
+    let mut accounts = HashMap::new();
+    let mut cursor = None;
+    loop {
+        let options = ScanOptions::new(1_000)
+            .prefix(LEAF_PREFIX.as_bytes())
+            .after(cursor);
+        let page = store.scan(StorageKind::Chain.name(), &options)?;
+        for (_, value) in page.items {
+            let leaf: StateLeaf = schema::decode_record(&value)?;
+            accounts.insert(leaf.address, leaf.state);
+        }
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            return Ok(accounts);
+        }
+    }
+}
+
+/// Stages `state` as the committed state of `address`.
+pub fn stage(
+    batch: &mut StorageBatch,
+    address: &str,
+    state: AccountState,
+) -> Result<(), ChainError> {
+    let key = format!("{}{}", LEAF_PREFIX, hex::encode(leaf_key(address))).into_bytes();
+    if state == AccountState::default() {
+        batch.delete(StorageKind::Chain.name(), &key);
+    } else {
+        let leaf = StateLeaf {
+            address: address.to_string(),
+            state,
+        };
+        batch.put(
+            StorageKind::Chain.name(),
+            &key,
+            &schema::encode_record(&leaf)?,
+        );
+    }
+    Ok(())
+}
+
+/// The committed state tree, kept in memory next to the chain: every leaf plus the hash of
+/// every subtree holding two or more of them. A root over a block's changes only rehashes
+/// the paths to the accounts it touched.
+#[derive(Debug, Clone, Default)]
+pub struct StateTree {
+    leaves: BTreeMap<[u8; 32], [u8; 32]>,
+    /// Hashes of the subtrees with several leaves, by depth and key prefix.
+    nodes: HashMap<(usize, [u8; 32]), [u8; 32]>,
+}
+
+/// New leaf hashes by leaf key, `None` for accounts back in their initial state.
+type Changes = BTreeMap<[u8; 32], Option<[u8; 32]>>;
+
+impl StateTree {
+    pub fn new(accounts: &HashMap<String, AccountState>) -> Self {
+        let mut tree = StateTree::default();
+        tree.apply(accounts);
+        tree
+    }
+
+    /// Root of the committed state with `overlay` applied, leaving the tree untouched.
+    pub fn root_with(&self, overlay: &HashMap<String, AccountState>) -> String {
+        hex::encode(self.hash(0, [0; 32], &changes(overlay)))
+    }
+
+    /// Commits the new state of `accounts` and rehashes the paths to them.
+    pub fn apply(&mut self, accounts: &HashMap<String, AccountState>) {
+        for (key, hash) in changes(accounts) {
+            for depth in 0..=256 {
+                self.nodes.remove(&(depth, prefix(&key, depth)));
+            }
+            match hash {
+                Some(hash) => self.leaves.insert(key, hash),
+                None => self.leaves.remove(&key),
+            };
+        }
+        self.fill(0, [0; 32]);
+    }
+
+    /// Hash of the committed subtree at `depth` under `prefix`, computing and caching the
+    /// node hashes that are missing.
+    fn fill(&mut self, depth: usize, prefix: [u8; 32]) -> [u8; 32] {
+        let mut leaves = self.leaves.range(prefix..=last_key(&prefix, depth));
+        match (leaves.next(), leaves.next()) {
+            (None, _) => EMPTY,
+            (Some((_, hash)), None) => *hash,
+            _ => {
+                if let Some(hash) = self.nodes.get(&(depth, prefix)) {
+                    return *hash;
+                }
+                let left = self.fill(depth + 1, prefix);
+                let right = self.fill(depth + 1, with_bit(&prefix, depth));
+                let hash = node_hash(&left, &right);
+                self.nodes.insert((depth, prefix), hash);
+                hash
+            }
+        }
+    }
+
+    /// Hash of the subtree at `depth` under `prefix` with `changes` applied. Subtrees none of
+    /// the changes fall in come straight from the cache.
+    fn hash(&self, depth: usize, prefix: [u8; 32], changes: &Changes) -> [u8; 32] {
+        let last = last_key(&prefix, depth);
+        let mut changed = changes.range(prefix..=last).peekable();
+        if changed.peek().is_none() {
+            let mut leaves = self.leaves.range(prefix..=last);
+            return match (leaves.next(), leaves.next()) {
+                (None, _) => EMPTY,
+                (Some((_, hash)), None) => *hash,
+                _ => self.nodes[&(depth, prefix)],
+            };
+        }
+
+        // Up to two leaves of the changed subtree tell whether it still needs a node.
+        let mut live: Vec<[u8; 32]> = self
+            .leaves
+            .range(prefix..=last)
+            .filter(|(key, _)| !changes.contains_key(*key))
+            .map(|(_, hash)| *hash)
+            .take(2)
+            .collect();
+        live.extend(changed.filter_map(|(_, hash)| *hash).take(2));
+        match live[..] {
+            [] => EMPTY,
+            [hash] => hash,
+            _ => node_hash(
+                &self.hash(depth + 1, prefix, changes),
+                &self.hash(depth + 1, with_bit(&prefix, depth), changes),
+            ),
+        }
+    }
+}
+
+fn changes(accounts: &HashMap<String, AccountState>) -> Changes {
+    accounts
+        .iter()
+        .map(|(address, state)| {
+            let key = leaf_key(address);
+            let hash = (*state != AccountState::default()).then(|| leaf_hash(&key, state));
+            (key, hash)
+        })
+        .collect()
+}
+
+/// `key` with every bit from `depth` on cleared: the first key of its subtree at `depth`.
+fn prefix(key: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut prefix = *key;
+    for bit in depth..256 {
+        prefix[bit / 8] &= !(0x80 >> (bit % 8));
+    }
+    prefix
+}
+
+/// The last key of the subtree at `depth` under `prefix`.
+fn last_key(prefix: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut last = *prefix;
+    for bit in depth..256 {
+        last[bit / 8] |= 0x80 >> (bit % 8);
+    }
+    last
+}
+
+fn with_bit(prefix: &[u8; 32], depth: usize) -> [u8; 32] {
+    let mut prefix = *prefix;
+    prefix[depth / 8] |= 0x80 >> (depth % 8);
+    prefix
+}
+
+/// Sparse Merkle root over blake3(address). A subtree holding a single account is
+/// represented by its leaf and an empty one by zeros, so only populated paths are hashed.
+pub fn root(accounts: &HashMap<String, AccountState>) -> String {
+    hex::encode(subtree(&sorted_leaves(accounts), 0))
+}
+
+pub fn prove(accounts: &HashMap<String, AccountState>, address: &str) -> Option<StateProof> {
+    let state = *accounts.get(address)?;
+    if state == AccountState::default() {
+        return None;
+    }
+
+    let key = leaf_key(address);
+    let leaves = sorted_leaves(accounts);
+    let mut slice = &leaves[..];
+    let mut siblings = Vec::new();
+    let mut depth = 0;
+    while slice.len() > 1 {
+        let (left, right) = split(slice, depth);
+        if bit(&key, depth) {
+            siblings.push(hex::encode(subtree(left, depth + 1)));
+            slice = right;
+        } else {
+            siblings.push(hex::encode(subtree(right, depth + 1)));
+            slice = left;
+        }
+        depth += 1;
+    }
+
+    Some(StateProof {
+        address: address.to_string(),
+        state,
+        siblings,
+    })
+}
+
+fn sorted_leaves(accounts: &HashMap<String, AccountState>) -> Vec<([u8; 32], [u8; 32])> {
+    let mut leaves: Vec<_> = accounts
+        .iter()
+        .filter(|(_, state)| **state != AccountState::default())
+        .map(|(address, state)| {
+            let key = leaf_key(address);
+            (key, leaf_hash(&key, state))
+        })
+        .collect();
+    leaves.sort_unstable_by_key(|(key, _)| *key);
+    leaves
+}
+
+fn subtree(leaves: Leaves<'_>, depth: usize) -> [u8; 32] {
+    match leaves {
+        [] => EMPTY,
+        [(_, hash)] => *hash,
+        _ => {
+            let (left, right) = split(leaves, depth);
+            node_hash(&subtree(left, depth + 1), &subtree(right, depth + 1))
+        }
+    }
+}
+
+/// Leaf keys and hashes, sorted by key.
+type Leaves<'a> = &'a [([u8; 32], [u8; 32])];
+
+fn split(leaves: Leaves<'_>, depth: usize) -> (Leaves<'_>, Leaves<'_>) {
+    leaves.split_at(leaves.partition_point(|(key, _)| !bit(key, depth)))
+}
+
+fn bit(key: &[u8; 32], depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+fn leaf_key(address: &str) -> [u8; 32] {
+    *blake3::hash(address.as_bytes()).as_bytes()
+}
+
+fn leaf_hash(key: &[u8; 32], state: &AccountState) -> [u8; 32] {
+    let mut hasher = Hasher::new();
+    hasher.update(&[LEAF_TAG]);
+    hasher.update(key);
+    hasher.update(&state.balance.to_bits().to_be_bytes());
+    hasher.update(&state.nonce.to_be_bytes());
+    *hasher.finalize().as_bytes()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Hasher::new();
+    hasher.update(&[NODE_TAG]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
@@ -0,0 +1,243 @@
+use crate::account::wallet::Wallet;
+use crate::chain::block::{calculate_merkle_root, transactions_size};
+use crate::chain::emission::capped_subsidy;
+use crate::chain::{Block, BlockHeader, BlockIndex};
+use crate::config;
+use crate::tx::transaction::PlainTransaction;
+use crate::tx::Transaction;
+use crate::util::codec::Encode;
+use crate::util::error::{AccountError, BlockRejection, ChainError};
+use std::collections::HashSet;
+
+pub fn reject(block: &Block, reason: BlockRejection) -> ChainError {
+    reject_header(&block.header, reason)
+}
+
+pub fn reject_header(header: &BlockHeader, reason: BlockRejection) -> ChainError {
+    ChainError::InvalidBlock {
+        hash: header.hash.clone(),
+        reason,
+    }
+}
+
+/// Median of the given block timestamps, usually the last `MEDIAN_TIME_SPAN` ancestors.
+pub fn median_time_past(timestamps: &[i64]) -> Option<i64> {
+    let mut sorted = timestamps.to_vec();
+    sorted.sort_unstable();
+    sorted.get(sorted.len() / 2).copied()
+}
+
+/// Checks that don't need account state: linkage, hash, proof of work and timestamp bounds.
+/// `ancestor_timestamps` are the timestamps of the parent and the blocks before it. Needs no
+/// body, so headers can be checked before their blocks are downloaded.
+pub fn validate_header(
+    header: &BlockHeader,
+    parent: &BlockIndex,
+    ancestor_timestamps: &[i64],
+    expected_difficulty: u64,
+    now: i64,
+) -> Result<(), ChainError> {
+    if header.version != BlockHeader::VERSION {
+        return Err(reject_header(
+            header,
+            BlockRejection::UnsupportedVersion(header.version),
+        ));
+    }
+    if header.index != parent.height + 1 {
+        return Err(reject_header(
+            header,
+            BlockRejection::InvalidHeight {
+                parent: parent.height,
+                found: header.index,
+            },
+        ));
+    }
+    if header.hash != header.calculate_hash() {
+        return Err(reject_header(header, BlockRejection::HashMismatch));
+    }
+    if header.difficulty != expected_difficulty {
+        return Err(reject_header(
+            header,
+            BlockRejection::UnexpectedDifficulty {
+                expected: expected_difficulty,
+                found: header.difficulty,
+            },
+        ));
+    }
+    if !header.meets_difficulty() {
+        return Err(reject_header(
+            header,
+            BlockRejection::InsufficientProofOfWork(header.difficulty),
+        ));
+    }
+
+    if let Some(median) = median_time_past(ancestor_timestamps) {
+        if header.timestamp <= median {
+            return Err(reject_header(
+                header,
+                BlockRejection::TimestampTooOld {
+                    timestamp: header.timestamp,
+                    median,
+                },
+            ));
+        }
+    }
+    if header.timestamp > now.saturating_add(config::MAX_FUTURE_DRIFT_SECONDS) {
+        return Err(reject_header(
+            header,
+            BlockRejection::TimestampTooNew {
+                timestamp: header.timestamp,
+                now,
+                max_drift: config::MAX_FUTURE_DRIFT_SECONDS,
+            },
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks on the block body that hold on any branch: size limits, the Merkle root, coinbase
+/// placement, duplicate transactions and sender signatures. Balances and nonces are checked
+/// when connecting.
+pub fn validate_body(block: &Block) -> Result<(), ChainError> {
+    let size = block.encode().len() as u64;
+    if size > config::MAX_BLOCK_SIZE_BYTES {
+        return Err(reject(
+            block,
+            BlockRejection::TooLarge {
+                size,
+                max: config::MAX_BLOCK_SIZE_BYTES,
+            },
+        ));
+    }
+    let transactions_size = transactions_size(&block.transactions);
+    if block.header.block_size != transactions_size {
+        return Err(reject(
+            block,
+            BlockRejection::SizeMismatch {
+                declared: block.header.block_size,
+                actual: transactions_size,
+            },
+        ));
+    }
+    if block.header.merkle_root != calculate_merkle_root(&block.transactions) {
+        return Err(reject(block, BlockRejection::MerkleRootMismatch));
+    }
+
+    if !matches!(block.transactions.first(), Some(Transaction::Coinbase(_))) {
+        return Err(reject(block, BlockRejection::MissingCoinbase));
+    }
+
+    let mut seen = HashSet::new();
+    for (position, tx) in block.transactions.iter().enumerate() {
+        if !seen.insert(tx.id()) {
+            return Err(reject(
+                block,
+                BlockRejection::DuplicateTransaction(tx.id().to_string()),
+            ));
+        }
+
+        let tx = match tx {
+            Transaction::Plain(plain) => plain,
+            Transaction::Coinbase(coinbase) if position == 0 => {
+                if coinbase.height() != block.header.index {
+                    return Err(reject(
+                        block,
+                        BlockRejection::CoinbaseHeightMismatch {
+                            expected: block.header.index,
+                            found: coinbase.height(),
+                        },
+                    ));
+                }
+                if !coinbase.amount().is_finite() || coinbase.amount() < 0.0 {
+                    return Err(reject(
+                        block,
+                        BlockRejection::InvalidAmount(tx.id().to_string()),
+                    ));
+                }
+                continue;
+            }
+            Transaction::Coinbase(_) => {
+                return Err(reject(
+                    block,
+                    BlockRejection::MisplacedCoinbase(tx.id().to_string()),
+                ))
+            }
+            Transaction::Encrypted(_) => {
+                return Err(reject(
+                    block,
+                    BlockRejection::EncryptedTransaction(tx.id().to_string()),
+                ))
+            }
+        };
+        if let Some(reason) = validate_transfer(tx)? {
+            return Err(reject(block, reason));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the coinbase against the emission schedule and returns the total supply issued once
+/// the block is connected. Fees only move existing coins, so the subsidy is the part of the
+/// coinbase that counts as new issuance.
+pub fn validate_coinbase(block: &Block, parent: &BlockIndex) -> Result<f64, ChainError> {
+    let claimed = match block.transactions.first() {
+        Some(Transaction::Coinbase(coinbase)) => coinbase.amount(),
+        _ => return Err(reject(block, BlockRejection::MissingCoinbase)),
+    };
+    let fees: f64 = block.transactions.iter().map(Transaction::fee).sum();
+    let subsidy = capped_subsidy(block.header.index, parent.supply);
+    if claimed > subsidy + fees {
+        return Err(reject(
+            block,
+            BlockRejection::ExcessiveCoinbase {
+                claimed,
+                allowed: subsidy + fees,
+            },
+        ));
+    }
+
+    let supply = parent.supply + (claimed - fees).max(0.0);
+    if supply > config::MAX_SUPPLY as f64 {
+        return Err(reject(
+            block,
+            BlockRejection::SupplyExceeded {
+                supply,
+                max: config::MAX_SUPPLY,
+            },
+        ));
+    }
+    Ok(supply)
+}
+
+/// Stateless checks on a transfer, shared by blocks and the mempool: an id derived from its
+/// content, a positive amount, a non-negative fee, a key that opens the sender address and
+/// the signature under that key. Returns why the transfer is invalid, if it is.
+pub fn validate_transfer(tx: &PlainTransaction) -> Result<Option<BlockRejection>, AccountError> {
+    if tx.id() != tx.content_id() {
+        return Ok(Some(BlockRejection::IdMismatch(tx.id().to_string())));
+    }
+    let valid_amount = tx.amount().is_finite() && tx.amount() > 0.0;
+    let valid_fee = tx.fee().is_finite() && tx.fee() >= 0.0;
+    if !valid_amount || !valid_fee {
+        return Ok(Some(BlockRejection::InvalidAmount(tx.id().to_string())));
+    }
+
+    match Wallet::open_address(tx.sender(), tx.sender_key(), tx.sender_blinding()) {
+        Ok(true) => {}
+        Ok(false) => return Ok(Some(BlockRejection::SenderKeyMismatch(tx.id().to_string()))),
+        Err(AccountError::InvalidAddress(_)) => {
+            return Ok(Some(BlockRejection::UnknownAccount {
+                id: tx.id().to_string(),
+                address: tx.sender().to_string(),
+            }))
+        }
+        Err(e) => return Err(e),
+    }
+    if !tx.verify_signature(tx.sender_key()).unwrap_or(false) {
+        return Ok(Some(BlockRejection::InvalidSignature(tx.id().to_string())));
+    }
+
+    Ok(None)
+}
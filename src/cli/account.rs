@@ -1,7 +1,138 @@
-use crate::store::{Storage, StorageKind};
-use crate::{account::wallet::Wallet, account::Account};
-use clap::{Arg, Command};
-use clap_derive::Subcommand; // Import from clap_derive
-use serde::{Deserialize, Serialize};
+use crate::account::{Account, AccountWithPrivateKey, Balance};
+use crate::api::AccountView;
+use crate::cli::output::{self, cell, OutputFormat, Render, Rows};
+use crate::store::{Cursor, Storage};
+use crate::util::error::{AccountError, Error, StorageError};
+use clap::Subcommand;
+use serde::Serialize;
 
-struct
+#[derive(Subcommand, Debug)]
+pub enum AccountCommand {
+    /// Creates a new account and prints its keys
+    Create,
+    /// Shows an account by address; its balance stays encrypted
+    Get { address: String },
+    /// Decrypts the balance of an account with its private key
+    Balance {
+        address: String,
+        private_key: String,
+    },
+    /// Lists accounts a page at a time
+    List {
+        /// Accounts per page
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Cursor printed with the previous page
+        #[arg(long)]
+        cursor: Option<Cursor>,
+    },
+    /// Counts the accounts
+    Total,
+}
+
+/// A page of accounts and the cursor of the next one, if any.
+#[derive(Debug, Serialize)]
+pub struct AccountPage {
+    pub accounts: Vec<AccountView>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountTotal {
+    pub total: i64,
+}
+
+pub fn run(store: &Storage, command: AccountCommand, format: OutputFormat) -> Result<(), Error> {
+    match command {
+        AccountCommand::Create => output::print(format, &Account::new(store)?),
+        AccountCommand::Get { address } => {
+            let account = match Account::get_account(store, address.clone()) {
+                Err(AccountError::Storage(StorageError::NotFound)) => {
+                    return Err(AccountError::NotFound(address).into())
+                }
+                result => result?,
+            };
+            output::print(format, &AccountView::from(&account));
+        }
+        AccountCommand::Balance {
+            address,
+            private_key,
+        } => output::print(format, &Account::get_balance(store, address, private_key)?),
+        AccountCommand::List { limit, cursor } => {
+            let page = Account::get_accounts(store, cursor, limit)?;
+            let page = AccountPage {
+                accounts: page.items.iter().map(AccountView::from).collect(),
+                next_cursor: page.next_cursor.map(|cursor| cursor.to_string()),
+            };
+            output::print(format, &page);
+        }
+        AccountCommand::Total => output::print(
+            format,
+            &AccountTotal {
+                total: Account::total_accounts(store)?,
+            },
+        ),
+    }
+    Ok(())
+}
+
+impl Render for AccountWithPrivateKey {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Address", self.address.clone()),
+            ("Public key", self.public_key.clone()),
+            ("Private key", self.private_key.clone()),
+            ("Balance", self.balance.to_string()),
+            ("Created at", self.timestamp.to_string()),
+        ])
+    }
+}
+
+impl Render for AccountView {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Address", self.address.clone()),
+            ("Created at", cell(self.created_at)),
+            ("Nonce", cell(self.nonce)),
+            ("Balance", cell(self.balance.as_ref())),
+        ])
+    }
+}
+
+impl Render for Balance {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Address", self.address.clone()),
+            ("Balance", self.balance.to_string()),
+        ])
+    }
+}
+
+impl Render for AccountPage {
+    fn rows(&self) -> Rows {
+        Rows::Columns {
+            header: vec!["address", "created at", "nonce"],
+            rows: self
+                .accounts
+                .iter()
+                .map(|account| {
+                    vec![
+                        account.address.clone(),
+                        cell(account.created_at),
+                        cell(account.nonce),
+                    ]
+                })
+                .collect(),
+            note: self
+                .next_cursor
+                .as_ref()
+                .map(|cursor| format!("Next page: --cursor {}", cursor)),
+        }
+    }
+}
+
+impl Render for AccountTotal {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![("Total", self.total.to_string())])
+    }
+}
@@ -0,0 +1,222 @@
+use crate::account::wallet::Wallet;
+use crate::api::BlockView;
+use crate::chain::{Block, Blockchain, GenesisConfig};
+use crate::cli::cli::open_chain;
+use crate::cli::output::{self, OutputFormat, Render, Rows};
+use crate::config;
+use crate::store::Storage;
+use crate::tx::Mempool;
+use crate::util::error::{ChainError, Error};
+use clap::{Args, Subcommand};
+use serde::Serialize;
+
+#[derive(Subcommand, Debug)]
+pub enum ChainCommand {
+    /// Shows the tip, height and supply of the active chain
+    Info,
+    /// Shows an active block by height, or any known block by hash
+    Block {
+        /// Height or hash of the block
+        id: String,
+    },
+    /// Re-checks the headers, bodies and coinbases of the active chain
+    Validate,
+}
+
+#[derive(Args, Debug)]
+pub struct MineArgs {
+    /// Address the block rewards are paid to
+    #[arg(long)]
+    pub address: String,
+    /// Number of blocks to mine
+    #[arg(long, default_value_t = 1)]
+    pub blocks: u64,
+}
+
+/// The active chain as `chain_info` reports it over JSON-RPC, without the network state.
+#[derive(Debug, Serialize)]
+pub struct ChainInfo {
+    pub network_id: String,
+    pub genesis_hash: String,
+    pub height: u64,
+    pub tip_hash: String,
+    pub difficulty: u64,
+    pub supply: f64,
+    pub mempool_size: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainValidation {
+    pub valid: bool,
+    pub height: u64,
+}
+
+/// A block just mined and added to the chain.
+#[derive(Debug, Serialize)]
+pub struct MinedBlock {
+    pub height: u64,
+    pub hash: String,
+    pub transaction_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MinedBlocks {
+    pub blocks: Vec<MinedBlock>,
+}
+
+pub fn run(
+    store: &Storage,
+    genesis: &GenesisConfig,
+    command: ChainCommand,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    let chain = open_chain(store, genesis)?;
+    match command {
+        ChainCommand::Info => {
+            let tip = &chain.tip().header;
+            let info = ChainInfo {
+                network_id: genesis.network_id.clone(),
+                genesis_hash: chain.blocks[0].header.hash.clone(),
+                height: tip.index,
+                tip_hash: tip.hash.clone(),
+                difficulty: tip.difficulty,
+                supply: chain.supply(),
+                mempool_size: Mempool::default().load(store)?,
+            };
+            output::print(format, &info);
+        }
+        ChainCommand::Block { id } => {
+            output::print(format, &BlockView::from(&find_block(&chain, &id)?));
+        }
+        ChainCommand::Validate => {
+            chain.validate_chain()?;
+            let validation = ChainValidation {
+                valid: true,
+                height: chain.height(),
+            };
+            output::print(format, &validation);
+        }
+    }
+    Ok(())
+}
+
+/// Mines `args.blocks` blocks on top of the tip with the pending transfers, like a node
+/// would, and keeps what is left pending for the next run.
+pub fn mine(
+    store: &Storage,
+    genesis: &GenesisConfig,
+    args: MineArgs,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    Wallet::check_address(&args.address)?;
+    let mut chain = open_chain(store, genesis)?;
+    let mut mempool = Mempool::default();
+    mempool.load(store)?;
+
+    let budget = config::MAX_BLOCK_SIZE_BYTES - config::BLOCK_OVERHEAD_BYTES;
+    let mut mined = MinedBlocks { blocks: Vec::new() };
+    for _ in 0..args.blocks {
+        let transactions = mempool.select(store, budget);
+        let mut block = chain.next_block(&args.address, transactions)?;
+        block.mine();
+        chain.add_block(block.clone())?;
+        mempool.prune(store);
+        mined.blocks.push(MinedBlock::from(&block));
+    }
+    mempool.persist(store)?;
+    output::print(format, &mined);
+    Ok(())
+}
+
+fn find_block(chain: &Blockchain, id: &str) -> Result<Block, ChainError> {
+    match id.parse::<u64>() {
+        Ok(height) => chain
+            .block_at(height)
+            .cloned()
+            .ok_or_else(|| ChainError::UnknownBlock(id.to_string())),
+        Err(_) => chain.get_block(id),
+    }
+}
+
+impl From<&Block> for MinedBlock {
+    fn from(block: &Block) -> Self {
+        MinedBlock {
+            height: block.header.index,
+            hash: block.header.hash.clone(),
+            transaction_count: block.transactions.len(),
+        }
+    }
+}
+
+impl Render for ChainInfo {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Network", self.network_id.clone()),
+            ("Genesis", self.genesis_hash.clone()),
+            ("Height", self.height.to_string()),
+            ("Tip", self.tip_hash.clone()),
+            ("Difficulty", self.difficulty.to_string()),
+            ("Supply", self.supply.to_string()),
+            ("Mempool", self.mempool_size.to_string()),
+        ])
+    }
+}
+
+impl Render for BlockView {
+    fn rows(&self) -> Rows {
+        let header = &self.header;
+        let ids: Vec<&str> = self.transactions.iter().map(|tx| tx.id.as_str()).collect();
+        Rows::Fields(vec![
+            ("Height", header.height.to_string()),
+            ("Hash", header.hash.clone()),
+            ("Previous", header.prev_hash.clone()),
+            ("Timestamp", header.timestamp.to_string()),
+            ("Difficulty", header.difficulty.to_string()),
+            ("Nonce", header.nonce.to_string()),
+            ("Merkle root", header.merkle_root.clone()),
+            ("State root", header.state_root.clone()),
+            ("Size", header.size.to_string()),
+            ("Version", header.version.to_string()),
+            ("Transactions", ids.join(",")),
+        ])
+    }
+}
+
+impl Render for ChainValidation {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Valid", self.valid.to_string()),
+            ("Height", self.height.to_string()),
+        ])
+    }
+}
+
+impl Render for MinedBlock {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Height", self.height.to_string()),
+            ("Hash", self.hash.clone()),
+            ("Transactions", self.transaction_count.to_string()),
+        ])
+    }
+}
+
+impl Render for MinedBlocks {
+    fn rows(&self) -> Rows {
+        Rows::Columns {
+            header: vec!["height", "hash", "transactions"],
+            rows: self
+                .blocks
+                .iter()
+                .map(|block| {
+                    vec![
+                        block.height.to_string(),
+                        block.hash.clone(),
+                        block.transaction_count.to_string(),
+                    ]
+                })
+                .collect(),
+            note: None,
+        }
+    }
+}
@@ -1,102 +1,444 @@
-use crate::store::{Storage, StorageKind};
-use crate::{account::wallet::Wallet, account::Account};
-use clap::{Arg, Command};
-use clap_derive::Subcommand; // Import from clap_derive
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Balance {
-    pub address: String,
-    pub balance: f64,
-}
-
-#[derive(Subcommand, Debug)] // Derive Subcommand
-pub enum CliCommand {
-    CreateAccount,
-    GetBalance {
-        address: String,
-        private_key: String,
+use crate::chain::{Blockchain, GenesisConfig};
+use crate::cli::account::{self, AccountCommand};
+use crate::cli::chain::{self, ChainCommand, MineArgs};
+use crate::cli::node::{self, NodeArgs};
+use crate::cli::output::{self, OutputFormat, Render, Rows};
+use crate::cli::tx::{self, TxCommand};
+use crate::config::{BACKUP_PATH, DB_PATH, PASSPHRASE_ENV};
+use crate::consensus::simulator::{self, Algorithm, Scenario};
+use crate::store::backup::BackupInfo;
+use crate::store::schema::MigrationReport;
+use crate::store::{backup, schema, Storage};
+use crate::util::error::{
+    AccountError, ChainError, Error, MempoolError, StorageError, TransactionError,
+};
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Exit status of a command that failed for any other reason than the ones below. Usage
+/// errors exit with 2, as clap reports them.
+pub const EXIT_FAILURE: u8 = 1;
+/// The account, transaction, block or backup asked for doesn't exist.
+pub const EXIT_NOT_FOUND: u8 = 3;
+/// The input was refused: an invalid address, or a transfer the mempool doesn't accept.
+pub const EXIT_REJECTED: u8 = 4;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "curve",
+    version,
+    about = "Accounts, transactions, mining and nodes for the Valtoria chain",
+    arg_required_else_help = true
+)]
+pub struct Cli {
+    /// Directory holding the database
+    #[arg(long, global = true, default_value = DB_PATH, value_name = "DIR")]
+    pub data_dir: PathBuf,
+    /// Genesis configuration of the network (the built-in one if omitted)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub genesis: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = format!(
+            "Also encrypt record keys when {} sets up a new encrypted database",
+            PASSPHRASE_ENV
+        )
+    )]
+    pub encrypt_keys: bool,
+    /// How to print results
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Creates and inspects accounts
+    #[command(subcommand)]
+    Account(AccountCommand),
+    /// Sends and looks up transactions
+    #[command(subcommand)]
+    Tx(TxCommand),
+    /// Inspects and validates the chain
+    #[command(subcommand)]
+    Chain(ChainCommand),
+    /// Mines blocks on this database with the pending transactions
+    Mine(MineArgs),
+    /// Runs a peer-to-peer node on this database
+    Node(NodeArgs),
+    /// Upgrades the database to the current schema version
+    Migrate {
+        /// Only report the migrations that would run
+        #[arg(long)]
+        dry_run: bool,
     },
-    GetAccount {
-        private_key: String,
+    /// Creates a consistent point-in-time copy of the database
+    Checkpoint {
+        /// Directory to write the checkpoint to; must not exist yet
+        path: PathBuf,
+    },
+    /// Manages incremental database backups
+    Backup {
+        /// Backup directory
+        #[arg(long, global = true, default_value = BACKUP_PATH, value_name = "DIR")]
+        dir: PathBuf,
+        #[command(subcommand)]
+        command: BackupCommand,
+    },
+    /// Compares the LWMA difficulty adjustment with a naive retarget under hashrate swings
+    SimulateDifficulty {
+        /// Number of blocks to simulate
+        #[arg(long, default_value_t = 5_000)]
+        blocks: usize,
+        /// Seed for the simulated mining luck
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+        /// Also draw both difficulty curves into this PNG file
+        #[arg(long, value_name = "PATH")]
+        plot: Option<PathBuf>,
     },
 }
 
-pub fn cli() {
-    let matches = Command::new("Crypto CLI")
-        .version("1.0")
-        .author("Your Name <youremail@example.com>")
-        .about("CLI for managing cryptocurrency accounts")
-        .subcommand_required(true)
-        .arg_required_else_help(true)
-        .subcommand(Command::new("create-account").about("Creates a new account"))
-        .subcommand(
-            Command::new("get-balance")
-                .about("Gets the balance of an account")
-                .arg(
-                    Arg::new("address")
-                        .help("Address of the account to check")
-                        .required(true) // Marks the argument as required
-                        .value_name("ADDRESS"), // Sets the name for the argument
-                )
-                .arg(
-                    Arg::new("private_key")
-                        .help("Private key of the account to check balance")
-                        .required(true)
-                        .value_name("PRIVATE_KEY"),
-                ),
-        )
-        .subcommand(
-            Command::new("get-account")
-                .about("Gets account details by private key")
-                .arg(
-                    Arg::new("private_key")
-                        .help("Private key of the account")
-                        .required(true)
-                        .value_name("PRIVATE_KEY"),
-                ),
+#[derive(Subcommand, Debug)]
+pub enum BackupCommand {
+    /// Creates a new backup
+    Create,
+    /// Lists available backups
+    List,
+    /// Verifies the files of a backup
+    Verify { id: u32 },
+    /// Restores a backup into the data directory (latest if no id given)
+    Restore { id: Option<u32> },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupList {
+    pub backups: Vec<BackupInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupVerified {
+    pub id: u32,
+    pub valid: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupRestored {
+    /// The backup restored, or none for the latest.
+    pub id: Option<u32>,
+    pub data_dir: PathBuf,
+}
+
+/// Solve time statistics of one difficulty algorithm, in seconds.
+#[derive(Debug, Serialize)]
+pub struct SimulationSummary {
+    pub algorithm: String,
+    pub mean_solvetime: f64,
+    pub solvetime_stddev: f64,
+    pub longest_solvetime: f64,
+    pub delayed_blocks: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Simulation {
+    pub blocks: usize,
+    pub seed: u64,
+    pub reports: Vec<SimulationSummary>,
+    pub plot: Option<PathBuf>,
+}
+
+pub fn cli() -> ExitCode {
+    let cli = Cli::parse();
+    let format = cli.output;
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            output::print_error(format, &e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+pub fn run(cli: Cli) -> Result<(), Error> {
+    let format = cli.output;
+    match cli.command {
+        Command::SimulateDifficulty { blocks, seed, plot } => {
+            return simulate_difficulty(blocks, seed, plot, format);
+        }
+        // Restoring replaces the database files, so it has to run before the store is opened.
+        Command::Backup {
+            dir,
+            command: BackupCommand::Restore { id },
+        } => {
+            backup::restore_backup(&dir, id, &cli.data_dir)?;
+            let restored = BackupRestored {
+                id,
+                data_dir: cli.data_dir,
+            };
+            output::print(format, &restored);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let store = open_store(&cli.data_dir, cli.encrypt_keys)?;
+    if let Command::Migrate { dry_run } = cli.command {
+        output::print(format, &schema::migrate(&store, dry_run)?);
+        return Ok(());
+    }
+    schema::migrate(&store, false)?;
+
+    let genesis = load_genesis(cli.genesis.as_deref())?;
+    match cli.command {
+        Command::Account(command) => account::run(&store, command, format),
+        Command::Tx(command) => tx::run(&store, &genesis, command, format),
+        Command::Chain(command) => chain::run(&store, &genesis, command, format),
+        Command::Mine(args) => chain::mine(&store, &genesis, args, format),
+        Command::Node(args) => node::run(store, &genesis, &cli.data_dir, args, format),
+        Command::Checkpoint { path } => {
+            store.create_checkpoint(&path)?;
+            output::print(format, &Checkpoint { path });
+            Ok(())
+        }
+        Command::Backup { dir, command } => run_backup(&store, &dir, command, format),
+        Command::Migrate { .. } | Command::SimulateDifficulty { .. } => unreachable!(),
+    }
+}
+
+/// Exit status `error` is reported with; see `EXIT_FAILURE` and the codes next to it.
+pub fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::Storage(StorageError::NotFound)
+        | Error::Account(
+            AccountError::NotFound(_) | AccountError::Storage(StorageError::NotFound),
         )
-        .get_matches();
-
-    match matches.subcommand() {
-        Some(("create-account", _)) => match Account::new() {
-            Ok(account_with_private_key) => {
-                println!(
-                    "Account created successfully: {:?}",
-                    account_with_private_key
-                );
-            }
+        | Error::Transaction(TransactionError::NotFound(_))
+        | Error::Chain(ChainError::UnknownBlock(_)) => EXIT_NOT_FOUND,
+        Error::Account(AccountError::InvalidAddress(_) | AccountError::NotOwner(_))
+        | Error::Mempool(
+            MempoolError::Duplicate(_)
+            | MempoolError::NonceInUse { .. }
+            | MempoolError::Full(_)
+            | MempoolError::Invalid(_)
+            | MempoolError::Account(AccountError::InvalidAddress(_)),
+        ) => EXIT_REJECTED,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Opens the database in `dir`, unlocking it with the passphrase from `PASSPHRASE_ENV` if set.
+fn open_store(dir: &Path, encrypt_keys: bool) -> Result<Storage, StorageError> {
+    match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => {
+            Storage::open_encrypted(dir, Storage::default_options(), &passphrase, encrypt_keys)
+        }
+        Err(_) => Storage::open(dir, Storage::default_options()),
+    }
+}
+
+fn load_genesis(path: Option<&Path>) -> Result<GenesisConfig, ChainError> {
+    match path {
+        Some(path) => GenesisConfig::load(path),
+        None => Ok(GenesisConfig::default()),
+    }
+}
+
+/// Opens the chain of `store`, writing the genesis block on first use.
+pub(crate) fn open_chain(store: &Storage, genesis: &GenesisConfig) -> Result<Blockchain, Error> {
+    Ok(Blockchain::open(store, genesis)?)
+}
+
+fn simulate_difficulty(
+    blocks: usize,
+    seed: u64,
+    plot: Option<PathBuf>,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    let scenario = Scenario::hashrate_swings(blocks);
+
+    let reports: Vec<_> = [Algorithm::Lwma, Algorithm::Naive]
+        .into_iter()
+        .map(|algorithm| simulator::simulate(algorithm, &scenario, seed))
+        .collect();
+    let plot = plot.filter(
+        |path| match simulator::plot(&reports, &path.to_string_lossy()) {
+            Ok(()) => true,
             Err(e) => {
-                eprintln!("Error creating account: {}", e);
+                output::print_error(format, &*e);
+                false
             }
         },
-        Some(("get-balance", sub_m)) => {
-            let address = sub_m.get_one::<String>("address").unwrap();
-            let private_key = sub_m.get_one::<String>("private_key").unwrap();
-
-            match Account::get_balance(address.to_string(), private_key.to_string()) {
-                Ok(balance) => {
-                    println!("Balance for {}: {}", balance.address, balance.balance);
-                }
-                Err(e) => {
-                    eprintln!("Error retrieving balance: {}", e);
-                }
-            }
+    );
+    let simulation = Simulation {
+        blocks,
+        seed,
+        reports: reports
+            .iter()
+            .map(|report| SimulationSummary {
+                algorithm: report.algorithm.to_string(),
+                mean_solvetime: report.mean_solvetime(),
+                solvetime_stddev: report.solvetime_stddev(),
+                longest_solvetime: report.longest_solvetime(),
+                delayed_blocks: report.delayed_blocks(),
+            })
+            .collect(),
+        plot,
+    };
+    output::print(format, &simulation);
+    Ok(())
+}
+
+fn run_backup(
+    store: &Storage,
+    dir: &Path,
+    command: BackupCommand,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    match command {
+        BackupCommand::Create => output::print(format, &store.create_backup(dir)?),
+        BackupCommand::List => {
+            let backups = BackupList {
+                backups: backup::list_backups(dir)?,
+            };
+            output::print(format, &backups);
         }
-        Some(("get-account", sub_m)) => {
-            let private_key = sub_m.get_one::<String>("private_key").unwrap();
-
-            match Account::get_account(private_key.to_string()) {
-                Ok(account) => {
-                    println!("Account details: {:?}", account);
-                }
-                Err(e) => {
-                    eprintln!("Error retrieving account: {}", e);
-                }
-            }
+        BackupCommand::Verify { id } => {
+            backup::verify_backup(dir, id)?;
+            output::print(format, &BackupVerified { id, valid: true });
+        }
+        BackupCommand::Restore { .. } => unreachable!("restored before the store is opened"),
+    }
+    Ok(())
+}
+
+impl Render for MigrationReport {
+    fn rows(&self) -> Rows {
+        let note = if self.applied.is_empty() {
+            format!("Schema is up to date (v{})", self.to_version)
+        } else if self.dry_run {
+            format!(
+                "Would migrate from v{} to v{}",
+                self.from_version, self.to_version
+            )
+        } else {
+            format!(
+                "Migrated from v{} to v{}",
+                self.from_version, self.to_version
+            )
+        };
+        Rows::Columns {
+            header: vec!["version", "description", "records"],
+            rows: self
+                .applied
+                .iter()
+                .map(|migration| {
+                    vec![
+                        migration.version.to_string(),
+                        migration.description.to_string(),
+                        migration.records.to_string(),
+                    ]
+                })
+                .collect(),
+            note: Some(note),
         }
-        _ => {
-            eprintln!("Invalid command.");
+    }
+}
+
+impl Render for Checkpoint {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![("Checkpoint", self.path.display().to_string())])
+    }
+}
+
+impl Render for BackupInfo {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Backup", self.id.to_string()),
+            ("Timestamp", self.timestamp.to_string()),
+            ("Files", self.num_files.to_string()),
+            ("Size", self.size.to_string()),
+        ])
+    }
+}
+
+impl Render for BackupList {
+    fn rows(&self) -> Rows {
+        Rows::Columns {
+            header: vec!["id", "timestamp", "files", "size"],
+            rows: self
+                .backups
+                .iter()
+                .map(|info| {
+                    vec![
+                        info.id.to_string(),
+                        info.timestamp.to_string(),
+                        info.num_files.to_string(),
+                        info.size.to_string(),
+                    ]
+                })
+                .collect(),
+            note: None,
+        }
+    }
+}
+
+impl Render for BackupVerified {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Backup", self.id.to_string()),
+            ("Valid", self.valid.to_string()),
+        ])
+    }
+}
+
+impl Render for BackupRestored {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            (
+                "Backup",
+                self.id.map_or("latest".to_string(), |id| id.to_string()),
+            ),
+            ("Restored into", self.data_dir.display().to_string()),
+        ])
+    }
+}
+
+impl Render for Simulation {
+    fn rows(&self) -> Rows {
+        Rows::Columns {
+            header: vec![
+                "algorithm",
+                "mean (s)",
+                "stddev (s)",
+                "longest (s)",
+                "delayed blocks",
+            ],
+            rows: self
+                .reports
+                .iter()
+                .map(|report| {
+                    vec![
+                        report.algorithm.clone(),
+                        format!("{:.1}", report.mean_solvetime),
+                        format!("{:.1}", report.solvetime_stddev),
+                        format!("{:.1}", report.longest_solvetime),
+                        report.delayed_blocks.to_string(),
+                    ]
+                })
+                .collect(),
+            note: self
+                .plot
+                .as_ref()
+                .map(|path| format!("Plot written to {}", path.display())),
         }
     }
 }
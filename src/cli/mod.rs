@@ -1 +1,6 @@
+pub mod account;
+pub mod chain;
 pub mod cli;
+pub mod node;
+pub mod output;
+pub mod tx;
@@ -0,0 +1,223 @@
+use crate::api::rpc::{self, RpcConfig, RpcServer};
+use crate::api::{ExplorerConfig, ExplorerServer, SubscriptionConfig, SubscriptionServer};
+use crate::chain::GenesisConfig;
+use crate::cli::chain::MinedBlock;
+use crate::cli::output::{self, OutputFormat, Render, Rows};
+use crate::config::{EXPLORER_PORT, P2P_PORT, RPC_COOKIE_FILE, RPC_PORT, RPC_TOKEN_ENV, WS_PORT};
+use crate::net::{Node, NodeConfig};
+use crate::store::Storage;
+use crate::util::error::{ApiError, Error};
+use clap::Args;
+use serde::Serialize;
+use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+#[derive(Args, Debug)]
+pub struct NodeArgs {
+    #[arg(
+        long,
+        value_name = "ADDR",
+        help = format!("Address to accept peers on [default: 127.0.0.1:{}]", P2P_PORT)
+    )]
+    pub listen: Option<SocketAddr>,
+    /// Peer to connect to; may be repeated
+    #[arg(long = "peer", value_name = "ADDR")]
+    pub peers: Vec<SocketAddr>,
+    /// Seed node to learn peer addresses from; may be repeated
+    #[arg(long = "seed", value_name = "HOST:PORT")]
+    pub seeds: Vec<String>,
+    /// Only keep the --peer connections and those made to this node
+    #[arg(long)]
+    pub no_discover: bool,
+    /// Serve the JSON-RPC API
+    #[arg(long)]
+    pub rpc: bool,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        help = format!(
+            "Loopback address to serve the JSON-RPC API on [default: 127.0.0.1:{}]",
+            RPC_PORT
+        )
+    )]
+    pub rpc_listen: Option<SocketAddr>,
+    #[arg(
+        long = "rpc-token",
+        value_name = "TOKEN",
+        help = format!(
+            "Bearer token the JSON-RPC and subscription APIs accept; may be repeated. Read from {} \
+             if omitted, else generated into {} in the data directory",
+            RPC_TOKEN_ENV, RPC_COOKIE_FILE
+        )
+    )]
+    pub rpc_tokens: Vec<String>,
+    /// Serve event subscriptions over WebSocket
+    #[arg(long)]
+    pub ws: bool,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        help = format!(
+            "Loopback address to serve subscriptions on [default: 127.0.0.1:{}]",
+            WS_PORT
+        )
+    )]
+    pub ws_listen: Option<SocketAddr>,
+    /// Serve the read-only explorer API
+    #[arg(long)]
+    pub explorer: bool,
+    #[arg(
+        long,
+        value_name = "ADDR",
+        help = format!("Address to serve the explorer API on [default: 127.0.0.1:{}]", EXPLORER_PORT)
+    )]
+    pub explorer_listen: Option<SocketAddr>,
+    /// Mine blocks continuously, paying the rewards to this address
+    #[arg(long, value_name = "ADDRESS")]
+    pub mine: Option<String>,
+}
+
+/// An API the node started serving.
+#[derive(Debug, Serialize)]
+pub struct Listening {
+    /// `rpc`, `subscriptions` or `explorer`.
+    pub service: &'static str,
+    pub address: SocketAddr,
+}
+
+/// Where the generated API token was written.
+#[derive(Debug, Serialize)]
+pub struct TokenCookie {
+    pub path: PathBuf,
+}
+
+pub fn run(
+    store: Storage,
+    genesis: &GenesisConfig,
+    data_dir: &Path,
+    args: NodeArgs,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut config = NodeConfig::default();
+    if let Some(listen) = args.listen {
+        config.listen = listen;
+    }
+    config.peers = args.peers.clone();
+    config.seeds = args.seeds.clone();
+    config.discover = !args.no_discover;
+    let node = Node::start(store, genesis, config)?;
+
+    let tokens = if args.rpc || args.ws {
+        api_tokens(&args, data_dir, format)?
+    } else {
+        Vec::new()
+    };
+    // Kept alive for as long as the node runs.
+    let _rpc = if args.rpc {
+        Some(start_rpc(&node, &args, tokens.clone(), format)?)
+    } else {
+        None
+    };
+    let _ws = if args.ws {
+        let mut config = SubscriptionConfig {
+            tokens,
+            ..SubscriptionConfig::default()
+        };
+        if let Some(listen) = args.ws_listen {
+            config.listen = listen;
+        }
+        let server = SubscriptionServer::start(node.clone(), config)?;
+        listening(format, "subscriptions", server.local_addr());
+        Some(server)
+    } else {
+        None
+    };
+    let _explorer = if args.explorer {
+        let mut config = ExplorerConfig::default();
+        if let Some(listen) = args.explorer_listen {
+            config.listen = listen;
+        }
+        let server = ExplorerServer::start(node.clone(), config)?;
+        listening(format, "explorer", server.local_addr());
+        Some(server)
+    } else {
+        None
+    };
+
+    match args.mine {
+        Some(miner) => loop {
+            match node.mine_block(&miner) {
+                Ok(block) => output::print(format, &MinedBlock::from(&block)),
+                Err(e) => output::print_error(format, &e),
+            }
+        },
+        None => loop {
+            thread::park();
+        },
+    }
+}
+
+/// Tokens from `--rpc-token`, else from the environment, else a cookie written into the
+/// data directory for local tools.
+fn api_tokens(
+    args: &NodeArgs,
+    data_dir: &Path,
+    format: OutputFormat,
+) -> Result<Vec<String>, ApiError> {
+    if !args.rpc_tokens.is_empty() {
+        return Ok(args.rpc_tokens.clone());
+    }
+    let tokens = match env::var(RPC_TOKEN_ENV) {
+        Ok(token) => vec![token],
+        Err(_) => {
+            let token = rpc::write_cookie(data_dir)?;
+            let cookie = TokenCookie {
+                path: data_dir.join(RPC_COOKIE_FILE),
+            };
+            output::print(format, &cookie);
+            vec![token]
+        }
+    };
+    Ok(tokens)
+}
+
+fn start_rpc(
+    node: &Node,
+    args: &NodeArgs,
+    tokens: Vec<String>,
+    format: OutputFormat,
+) -> Result<RpcServer, ApiError> {
+    let mut config = RpcConfig {
+        tokens,
+        ..RpcConfig::default()
+    };
+    if let Some(listen) = args.rpc_listen {
+        config.listen = listen;
+    }
+    let server = RpcServer::start(node.clone(), config)?;
+    listening(format, "rpc", server.local_addr());
+    Ok(server)
+}
+
+fn listening(format: OutputFormat, service: &'static str, address: SocketAddr) {
+    output::print(format, &Listening { service, address });
+}
+
+impl Render for Listening {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Service", self.service.to_string()),
+            ("Listening on", self.address.to_string()),
+        ])
+    }
+}
+
+impl Render for TokenCookie {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![("RPC token file", self.path.display().to_string())])
+    }
+}
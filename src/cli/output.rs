@@ -0,0 +1,107 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+
+/// How commands print their results. JSON output is one compact document per line, with
+/// the same field names as the JSON-RPC and explorer APIs, so scripts can rely on it.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    /// Aligned fields, or columns under a header.
+    #[default]
+    Table,
+    /// Bare values, tab-separated and one record per line.
+    Plain,
+}
+
+/// What a command prints outside of JSON.
+pub enum Rows {
+    /// A single record, as labelled fields.
+    Fields(Vec<(&'static str, String)>),
+    /// Records under a header, with an optional note after them, such as a paging cursor,
+    /// that plain output leaves out.
+    Columns {
+        header: Vec<&'static str>,
+        rows: Vec<Vec<String>>,
+        note: Option<String>,
+    },
+}
+
+/// A command result in each of the output formats.
+pub trait Render: Serialize {
+    fn rows(&self) -> Rows;
+}
+
+pub fn print<T: Render>(format: OutputFormat, value: &T) {
+    match format {
+        OutputFormat::Json => match serde_json::to_string(value) {
+            Ok(json) => println!("{}", json),
+            Err(e) => print_error(format, &e),
+        },
+        OutputFormat::Table => print!("{}", table(&value.rows())),
+        OutputFormat::Plain => print!("{}", plain(&value.rows())),
+    }
+}
+
+/// Prints a failure to stderr, as `{"error": ...}` in JSON.
+pub fn print_error(format: OutputFormat, error: &dyn std::error::Error) {
+    match format {
+        OutputFormat::Json => eprintln!("{}", json!({ "error": error.to_string() })),
+        OutputFormat::Table | OutputFormat::Plain => eprintln!("Error: {}", error),
+    }
+}
+
+fn table(rows: &Rows) -> String {
+    let mut out = String::new();
+    match rows {
+        Rows::Fields(fields) => {
+            let width = fields
+                .iter()
+                .map(|(label, _)| label.len())
+                .max()
+                .unwrap_or(0)
+                + 1;
+            for (label, value) in fields {
+                out.push_str(&format!("{:width$} {}\n", format!("{}:", label), value));
+            }
+        }
+        Rows::Columns { header, rows, note } => {
+            let mut widths: Vec<usize> = header.iter().map(|name| name.len()).collect();
+            for row in rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.chars().count());
+                }
+            }
+            let header: Vec<String> = header.iter().map(|name| name.to_uppercase()).collect();
+            for row in std::iter::once(&header).chain(rows) {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:width$}", cell))
+                    .collect();
+                out.push_str(cells.join("  ").trim_end());
+                out.push('\n');
+            }
+            if let Some(note) = note {
+                out.push_str(note);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+fn plain(rows: &Rows) -> String {
+    match rows {
+        Rows::Fields(fields) => {
+            let values: Vec<&str> = fields.iter().map(|(_, value)| value.as_str()).collect();
+            format!("{}\n", values.join("\t"))
+        }
+        Rows::Columns { rows, .. } => rows.iter().map(|row| row.join("\t") + "\n").collect(),
+    }
+}
+
+/// Renders an optional value as an empty cell when it is missing.
+pub fn cell<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
@@ -0,0 +1,274 @@
+use crate::account::wallet::Wallet;
+use crate::api::TransactionView;
+use crate::chain::{Block, Blockchain, GenesisConfig};
+use crate::cli::cli::open_chain;
+use crate::cli::output::{self, cell, OutputFormat, Render, Rows};
+use crate::store::Storage;
+use crate::tx::{Mempool, Transaction, TransactionStatus};
+use crate::util::error::{Error, StorageError, TransactionError};
+use clap::Subcommand;
+use serde::Serialize;
+
+#[derive(Subcommand, Debug)]
+pub enum TxCommand {
+    /// Signs a transfer and queues it for the next block
+    Send {
+        /// Address paying the amount and the fee
+        #[arg(long)]
+        from: String,
+        /// Address receiving the amount
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        amount: f64,
+        /// Private key of the sender
+        #[arg(long)]
+        private_key: String,
+        #[arg(long, default_value = "")]
+        narration: String,
+    },
+    /// Shows a transaction by id
+    Get { id: String },
+    /// Lists the transactions paying or paid by an address, pending ones first
+    History {
+        address: String,
+        /// Most transactions to list
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Shows whether a transaction is pending or in a block
+    Status { id: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct SentTransaction {
+    pub id: String,
+    pub nonce: u64,
+    pub status: String,
+}
+
+/// A transaction with where it stands: pending, or in the active chain at `block_height`.
+/// Transactions only known from their stored record show their id, fee and status.
+#[derive(Debug, Serialize)]
+pub struct TransactionReport {
+    #[serde(flatten)]
+    pub transaction: TransactionView,
+    pub status: String,
+    pub block_height: Option<u64>,
+    pub block_hash: Option<String>,
+    /// Blocks on top of and including the one holding the transaction.
+    pub confirmations: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub id: String,
+    pub status: String,
+    pub block_height: Option<u64>,
+    pub confirmations: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransactionHistory {
+    pub address: String,
+    /// Pending transactions first, then the confirmed ones, newest first.
+    pub transactions: Vec<TransactionReport>,
+}
+
+pub fn run(
+    store: &Storage,
+    genesis: &GenesisConfig,
+    command: TxCommand,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    let chain = open_chain(store, genesis)?;
+    let mut mempool = Mempool::default();
+    mempool.load(store)?;
+
+    match command {
+        TxCommand::Send {
+            from,
+            to,
+            amount,
+            private_key,
+            narration,
+        } => {
+            let nonce = mempool.next_nonce(store, &from)?;
+            let mut tx = Transaction::init(from, to, amount, narration);
+            tx.sign(&private_key, nonce)?;
+            let id = tx.id().to_string();
+            mempool.add(store, tx)?;
+            mempool.persist(store)?;
+            output::print(
+                format,
+                &SentTransaction {
+                    id,
+                    nonce,
+                    status: TransactionStatus::Pending.as_str().to_string(),
+                },
+            );
+        }
+        TxCommand::Get { id } => output::print(format, &locate(store, &chain, &mempool, &id)?),
+        TxCommand::Status { id } => {
+            let report = locate(store, &chain, &mempool, &id)?;
+            output::print(
+                format,
+                &StatusReport {
+                    id: report.transaction.id,
+                    status: report.status,
+                    block_height: report.block_height,
+                    confirmations: report.confirmations,
+                },
+            );
+        }
+        TxCommand::History { address, limit } => {
+            Wallet::check_address(&address)?;
+            let mut pending: Vec<_> = mempool
+                .ids()
+                .into_iter()
+                .filter_map(|id| match mempool.get(&id) {
+                    Some(Transaction::Plain(tx))
+                        if tx.sender() == address || tx.receiver() == address =>
+                    {
+                        Some(tx)
+                    }
+                    _ => None,
+                })
+                .collect();
+            pending.sort_by_key(|tx| std::cmp::Reverse((tx.timestamp(), tx.nonce())));
+            let pending = pending
+                .into_iter()
+                .map(|tx| report(&chain, &Transaction::Plain(tx), None));
+            let confirmed = chain
+                .transactions_of(&address)
+                .into_iter()
+                .map(|(block, tx)| report(&chain, tx, Some(block)));
+            let transactions = pending.chain(confirmed).take(limit).collect();
+            output::print(
+                format,
+                &TransactionHistory {
+                    address,
+                    transactions,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+fn report(chain: &Blockchain, tx: &Transaction, block: Option<&Block>) -> TransactionReport {
+    let status = match block {
+        Some(_) => TransactionStatus::Completed,
+        None => TransactionStatus::Pending,
+    };
+    TransactionReport {
+        transaction: TransactionView::from(tx),
+        status: status.as_str().to_string(),
+        block_height: block.map(|block| block.header.index),
+        block_hash: block.map(|block| block.header.hash.clone()),
+        confirmations: block.map_or(0, |block| chain.height() - block.header.index + 1),
+    }
+}
+
+/// Looks a transaction up in the active chain, then the mempool, then the stored records.
+fn locate(
+    store: &Storage,
+    chain: &Blockchain,
+    mempool: &Mempool,
+    id: &str,
+) -> Result<TransactionReport, Error> {
+    if let Some((block, tx)) = chain.find_transaction(id) {
+        return Ok(report(chain, tx, Some(block)));
+    }
+    if let Some(tx) = mempool.get(id) {
+        return Ok(report(chain, &tx, None));
+    }
+    match Transaction::get_record(store, id) {
+        Ok(record) => Ok(TransactionReport {
+            status: record.status().to_string(),
+            transaction: TransactionView::from(&Transaction::Encrypted(record)),
+            block_height: None,
+            block_hash: None,
+            confirmations: 0,
+        }),
+        Err(TransactionError::Storage(StorageError::NotFound)) => {
+            Err(TransactionError::NotFound(id.to_string()).into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl Render for SentTransaction {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Id", self.id.clone()),
+            ("Nonce", self.nonce.to_string()),
+            ("Status", self.status.clone()),
+        ])
+    }
+}
+
+impl Render for TransactionReport {
+    fn rows(&self) -> Rows {
+        let tx = &self.transaction;
+        Rows::Fields(vec![
+            ("Id", tx.id.clone()),
+            ("Kind", tx.kind.clone()),
+            ("From", cell(tx.sender.as_ref())),
+            ("To", cell(tx.receiver.as_ref())),
+            ("Amount", cell(tx.amount)),
+            ("Fee", tx.fee.to_string()),
+            ("Nonce", cell(tx.nonce)),
+            ("Timestamp", cell(tx.timestamp)),
+            ("Status", self.status.clone()),
+            ("Block height", cell(self.block_height)),
+            ("Block hash", cell(self.block_hash.as_ref())),
+            ("Confirmations", self.confirmations.to_string()),
+        ])
+    }
+}
+
+impl Render for StatusReport {
+    fn rows(&self) -> Rows {
+        Rows::Fields(vec![
+            ("Id", self.id.clone()),
+            ("Status", self.status.clone()),
+            ("Block height", cell(self.block_height)),
+            ("Confirmations", self.confirmations.to_string()),
+        ])
+    }
+}
+
+impl Render for TransactionHistory {
+    fn rows(&self) -> Rows {
+        Rows::Columns {
+            header: vec!["id", "kind", "height", "change", "status"],
+            rows: self
+                .transactions
+                .iter()
+                .map(|report| {
+                    vec![
+                        report.transaction.id.clone(),
+                        report.transaction.kind.clone(),
+                        cell(report.block_height),
+                        change(&report.transaction, &self.address).to_string(),
+                        report.status.clone(),
+                    ]
+                })
+                .collect(),
+            note: None,
+        }
+    }
+}
+
+/// How much `tx` moved the balance of `address`: negative, fee included, when it pays.
+fn change(tx: &TransactionView, address: &str) -> f64 {
+    let amount = tx.amount.unwrap_or(0.0);
+    let paid = tx.sender.as_deref() == Some(address);
+    let received = tx.receiver.as_deref() == Some(address);
+    match (paid, received) {
+        (true, true) => -tx.fee,
+        (true, false) => -(amount + tx.fee),
+        (false, _) => amount,
+    }
+}
@@ -1 +1,2 @@
 pub mod pow;
+pub mod simulator;
@@ -1,101 +1,85 @@
-use crate::blockchain::block::Block;
-use crate::blockchain::chain::Blockchain;
-use crate::store;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-const MAX_SUPPLY: u64 = 1_000_000_000; // New max supply for 1 billion tokens
-const BLOCK_TIME_SECONDS: u64 = 120; // 2 minutes block time
-const DIFFICULTY_WINDOW: usize = 720; // Window size for dynamic difficulty adjustment
-const MIN_DIFFICULTY: usize = 1_000; // Minimum difficulty for low network hashrate
-
-/// Struct representing Proof of Work
-pub struct ProofOfWork {
-    difficulty: usize,
+use crate::config;
+use ethnum::U256;
+
+/// Longest solve time a single block contributes, in block times. Caps the pull of a
+/// timestamp far in the future while still letting difficulty fall after a hashrate drop.
+const MAX_SOLVETIME_FACTOR: i64 = 6;
+
+/// A block as the difficulty adjustment sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSample {
+    pub timestamp: i64,
+    pub difficulty: u64,
 }
 
-impl ProofOfWork {
-    pub fn new(initial_difficulty: usize) -> Self {
-        ProofOfWork {
-            difficulty: initial_difficulty,
-        }
-    }
-
-    /// Dynamically adjust the difficulty based on the block times in the recent history
-    pub fn adjust_difficulty(&mut self, blockchain: &Blockchain) {
-        let last_blocks = blockchain.get_last_blocks(DIFFICULTY_WINDOW);
-
-        if last_blocks.len() < DIFFICULTY_WINDOW {
-            self.difficulty = MIN_DIFFICULTY;
-            return;
-        }
-
-        let total_time: u64 = last_blocks
-            .windows(2)
-            .map(|window| {
-                let block_time_diff = window[1].timestamp() - window[0].timestamp();
-                block_time_diff
-            })
-            .sum();
-
-        let avg_block_time = total_time / (last_blocks.len() as u64 - 1);
-        let target_time = BLOCK_TIME_SECONDS;
-
-        // Adjust difficulty based on block time:
-        if avg_block_time > target_time {
-            self.difficulty -=
-                (self.difficulty as u64 * (avg_block_time - target_time) / target_time) as usize;
-        } else {
-            self.difficulty +=
-                (self.difficulty as u64 * (target_time - avg_block_time) / target_time) as usize;
-        }
-
-        // Ensure difficulty doesn't drop below a minimum level
-        if self.difficulty < MIN_DIFFICULTY {
-            self.difficulty = MIN_DIFFICULTY;
-        }
-    }
+/// Largest hash, read as a big-endian 256-bit integer, that satisfies `difficulty`.
+/// Difficulty is the expected number of hashes per block, so the target is
+/// `(2^256 - 1) / difficulty`.
+pub fn target(difficulty: u64) -> U256 {
+    U256::MAX / U256::from(difficulty.max(1))
+}
 
-    /// Validates a block by checking its hash with the required difficulty
-    pub fn validate(
-        &self,
-        block: &Block,
-        blockchain: &Blockchain,
-        db: &Database,
-    ) -> Result<bool, String> {
-        let hash = block.hash();
-        let target = self.target_difficulty();
+/// Inverse of `target`, saturating at `u64::MAX`.
+pub fn difficulty_of(target: U256) -> u64 {
+    let difficulty = U256::MAX / target.max(U256::ONE);
+    u64::try_from(difficulty).unwrap_or(u64::MAX)
+}
 
-        if &hash[..self.difficulty] == target {
-            Ok(true)
-        } else {
-            Err("Invalid proof of work".to_string())
-        }
+/// Whether the hex `hash` is at or below the target for `difficulty`.
+pub fn meets_target(hash: &str, difficulty: u64) -> bool {
+    let mut bytes = [0u8; 32];
+    match hex::decode_to_slice(hash, &mut bytes) {
+        Ok(()) => U256::from_be_bytes(bytes) <= target(difficulty),
+        Err(_) => false,
     }
+}
 
-    /// Mines a new block
-    pub fn mine_new_block(&self, blockchain: &Blockchain, db: &Database) -> Result<Block, String> {
-        if blockchain.total_supply() >= MAX_SUPPLY {
-            return Err("Maximum supply reached".to_string());
-        }
-
-        let mut block = Block::new();
-        let target = self.target_difficulty();
-        let mut nonce = 0;
-
-        while !block.hash().starts_with(target) {
-            nonce += 1;
-            block.set_nonce(nonce);
-        }
-
-        block.set_timestamp(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
-
-        blockchain.add_block(block.clone(), db)?;
-        self.adjust_difficulty(blockchain); // Adjust difficulty after the block is mined
+/// Difficulty of the block after `samples`, which hold the most recent blocks oldest first.
+pub fn next_difficulty(samples: &[BlockSample]) -> u64 {
+    lwma(
+        samples,
+        config::DIFFICULTY_WINDOW,
+        config::BLOCK_TIME_SECONDS as i64,
+    )
+}
 
-        Ok(block)
+/// Linearly weighted moving average over the last `window` solve times: recent blocks weigh
+/// more, so the target follows hashrate within a few blocks without oscillating.
+///
+/// Timestamps are only trusted as far as they move forward: each one is raised to at least
+/// one second past the previous, and no solve time counts for more than
+/// `MAX_SOLVETIME_FACTOR` block times, so a miner can't drag difficulty far by lying about
+/// time. The result never moves more than `MAX_DIFFICULTY_ADJUSTMENT` times away from the
+/// last block's difficulty and never drops below `MIN_DIFFICULTY`.
+pub fn lwma(samples: &[BlockSample], window: usize, block_time: i64) -> u64 {
+    let samples = &samples[samples.len().saturating_sub(window + 1)..];
+    let (first, solved) = match samples {
+        [] => return config::MIN_DIFFICULTY,
+        [only] => return only.difficulty.max(config::MIN_DIFFICULTY),
+        [first, solved @ ..] => (first, solved),
+    };
+    let last = solved[solved.len() - 1];
+
+    let n = solved.len() as u64;
+    // Sum of weights times the block time: what the weighted solve times add up to on target.
+    let ideal = n * (n + 1) / 2 * block_time as u64;
+    let mut previous = first.timestamp;
+    let mut weighted: u64 = 0;
+    let mut target_sum = U256::ZERO;
+    for (weight, sample) in (1..).zip(solved) {
+        let timestamp = sample.timestamp.max(previous + 1);
+        let solvetime = (timestamp - previous).min(MAX_SOLVETIME_FACTOR * block_time);
+        previous = timestamp;
+
+        weighted += solvetime as u64 * weight;
+        target_sum += target(sample.difficulty) / U256::from(n * ideal);
     }
 
-    fn target_difficulty(&self) -> String {
-        "0".repeat(self.difficulty)
-    }
+    let next = target_sum
+        .checked_mul(U256::from(weighted))
+        .unwrap_or(U256::MAX);
+    let step = config::MAX_DIFFICULTY_ADJUSTMENT;
+    difficulty_of(next)
+        .clamp(last.difficulty / step, last.difficulty.saturating_mul(step))
+        .max(config::MIN_DIFFICULTY)
 }
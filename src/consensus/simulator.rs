@@ -0,0 +1,201 @@
+use crate::config;
+use crate::consensus::pow::{self, BlockSample};
+use plotters::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// The per-block weighted average the chain uses.
+    Lwma,
+    /// Retargets every `ADJUSTMENT_INTERVAL` blocks by the ratio of the block time to the
+    /// interval's average solve time, trusting timestamps as they are.
+    Naive,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::Lwma => write!(f, "lwma"),
+            Algorithm::Naive => write!(f, "naive"),
+        }
+    }
+}
+
+/// Network hashrate over the simulated chain: a base rate, multiplied during each swing.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub blocks: usize,
+    /// Hashes per second.
+    pub base_hashrate: f64,
+    /// `(first block, last block, multiplier)`.
+    pub swings: Vec<(usize, usize, f64)>,
+}
+
+impl Scenario {
+    /// A large miner joins, later leaves, and then hops on and off every 50 blocks.
+    pub fn hashrate_swings(blocks: usize) -> Self {
+        let base_hashrate =
+            config::MIN_DIFFICULTY as f64 * 100.0 / config::BLOCK_TIME_SECONDS as f64;
+        let mut swings = vec![
+            (blocks / 5, blocks * 2 / 5, 10.0),
+            (blocks * 2 / 5, blocks * 3 / 5, 0.2),
+        ];
+        swings.extend(
+            (blocks * 3 / 5..blocks)
+                .step_by(100)
+                .map(|start| (start, start + 49, 5.0)),
+        );
+        Scenario {
+            blocks,
+            base_hashrate,
+            swings,
+        }
+    }
+
+    pub fn hashrate(&self, height: usize) -> f64 {
+        self.swings
+            .iter()
+            .filter(|(first, last, _)| (*first..=*last).contains(&height))
+            .fold(self.base_hashrate, |rate, (_, _, multiplier)| {
+                rate * multiplier
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationReport {
+    pub algorithm: Algorithm,
+    pub difficulties: Vec<u64>,
+    pub solvetimes: Vec<f64>,
+}
+
+impl SimulationReport {
+    pub fn mean_solvetime(&self) -> f64 {
+        self.solvetimes.iter().sum::<f64>() / self.solvetimes.len().max(1) as f64
+    }
+
+    pub fn solvetime_stddev(&self) -> f64 {
+        let mean = self.mean_solvetime();
+        let variance = self
+            .solvetimes
+            .iter()
+            .map(|solvetime| (solvetime - mean).powi(2))
+            .sum::<f64>()
+            / self.solvetimes.len().max(1) as f64;
+        variance.sqrt()
+    }
+
+    pub fn longest_solvetime(&self) -> f64 {
+        self.solvetimes.iter().cloned().fold(0.0, f64::max)
+    }
+
+    /// Blocks that took longer than four block times.
+    pub fn delayed_blocks(&self) -> usize {
+        let limit = 4.0 * config::BLOCK_TIME_SECONDS as f64;
+        self.solvetimes.iter().filter(|&&s| s > limit).count()
+    }
+}
+
+/// Mines `scenario.blocks` blocks with exponentially distributed solve times whose mean is
+/// difficulty over hashrate. The same seed replays the same luck for every algorithm.
+pub fn simulate(algorithm: Algorithm, scenario: &Scenario, seed: u64) -> SimulationReport {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut clock = 0.0;
+    let mut samples = vec![BlockSample {
+        timestamp: 0,
+        difficulty: config::MIN_DIFFICULTY,
+    }];
+    let mut report = SimulationReport {
+        algorithm,
+        difficulties: Vec::with_capacity(scenario.blocks),
+        solvetimes: Vec::with_capacity(scenario.blocks),
+    };
+
+    for height in 1..=scenario.blocks {
+        let difficulty = match algorithm {
+            Algorithm::Lwma => pow::next_difficulty(&samples),
+            Algorithm::Naive => naive_difficulty(&samples, height),
+        };
+        let mean = difficulty as f64 / scenario.hashrate(height);
+        let solvetime = -mean * (1.0 - rng.gen::<f64>()).ln();
+        clock += solvetime;
+
+        samples.push(BlockSample {
+            timestamp: clock as i64,
+            difficulty,
+        });
+        if samples.len() > config::DIFFICULTY_WINDOW + 1 {
+            samples.remove(0);
+        }
+        report.difficulties.push(difficulty);
+        report.solvetimes.push(solvetime);
+    }
+
+    report
+}
+
+fn naive_difficulty(samples: &[BlockSample], height: usize) -> u64 {
+    let last = samples[samples.len() - 1];
+    let interval = config::ADJUSTMENT_INTERVAL;
+    if samples.len() <= interval || !height.is_multiple_of(interval) {
+        return last.difficulty;
+    }
+
+    let first = samples[samples.len() - 1 - interval];
+    let elapsed = (last.timestamp - first.timestamp).max(1) as f64;
+    let expected = (interval as u64 * config::BLOCK_TIME_SECONDS) as f64;
+    ((last.difficulty as f64 * expected / elapsed) as u64).max(config::MIN_DIFFICULTY)
+}
+
+/// Draws each report's difficulty over height into a PNG at `path`.
+pub fn plot(reports: &[SimulationReport], path: &str) -> Result<(), Box<dyn Error>> {
+    let blocks = reports
+        .iter()
+        .map(|r| r.difficulties.len())
+        .max()
+        .unwrap_or(0);
+    let highest = reports
+        .iter()
+        .flat_map(|r| r.difficulties.iter())
+        .cloned()
+        .max()
+        .unwrap_or(1) as f64;
+
+    let root = BitMapBackend::new(path, (1024, 480)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Difficulty under hashrate swings", ("sans-serif", 30))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..blocks as u32, 0.0..highest * 1.1)?;
+    chart
+        .configure_mesh()
+        .x_desc("Height")
+        .y_desc("Difficulty")
+        .draw()?;
+
+    for (report, color) in reports.iter().zip([RED, BLUE, GREEN]) {
+        chart
+            .draw_series(LineSeries::new(
+                report
+                    .difficulties
+                    .iter()
+                    .enumerate()
+                    .map(|(x, &y)| (x as u32, y as f64)),
+                &color,
+            ))?
+            .label(report.algorithm.to_string())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    chart
+        .configure_series_labels()
+        .background_style(WHITE)
+        .border_style(BLACK)
+        .draw()?;
+    root.present()?;
+    Ok(())
+}
@@ -17,15 +17,11 @@ pub struct Contract {
 }
 
 impl Contract {
-    fn ledger() -> Storage {
-        Storage::init().unwrap()
-    }
-
-    pub fn new() -> Result<Contract, String> {
+    pub fn new(store: &Storage) -> Result<Contract, String> {
         let keypair = KeyPair.generate();
     }
 
-    pub fn get_contract(private_key: String) -> Result<Contract, String> {}
+    pub fn get_contract(store: &Storage, private_key: String) -> Result<Contract, String> {}
 
-    pub fn get_contracts(private_key: String) -> Result<Contract, String> {}
+    pub fn get_contracts(store: &Storage, private_key: String) -> Result<Contract, String> {}
 }
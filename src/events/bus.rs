@@ -0,0 +1,44 @@
+use crate::config;
+use crate::events::Event;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+/// Fans events out to every subscriber through a queue of `EVENT_QUEUE_CAPACITY` events.
+/// Publishing never blocks: a subscriber whose queue is full is dropped, and its receiver
+/// disconnects once it has drained what was queued. Clones share their subscribers.
+#[derive(Debug, Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<SyncSender<Event>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receives every event published from now on.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::sync_channel(config::EVENT_QUEUE_CAPACITY);
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers
+            .lock()
+            .map(|subscribers| subscribers.len())
+            .unwrap_or(0)
+    }
+
+    pub fn publish(&self, event: Event) {
+        let Ok(mut subscribers) = self.subscribers.lock() else {
+            return;
+        };
+        subscribers.retain(|subscriber| match subscriber.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
@@ -0,0 +1,25 @@
+use crate::chain::BlockHeader;
+use crate::tx::{Transaction, TransactionStatus};
+
+/// A change to the chain or the mempool, as published on the `EventBus`.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A block joined the active chain.
+    NewHeader(BlockHeader),
+    /// A transfer was admitted to the mempool.
+    TransactionAdmitted(Transaction),
+    /// A transfer was mined into the active chain at `height` and is `Completed`, or its
+    /// block was disconnected and it is `Pending` again with no height.
+    StatusChanged {
+        id: String,
+        status: TransactionStatus,
+        height: Option<u64>,
+    },
+    /// `address` was paid `amount` by transaction `id` in the active block at `height`.
+    Payment {
+        address: String,
+        id: String,
+        amount: f64,
+        height: u64,
+    },
+}
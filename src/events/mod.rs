@@ -0,0 +1,5 @@
+pub mod bus;
+pub mod event;
+
+pub use bus::EventBus;
+pub use event::Event;
@@ -1,13 +1,20 @@
+#![allow(clippy::module_inception)]
+
 pub mod util;
 pub use util::config;
+pub use util::error::Error;
 pub mod account;
-// pub mod chain;
-// pub mod cli;
+pub mod api;
+pub mod chain;
+pub mod cli;
+pub mod consensus;
+pub mod events;
+pub mod net;
 pub mod store;
 pub mod tx;
 pub mod vault;
 
-// pub use chain::{Block, BlockHeader, Blockchain};
+pub use chain::{Block, BlockHeader, Blockchain};
 // pub use store::Binary;
 // pub use store::Storage;
 // pub use account::transaction_status;
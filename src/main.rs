@@ -1,7 +1,5 @@
-use curve::account::Account;
+use std::process::ExitCode;
 
-fn main() {
-    let account = Account::new();
-
-    println!("{:?}", account)
+fn main() -> ExitCode {
+    curve::cli::cli::cli()
 }
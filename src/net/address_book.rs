@@ -0,0 +1,216 @@
+use crate::config;
+use crate::net::message::PeerAddress;
+use crate::store::{schema, ScanOptions, Storage, StorageBatch, StorageKind};
+use crate::util::error::StorageError;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+const ADDRESS_PREFIX: &[u8] = b"addr:";
+const BAN_PREFIX: &[u8] = b"ban:";
+/// Consecutive failed connection attempts after which an address is forgotten.
+const MAX_CONNECTION_FAILURES: u32 = 8;
+/// Wait before retrying an address, doubled for every failure in a row.
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A peer address the node has heard of.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub addr: SocketAddr,
+    /// Unix seconds when the peer was last connected to, or announced by another peer.
+    pub last_seen: i64,
+    /// Failed connection attempts since the last success.
+    pub failures: u32,
+}
+
+/// The peer table: addresses learnt from seeds, handshakes and `Addr` messages, and the
+/// banned IPs, all kept in the peers column family so a restarted node can reconnect without
+/// its seeds and keeps refusing peers it banned.
+pub struct AddressBook {
+    store: Storage,
+    addresses: HashMap<SocketAddr, PeerRecord>,
+    /// Unix seconds at which each ban ends.
+    bans: HashMap<IpAddr, i64>,
+    /// When each address was last dialled, which only matters while the node runs.
+    attempts: HashMap<SocketAddr, Instant>,
+}
+
+impl AddressBook {
+    pub fn load(store: &Storage) -> Result<Self, StorageError> {
+        let cf = StorageKind::Peer.name();
+        let mut book = AddressBook {
+            store: store.clone(),
+            addresses: HashMap::new(),
+            bans: HashMap::new(),
+            attempts: HashMap::new(),
+        };
+        let mut cursor = None;
+        loop {
+            let page = store.scan(cf, &ScanOptions::new(1_000).after(cursor))?;
+            for (key, value) in page.items {
+                if key.starts_with(ADDRESS_PREFIX) {
+                    let record: PeerRecord = schema::decode_record(&value)?;
+                    book.addresses.insert(record.addr, record);
+                } else if key.starts_with(BAN_PREFIX) {
+                    let (ip, until): (IpAddr, i64) = schema::decode_record(&value)?;
+                    book.bans.insert(ip, until);
+                }
+            }
+            cursor = match page.next_cursor {
+                Some(cursor) => Some(cursor),
+                None => break,
+            };
+        }
+        Ok(book)
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn get(&self, addr: &SocketAddr) -> Option<&PeerRecord> {
+        self.addresses.get(addr)
+    }
+
+    /// Every known address, most recently seen first.
+    pub fn records(&self) -> Vec<PeerRecord> {
+        let mut records: Vec<PeerRecord> = self.addresses.values().cloned().collect();
+        records.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then(a.addr.cmp(&b.addr)));
+        records
+    }
+
+    /// Records an address heard of at `last_seen`, which is capped at the current time. New
+    /// addresses are dropped once the book is full. Returns whether the address was new.
+    pub fn add(&mut self, addr: SocketAddr, last_seen: i64) -> Result<bool, StorageError> {
+        let last_seen = last_seen.min(Utc::now().timestamp());
+        let record = match self.addresses.get(&addr) {
+            Some(record) if record.last_seen >= last_seen => return Ok(false),
+            Some(record) => PeerRecord {
+                last_seen,
+                ..record.clone()
+            },
+            None if self.addresses.len() >= config::MAX_KNOWN_PEER_ADDRESSES => return Ok(false),
+            None => PeerRecord {
+                addr,
+                last_seen,
+                failures: 0,
+            },
+        };
+        let new = !self.addresses.contains_key(&addr);
+        self.save(record)?;
+        Ok(new)
+    }
+
+    /// A connection to `addr` completed its handshake.
+    pub fn connected(&mut self, addr: SocketAddr) -> Result<(), StorageError> {
+        self.attempts.remove(&addr);
+        self.save(PeerRecord {
+            addr,
+            last_seen: Utc::now().timestamp(),
+            failures: 0,
+        })
+    }
+
+    /// A connection to `addr` failed; addresses that keep failing are forgotten.
+    pub fn failed(&mut self, addr: SocketAddr) -> Result<(), StorageError> {
+        let Some(record) = self.addresses.get(&addr) else {
+            return Ok(());
+        };
+        if record.failures + 1 >= MAX_CONNECTION_FAILURES {
+            return self.remove(addr);
+        }
+        let record = PeerRecord {
+            failures: record.failures + 1,
+            ..record.clone()
+        };
+        self.save(record)
+    }
+
+    pub fn remove(&mut self, addr: SocketAddr) -> Result<(), StorageError> {
+        self.addresses.remove(&addr);
+        self.attempts.remove(&addr);
+        let mut batch = StorageBatch::new();
+        batch.delete(StorageKind::Peer.name(), &address_key(&addr));
+        self.store.write(batch)
+    }
+
+    /// Bans `ip` for `config::BAN_DURATION_SECONDS`.
+    pub fn ban(&mut self, ip: IpAddr) -> Result<(), StorageError> {
+        let until = Utc::now().timestamp() + config::BAN_DURATION_SECONDS;
+        self.bans.insert(ip, until);
+        self.store.put(
+            StorageKind::Peer.name(),
+            &ban_key(&ip),
+            &schema::encode_record(&(ip, until))?,
+            false,
+        )
+    }
+
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        self.bans
+            .get(ip)
+            .is_some_and(|until| *until > Utc::now().timestamp())
+    }
+
+    /// Up to `max` addresses to pass on to a peer, most recently seen first.
+    pub fn sample(&self, max: usize) -> Vec<PeerAddress> {
+        self.records()
+            .into_iter()
+            .filter(|record| !self.is_banned(&record.addr.ip()))
+            .take(max)
+            .map(|record| PeerAddress {
+                addr: record.addr,
+                last_seen: record.last_seen,
+            })
+            .collect()
+    }
+
+    /// The next address worth dialling: not banned, not `skip`ped, not tried too recently,
+    /// with the fewest failures and then the most recently seen. Marks it as attempted.
+    pub fn next_candidate(&mut self, skip: impl Fn(&SocketAddr) -> bool) -> Option<SocketAddr> {
+        let now = Instant::now();
+        let addr = self
+            .addresses
+            .values()
+            .filter(|record| !skip(&record.addr) && !self.is_banned(&record.addr.ip()))
+            .filter(|record| match self.attempts.get(&record.addr) {
+                Some(attempted) => {
+                    now.duration_since(*attempted) >= RETRY_INTERVAL * 2u32.pow(record.failures)
+                }
+                None => true,
+            })
+            .min_by(|a, b| {
+                a.failures
+                    .cmp(&b.failures)
+                    .then(b.last_seen.cmp(&a.last_seen))
+            })?
+            .addr;
+        self.attempts.insert(addr, now);
+        Some(addr)
+    }
+
+    fn save(&mut self, record: PeerRecord) -> Result<(), StorageError> {
+        self.store.put(
+            StorageKind::Peer.name(),
+            &address_key(&record.addr),
+            &schema::encode_record(&record)?,
+            false,
+        )?;
+        self.addresses.insert(record.addr, record);
+        Ok(())
+    }
+}
+
+fn address_key(addr: &SocketAddr) -> Vec<u8> {
+    [ADDRESS_PREFIX, addr.to_string().as_bytes()].concat()
+}
+
+fn ban_key(ip: &IpAddr) -> Vec<u8> {
+    [BAN_PREFIX, ip.to_string().as_bytes()].concat()
+}
@@ -0,0 +1,31 @@
+use crate::store::{schema, Storage, StorageKind};
+use crate::util::error::{NetworkError, StorageError};
+use crate::vault::KeyPair;
+use zeroize::Zeroizing;
+
+const IDENTITY_KEY: &[u8] = b"node_identity";
+
+/// Loads the node's long-term identity key from the meta column family, generating and
+/// storing one on first start. Peers recognise the node by its public half across restarts;
+/// the private half is as safe as the store, so encrypt the store to protect it.
+pub fn load_or_create(store: &Storage) -> Result<KeyPair, NetworkError> {
+    let cf = StorageKind::Meta.name();
+    match store.get(cf, IDENTITY_KEY) {
+        Ok(value) => {
+            let private_key: Zeroizing<String> = Zeroizing::new(schema::decode_record(&value)?);
+            Ok(KeyPair::from_private_key(&private_key)?)
+        }
+        Err(StorageError::NotFound) => {
+            let key = KeyPair::generate();
+            let private_key = Zeroizing::new(hex::encode(key.private_key.to_bytes()));
+            store.put(
+                cf,
+                IDENTITY_KEY,
+                &schema::encode_record(&*private_key)?,
+                false,
+            )?;
+            Ok(key)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,278 @@
+use crate::chain::{Block, BlockHeader};
+use crate::config;
+use crate::tx::Transaction;
+use crate::util::codec::{Decode, Decoder, Encode, Encoder};
+use crate::util::error::CodecError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Version of the wire protocol; peers speaking another one are disconnected.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Largest message a peer may send: a full block plus room for the framing around it.
+pub const MAX_MESSAGE_SIZE: usize = config::MAX_BLOCK_SIZE_BYTES as usize + 64 * 1024;
+
+/// Most headers sent in one `Headers` message; a full one means the peer has more.
+pub const MAX_HEADERS_PER_MESSAGE: usize = 2_000;
+
+/// Most peer addresses sent in one `Addr` message.
+pub const MAX_ADDRESSES_PER_MESSAGE: usize = 1_000;
+
+/// What each side states about itself before anything else is exchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub network_id: String,
+    pub genesis_hash: String,
+    pub best_height: u64,
+    /// Port the sender accepts connections on, so inbound peers can be dialled back and
+    /// passed on to others.
+    pub listen_port: u16,
+}
+
+/// A peer address passed on in `Addr`, with when it was last known to be reachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerAddress {
+    pub addr: SocketAddr,
+    pub last_seen: i64,
+}
+
+/// A transaction or block announced by id, fetched with `GetData` if unknown.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Inventory {
+    Transaction(String),
+    Block(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Version(Handshake),
+    /// Acknowledges the peer's `Version`; nothing else is accepted before it.
+    Verack,
+    Inv(Vec<Inventory>),
+    GetData(Vec<Inventory>),
+    Transaction(Transaction),
+    Block(Block),
+    /// Asks for the active-chain headers after the first locator hash the peer knows.
+    GetHeaders(Vec<String>),
+    Headers(Vec<BlockHeader>),
+    /// Asks for addresses of other peers.
+    GetAddr,
+    Addr(Vec<PeerAddress>),
+}
+
+const VERSION_TAG: u8 = 0;
+const VERACK_TAG: u8 = 1;
+const INV_TAG: u8 = 2;
+const GET_DATA_TAG: u8 = 3;
+const TRANSACTION_TAG: u8 = 4;
+const BLOCK_TAG: u8 = 5;
+const GET_HEADERS_TAG: u8 = 6;
+const HEADERS_TAG: u8 = 7;
+const GET_ADDR_TAG: u8 = 8;
+const ADDR_TAG: u8 = 9;
+
+impl Message {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Version(_) => "version",
+            Message::Verack => "verack",
+            Message::Inv(_) => "inv",
+            Message::GetData(_) => "getdata",
+            Message::Transaction(_) => "transaction",
+            Message::Block(_) => "block",
+            Message::GetHeaders(_) => "getheaders",
+            Message::Headers(_) => "headers",
+            Message::GetAddr => "getaddr",
+            Message::Addr(_) => "addr",
+        }
+    }
+}
+
+impl Encode for Handshake {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        encoder
+            .u32(self.protocol_version)
+            .str(&self.network_id)
+            .str(&self.genesis_hash)
+            .u64(self.best_height)
+            .u16(self.listen_port);
+    }
+}
+
+impl Decode for Handshake {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError> {
+        Ok(Handshake {
+            protocol_version: decoder.u32()?,
+            network_id: decoder.string()?,
+            genesis_hash: decoder.string()?,
+            best_height: decoder.u64()?,
+            listen_port: decoder.u16()?,
+        })
+    }
+}
+
+impl Encode for Inventory {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        match self {
+            Inventory::Transaction(id) => encoder.u8(0).str(id),
+            Inventory::Block(hash) => encoder.u8(1).str(hash),
+        };
+    }
+}
+
+impl Decode for Inventory {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError> {
+        match decoder.u8()? {
+            0 => Ok(Inventory::Transaction(decoder.string()?)),
+            1 => Ok(Inventory::Block(decoder.string()?)),
+            tag => Err(CodecError::InvalidTag {
+                kind: "inventory",
+                tag,
+            }),
+        }
+    }
+}
+
+impl Encode for PeerAddress {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        match self.addr.ip() {
+            IpAddr::V4(ip) => encoder.u8(4).u32(ip.into()),
+            IpAddr::V6(ip) => {
+                let bits = u128::from(ip);
+                encoder.u8(6).u64((bits >> 64) as u64).u64(bits as u64)
+            }
+        };
+        encoder.u16(self.addr.port()).i64(self.last_seen);
+    }
+}
+
+impl Decode for PeerAddress {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError> {
+        let ip = match decoder.u8()? {
+            4 => IpAddr::V4(Ipv4Addr::from(decoder.u32()?)),
+            6 => {
+                let high = decoder.u64()? as u128;
+                let low = decoder.u64()? as u128;
+                IpAddr::V6(Ipv6Addr::from(high << 64 | low))
+            }
+            tag => {
+                return Err(CodecError::InvalidTag {
+                    kind: "address",
+                    tag,
+                })
+            }
+        };
+        Ok(PeerAddress {
+            addr: SocketAddr::new(ip, decoder.u16()?),
+            last_seen: decoder.i64()?,
+        })
+    }
+}
+
+fn count(len: usize) -> u32 {
+    u32::try_from(len).expect("message list exceeds u32::MAX items")
+}
+
+fn encode_inventory(encoder: &mut Encoder, items: &[Inventory]) {
+    encoder.u32(count(items.len()));
+    for item in items {
+        encoder.value(item);
+    }
+}
+
+fn decode_inventory(decoder: &mut Decoder<'_>) -> Result<Vec<Inventory>, CodecError> {
+    let count = decoder.u32()? as usize;
+    // Every item takes at least five bytes, so a bogus count can't force a huge allocation.
+    let mut items = Vec::with_capacity(count.min(decoder.remaining() / 5));
+    for _ in 0..count {
+        items.push(decoder.value()?);
+    }
+    Ok(items)
+}
+
+impl Encode for Message {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        match self {
+            Message::Version(handshake) => {
+                encoder.u8(VERSION_TAG).value(handshake);
+            }
+            Message::Verack => {
+                encoder.u8(VERACK_TAG);
+            }
+            Message::Inv(items) => {
+                encoder.u8(INV_TAG);
+                encode_inventory(encoder, items);
+            }
+            Message::GetData(items) => {
+                encoder.u8(GET_DATA_TAG);
+                encode_inventory(encoder, items);
+            }
+            Message::Transaction(tx) => {
+                encoder.u8(TRANSACTION_TAG).value(tx);
+            }
+            Message::Block(block) => {
+                encoder.u8(BLOCK_TAG).value(block);
+            }
+            Message::GetHeaders(locator) => {
+                encoder.u8(GET_HEADERS_TAG).u32(count(locator.len()));
+                for hash in locator {
+                    encoder.str(hash);
+                }
+            }
+            Message::Headers(headers) => {
+                encoder.u8(HEADERS_TAG).u32(count(headers.len()));
+                for header in headers {
+                    encoder.value(header);
+                }
+            }
+            Message::GetAddr => {
+                encoder.u8(GET_ADDR_TAG);
+            }
+            Message::Addr(addresses) => {
+                encoder.u8(ADDR_TAG).u32(count(addresses.len()));
+                for address in addresses {
+                    encoder.value(address);
+                }
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError> {
+        match decoder.u8()? {
+            VERSION_TAG => Ok(Message::Version(decoder.value()?)),
+            VERACK_TAG => Ok(Message::Verack),
+            INV_TAG => Ok(Message::Inv(decode_inventory(decoder)?)),
+            GET_DATA_TAG => Ok(Message::GetData(decode_inventory(decoder)?)),
+            TRANSACTION_TAG => Ok(Message::Transaction(decoder.value()?)),
+            BLOCK_TAG => Ok(Message::Block(decoder.value()?)),
+            GET_HEADERS_TAG => {
+                let count = decoder.u32()?;
+                let locator = (0..count)
+                    .map(|_| decoder.string())
+                    .collect::<Result<_, _>>()?;
+                Ok(Message::GetHeaders(locator))
+            }
+            HEADERS_TAG => {
+                let count = decoder.u32()?;
+                let headers = (0..count)
+                    .map(|_| decoder.value())
+                    .collect::<Result<_, _>>()?;
+                Ok(Message::Headers(headers))
+            }
+            GET_ADDR_TAG => Ok(Message::GetAddr),
+            ADDR_TAG => {
+                let count = decoder.u32()?;
+                let addresses = (0..count)
+                    .map(|_| decoder.value())
+                    .collect::<Result<_, _>>()?;
+                Ok(Message::Addr(addresses))
+            }
+            tag => Err(CodecError::InvalidTag {
+                kind: "message",
+                tag,
+            }),
+        }
+    }
+}
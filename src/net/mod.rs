@@ -0,0 +1,12 @@
+pub mod address_book;
+pub mod identity;
+pub mod message;
+pub mod node;
+pub mod peer;
+pub mod sync;
+pub mod transport;
+
+pub use address_book::{AddressBook, PeerRecord};
+pub use message::{Handshake, Inventory, Message, PeerAddress};
+pub use node::{Node, NodeConfig};
+pub use peer::Peer;
@@ -0,0 +1,875 @@
+use crate::chain::{Block, BlockHeader, BlockOutcome, Blockchain, GenesisConfig};
+use crate::config;
+use crate::events::EventBus;
+use crate::net::address_book::AddressBook;
+use crate::net::identity;
+use crate::net::message::{
+    Handshake, Inventory, Message, MAX_ADDRESSES_PER_MESSAGE, MAX_HEADERS_PER_MESSAGE,
+    PROTOCOL_VERSION,
+};
+use crate::net::peer::Peer;
+use crate::net::sync::BlockDownloader;
+use crate::net::transport::{self, SecureReader};
+use crate::store::Storage;
+use crate::tx::{Mempool, Transaction};
+use crate::util::error::{BlockRejection, ChainError, MempoolError, NetworkError};
+use crate::vault::KeyPair;
+use chrono::Utc;
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often stalled block downloads are checked for and handed to other peers.
+const SYNC_INTERVAL: Duration = Duration::from_millis(500);
+/// How often another known address is dialled while outbound slots are free.
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(1);
+/// Ban score for a block or header that fails validation.
+const INVALID_BLOCK_PENALTY: u32 = 50;
+/// Ban score for a transaction that is invalid whatever the chain state.
+const MALFORMED_TRANSACTION_PENALTY: u32 = 20;
+/// Ban score for an authenticated message that doesn't decode.
+const MALFORMED_MESSAGE_PENALTY: u32 = 20;
+
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    pub listen: SocketAddr,
+    /// Peers to connect to on start.
+    pub peers: Vec<SocketAddr>,
+    /// `host:port` seed nodes added to the address book on start, so a node with an empty
+    /// peer table has somewhere to begin.
+    pub seeds: Vec<String>,
+    /// Whether to keep dialling known addresses until `MAX_OUTBOUND_PEERS` links are up.
+    /// Without it the node keeps only the connections in `peers` and those made to it.
+    pub discover: bool,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            listen: SocketAddr::from(([127, 0, 0, 1], config::P2P_PORT)),
+            peers: Vec::new(),
+            seeds: Vec::new(),
+            discover: true,
+        }
+    }
+}
+
+struct Shared {
+    store: Storage,
+    identity: KeyPair,
+    chain: Mutex<Blockchain>,
+    mempool: Mutex<Mempool>,
+    events: EventBus,
+    network_id: String,
+    genesis_hash: String,
+    local_addr: SocketAddr,
+    peers: Mutex<HashMap<SocketAddr, Arc<Peer>>>,
+    /// Inbound handshakes in flight. Each holds an inbound slot until it finishes, and is only
+    /// added to under the `peers` lock.
+    handshakes: AtomicUsize,
+    book: Mutex<AddressBook>,
+    /// Blocks whose parent hasn't arrived yet, by hash.
+    orphans: Mutex<HashMap<String, Block>>,
+    sync: Mutex<BlockDownloader>,
+    running: AtomicBool,
+}
+
+/// A full node: owns the chain and mempool of one store, accepts peers on a TCP listener and
+/// gossips transactions and blocks between them.
+///
+/// Every connection gets its own thread. Locks are taken chain first, then mempool, and never
+/// held while writing to a peer. The address book is locked on its own.
+#[derive(Clone)]
+pub struct Node {
+    shared: Arc<Shared>,
+}
+
+impl Node {
+    /// Opens the chain in `store`, readmits the transfers saved with `Mempool::persist`,
+    /// starts listening on `config.listen` and connects to `config.peers`. Peers that can't be
+    /// reached are logged and skipped. Seeds join the persisted address book, which is dialled
+    /// from in the background when `config.discover` is set.
+    pub fn start(
+        store: Storage,
+        genesis: &GenesisConfig,
+        config: NodeConfig,
+    ) -> Result<Node, NetworkError> {
+        let events = EventBus::new();
+        let chain = Blockchain::open(&store, genesis)?.with_events(events.clone());
+        let mut mempool = Mempool::default().with_events(events.clone());
+        let saved = mempool.load(&store)?;
+        if saved > 0 {
+            info!("Loaded {} saved transactions into the mempool", saved);
+        }
+        let identity = identity::load_or_create(&store)?;
+        let genesis_hash = chain.blocks[0].header.hash.clone();
+        let listener = TcpListener::bind(config.listen)?;
+        listener.set_nonblocking(true)?;
+        let mut book = AddressBook::load(&store)?;
+        for seed in &config.seeds {
+            match seed.to_socket_addrs() {
+                Ok(addrs) => {
+                    for addr in addrs {
+                        book.add(addr, 0)?;
+                    }
+                }
+                Err(e) => warn!("Could not resolve seed {}: {}", seed, e),
+            }
+        }
+
+        let node = Node {
+            shared: Arc::new(Shared {
+                store,
+                identity,
+                chain: Mutex::new(chain),
+                mempool: Mutex::new(mempool),
+                events,
+                network_id: genesis.network_id.clone(),
+                genesis_hash,
+                local_addr: listener.local_addr()?,
+                peers: Mutex::new(HashMap::new()),
+                handshakes: AtomicUsize::new(0),
+                book: Mutex::new(book),
+                orphans: Mutex::new(HashMap::new()),
+                sync: Mutex::new(BlockDownloader::default()),
+                running: AtomicBool::new(true),
+            }),
+        };
+        info!(
+            "Listening for peers on {} as {}",
+            node.local_addr(),
+            node.identity()
+        );
+
+        let acceptor = node.clone();
+        thread::spawn(move || acceptor.accept_loop(listener));
+        let syncer = node.clone();
+        thread::spawn(move || syncer.sync_loop());
+        if config.discover {
+            let dialler = node.clone();
+            thread::spawn(move || dialler.discovery_loop());
+        }
+        for addr in config.peers {
+            if let Err(e) = node.connect(addr) {
+                warn!("Could not connect to {}: {}", addr, e);
+            }
+        }
+        Ok(node)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.shared.local_addr
+    }
+
+    /// Hex encoded public identity key, which peers see in the transport handshake and can
+    /// pin with `connect_to`.
+    pub fn identity(&self) -> String {
+        hex::encode(self.shared.identity.public_key.compress().to_bytes())
+    }
+
+    pub fn network_id(&self) -> &str {
+        &self.shared.network_id
+    }
+
+    pub fn genesis_hash(&self) -> &str {
+        &self.shared.genesis_hash
+    }
+
+    pub fn store(&self) -> &Storage {
+        &self.shared.store
+    }
+
+    pub fn chain(&self) -> Result<MutexGuard<'_, Blockchain>, NetworkError> {
+        self.shared.chain.lock().map_err(|_| NetworkError::Lock)
+    }
+
+    pub fn mempool(&self) -> Result<MutexGuard<'_, Mempool>, NetworkError> {
+        self.shared.mempool.lock().map_err(|_| NetworkError::Lock)
+    }
+
+    /// New headers, mempool admissions, status changes and payments, as the chain and mempool
+    /// publish them.
+    pub fn events(&self) -> &EventBus {
+        &self.shared.events
+    }
+
+    pub fn height(&self) -> Result<u64, NetworkError> {
+        Ok(self.chain()?.height())
+    }
+
+    /// The peer table and ban list.
+    pub fn address_book(&self) -> Result<MutexGuard<'_, AddressBook>, NetworkError> {
+        self.shared.book.lock().map_err(|_| NetworkError::Lock)
+    }
+
+    /// Addresses of the peers that completed the handshake.
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        match self.shared.peers.lock() {
+            Ok(peers) => peers.keys().copied().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Connects to `addr` and completes the handshake before returning, so a peer on another
+    /// network or with another genesis block is reported as an error.
+    pub fn connect(&self, addr: SocketAddr) -> Result<(), NetworkError> {
+        self.connect_to(addr, None)
+    }
+
+    /// Like `connect`, but also requires the peer to prove the identity key `identity`.
+    /// Banned addresses and connections beyond `MAX_OUTBOUND_PEERS` are refused.
+    pub fn connect_to(&self, addr: SocketAddr, identity: Option<&str>) -> Result<(), NetworkError> {
+        let peers = self.peer_list()?;
+        if peers
+            .iter()
+            .any(|peer| peer.addr() == addr || peer.listen_addr() == addr)
+        {
+            return Ok(());
+        }
+        if self.address_book()?.is_banned(&addr.ip()) {
+            return Err(NetworkError::Banned(addr.ip()));
+        }
+        if peers.iter().filter(|peer| !peer.is_inbound()).count() >= config::MAX_OUTBOUND_PEERS {
+            return Err(NetworkError::TooManyPeers(config::MAX_OUTBOUND_PEERS));
+        }
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        let (peer, reader) = self.handshake(stream, false, identity)?;
+        let node = self.clone();
+        thread::spawn(move || node.serve(peer, reader));
+        Ok(())
+    }
+
+    /// Adds a transaction to the mempool and announces it to every peer.
+    pub fn submit_transaction(&self, tx: Transaction) -> Result<(), NetworkError> {
+        let id = tx.id().to_string();
+        self.mempool()?.add(&self.shared.store, tx)?;
+        self.relay(Inventory::Transaction(id), None);
+        Ok(())
+    }
+
+    /// Adds a block to the chain and announces it to every peer.
+    pub fn submit_block(&self, block: Block) -> Result<BlockOutcome, NetworkError> {
+        let hash = block.header.hash.clone();
+        let outcome = self.connect_block(block)?;
+        self.relay(Inventory::Block(hash.clone()), None);
+        self.connect_orphans(&hash);
+        Ok(outcome)
+    }
+
+    /// Mines a block on the current tip with the mempool's transactions, paying `miner`, and
+    /// submits it. The chain isn't locked while searching for the nonce.
+    pub fn mine_block(&self, miner: &str) -> Result<Block, NetworkError> {
+        let mut block = {
+            let chain = self.chain()?;
+            let budget = config::MAX_BLOCK_SIZE_BYTES - config::BLOCK_OVERHEAD_BYTES;
+            let transactions = self.mempool()?.select(&self.shared.store, budget);
+            chain.next_block(miner, transactions)?
+        };
+        block.mine();
+        self.submit_block(block.clone())?;
+        Ok(block)
+    }
+
+    /// Whether blocks of headers already validated are still being downloaded.
+    pub fn is_syncing(&self) -> Result<bool, NetworkError> {
+        Ok(self.sync()?.is_syncing())
+    }
+
+    /// Stops accepting connections and disconnects every peer.
+    pub fn shutdown(&self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+        if let Ok(mut peers) = self.shared.peers.lock() {
+            for (_, peer) in peers.drain() {
+                peer.disconnect();
+            }
+        }
+    }
+
+    fn accept_loop(&self, listener: TcpListener) {
+        while self.shared.running.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    if let Err(e) = self.admit(addr) {
+                        debug!("Refusing connection from {}: {}", addr, e);
+                        continue;
+                    }
+                    let node = self.clone();
+                    thread::spawn(move || {
+                        let handshake = node.handshake(stream, true, None);
+                        // A registered peer now holds the slot itself.
+                        node.shared.handshakes.fetch_sub(1, Ordering::SeqCst);
+                        match handshake {
+                            Ok((peer, reader)) => node.serve(peer, reader),
+                            Err(e) => warn!("Handshake with {} failed: {}", addr, e),
+                        }
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_POLL_INTERVAL)
+                }
+                Err(e) => warn!("Error accepting connection: {}", e),
+            }
+        }
+    }
+
+    /// Checks an inbound connection against the ban list and reserves it one of the
+    /// `MAX_INBOUND_PEERS` slots. Handshakes still in flight hold a slot too, so a burst of
+    /// connects can neither overshoot the limit nor spawn more handshakes than it allows.
+    fn admit(&self, addr: SocketAddr) -> Result<(), NetworkError> {
+        if self.address_book()?.is_banned(&addr.ip()) {
+            return Err(NetworkError::Banned(addr.ip()));
+        }
+        let peers = self.shared.peers.lock().map_err(|_| NetworkError::Lock)?;
+        let inbound = peers.values().filter(|peer| peer.is_inbound()).count();
+        if inbound + self.shared.handshakes.load(Ordering::SeqCst) >= config::MAX_INBOUND_PEERS {
+            return Err(NetworkError::TooManyPeers(config::MAX_INBOUND_PEERS));
+        }
+        self.shared.handshakes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn peer_list(&self) -> Result<Vec<Arc<Peer>>, NetworkError> {
+        Ok(self
+            .shared
+            .peers
+            .lock()
+            .map_err(|_| NetworkError::Lock)?
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn discovery_loop(&self) {
+        while self.shared.running.load(Ordering::SeqCst) {
+            if let Err(e) = self.dial_next() {
+                warn!("Error dialling known peers: {}", e);
+            }
+            thread::sleep(DISCOVERY_INTERVAL);
+        }
+    }
+
+    /// Dials the next address from the book while outbound slots are free. Addresses that
+    /// turn out to be ourselves or on another network are forgotten.
+    fn dial_next(&self) -> Result<(), NetworkError> {
+        let peers = self.peer_list()?;
+        if peers.iter().filter(|peer| !peer.is_inbound()).count() >= config::MAX_OUTBOUND_PEERS {
+            return Ok(());
+        }
+        let connected: HashSet<SocketAddr> = peers.iter().map(|peer| peer.listen_addr()).collect();
+        let local = self.local_addr();
+        let candidate = self
+            .address_book()?
+            .next_candidate(|addr| *addr == local || connected.contains(addr));
+        let Some(addr) = candidate else {
+            return Ok(());
+        };
+        match self.connect(addr) {
+            Ok(()) | Err(NetworkError::AlreadyConnected(_)) => Ok(()),
+            Err(
+                e @ (NetworkError::SelfConnection
+                | NetworkError::WrongNetwork { .. }
+                | NetworkError::WrongGenesis { .. }
+                | NetworkError::UnsupportedProtocol(_)),
+            ) => {
+                debug!("Forgetting {}: {}", addr, e);
+                Ok(self.address_book()?.remove(addr)?)
+            }
+            Err(e) => {
+                debug!("Could not connect to {}: {}", addr, e);
+                Ok(self.address_book()?.failed(addr)?)
+            }
+        }
+    }
+
+    fn sync(&self) -> Result<MutexGuard<'_, BlockDownloader>, NetworkError> {
+        self.shared.sync.lock().map_err(|_| NetworkError::Lock)
+    }
+
+    fn sync_loop(&self) {
+        while self.shared.running.load(Ordering::SeqCst) {
+            if let Err(e) = self.request_blocks() {
+                warn!("Error scheduling block downloads: {}", e);
+            }
+            thread::sleep(SYNC_INTERVAL);
+        }
+    }
+
+    fn local_handshake(&self) -> Result<Handshake, NetworkError> {
+        Ok(Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            network_id: self.shared.network_id.clone(),
+            genesis_hash: self.shared.genesis_hash.clone(),
+            best_height: self.height()?,
+            listen_port: self.local_addr().port(),
+        })
+    }
+
+    /// Secures the link with the transport handshake, in which the connecting side is the
+    /// initiator, then both sides send `Version`, check the other's, and acknowledge it with
+    /// `Verack` over the encrypted link. The peer is registered once both acknowledgements are
+    /// through, entered in the address book, asked for addresses if we dialled it, and asked
+    /// for headers if it is ahead.
+    fn handshake(
+        &self,
+        stream: TcpStream,
+        inbound: bool,
+        expected_identity: Option<&str>,
+    ) -> Result<(Arc<Peer>, SecureReader), NetworkError> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let addr = stream.peer_addr()?;
+        let (mut reader, mut writer, remote_identity) =
+            transport::handshake(stream, &self.shared.identity, !inbound)?;
+        let remote_identity = hex::encode(remote_identity.compress().to_bytes());
+        if remote_identity == self.identity() {
+            return Err(NetworkError::SelfConnection);
+        }
+        if let Some(expected) = expected_identity {
+            if remote_identity != expected {
+                return Err(NetworkError::UnexpectedIdentity {
+                    expected: expected.to_string(),
+                    found: remote_identity,
+                });
+            }
+        }
+        if self
+            .peer_list()?
+            .iter()
+            .any(|peer| peer.identity() == remote_identity)
+        {
+            return Err(NetworkError::AlreadyConnected(remote_identity));
+        }
+
+        let local = self.local_handshake()?;
+        writer.send(&Message::Version(local.clone()))?;
+        let remote = match reader.receive()? {
+            Message::Version(remote) => remote,
+            other => return Err(NetworkError::UnexpectedMessage(other.kind())),
+        };
+        if remote.protocol_version != PROTOCOL_VERSION {
+            return Err(NetworkError::UnsupportedProtocol(remote.protocol_version));
+        }
+        if remote.network_id != local.network_id {
+            return Err(NetworkError::WrongNetwork {
+                expected: local.network_id,
+                found: remote.network_id,
+            });
+        }
+        if remote.genesis_hash != local.genesis_hash {
+            return Err(NetworkError::WrongGenesis {
+                expected: local.genesis_hash,
+                found: remote.genesis_hash,
+            });
+        }
+        writer.send(&Message::Verack)?;
+        match reader.receive()? {
+            Message::Verack => {}
+            other => return Err(NetworkError::UnexpectedMessage(other.kind())),
+        }
+        reader.stream().set_read_timeout(None)?;
+
+        let peer = Arc::new(Peer::new(addr, inbound, remote_identity, writer, remote));
+        {
+            let mut peers = self.shared.peers.lock().map_err(|_| NetworkError::Lock)?;
+            // Checked under the lock `shutdown` drains the peers with, so no link outlives it.
+            if !self.shared.running.load(Ordering::SeqCst) {
+                return Err(NetworkError::ShutDown);
+            }
+            // Checked again here, as other handshakes may have finished since the checks above.
+            if peers
+                .values()
+                .any(|other| other.identity() == peer.identity())
+            {
+                return Err(NetworkError::AlreadyConnected(peer.identity().to_string()));
+            }
+            let limit = if inbound {
+                config::MAX_INBOUND_PEERS
+            } else {
+                config::MAX_OUTBOUND_PEERS
+            };
+            if peers
+                .values()
+                .filter(|other| other.is_inbound() == inbound)
+                .count()
+                >= limit
+            {
+                return Err(NetworkError::TooManyPeers(limit));
+            }
+            peers.insert(addr, peer.clone());
+        }
+        info!(
+            "Connected to {} {} ({}, height {})",
+            peer.identity(),
+            addr,
+            if inbound { "inbound" } else { "outbound" },
+            peer.handshake().best_height
+        );
+
+        if inbound {
+            // Only a claim until someone dials it.
+            self.address_book()?
+                .add(peer.listen_addr(), Utc::now().timestamp())?;
+        } else {
+            self.address_book()?.connected(addr)?;
+            peer.send(&Message::GetAddr)?;
+        }
+        if peer.best_height() > local.best_height {
+            self.request_headers(&peer)?;
+        }
+        Ok((peer, reader))
+    }
+
+    /// Handles the peer's messages until the connection closes.
+    fn serve(&self, peer: Arc<Peer>, mut reader: SecureReader) {
+        loop {
+            let message = match reader.receive() {
+                Ok(message) => message,
+                // The frame was authenticated and read whole, so the link is still usable.
+                Err(e @ NetworkError::Codec(_)) => {
+                    if self.penalize(&peer, MALFORMED_MESSAGE_PENALTY, &e) {
+                        break;
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    debug!("Connection to {} closed: {}", peer.addr(), e);
+                    break;
+                }
+            };
+            match self.handle(&peer, message) {
+                Ok(()) => {}
+                Err(e @ NetworkError::UnexpectedMessage(_)) => {
+                    warn!("Disconnecting {}: {}", peer.addr(), e);
+                    break;
+                }
+                Err(e) => match penalty(&e) {
+                    0 => debug!("Error handling message from {}: {}", peer.addr(), e),
+                    points => {
+                        if self.penalize(&peer, points, &e) {
+                            break;
+                        }
+                    }
+                },
+            }
+        }
+        peer.disconnect();
+        if let Ok(mut peers) = self.shared.peers.lock() {
+            peers.remove(&peer.addr());
+        }
+    }
+
+    fn handle(&self, peer: &Arc<Peer>, message: Message) -> Result<(), NetworkError> {
+        match message {
+            Message::Inv(items) => {
+                let mut wanted = Vec::new();
+                for item in items {
+                    if !self.knows(&item)? {
+                        wanted.push(item);
+                    }
+                }
+                if !wanted.is_empty() {
+                    peer.send(&Message::GetData(wanted))?;
+                }
+                Ok(())
+            }
+            Message::GetData(items) => {
+                for item in items {
+                    let reply = match item {
+                        Inventory::Transaction(id) => {
+                            self.mempool()?.get(&id).map(Message::Transaction)
+                        }
+                        Inventory::Block(hash) => {
+                            self.chain()?.get_block(&hash).ok().map(Message::Block)
+                        }
+                    };
+                    if let Some(reply) = reply {
+                        peer.send(&reply)?;
+                    }
+                }
+                Ok(())
+            }
+            Message::Transaction(tx) => {
+                let id = tx.id().to_string();
+                match self.mempool()?.add(&self.shared.store, tx) {
+                    Ok(()) => {}
+                    Err(MempoolError::Duplicate(_)) => return Ok(()),
+                    Err(e) => return Err(e.into()),
+                }
+                self.relay(Inventory::Transaction(id), Some(peer.addr()));
+                Ok(())
+            }
+            Message::Block(block) => self.receive_block(peer, block),
+            Message::GetHeaders(locator) => {
+                let headers = self
+                    .chain()?
+                    .headers_after(&locator, MAX_HEADERS_PER_MESSAGE);
+                peer.send(&Message::Headers(headers))
+            }
+            Message::Headers(headers) => self.receive_headers(peer, headers),
+            Message::GetAddr => {
+                let addresses = self.address_book()?.sample(MAX_ADDRESSES_PER_MESSAGE);
+                peer.send(&Message::Addr(addresses))
+            }
+            Message::Addr(addresses) => {
+                let mut book = self.address_book()?;
+                for address in addresses.into_iter().take(MAX_ADDRESSES_PER_MESSAGE) {
+                    if address.addr.port() != 0 && !book.is_banned(&address.addr.ip()) {
+                        book.add(address.addr, address.last_seen)?;
+                    }
+                }
+                Ok(())
+            }
+            other @ (Message::Version(_) | Message::Verack) => {
+                Err(NetworkError::UnexpectedMessage(other.kind()))
+            }
+        }
+    }
+
+    /// Raises `peer`'s ban score by `points` for `error`. Once the score reaches
+    /// `BAN_SCORE_THRESHOLD` its IP is banned and every peer from it disconnected; returns
+    /// whether that happened.
+    fn penalize(&self, peer: &Peer, points: u32, error: &NetworkError) -> bool {
+        let score = peer.misbehaved(points);
+        warn!(
+            "Peer {} misbehaved, ban score {}: {}",
+            peer.addr(),
+            score,
+            error
+        );
+        if score < config::BAN_SCORE_THRESHOLD {
+            return false;
+        }
+        let ip = peer.addr().ip();
+        warn!("Banning {} for {}s", ip, config::BAN_DURATION_SECONDS);
+        match self.address_book() {
+            Ok(mut book) => {
+                if let Err(e) = book.ban(ip) {
+                    warn!("Could not store the ban of {}: {}", ip, e);
+                }
+            }
+            Err(e) => warn!("Could not ban {}: {}", ip, e),
+        }
+        if let Ok(peers) = self.peer_list() {
+            for other in peers.iter().filter(|other| other.addr().ip() == ip) {
+                other.disconnect();
+            }
+        }
+        true
+    }
+
+    fn knows(&self, item: &Inventory) -> Result<bool, NetworkError> {
+        Ok(match item {
+            Inventory::Transaction(id) => self.mempool()?.contains(id),
+            Inventory::Block(hash) => {
+                self.chain()?.contains(hash)
+                    || self
+                        .shared
+                        .orphans
+                        .lock()
+                        .map_err(|_| NetworkError::Lock)?
+                        .contains_key(hash)
+            }
+        })
+    }
+
+    fn request_headers(&self, peer: &Peer) -> Result<(), NetworkError> {
+        let locator = self.chain()?.locator();
+        peer.send(&Message::GetHeaders(locator))
+    }
+
+    /// Validates headers from `peer`, asks for more while it sends full batches, and starts
+    /// downloading the blocks behind them.
+    fn receive_headers(
+        &self,
+        peer: &Arc<Peer>,
+        headers: Vec<BlockHeader>,
+    ) -> Result<(), NetworkError> {
+        let Some(last) = headers.last() else {
+            return Ok(());
+        };
+        peer.note_height(last.index);
+        {
+            let mut chain = self.chain()?;
+            if chain.add_headers(&headers)? > 0 {
+                self.sync()?.headers_changed(&chain);
+            }
+        }
+        if headers.len() == MAX_HEADERS_PER_MESSAGE {
+            self.request_headers(peer)?;
+        }
+        self.request_blocks()
+    }
+
+    /// Sends `GetData` for the next blocks of the best header chain, spread over the peers.
+    fn request_blocks(&self) -> Result<(), NetworkError> {
+        let peers = self.peer_list()?;
+        let batches = {
+            let chain = self.chain()?;
+            let mut sync = self.sync()?;
+            if !sync.is_syncing() {
+                return Ok(());
+            }
+            let orphans = self.shared.orphans.lock().map_err(|_| NetworkError::Lock)?;
+            sync.schedule(&chain, &peers, |hash| orphans.contains_key(hash))
+        };
+        for (peer, items) in batches {
+            if let Err(e) = peer.send(&Message::GetData(items)) {
+                debug!("Could not request blocks from {}: {}", peer.addr(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Connects a block from `peer`, or keeps it until its parent arrives. A block whose
+    /// parent isn't even a known header means the peer is ahead, so its headers are fetched.
+    fn receive_block(&self, peer: &Arc<Peer>, block: Block) -> Result<(), NetworkError> {
+        let hash = block.header.hash.clone();
+        peer.note_height(block.header.index);
+        self.sync()?.received(&hash);
+        if self.knows(&Inventory::Block(hash.clone()))? {
+            return Ok(());
+        }
+        let parent = block.header.prev_hash.clone();
+        let (has_parent, has_parent_header) = {
+            let chain = self.chain()?;
+            (chain.contains(&parent), chain.contains_header(&parent))
+        };
+        if !has_parent {
+            let mut orphans = self.shared.orphans.lock().map_err(|_| NetworkError::Lock)?;
+            if orphans.len() >= config::MAX_ORPHAN_BLOCKS {
+                if let Some(evicted) = orphans.keys().next().cloned() {
+                    orphans.remove(&evicted);
+                }
+            }
+            orphans.insert(hash, block);
+            drop(orphans);
+            if !has_parent_header {
+                self.request_headers(peer)?;
+            }
+            return Ok(());
+        }
+
+        self.connect_block(block)?;
+        if !self.is_syncing()? {
+            self.relay(Inventory::Block(hash.clone()), Some(peer.addr()));
+        }
+        self.connect_orphans(&hash);
+        self.request_blocks()
+    }
+
+    /// Adds a block to the chain and updates the mempool and the download queue. A block
+    /// whose header was accepted but whose body is invalid takes its header branch with it.
+    fn connect_block(&self, block: Block) -> Result<BlockOutcome, NetworkError> {
+        let hash = block.header.hash.clone();
+        let mut chain = self.chain()?;
+        let outcome = match chain.add_block(block) {
+            Ok(outcome) => outcome,
+            Err(e @ ChainError::InvalidBlock { .. }) => {
+                chain.discard_header(&hash);
+                self.sync()?.headers_changed(&chain);
+                return Err(e.into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut mempool = self.mempool()?;
+        if let BlockOutcome::Reorganized { disconnected, .. } = &outcome {
+            mempool.restore(&self.shared.store, disconnected);
+        }
+        mempool.prune(&self.shared.store);
+        drop(mempool);
+        let mut sync = self.sync()?;
+        sync.connected(&chain);
+        // The progress bar reports on blocks fetched during sync.
+        if !sync.is_syncing() {
+            info!(
+                "Chain at height {} ({})",
+                chain.height(),
+                outcome_kind(&outcome)
+            );
+        }
+        Ok(outcome)
+    }
+
+    /// Connects the orphans that descend from `parent`, now that it is in the block tree.
+    fn connect_orphans(&self, parent: &str) {
+        let mut parents = vec![parent.to_string()];
+        while let Some(parent) = parents.pop() {
+            let children: Vec<Block> = match self.shared.orphans.lock() {
+                Ok(mut orphans) => {
+                    let hashes: Vec<String> = orphans
+                        .values()
+                        .filter(|block| block.header.prev_hash == parent)
+                        .map(|block| block.header.hash.clone())
+                        .collect();
+                    hashes
+                        .iter()
+                        .filter_map(|hash| orphans.remove(hash))
+                        .collect()
+                }
+                Err(_) => return,
+            };
+            for block in children {
+                let hash = block.header.hash.clone();
+                match self.connect_block(block) {
+                    Ok(_) => {
+                        if !self.is_syncing().unwrap_or(true) {
+                            self.relay(Inventory::Block(hash.clone()), None);
+                        }
+                        parents.push(hash);
+                    }
+                    Err(NetworkError::Chain(ChainError::DuplicateBlock(_))) => {}
+                    Err(e) => warn!("Dropping orphan block {}: {}", hash, e),
+                }
+            }
+        }
+    }
+
+    /// Announces `item` to every peer except `except`.
+    fn relay(&self, item: Inventory, except: Option<SocketAddr>) {
+        let peers: Vec<Arc<Peer>> = match self.shared.peers.lock() {
+            Ok(peers) => peers.values().cloned().collect(),
+            Err(_) => return,
+        };
+        let message = Message::Inv(vec![item]);
+        for peer in peers.iter().filter(|peer| Some(peer.addr()) != except) {
+            if let Err(e) = peer.send(&message) {
+                debug!("Could not relay to {}: {}", peer.addr(), e);
+            }
+        }
+    }
+}
+
+/// Ban score earned by a peer whose message failed with `error`. Failures that could come
+/// from an honest peer with other chain state or another clock earn nothing.
+fn penalty(error: &NetworkError) -> u32 {
+    match error {
+        NetworkError::Chain(ChainError::InvalidBlock {
+            reason: BlockRejection::TimestampTooNew { .. },
+            ..
+        }) => 0,
+        NetworkError::Chain(ChainError::InvalidBlock { .. }) => INVALID_BLOCK_PENALTY,
+        NetworkError::Mempool(MempoolError::Invalid(
+            BlockRejection::InvalidAmount(_)
+            | BlockRejection::InvalidSignature(_)
+            | BlockRejection::EncryptedTransaction(_)
+            | BlockRejection::MisplacedCoinbase(_),
+        )) => MALFORMED_TRANSACTION_PENALTY,
+        _ => 0,
+    }
+}
+
+fn outcome_kind(outcome: &BlockOutcome) -> &'static str {
+    match outcome {
+        BlockOutcome::Extended => "extended",
+        BlockOutcome::Reorganized { .. } => "reorganized",
+        BlockOutcome::SideChain => "side chain",
+    }
+}
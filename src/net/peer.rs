@@ -0,0 +1,97 @@
+use crate::net::message::{Handshake, Message};
+use crate::net::transport::SecureWriter;
+use crate::util::error::NetworkError;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// A connected peer. Reading happens on the connection's own thread; `send` may be called
+/// from any thread.
+pub struct Peer {
+    addr: SocketAddr,
+    inbound: bool,
+    /// Hex encoded public identity key the peer proved in the transport handshake.
+    identity: String,
+    writer: Mutex<SecureWriter>,
+    handshake: Handshake,
+    best_height: AtomicU64,
+    ban_score: AtomicU32,
+}
+
+impl Peer {
+    pub fn new(
+        addr: SocketAddr,
+        inbound: bool,
+        identity: String,
+        writer: SecureWriter,
+        handshake: Handshake,
+    ) -> Self {
+        Peer {
+            addr,
+            inbound,
+            identity,
+            writer: Mutex::new(writer),
+            best_height: AtomicU64::new(handshake.best_height),
+            ban_score: AtomicU32::new(0),
+            handshake,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Whether the peer connected to us rather than the other way round.
+    pub fn is_inbound(&self) -> bool {
+        self.inbound
+    }
+
+    /// Where the peer accepts connections: its IP with the port from its handshake.
+    pub fn listen_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.addr.ip(), self.handshake.listen_port)
+    }
+
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// What the peer stated in its `Version` message.
+    pub fn handshake(&self) -> &Handshake {
+        &self.handshake
+    }
+
+    /// Highest block the peer is known to have: its handshake height, raised by the headers
+    /// and blocks it sends since.
+    pub fn best_height(&self) -> u64 {
+        self.best_height.load(Ordering::Relaxed)
+    }
+
+    pub fn note_height(&self, height: u64) {
+        self.best_height.fetch_max(height, Ordering::Relaxed);
+    }
+
+    pub fn ban_score(&self) -> u32 {
+        self.ban_score.load(Ordering::Relaxed)
+    }
+
+    /// Adds `points` to the peer's ban score and returns the new score.
+    pub fn misbehaved(&self, points: u32) -> u32 {
+        self.ban_score
+            .fetch_add(points, Ordering::Relaxed)
+            .saturating_add(points)
+    }
+
+    pub fn send(&self, message: &Message) -> Result<(), NetworkError> {
+        self.writer
+            .lock()
+            .map_err(|_| NetworkError::Lock)?
+            .send(message)
+    }
+
+    /// Closes both directions, which also ends the peer's reading thread.
+    pub fn disconnect(&self) {
+        if let Ok(writer) = self.writer.lock() {
+            writer.shutdown();
+        }
+    }
+}
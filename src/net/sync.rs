@@ -0,0 +1,133 @@
+use crate::chain::{BlockIndex, Blockchain};
+use crate::net::message::Inventory;
+use crate::net::peer::Peer;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How far past the tip blocks are requested; out-of-order arrivals wait among the orphans,
+/// so this stays below `MAX_ORPHAN_BLOCKS`.
+const DOWNLOAD_WINDOW: usize = 64;
+const MAX_BLOCKS_IN_FLIGHT_PER_PEER: usize = 16;
+/// After this long a requested block is asked of another peer.
+pub const BLOCK_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Fetches the blocks of validated headers from every peer that has them, a window at a time
+/// and spread over the peers with the fewest requests outstanding.
+#[derive(Default)]
+pub struct BlockDownloader {
+    /// Headers whose blocks are missing, oldest first.
+    queue: VecDeque<BlockIndex>,
+    in_flight: HashMap<String, (SocketAddr, Instant)>,
+    progress: Option<ProgressBar>,
+}
+
+impl BlockDownloader {
+    pub fn is_syncing(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Rebuilds the queue after headers were accepted or discarded.
+    pub fn headers_changed(&mut self, chain: &Blockchain) {
+        self.queue = chain.missing_blocks().into();
+        if self.queue.is_empty() {
+            return;
+        }
+        let target = chain.best_header().height;
+        let progress = self.progress.get_or_insert_with(|| {
+            let progress = ProgressBar::new(target);
+            progress.set_style(
+                ProgressStyle::with_template(
+                    "Syncing [{bar:40}] {pos}/{len} blocks ({per_sec}, {eta} left)",
+                )
+                .expect("the progress template is valid")
+                .progress_chars("=> "),
+            );
+            progress
+        });
+        progress.set_length(target);
+        progress.set_position(chain.height());
+    }
+
+    /// A requested block arrived, whether or not it could be connected yet.
+    pub fn received(&mut self, hash: &str) {
+        self.in_flight.remove(hash);
+    }
+
+    /// Drops the blocks now on the chain from the queue and moves the progress bar.
+    pub fn connected(&mut self, chain: &Blockchain) {
+        while self
+            .queue
+            .front()
+            .is_some_and(|entry| chain.contains(&entry.hash))
+        {
+            let entry = self.queue.pop_front().expect("the queue is not empty");
+            self.in_flight.remove(&entry.hash);
+        }
+        if let Some(progress) = &self.progress {
+            progress.set_position(chain.height());
+            if self.queue.is_empty() {
+                progress.finish();
+                self.progress = None;
+            }
+        }
+    }
+
+    /// Assigns the next blocks in the window to peers that claim to have them, re-requesting
+    /// those that timed out or whose peer left. `buffered` tells which blocks arrived but
+    /// still wait for their parent. Returns the `GetData` batches to send.
+    pub fn schedule(
+        &mut self,
+        chain: &Blockchain,
+        peers: &[Arc<Peer>],
+        buffered: impl Fn(&str) -> bool,
+    ) -> Vec<(Arc<Peer>, Vec<Inventory>)> {
+        let now = Instant::now();
+        self.in_flight.retain(|hash, (addr, requested)| {
+            !chain.contains(hash)
+                && now.duration_since(*requested) < BLOCK_DOWNLOAD_TIMEOUT
+                && peers.iter().any(|peer| peer.addr() == *addr)
+        });
+
+        let mut load: HashMap<SocketAddr, usize> = HashMap::new();
+        for (addr, _) in self.in_flight.values() {
+            *load.entry(*addr).or_default() += 1;
+        }
+        let mut batches: HashMap<SocketAddr, Vec<Inventory>> = HashMap::new();
+        for entry in self.queue.iter().take(DOWNLOAD_WINDOW) {
+            if self.in_flight.contains_key(&entry.hash)
+                || chain.contains(&entry.hash)
+                || buffered(&entry.hash)
+            {
+                continue;
+            }
+            let peer = peers
+                .iter()
+                .filter(|peer| peer.best_height() >= entry.height)
+                .filter(|peer| {
+                    load.get(&peer.addr()).copied().unwrap_or(0) < MAX_BLOCKS_IN_FLIGHT_PER_PEER
+                })
+                .min_by_key(|peer| load.get(&peer.addr()).copied().unwrap_or(0));
+            let Some(peer) = peer else {
+                continue;
+            };
+            *load.entry(peer.addr()).or_default() += 1;
+            self.in_flight
+                .insert(entry.hash.clone(), (peer.addr(), now));
+            batches
+                .entry(peer.addr())
+                .or_default()
+                .push(Inventory::Block(entry.hash.clone()));
+        }
+
+        batches
+            .into_iter()
+            .filter_map(|(addr, items)| {
+                let peer = peers.iter().find(|peer| peer.addr() == addr)?;
+                Some((peer.clone(), items))
+            })
+            .collect()
+    }
+}
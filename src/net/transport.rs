@@ -0,0 +1,305 @@
+use crate::net::message::{Message, MAX_MESSAGE_SIZE};
+use crate::util::codec::{Decode, Encode};
+use crate::util::error::{CryptoError, NetworkError};
+use crate::vault::KeyPair;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+
+/// Names the pattern, group, cipher and hash the way Noise protocol names do. BLAKE3 in keyed
+/// mode stands in for HMAC in the key derivation.
+const PROTOCOL_NAME: &[u8] = b"Noise_XX_Ristretto255_ChaChaPoly_BLAKE3";
+const PROLOGUE: &[u8] = b"valtoria p2p v1";
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+/// The largest handshake message: an ephemeral key, an encrypted static key and an empty
+/// encrypted payload.
+const MAX_HANDSHAKE_MESSAGE: usize = 2 * KEY_LEN + 2 * TAG_LEN;
+
+/// One direction of an encrypted link: a ChaCha20-Poly1305 key and the counter used as the
+/// nonce, so every frame is authenticated and replayed or reordered frames fail to decrypt.
+struct CipherState {
+    cipher: ChaCha20Poly1305,
+    nonce: u64,
+}
+
+impl CipherState {
+    fn new(key: &[u8; KEY_LEN]) -> Self {
+        CipherState {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            nonce: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> Result<Nonce, NetworkError> {
+        if self.nonce == u64::MAX {
+            return Err(NetworkError::NonceExhausted);
+        }
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.nonce.to_le_bytes());
+        self.nonce += 1;
+        Ok(*Nonce::from_slice(&nonce))
+    }
+
+    fn encrypt(&mut self, ad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, NetworkError> {
+        let nonce = self.next_nonce()?;
+        self.cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: ad,
+                },
+            )
+            .map_err(|_| CryptoError::EncryptionFailed.into())
+    }
+
+    fn decrypt(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, NetworkError> {
+        let nonce = self.next_nonce()?;
+        self.cipher
+            .decrypt(
+                &nonce,
+                Payload {
+                    msg: ciphertext,
+                    aad: ad,
+                },
+            )
+            .map_err(|_| CryptoError::DecryptionFailed.into())
+    }
+}
+
+/// The handshake transcript hash and chaining key, as in Noise's SymmetricState.
+struct SymmetricState {
+    chaining_key: [u8; KEY_LEN],
+    hash: [u8; KEY_LEN],
+    cipher: Option<CipherState>,
+}
+
+impl SymmetricState {
+    fn new() -> Self {
+        let hash = *blake3::hash(PROTOCOL_NAME).as_bytes();
+        let mut state = SymmetricState {
+            chaining_key: hash,
+            hash,
+            cipher: None,
+        };
+        state.mix_hash(PROLOGUE);
+        state
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.hash);
+        hasher.update(data);
+        self.hash = *hasher.finalize().as_bytes();
+    }
+
+    fn mix_key(&mut self, input: &[u8]) {
+        let (chaining_key, key) = hkdf(&self.chaining_key, input);
+        self.chaining_key = chaining_key;
+        self.cipher = Some(CipherState::new(&key));
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, NetworkError> {
+        let ciphertext = match &mut self.cipher {
+            Some(cipher) => cipher.encrypt(&self.hash, plaintext)?,
+            None => plaintext.to_vec(),
+        };
+        self.mix_hash(&ciphertext);
+        Ok(ciphertext)
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, NetworkError> {
+        let plaintext = match &mut self.cipher {
+            Some(cipher) => cipher.decrypt(&self.hash, ciphertext)?,
+            None => ciphertext.to_vec(),
+        };
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    /// The initiator's sending and receiving ciphers, in that order.
+    fn split(&self) -> (CipherState, CipherState) {
+        let (first, second) = hkdf(&self.chaining_key, &[]);
+        (CipherState::new(&first), CipherState::new(&second))
+    }
+}
+
+fn hkdf(chaining_key: &[u8; KEY_LEN], input: &[u8]) -> ([u8; KEY_LEN], [u8; KEY_LEN]) {
+    let secret = *blake3::keyed_hash(chaining_key, input).as_bytes();
+    let first = *blake3::keyed_hash(&secret, &[1]).as_bytes();
+    let mut second = blake3::Hasher::new_keyed(&secret);
+    second.update(&first).update(&[2]);
+    (first, *second.finalize().as_bytes())
+}
+
+fn dh(private_key: &Scalar, public_key: &RistrettoPoint) -> [u8; KEY_LEN] {
+    (private_key * public_key).compress().to_bytes()
+}
+
+fn parse_key(bytes: &[u8]) -> Result<RistrettoPoint, NetworkError> {
+    let point = CompressedRistretto::from_slice(bytes)
+        .ok()
+        .and_then(|key| key.decompress())
+        .ok_or(NetworkError::InvalidPeerKey)?;
+    // The identity would make every shared secret known in advance.
+    if point == RistrettoPoint::identity() {
+        return Err(NetworkError::InvalidPeerKey);
+    }
+    Ok(point)
+}
+
+fn write_frame(writer: &mut impl Write, payload: &[u8]) -> Result<(), NetworkError> {
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads one `u32` big-endian length-prefixed frame, refusing lengths above `max` before
+/// allocating for them.
+fn read_frame(reader: &mut impl Read, max: usize) -> Result<Vec<u8>, NetworkError> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max {
+        return Err(NetworkError::MessageTooLarge(len));
+    }
+    let mut frame = vec![0u8; len];
+    reader.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+/// Runs the Noise XX handshake over `stream`:
+///
+/// ```text
+/// -> e
+/// <- e, ee, s, es
+/// -> s, se
+/// ```
+///
+/// Both sides learn and authenticate each other's static identity key, which is only ever
+/// sent encrypted, and derive one ChaCha20-Poly1305 key per direction. Returns the encrypted
+/// halves of the link and the peer's identity key.
+pub fn handshake(
+    mut stream: TcpStream,
+    identity: &KeyPair,
+    initiator: bool,
+) -> Result<(SecureReader, SecureWriter, RistrettoPoint), NetworkError> {
+    let mut state = SymmetricState::new();
+    let ephemeral = KeyPair::generate();
+    let ephemeral_public = ephemeral.public_key.compress().to_bytes();
+    let static_public = identity.public_key.compress().to_bytes();
+
+    let (sending, receiving, remote_static) = if initiator {
+        state.mix_hash(&ephemeral_public);
+        let mut first = ephemeral_public.to_vec();
+        first.extend(state.encrypt_and_hash(&[])?);
+        write_frame(&mut stream, &first)?;
+
+        let second = read_frame(&mut stream, MAX_HANDSHAKE_MESSAGE)?;
+        if second.len() != MAX_HANDSHAKE_MESSAGE {
+            return Err(NetworkError::MalformedHandshake);
+        }
+        let (remote_ephemeral, rest) = second.split_at(KEY_LEN);
+        let remote_ephemeral_point = parse_key(remote_ephemeral)?;
+        state.mix_hash(remote_ephemeral);
+        state.mix_key(&dh(&ephemeral.private_key, &remote_ephemeral_point));
+        let (encrypted_static, payload) = rest.split_at(KEY_LEN + TAG_LEN);
+        let remote_static = parse_key(&state.decrypt_and_hash(encrypted_static)?)?;
+        state.mix_key(&dh(&ephemeral.private_key, &remote_static));
+        state.decrypt_and_hash(payload)?;
+
+        let mut third = state.encrypt_and_hash(&static_public)?;
+        state.mix_key(&dh(&identity.private_key, &remote_ephemeral_point));
+        third.extend(state.encrypt_and_hash(&[])?);
+        write_frame(&mut stream, &third)?;
+
+        let (sending, receiving) = state.split();
+        (sending, receiving, remote_static)
+    } else {
+        let first = read_frame(&mut stream, MAX_HANDSHAKE_MESSAGE)?;
+        if first.len() != KEY_LEN {
+            return Err(NetworkError::MalformedHandshake);
+        }
+        let remote_ephemeral = parse_key(&first)?;
+        state.mix_hash(&first);
+        state.decrypt_and_hash(&[])?;
+
+        state.mix_hash(&ephemeral_public);
+        let mut second = ephemeral_public.to_vec();
+        state.mix_key(&dh(&ephemeral.private_key, &remote_ephemeral));
+        second.extend(state.encrypt_and_hash(&static_public)?);
+        state.mix_key(&dh(&identity.private_key, &remote_ephemeral));
+        second.extend(state.encrypt_and_hash(&[])?);
+        write_frame(&mut stream, &second)?;
+
+        let third = read_frame(&mut stream, MAX_HANDSHAKE_MESSAGE)?;
+        if third.len() != KEY_LEN + 2 * TAG_LEN {
+            return Err(NetworkError::MalformedHandshake);
+        }
+        let (encrypted_static, payload) = third.split_at(KEY_LEN + TAG_LEN);
+        let remote_static = parse_key(&state.decrypt_and_hash(encrypted_static)?)?;
+        state.mix_key(&dh(&ephemeral.private_key, &remote_static));
+        state.decrypt_and_hash(payload)?;
+
+        let (receiving, sending) = state.split();
+        (sending, receiving, remote_static)
+    };
+
+    let reader = SecureReader {
+        stream: stream.try_clone()?,
+        cipher: receiving,
+    };
+    let writer = SecureWriter {
+        stream,
+        cipher: sending,
+    };
+    Ok((reader, writer, remote_static))
+}
+
+/// The receiving half of an encrypted link.
+pub struct SecureReader {
+    stream: TcpStream,
+    cipher: CipherState,
+}
+
+impl SecureReader {
+    /// Reads, authenticates and decodes the next message. Any tampering fails decryption.
+    pub fn receive(&mut self) -> Result<Message, NetworkError> {
+        let frame = read_frame(&mut self.stream, MAX_MESSAGE_SIZE + TAG_LEN)?;
+        let payload = self.cipher.decrypt(&[], &frame)?;
+        Ok(Message::decode(&payload)?)
+    }
+
+    pub fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+}
+
+/// The sending half of an encrypted link.
+pub struct SecureWriter {
+    stream: TcpStream,
+    cipher: CipherState,
+}
+
+impl SecureWriter {
+    /// Encrypts `message` into one frame: a `u32` big-endian length, then the ciphertext and
+    /// its tag.
+    pub fn send(&mut self, message: &Message) -> Result<(), NetworkError> {
+        let payload = message.encode();
+        if payload.len() > MAX_MESSAGE_SIZE {
+            return Err(NetworkError::MessageTooLarge(payload.len()));
+        }
+        let frame = self.cipher.encrypt(&[], &payload)?;
+        write_frame(&mut self.stream, &frame)
+    }
+
+    pub fn shutdown(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
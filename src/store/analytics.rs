@@ -0,0 +1,162 @@
+use super::{BatchOp, StorageBatch, StorageKind};
+use crate::util::error::StorageError;
+use rocksdb::MergeOperands;
+
+const METRIC_PREFIX: &str = "metric";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    AccountsCreated,
+    Transactions,
+    Volume,
+    Fees,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    Hour,
+    Day,
+    Total,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricPoint {
+    pub bucket_start: u64,
+    pub value: f64,
+}
+
+impl Metric {
+    pub fn all() -> [Metric; 4] {
+        [
+            Metric::AccountsCreated,
+            Metric::Transactions,
+            Metric::Volume,
+            Metric::Fees,
+        ]
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Metric::AccountsCreated => "accounts_created",
+            Metric::Transactions => "transactions",
+            Metric::Volume => "volume",
+            Metric::Fees => "fees",
+        }
+    }
+}
+
+impl Bucket {
+    pub fn name(&self) -> &str {
+        match self {
+            Bucket::Hour => "hour",
+            Bucket::Day => "day",
+            Bucket::Total => "total",
+        }
+    }
+
+    pub fn start(&self, timestamp: u64) -> u64 {
+        match self {
+            Bucket::Hour => timestamp - timestamp % 3_600,
+            Bucket::Day => timestamp - timestamp % 86_400,
+            Bucket::Total => 0,
+        }
+    }
+}
+
+/// `metric:<name>:<bucket>:` followed by the big-endian bucket start, so a bucket's keys
+/// sort chronologically.
+pub fn metric_key(metric: Metric, bucket: Bucket, bucket_start: u64) -> Vec<u8> {
+    let mut key = metric_prefix(metric, bucket);
+    key.extend_from_slice(&bucket_start.to_be_bytes());
+    key
+}
+
+pub(crate) fn metric_prefix(metric: Metric, bucket: Bucket) -> Vec<u8> {
+    format!("{}:{}:{}:", METRIC_PREFIX, metric.name(), bucket.name()).into_bytes()
+}
+
+pub(crate) fn bucket_start_from_key(key: &[u8]) -> Option<u64> {
+    let start = key.len().checked_sub(8)?;
+    let bytes: [u8; 8] = key[start..].try_into().ok()?;
+    Some(u64::from_be_bytes(bytes))
+}
+
+/// Adds `value` to the hourly, daily and all-time buckets of `metric`.
+pub(crate) fn record_metric(
+    batch: &mut StorageBatch,
+    metric: Metric,
+    value: f64,
+    timestamp: u64,
+) -> Result<(), StorageError> {
+    let operand = bincode::serialize(&value)?;
+    for bucket in [Bucket::Hour, Bucket::Day, Bucket::Total] {
+        let key = metric_key(metric, bucket, bucket.start(timestamp));
+        batch.merge(StorageKind::Analytics.name(), &key, &operand);
+    }
+    Ok(())
+}
+
+/// Appends a per-column-family counter merge for every insert in the batch. Inserts fail on
+/// existing keys, so the counters count records created and not updates to them.
+pub(crate) fn count_writes(batch: &mut StorageBatch) -> Result<(), StorageError> {
+    let analytics = StorageKind::Analytics.name();
+    let mut counters: Vec<(String, i64)> = Vec::new();
+    for op in batch.ops() {
+        match op {
+            BatchOp::Insert { cf, .. } if cf != analytics => {
+                match counters.iter_mut().find(|(name, _)| name == cf) {
+                    Some((_, count)) => *count += 1,
+                    None => counters.push((cf.clone(), 1)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (cf, count) in counters {
+        batch.merge(analytics, cf.as_bytes(), &bincode::serialize(&count)?);
+    }
+    Ok(())
+}
+
+/// Whether `key` is one of the per-column-family counters kept by `count_writes`.
+pub(crate) fn is_write_counter(key: &[u8]) -> bool {
+    StorageKind::all()
+        .iter()
+        .any(|kind| kind.name().as_bytes() == key)
+}
+
+/// Sums counter operands into the existing value. Metric keys hold `f64` sums, plain
+/// column family counters hold `i64` counts.
+pub fn merge_values<'a, I>(key: &[u8], existing: Option<&[u8]>, operands: I) -> Option<Vec<u8>>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    if key.starts_with(METRIC_PREFIX.as_bytes()) {
+        let mut total: f64 = match existing {
+            Some(data) => bincode::deserialize(data).ok()?,
+            None => 0.0,
+        };
+        for operand in operands {
+            total += bincode::deserialize::<f64>(operand).ok()?;
+        }
+        bincode::serialize(&total).ok()
+    } else {
+        let mut total: i64 = match existing {
+            Some(data) => bincode::deserialize(data).ok()?,
+            None => 0,
+        };
+        for operand in operands {
+            total += bincode::deserialize::<i64>(operand).ok()?;
+        }
+        bincode::serialize(&total).ok()
+    }
+}
+
+pub fn merge_counter(
+    key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    merge_values(key, existing, operands.iter())
+}
@@ -0,0 +1,68 @@
+use crate::util::error::StorageError;
+use rocksdb::backup::{BackupEngine, BackupEngineInfo, BackupEngineOptions, RestoreOptions};
+use rocksdb::Env;
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub id: u32,
+    pub timestamp: i64,
+    pub size: u64,
+    pub num_files: u32,
+}
+
+impl From<&BackupEngineInfo> for BackupInfo {
+    fn from(info: &BackupEngineInfo) -> Self {
+        BackupInfo {
+            id: info.backup_id,
+            timestamp: info.timestamp,
+            size: info.size,
+            num_files: info.num_files,
+        }
+    }
+}
+
+pub(crate) fn open_engine<P: AsRef<Path>>(backup_dir: P) -> Result<BackupEngine, StorageError> {
+    let opts = BackupEngineOptions::new(backup_dir)?;
+    let env = Env::new()?;
+    Ok(BackupEngine::open(&opts, &env)?)
+}
+
+pub fn list_backups<P: AsRef<Path>>(backup_dir: P) -> Result<Vec<BackupInfo>, StorageError> {
+    let engine = open_engine(backup_dir)?;
+    Ok(engine
+        .get_backup_info()
+        .iter()
+        .map(BackupInfo::from)
+        .collect())
+}
+
+pub fn verify_backup<P: AsRef<Path>>(backup_dir: P, backup_id: u32) -> Result<(), StorageError> {
+    let engine = open_engine(backup_dir)?;
+    engine.verify_backup(backup_id)?;
+    Ok(())
+}
+
+/// Restores a backup (the latest one if `backup_id` is `None`) into `db_path`. The database
+/// must not be open while restoring.
+pub fn restore_backup<P: AsRef<Path>, D: AsRef<Path>>(
+    backup_dir: P,
+    backup_id: Option<u32>,
+    db_path: D,
+) -> Result<(), StorageError> {
+    let mut engine = open_engine(backup_dir)?;
+    let opts = RestoreOptions::default();
+    let db_path = db_path.as_ref();
+    match backup_id {
+        Some(backup_id) => engine.restore_from_backup(db_path, db_path, &opts, backup_id)?,
+        None => engine.restore_from_latest_backup(db_path, db_path, &opts)?,
+    }
+    Ok(())
+}
+
+pub fn purge_backups<P: AsRef<Path>>(backup_dir: P, keep: usize) -> Result<(), StorageError> {
+    let mut engine = open_engine(backup_dir)?;
+    engine.purge_old_backups(keep)?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use super::analytics::{self, Metric};
+use crate::util::error::StorageError;
+
+#[derive(Debug, Clone)]
+pub enum BatchOp {
+    Put {
+        cf: String,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Insert {
+        cf: String,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        cf: String,
+        key: Vec<u8>,
+    },
+    Merge {
+        cf: String,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+}
+
+impl BatchOp {
+    pub fn cf(&self) -> &str {
+        match self {
+            BatchOp::Put { cf, .. }
+            | BatchOp::Insert { cf, .. }
+            | BatchOp::Delete { cf, .. }
+            | BatchOp::Merge { cf, .. } => cf,
+        }
+    }
+}
+
+/// A group of writes across column families that commits or fails as a whole.
+/// `insert` fails the entire batch with `RecordExists` if the key is already present.
+#[derive(Debug, Clone, Default)]
+pub struct StorageBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl StorageBatch {
+    pub fn new() -> Self {
+        StorageBatch { ops: Vec::new() }
+    }
+
+    pub fn put(&mut self, cf: &str, key: &[u8], value: &[u8]) -> &mut Self {
+        self.ops.push(BatchOp::Put {
+            cf: cf.to_string(),
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        self
+    }
+
+    pub fn insert(&mut self, cf: &str, key: &[u8], value: &[u8]) -> &mut Self {
+        self.ops.push(BatchOp::Insert {
+            cf: cf.to_string(),
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        self
+    }
+
+    pub fn delete(&mut self, cf: &str, key: &[u8]) -> &mut Self {
+        self.ops.push(BatchOp::Delete {
+            cf: cf.to_string(),
+            key: key.to_vec(),
+        });
+        self
+    }
+
+    pub fn merge(&mut self, cf: &str, key: &[u8], value: &[u8]) -> &mut Self {
+        self.ops.push(BatchOp::Merge {
+            cf: cf.to_string(),
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+        self
+    }
+
+    pub fn record_metric(
+        &mut self,
+        metric: Metric,
+        value: f64,
+        timestamp: u64,
+    ) -> Result<&mut Self, StorageError> {
+        analytics::record_metric(self, metric, value, timestamp)?;
+        Ok(self)
+    }
+
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
@@ -0,0 +1,359 @@
+use super::analytics;
+use super::scan::{above_lower, below_upper};
+use super::{
+    BackupInfo, BatchOp, KeyValue, KeyValuePage, KvStore, ScanOptions, StorageBatch, StorageKind,
+};
+use crate::util::error::StorageError;
+use crate::vault::Crypto;
+use argon2::Argon2;
+use bincode;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroizing;
+
+/// Plaintext header in the meta column family holding what's needed to re-derive the key.
+pub const ENCRYPTION_HEADER_KEY: &[u8] = b"storage_encryption";
+
+const HEADER_VERSION: u16 = 1;
+const CHECK_PLAINTEXT: &[u8] = b"valtoria storage";
+const KEY_NAME_CONTEXT: &str = "valtoria storage key names v1";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptionHeader {
+    version: u16,
+    salt: [u8; 16],
+    encrypt_keys: bool,
+    /// `CHECK_PLAINTEXT` encrypted with the master key, to reject a wrong passphrase up front.
+    check: Vec<u8>,
+}
+
+/// Wraps another backend and encrypts every value with a master key derived from a
+/// passphrase. With `encrypt_keys` the stored keys are keyed hashes and the plaintext key
+/// travels inside the encrypted value; ordered scans then sort in memory.
+///
+/// The per-column-family write counters kept by the inner backend stay in plaintext, since
+/// they are merged by the backend itself and only reveal record counts.
+pub struct EncryptedStore {
+    inner: Arc<dyn KvStore>,
+    master_key: Zeroizing<String>,
+    name_key: Zeroizing<[u8; 32]>,
+    encrypt_keys: bool,
+    write_lock: Mutex<()>,
+}
+
+impl EncryptedStore {
+    /// Unlocks an encrypted database, or sets up encryption on an empty one. `encrypt_keys`
+    /// only applies to a new database; afterwards the stored header decides.
+    pub fn open(
+        inner: Arc<dyn KvStore>,
+        passphrase: &str,
+        encrypt_keys: bool,
+    ) -> Result<Self, StorageError> {
+        let meta = StorageKind::Meta.name();
+        let header = match inner.get(meta, ENCRYPTION_HEADER_KEY) {
+            Ok(data) => bincode::deserialize::<EncryptionHeader>(&data)?,
+            Err(StorageError::NotFound) => {
+                if !Self::is_empty(inner.as_ref())? {
+                    return Err(StorageError::Unencrypted);
+                }
+                let mut salt = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut salt);
+                let master_key = derive_master_key(passphrase, &salt)?;
+                let check =
+                    Crypto::encrypt(CHECK_PLAINTEXT.to_vec(), Some(master_key.to_string()))?;
+                let header = EncryptionHeader {
+                    version: HEADER_VERSION,
+                    salt,
+                    encrypt_keys,
+                    check: check.data,
+                };
+                inner.put(
+                    meta,
+                    ENCRYPTION_HEADER_KEY,
+                    &bincode::serialize(&header)?,
+                    true,
+                )?;
+                header
+            }
+            Err(e) => return Err(e),
+        };
+
+        let master_key = derive_master_key(passphrase, &header.salt)?;
+        match Crypto::decrypt(header.check, &master_key) {
+            Ok(check) if check.data == CHECK_PLAINTEXT => {}
+            _ => return Err(StorageError::InvalidPassphrase),
+        }
+
+        let master_bytes = Zeroizing::new(
+            hex::decode(master_key.as_str()).map_err(|_| StorageError::InvalidPassphrase)?,
+        );
+        let name_key = Zeroizing::new(blake3::derive_key(KEY_NAME_CONTEXT, &master_bytes));
+
+        Ok(EncryptedStore {
+            inner,
+            master_key,
+            name_key,
+            encrypt_keys: header.encrypt_keys,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn is_encrypted(store: &dyn KvStore) -> Result<bool, StorageError> {
+        store.exists(StorageKind::Meta.name(), ENCRYPTION_HEADER_KEY)
+    }
+
+    fn is_empty(store: &dyn KvStore) -> Result<bool, StorageError> {
+        for kind in StorageKind::all() {
+            if !store
+                .scan(kind.name(), &ScanOptions::new(1))?
+                .items
+                .is_empty()
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Keys the inner backend reads or merges itself and which therefore stay in plaintext.
+    fn is_plaintext(cf: &str, key: &[u8]) -> bool {
+        (cf == StorageKind::Analytics.name() && analytics::is_write_counter(key))
+            || Self::is_header(cf, key)
+    }
+
+    fn is_header(cf: &str, key: &[u8]) -> bool {
+        cf == StorageKind::Meta.name() && key == ENCRYPTION_HEADER_KEY
+    }
+
+    fn stored_key(&self, cf: &str, key: &[u8]) -> Vec<u8> {
+        if !self.encrypt_keys || Self::is_plaintext(cf, key) {
+            return key.to_vec();
+        }
+        let mut hasher = blake3::Hasher::new_keyed(&self.name_key);
+        hasher.update(cf.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(key);
+        hasher.finalize().as_bytes().to_vec()
+    }
+
+    fn seal(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let plaintext = if self.encrypt_keys {
+            bincode::serialize(&(key, value))?
+        } else {
+            value.to_vec()
+        };
+        let encrypted = Crypto::encrypt(plaintext, Some(self.master_key.to_string()))?;
+        Ok(encrypted.data)
+    }
+
+    fn open_value(&self, value: Vec<u8>) -> Result<Vec<u8>, StorageError> {
+        Ok(Crypto::decrypt(value, &self.master_key)?.data)
+    }
+
+    /// Turns a stored entry back into its plaintext key and value.
+    fn open_entry(&self, cf: &str, key: Vec<u8>, value: Vec<u8>) -> Result<KeyValue, StorageError> {
+        if Self::is_plaintext(cf, &key) {
+            return Ok((key, value));
+        }
+        let plaintext = self.open_value(value)?;
+        if self.encrypt_keys {
+            Ok(bincode::deserialize(&plaintext)?)
+        } else {
+            Ok((key, plaintext))
+        }
+    }
+
+    fn read(&self, cf: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        match self.inner.get(cf, &self.stored_key(cf, key)) {
+            Ok(value) if Self::is_plaintext(cf, key) => Ok(Some(value)),
+            Ok(value) => Ok(Some(self.open_entry(cf, key.to_vec(), value)?.1)),
+            Err(StorageError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Every plaintext entry of `cf` in key order, minus the encryption header.
+    fn sorted_entries(&self, cf: &str) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, StorageError> {
+        let mut entries = BTreeMap::new();
+        let mut cursor = None;
+        loop {
+            let page = self
+                .inner
+                .scan(cf, &ScanOptions::new(1_000).after(cursor))?;
+            for (key, value) in page.items {
+                if Self::is_header(cf, &key) {
+                    continue;
+                }
+                let (key, value) = self.open_entry(cf, key, value)?;
+                entries.insert(key, value);
+            }
+
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                return Ok(entries);
+            }
+        }
+    }
+}
+
+fn derive_master_key(passphrase: &str, salt: &[u8]) -> Result<Zeroizing<String>, StorageError> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|_| StorageError::InvalidPassphrase)?;
+    Ok(Zeroizing::new(hex::encode(key.as_ref())))
+}
+
+impl KvStore for EncryptedStore {
+    fn put(
+        &self,
+        cf: &str,
+        key: &[u8],
+        value: &[u8],
+        check_exist: bool,
+    ) -> Result<(), StorageError> {
+        let mut batch = StorageBatch::new();
+        if check_exist {
+            batch.insert(cf, key, value);
+        } else {
+            batch.put(cf, key, value);
+        }
+        self.write(batch)
+    }
+
+    /// Merges can't run on ciphertext, so merges into encrypted keys are resolved here as
+    /// read-modify-write puts while holding the write lock. Each takes the place of its merge,
+    /// so later ops on the same key still apply after it.
+    fn write(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        let _guard = self.write_lock.lock().map_err(|_| StorageError::Lock)?;
+
+        let mut encrypted = StorageBatch::new();
+        // Plaintext value of each key as of the ops so far, so merges build on earlier ops.
+        let mut staged: HashMap<(&str, &[u8]), Option<Vec<u8>>> = HashMap::new();
+
+        for op in batch.ops() {
+            match op {
+                BatchOp::Put { cf, key, value } => {
+                    encrypted.put(cf, &self.stored_key(cf, key), &self.seal(key, value)?);
+                    staged.insert((cf, key), Some(value.clone()));
+                }
+                BatchOp::Insert { cf, key, value } => {
+                    encrypted.insert(cf, &self.stored_key(cf, key), &self.seal(key, value)?);
+                    staged.insert((cf, key), Some(value.clone()));
+                }
+                BatchOp::Delete { cf, key } => {
+                    encrypted.delete(cf, &self.stored_key(cf, key));
+                    staged.insert((cf, key), None);
+                }
+                BatchOp::Merge { cf, key, value } if Self::is_plaintext(cf, key) => {
+                    encrypted.merge(cf, key, value);
+                }
+                BatchOp::Merge { cf, key, value } => {
+                    let current = match staged.remove(&(cf.as_str(), key.as_slice())) {
+                        Some(current) => current,
+                        None => self.read(cf, key)?,
+                    };
+                    let value = analytics::merge_values(key, current.as_deref(), [&value[..]])
+                        .ok_or_else(|| StorageError::Merge(hex::encode(key)))?;
+                    encrypted.put(cf, &self.stored_key(cf, key), &self.seal(key, &value)?);
+                    staged.insert((cf, key), Some(value));
+                }
+            }
+        }
+
+        self.inner.write(encrypted)
+    }
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.read(cf, key)?.ok_or(StorageError::NotFound)
+    }
+
+    fn exists(&self, cf: &str, key: &[u8]) -> Result<bool, StorageError> {
+        self.inner.exists(cf, &self.stored_key(cf, key))
+    }
+
+    fn batch_put(&self, cf: &str, batch: Vec<(&[u8], &[u8])>) -> Result<(), StorageError> {
+        let mut write_batch = StorageBatch::new();
+        for (key, value) in batch {
+            write_batch.put(cf, key, value);
+        }
+        self.write(write_batch)
+    }
+
+    fn batch_get(
+        &self,
+        cf: &str,
+        start: usize,
+        limit: usize,
+    ) -> Result<Vec<KeyValue>, StorageError> {
+        if self.encrypt_keys {
+            return Ok(self
+                .sorted_entries(cf)?
+                .into_iter()
+                .skip(start)
+                .take(limit)
+                .collect());
+        }
+
+        self.inner
+            .batch_get(cf, start, limit)?
+            .into_iter()
+            .filter(|(key, _)| !Self::is_header(cf, key))
+            .map(|(key, value)| self.open_entry(cf, key, value))
+            .collect()
+    }
+
+    fn scan(&self, cf: &str, options: &ScanOptions) -> Result<KeyValuePage, StorageError> {
+        if !self.encrypt_keys {
+            let page = self.inner.scan(cf, options)?;
+            let items = page
+                .items
+                .into_iter()
+                .filter(|(key, _)| !Self::is_header(cf, key))
+                .map(|(key, value)| self.open_entry(cf, key, value))
+                .collect::<Result<Vec<KeyValue>, StorageError>>()?;
+            return Ok(KeyValuePage {
+                items,
+                next_cursor: page.next_cursor,
+            });
+        }
+
+        // Hashed keys carry no order, so range, prefix and cursor are applied to the
+        // decrypted keys of the whole column family.
+        let (lower, upper) = options.bounds();
+        let entries = self.sorted_entries(cf)?;
+        let in_range =
+            |(key, _): &(Vec<u8>, Vec<u8>)| above_lower(key, &lower) && below_upper(key, &upper);
+        let items: Vec<KeyValue> = if options.reverse {
+            entries
+                .into_iter()
+                .rev()
+                .filter(in_range)
+                .take(options.limit + 1)
+                .collect()
+        } else {
+            entries
+                .into_iter()
+                .filter(in_range)
+                .take(options.limit + 1)
+                .collect()
+        };
+
+        Ok(KeyValuePage::from_scan(items, options.limit))
+    }
+
+    fn get_analytics(&self, key: &[u8]) -> Result<i64, StorageError> {
+        self.inner.get_analytics(key)
+    }
+
+    fn create_checkpoint(&self, path: &Path) -> Result<(), StorageError> {
+        self.inner.create_checkpoint(path)
+    }
+
+    fn create_backup(&self, backup_dir: &Path) -> Result<BackupInfo, StorageError> {
+        self.inner.create_backup(backup_dir)
+    }
+}
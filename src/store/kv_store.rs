@@ -0,0 +1,44 @@
+use super::{BackupInfo, KeyValuePage, ScanOptions, StorageBatch};
+use crate::util::error::StorageError;
+use std::path::Path;
+
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+
+pub trait KvStore: Send + Sync {
+    fn put(
+        &self,
+        cf: &str,
+        key: &[u8],
+        value: &[u8],
+        check_exist: bool,
+    ) -> Result<(), StorageError>;
+
+    fn write(&self, batch: StorageBatch) -> Result<(), StorageError>;
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Vec<u8>, StorageError>;
+
+    fn exists(&self, cf: &str, key: &[u8]) -> Result<bool, StorageError>;
+
+    fn batch_put(&self, cf: &str, batch: Vec<(&[u8], &[u8])>) -> Result<(), StorageError>;
+
+    fn batch_get(
+        &self,
+        cf: &str,
+        start: usize,
+        limit: usize,
+    ) -> Result<Vec<KeyValue>, StorageError>;
+
+    fn scan(&self, cf: &str, options: &ScanOptions) -> Result<KeyValuePage, StorageError>;
+
+    fn get_analytics(&self, key: &[u8]) -> Result<i64, StorageError>;
+
+    /// Writes a consistent, openable copy of the database to `path`.
+    fn create_checkpoint(&self, _path: &Path) -> Result<(), StorageError> {
+        Err(StorageError::Unsupported("checkpoints"))
+    }
+
+    /// Adds an incremental backup of the database to `backup_dir`.
+    fn create_backup(&self, _backup_dir: &Path) -> Result<BackupInfo, StorageError> {
+        Err(StorageError::Unsupported("backups"))
+    }
+}
@@ -0,0 +1,188 @@
+use super::analytics;
+use super::scan::is_empty_range;
+use super::{BatchOp, KeyValue, KeyValuePage, KvStore, ScanOptions, StorageBatch, StorageKind};
+use crate::util::error::StorageError;
+use bincode;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+type ColumnFamily = BTreeMap<Vec<u8>, Vec<u8>>;
+
+pub struct MemoryStore {
+    cfs: RwLock<BTreeMap<String, ColumnFamily>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        let cfs = StorageKind::all()
+            .iter()
+            .map(|kind| (kind.name().to_string(), ColumnFamily::new()))
+            .collect();
+
+        MemoryStore {
+            cfs: RwLock::new(cfs),
+        }
+    }
+
+    fn with_cf<F, R>(&self, cf: &str, f: F) -> Result<R, StorageError>
+    where
+        F: FnOnce(&ColumnFamily) -> Result<R, StorageError>,
+    {
+        let cfs = self.cfs.read().map_err(|_| StorageError::Lock)?;
+        let cf_map = cfs
+            .get(cf)
+            .ok_or_else(|| StorageError::ColumnFamilyNotFound(cf.to_string()))?;
+        f(cf_map)
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KvStore for MemoryStore {
+    fn put(
+        &self,
+        cf: &str,
+        key: &[u8],
+        value: &[u8],
+        check_exist: bool,
+    ) -> Result<(), StorageError> {
+        let mut batch = StorageBatch::new();
+        if check_exist {
+            batch.insert(cf, key, value);
+        } else {
+            batch.put(cf, key, value);
+        }
+        self.write(batch)
+    }
+
+    fn write(&self, mut batch: StorageBatch) -> Result<(), StorageError> {
+        analytics::count_writes(&mut batch)?;
+
+        let mut cfs = self.cfs.write().map_err(|_| StorageError::Lock)?;
+
+        // Stage every op against the current state first so a failing op leaves nothing behind.
+        let mut staged: BTreeMap<(&str, &[u8]), Option<Vec<u8>>> = BTreeMap::new();
+        for op in batch.ops() {
+            let cf_map = cfs
+                .get(op.cf())
+                .ok_or_else(|| StorageError::ColumnFamilyNotFound(op.cf().to_string()))?;
+            match op {
+                BatchOp::Insert { cf, key, value } => {
+                    let current = match staged.get(&(cf.as_str(), key.as_slice())) {
+                        Some(staged_value) => staged_value.is_some(),
+                        None => cf_map.contains_key(key),
+                    };
+                    if current {
+                        return Err(StorageError::RecordExists);
+                    }
+                    staged.insert((cf, key), Some(value.clone()));
+                }
+                BatchOp::Put { cf, key, value } => {
+                    staged.insert((cf, key), Some(value.clone()));
+                }
+                BatchOp::Delete { cf, key } => {
+                    staged.insert((cf, key), None);
+                }
+                BatchOp::Merge { cf, key, value } => {
+                    let current = match staged.get(&(cf.as_str(), key.as_slice())) {
+                        Some(staged_value) => staged_value.clone(),
+                        None => cf_map.get(key).cloned(),
+                    };
+                    let merged = analytics::merge_values(key, current.as_deref(), [&value[..]])
+                        .ok_or_else(|| StorageError::Merge(hex::encode(key)))?;
+                    staged.insert((cf, key), Some(merged));
+                }
+            }
+        }
+
+        for ((cf, key), value) in staged {
+            let cf_map = cfs.entry(cf.to_string()).or_default();
+            match value {
+                Some(value) => {
+                    cf_map.insert(key.to_vec(), value);
+                }
+                None => {
+                    cf_map.remove(key);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.with_cf(cf, |cf_map| {
+            cf_map.get(key).cloned().ok_or(StorageError::NotFound)
+        })
+    }
+
+    fn exists(&self, cf: &str, key: &[u8]) -> Result<bool, StorageError> {
+        self.with_cf(cf, |cf_map| Ok(cf_map.contains_key(key)))
+    }
+
+    fn batch_put(&self, cf: &str, batch: Vec<(&[u8], &[u8])>) -> Result<(), StorageError> {
+        let mut write_batch = StorageBatch::new();
+        for (key, value) in batch {
+            write_batch.put(cf, key, value);
+        }
+        self.write(write_batch)
+    }
+
+    fn batch_get(
+        &self,
+        cf: &str,
+        start: usize,
+        limit: usize,
+    ) -> Result<Vec<KeyValue>, StorageError> {
+        self.with_cf(cf, |cf_map| {
+            let result: Vec<KeyValue> = cf_map
+                .iter()
+                .skip(start)
+                .take(limit)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+
+            Ok(result)
+        })
+    }
+
+    fn scan(&self, cf: &str, options: &ScanOptions) -> Result<KeyValuePage, StorageError> {
+        let (lower, upper) = options.bounds();
+
+        self.with_cf(cf, |cf_map| {
+            if is_empty_range(&lower, &upper) {
+                return Ok(KeyValuePage::from_scan(Vec::new(), options.limit));
+            }
+
+            let range = cf_map.range((lower, upper));
+            let items: Vec<KeyValue> = if options.reverse {
+                range
+                    .rev()
+                    .take(options.limit + 1)
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            } else {
+                range
+                    .take(options.limit + 1)
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            };
+
+            Ok(KeyValuePage::from_scan(items, options.limit))
+        })
+    }
+
+    fn get_analytics(&self, key: &[u8]) -> Result<i64, StorageError> {
+        let cf = StorageKind::Analytics.name();
+
+        self.with_cf(cf, |cf_map| {
+            let data = cf_map.get(key).ok_or(StorageError::AnalyticsNotFound)?;
+            let analytics: i64 = bincode::deserialize(data)?;
+            Ok(analytics)
+        })
+    }
+}
@@ -1,5 +1,23 @@
+pub mod analytics;
+pub mod backup;
+pub mod batch;
+pub mod encrypted_store;
+pub mod kv_store;
+pub mod memory_store;
+pub mod rocksdb_store;
+pub mod scan;
+pub mod schema;
 pub mod storage;
 pub mod storage_kind;
 
+pub use analytics::{Bucket, Metric, MetricPoint};
+pub use backup::BackupInfo;
+pub use batch::{BatchOp, StorageBatch};
+pub use encrypted_store::EncryptedStore;
+pub use kv_store::{KeyValue, KvStore};
+pub use memory_store::MemoryStore;
+pub use rocksdb::Options;
+pub use rocksdb_store::RocksDbStore;
+pub use scan::{Cursor, KeyValuePage, Page, ScanOptions, ScanRange};
 pub use storage::Storage;
 pub use storage_kind::StorageKind;
@@ -0,0 +1,248 @@
+use super::analytics;
+use super::backup;
+use super::scan::{above_lower, below_upper};
+use super::{
+    BackupInfo, BatchOp, KeyValue, KeyValuePage, KvStore, ScanOptions, StorageBatch, StorageKind,
+};
+use crate::util::error::StorageError;
+use bincode;
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{
+    ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, Direction, IteratorMode, Options,
+    WriteBatch, DB,
+};
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+pub struct RocksDbStore {
+    db: Arc<RwLock<DB>>,
+    write_lock: Mutex<()>,
+}
+
+impl RocksDbStore {
+    fn with_cf_handle<F, R>(&self, cf: &str, f: F) -> Result<R, StorageError>
+    where
+        F: FnOnce(&DB, &ColumnFamily) -> Result<R, StorageError>,
+    {
+        let db = self.db.read().map_err(|_| StorageError::Lock)?;
+        let cf_handle = db
+            .cf_handle(cf)
+            .ok_or_else(|| StorageError::ColumnFamilyNotFound(cf.to_string()))?;
+        f(&db, cf_handle)
+    }
+
+    fn cf_handle<'a>(db: &'a DB, cf: &str) -> Result<&'a ColumnFamily, StorageError> {
+        db.cf_handle(cf)
+            .ok_or_else(|| StorageError::ColumnFamilyNotFound(cf.to_string()))
+    }
+
+    pub fn default_options() -> Options {
+        let mut opts = Options::default();
+        opts.set_compression_type(DBCompressionType::Snappy);
+        opts.create_if_missing(true);
+        opts.set_level_compaction_dynamic_level_bytes(true);
+        opts
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, mut opts: Options) -> Result<Self, StorageError> {
+        opts.create_missing_column_families(true);
+
+        let cfs: Vec<ColumnFamilyDescriptor> = StorageKind::all()
+            .iter()
+            .map(|kind| {
+                let mut cf_opts = Options::default();
+                if let StorageKind::Analytics = kind {
+                    cf_opts.set_merge_operator_associative("counter", analytics::merge_counter);
+                }
+                ColumnFamilyDescriptor::new(kind.name(), cf_opts)
+            })
+            .collect();
+
+        let db = DB::open_cf_descriptors(&opts, path, cfs)?;
+        Ok(RocksDbStore {
+            db: Arc::new(RwLock::new(db)),
+            write_lock: Mutex::new(()),
+        })
+    }
+}
+
+impl KvStore for RocksDbStore {
+    fn put(
+        &self,
+        cf: &str,
+        key: &[u8],
+        value: &[u8],
+        check_exist: bool,
+    ) -> Result<(), StorageError> {
+        let mut batch = StorageBatch::new();
+        if check_exist {
+            batch.insert(cf, key, value);
+        } else {
+            batch.put(cf, key, value);
+        }
+        self.write(batch)
+    }
+
+    fn write(&self, mut batch: StorageBatch) -> Result<(), StorageError> {
+        analytics::count_writes(&mut batch)?;
+
+        let _guard = self.write_lock.lock().map_err(|_| StorageError::Lock)?;
+        let db = self.db.read().map_err(|_| StorageError::Lock)?;
+
+        let mut write_batch = WriteBatch::default();
+        // Whether each key the batch has touched so far exists once those ops apply, so that
+        // inserts see earlier puts and deletes in the same batch.
+        let mut staged: HashMap<(&str, &[u8]), bool> = HashMap::new();
+
+        for op in batch.ops() {
+            let cf_handle = Self::cf_handle(&db, op.cf())?;
+            match op {
+                BatchOp::Insert { cf, key, value } => {
+                    let exists = match staged.get(&(cf.as_str(), key.as_slice())) {
+                        Some(&exists) => exists,
+                        None => db.get_cf(cf_handle, key)?.is_some(),
+                    };
+                    if exists {
+                        return Err(StorageError::RecordExists);
+                    }
+                    staged.insert((cf, key), true);
+                    write_batch.put_cf(cf_handle, key, value);
+                }
+                BatchOp::Put { cf, key, value } => {
+                    staged.insert((cf, key), true);
+                    write_batch.put_cf(cf_handle, key, value);
+                }
+                BatchOp::Delete { cf, key } => {
+                    staged.insert((cf, key), false);
+                    write_batch.delete_cf(cf_handle, key);
+                }
+                BatchOp::Merge { cf, key, value } => {
+                    staged.insert((cf, key), true);
+                    write_batch.merge_cf(cf_handle, key, value);
+                }
+            }
+        }
+
+        db.write(write_batch)?;
+        Ok(())
+    }
+
+    fn get(&self, cf: &str, key: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.with_cf_handle(cf, |db, cf_handle| {
+            db.get_cf(cf_handle, key)?
+                .ok_or(StorageError::NotFound)
+                .map(|data| data.to_vec())
+        })
+    }
+
+    fn exists(&self, cf: &str, key: &[u8]) -> Result<bool, StorageError> {
+        self.with_cf_handle(cf, |db, cf_handle| match db.get_cf(cf_handle, key) {
+            Ok(Some(_)) => Ok(true),
+            Ok(None) => Ok(false),
+            Err(e) => Err(e.into()),
+        })
+    }
+
+    fn batch_put(&self, cf: &str, batch: Vec<(&[u8], &[u8])>) -> Result<(), StorageError> {
+        let mut write_batch = StorageBatch::new();
+        for (key, value) in batch {
+            write_batch.put(cf, key, value);
+        }
+        self.write(write_batch)
+    }
+
+    fn batch_get(
+        &self,
+        cf: &str,
+        start: usize,
+        limit: usize,
+    ) -> Result<Vec<KeyValue>, StorageError> {
+        self.with_cf_handle(cf, |db, cf_handle| {
+            let mut iter = db.iterator_cf(cf_handle, IteratorMode::Start);
+
+            for _ in 0..start {
+                if iter.next().is_none() {
+                    break;
+                }
+            }
+
+            let result: Vec<KeyValue> = iter
+                .take(limit)
+                .filter_map(|item| item.ok())
+                .map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .collect();
+
+            Ok(result)
+        })
+    }
+
+    fn scan(&self, cf: &str, options: &ScanOptions) -> Result<KeyValuePage, StorageError> {
+        let (lower, upper) = options.bounds();
+
+        self.with_cf_handle(cf, |db, cf_handle| {
+            let mode = match (options.reverse, &lower, &upper) {
+                (false, Bound::Included(key) | Bound::Excluded(key), _) => {
+                    IteratorMode::From(key, Direction::Forward)
+                }
+                (false, Bound::Unbounded, _) => IteratorMode::Start,
+                (true, _, Bound::Included(key) | Bound::Excluded(key)) => {
+                    IteratorMode::From(key, Direction::Reverse)
+                }
+                (true, _, Bound::Unbounded) => IteratorMode::End,
+            };
+
+            let mut items = Vec::new();
+            for item in db.iterator_cf(cf_handle, mode) {
+                let (key, value) = item?;
+                let (in_start, in_end) = if options.reverse {
+                    (below_upper(&key, &upper), above_lower(&key, &lower))
+                } else {
+                    (above_lower(&key, &lower), below_upper(&key, &upper))
+                };
+                // The seek lands on an excluded bound at most once; past the far bound we're done.
+                if !in_start {
+                    continue;
+                }
+                if !in_end || items.len() > options.limit {
+                    break;
+                }
+                items.push((key.to_vec(), value.to_vec()));
+            }
+
+            Ok(KeyValuePage::from_scan(items, options.limit))
+        })
+    }
+
+    fn get_analytics(&self, key: &[u8]) -> Result<i64, StorageError> {
+        let cf = StorageKind::Analytics.name();
+
+        self.with_cf_handle(cf, |db, cf_handle| {
+            let data = db
+                .get_cf(cf_handle, key)?
+                .ok_or(StorageError::AnalyticsNotFound)?;
+            let analytics: i64 = bincode::deserialize(&data)?;
+            Ok(analytics)
+        })
+    }
+
+    fn create_checkpoint(&self, path: &Path) -> Result<(), StorageError> {
+        let db = self.db.read().map_err(|_| StorageError::Lock)?;
+        Checkpoint::new(&db)?.create_checkpoint(path)?;
+        Ok(())
+    }
+
+    fn create_backup(&self, backup_dir: &Path) -> Result<BackupInfo, StorageError> {
+        let db = self.db.read().map_err(|_| StorageError::Lock)?;
+        let mut engine = backup::open_engine(backup_dir)?;
+        engine.create_new_backup_flush(&db, true)?;
+
+        engine
+            .get_backup_info()
+            .iter()
+            .max_by_key(|info| info.backup_id)
+            .map(BackupInfo::from)
+            .ok_or(StorageError::NotFound)
+    }
+}
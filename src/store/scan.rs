@@ -0,0 +1,192 @@
+use super::KeyValue;
+use crate::util::error::StorageError;
+use std::fmt;
+use std::ops::Bound;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanRange {
+    All,
+    Prefix(Vec<u8>),
+    /// Keys in `[start, end)`; a missing bound is open-ended.
+    Range {
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+    },
+}
+
+/// Opaque continuation token pointing at the last key of a page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor(Vec<u8>);
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub range: ScanRange,
+    pub reverse: bool,
+    pub limit: usize,
+    pub cursor: Option<Cursor>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<Cursor>,
+}
+
+impl Cursor {
+    pub(crate) fn from_key(key: &[u8]) -> Self {
+        Cursor(key.to_vec())
+    }
+
+    pub(crate) fn key(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", bs58::encode(&self.0).into_string())
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = StorageError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        bs58::decode(value)
+            .into_vec()
+            .map(Cursor)
+            .map_err(|_| StorageError::InvalidCursor)
+    }
+}
+
+impl ScanOptions {
+    pub fn new(limit: usize) -> Self {
+        ScanOptions {
+            range: ScanRange::All,
+            reverse: false,
+            limit,
+            cursor: None,
+        }
+    }
+
+    pub fn prefix(mut self, prefix: &[u8]) -> Self {
+        self.range = ScanRange::Prefix(prefix.to_vec());
+        self
+    }
+
+    pub fn range(mut self, start: Option<&[u8]>, end: Option<&[u8]>) -> Self {
+        self.range = ScanRange::Range {
+            start: start.map(|key| key.to_vec()),
+            end: end.map(|key| key.to_vec()),
+        };
+        self
+    }
+
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    pub fn after(mut self, cursor: Option<Cursor>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    /// Effective key bounds of the scan, narrowed by the cursor in the scan direction.
+    pub fn bounds(&self) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+        let (mut lower, mut upper) = match &self.range {
+            ScanRange::All => (Bound::Unbounded, Bound::Unbounded),
+            ScanRange::Prefix(prefix) => (
+                Bound::Included(prefix.clone()),
+                prefix_successor(prefix).map_or(Bound::Unbounded, Bound::Excluded),
+            ),
+            ScanRange::Range { start, end } => (
+                start.clone().map_or(Bound::Unbounded, Bound::Included),
+                end.clone().map_or(Bound::Unbounded, Bound::Excluded),
+            ),
+        };
+
+        // The cursor only ever narrows the bounds, so a cursor from another scan can't walk
+        // outside the requested range.
+        if let Some(cursor) = &self.cursor {
+            let key = cursor.key();
+            if self.reverse {
+                if below_upper(key, &upper) {
+                    upper = Bound::Excluded(key.to_vec());
+                }
+            } else if above_lower(key, &lower) {
+                lower = Bound::Excluded(key.to_vec());
+            }
+        }
+
+        (lower, upper)
+    }
+}
+
+impl<T> Page<T> {
+    pub fn map<U, F>(self, f: F) -> Page<U>
+    where
+        F: FnMut(T) -> U,
+    {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+pub type KeyValuePage = Page<KeyValue>;
+
+impl KeyValuePage {
+    /// Builds a page from up to `limit + 1` scanned items; the extra item only signals that
+    /// another page exists.
+    pub(crate) fn from_scan(mut items: Vec<KeyValue>, limit: usize) -> Self {
+        let has_more = items.len() > limit;
+        items.truncate(limit);
+        let next_cursor = if has_more {
+            items.last().map(|(key, _)| Cursor::from_key(key))
+        } else {
+            None
+        };
+
+        Page { items, next_cursor }
+    }
+}
+
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut successor = prefix.to_vec();
+    while let Some(last) = successor.pop() {
+        if last < u8::MAX {
+            successor.push(last + 1);
+            return Some(successor);
+        }
+    }
+    None
+}
+
+pub(crate) fn above_lower(key: &[u8], lower: &Bound<Vec<u8>>) -> bool {
+    match lower {
+        Bound::Included(bound) => key >= bound.as_slice(),
+        Bound::Excluded(bound) => key > bound.as_slice(),
+        Bound::Unbounded => true,
+    }
+}
+
+pub(crate) fn below_upper(key: &[u8], upper: &Bound<Vec<u8>>) -> bool {
+    match upper {
+        Bound::Included(bound) => key <= bound.as_slice(),
+        Bound::Excluded(bound) => key < bound.as_slice(),
+        Bound::Unbounded => true,
+    }
+}
+
+pub(crate) fn is_empty_range(lower: &Bound<Vec<u8>>, upper: &Bound<Vec<u8>>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(lo), Bound::Included(hi)) => lo > hi,
+        (Bound::Included(lo), Bound::Excluded(hi))
+        | (Bound::Excluded(lo), Bound::Included(hi))
+        | (Bound::Excluded(lo), Bound::Excluded(hi)) => lo >= hi,
+        _ => false,
+    }
+}
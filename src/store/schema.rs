@@ -0,0 +1,153 @@
+use super::{ScanOptions, Storage, StorageBatch, StorageKind};
+use crate::util::error::StorageError;
+use serde::{de::DeserializeOwned, Serialize};
+
+pub const SCHEMA_VERSION: u32 = 2;
+pub const RECORD_VERSION: u16 = 1;
+
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+const RECORD_MAGIC: &[u8; 2] = b"VR";
+const ENVELOPE_HEADER_LEN: usize = 4;
+
+pub struct Migration {
+    /// Schema version the database is at once this migration has been applied.
+    pub version: u32,
+    pub description: &'static str,
+    /// Stages the migration's writes into the batch and returns the number of records touched.
+    pub apply: fn(&Storage, &mut StorageBatch) -> Result<usize, StorageError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub description: &'static str,
+    pub records: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub dry_run: bool,
+    pub applied: Vec<AppliedMigration>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Wrap account and transaction records in versioned envelopes",
+        apply: wrap_legacy_records,
+    },
+    Migration {
+        version: 2,
+        description: "Add nonces to account records",
+        apply: crate::account::account::add_account_nonces,
+    },
+];
+
+/// Wraps a record as `"VR" | version (u16, big-endian) | bincode payload`.
+pub fn encode_record<T: Serialize>(value: &T) -> Result<Vec<u8>, StorageError> {
+    let payload = bincode::serialize(value)?;
+    Ok(envelope(RECORD_VERSION, &payload))
+}
+
+pub fn decode_record<T: DeserializeOwned>(data: &[u8]) -> Result<T, StorageError> {
+    let (version, payload) = open_envelope(data)?;
+    if version > RECORD_VERSION {
+        return Err(StorageError::UnsupportedRecordVersion(version));
+    }
+    Ok(bincode::deserialize(payload)?)
+}
+
+/// Decodes a record whether or not it has been wrapped in an envelope yet. Migrations are
+/// staged into one batch, so a later migration can still see records an earlier one rewrites.
+pub(crate) fn decode_legacy_record<T: DeserializeOwned>(data: &[u8]) -> Result<T, StorageError> {
+    match open_envelope(data) {
+        Ok((_, payload)) => Ok(bincode::deserialize(payload)?),
+        Err(_) => Ok(bincode::deserialize(data)?),
+    }
+}
+
+fn envelope(version: u16, payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(ENVELOPE_HEADER_LEN + payload.len());
+    data.extend_from_slice(RECORD_MAGIC);
+    data.extend_from_slice(&version.to_be_bytes());
+    data.extend_from_slice(payload);
+    data
+}
+
+fn open_envelope(data: &[u8]) -> Result<(u16, &[u8]), StorageError> {
+    if data.len() < ENVELOPE_HEADER_LEN || &data[..2] != RECORD_MAGIC {
+        return Err(StorageError::InvalidRecord);
+    }
+    let version = u16::from_be_bytes([data[2], data[3]]);
+    Ok((version, &data[ENVELOPE_HEADER_LEN..]))
+}
+
+/// Stored schema version; databases created before versioning report `0`.
+pub fn schema_version(store: &Storage) -> Result<u32, StorageError> {
+    match store.get(StorageKind::Meta.name(), SCHEMA_VERSION_KEY) {
+        Ok(data) => Ok(bincode::deserialize(&data)?),
+        Err(StorageError::NotFound) => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Brings the database up to `SCHEMA_VERSION`. Every pending migration is staged into one
+/// batch, so the upgrade either fully applies or leaves the database untouched. With
+/// `dry_run` nothing is written and the report shows what would change.
+pub fn migrate(store: &Storage, dry_run: bool) -> Result<MigrationReport, StorageError> {
+    let from_version = schema_version(store)?;
+    if from_version > SCHEMA_VERSION {
+        return Err(StorageError::SchemaTooNew(from_version));
+    }
+
+    let mut batch = StorageBatch::new();
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > from_version) {
+        let records = (migration.apply)(store, &mut batch)?;
+        applied.push(AppliedMigration {
+            version: migration.version,
+            description: migration.description,
+            records,
+        });
+    }
+
+    if !dry_run && from_version != SCHEMA_VERSION {
+        batch.put(
+            StorageKind::Meta.name(),
+            SCHEMA_VERSION_KEY,
+            &bincode::serialize(&SCHEMA_VERSION)?,
+        );
+        store.write(batch)?;
+    }
+
+    Ok(MigrationReport {
+        from_version,
+        to_version: SCHEMA_VERSION,
+        dry_run,
+        applied,
+    })
+}
+
+fn wrap_legacy_records(store: &Storage, batch: &mut StorageBatch) -> Result<usize, StorageError> {
+    let mut records = 0;
+    for kind in [StorageKind::Account, StorageKind::Transaction] {
+        let mut cursor = None;
+        loop {
+            let page = store.scan(kind.name(), &ScanOptions::new(1_000).after(cursor))?;
+            for (key, value) in page.items {
+                if open_envelope(&value).is_ok() {
+                    continue;
+                }
+                batch.put(kind.name(), &key, &envelope(RECORD_VERSION, &value));
+                records += 1;
+            }
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+    }
+    Ok(records)
+}
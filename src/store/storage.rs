@@ -1,90 +1,82 @@
-use super::StorageKind;
-use crate::config;
-use bincode;
-use rocksdb::{
-    ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, IteratorMode, Options, WriteBatch, DB,
+use super::analytics::{self, Bucket, Metric, MetricPoint};
+use super::{
+    BackupInfo, EncryptedStore, KeyValue, KeyValuePage, KvStore, MemoryStore, RocksDbStore,
+    ScanOptions, StorageBatch, StorageKind,
 };
-use std::sync::{Arc, RwLock};
+use crate::config;
+use crate::util::error::StorageError;
+use rocksdb::Options;
+use std::path::Path;
+use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Storage {
-    db: Arc<RwLock<DB>>,
+    backend: Arc<dyn KvStore>,
 }
 
 impl Storage {
-    fn with_cf_handle<F, R>(&self, cf: &str, f: F) -> Result<R, String>
-    where
-        F: FnOnce(&DB, &ColumnFamily) -> Result<R, String>,
-    {
-        let db = self.db.read().map_err(|_| "DB lock error".to_string())?;
-        let cf_handle = db
-            .cf_handle(cf)
-            .ok_or_else(|| format!("Column family {} not found", cf))?;
-        f(&db, cf_handle)
-    }
-
-    pub fn init() -> Result<Storage, String> {
-        let path = config::DB_PATH;
-        let mut opts = Options::default();
-        opts.set_compression_type(DBCompressionType::Snappy);
-        opts.create_if_missing(true);
-        opts.create_missing_column_families(true);
-        opts.set_level_compaction_dynamic_level_bytes(true);
-
-        let cfs = vec![
-            ColumnFamilyDescriptor::new(StorageKind::Account.name(), Options::default()),
-            ColumnFamilyDescriptor::new(StorageKind::Transaction.name(), Options::default()),
-            ColumnFamilyDescriptor::new(StorageKind::Contract.name(), Options::default()),
-            ColumnFamilyDescriptor::new(StorageKind::Chain.name(), Options::default()),
-            ColumnFamilyDescriptor::new(StorageKind::Index.name(), Options::default()),
-            ColumnFamilyDescriptor::new(StorageKind::Analytics.name(), Options::default()),
-        ];
-
-        let db = DB::open_cf_descriptors(&opts, path, cfs).map_err(|e| e.to_string())?;
-        Ok(Storage {
-            db: Arc::new(RwLock::new(db)),
-        })
-    }
-
-    pub fn put(&self, cf: &str, key: &[u8], value: &[u8], check_exist: bool) -> Result<(), String> {
-        self.with_cf_handle(cf, |db, cf_handle| {
-            if check_exist {
-                let data_exists = self.exists(cf, key)?;
-                if data_exists {
-                    return Err("Record exists! Mutating value is not allowed.".to_string());
-                }
-            }
-            db.put_cf(cf_handle, key, value.to_vec())
-                .map_err(|e| e.to_string())?;
-            Ok(())
-        })?;
-        self.update_analytics(cf.as_bytes())
-    }
-
-    pub fn get(&self, cf: &str, key: &[u8]) -> Result<Vec<u8>, String> {
-        self.with_cf_handle(cf, |db, cf_handle| {
-            db.get_cf(cf_handle, key)
-                .map_err(|e| e.to_string())?
-                .ok_or_else(|| "Key not found".to_string())
-                .map(|data| data.to_vec())
-        })
-    }
-
-    pub fn exists(&self, cf: &str, key: &[u8]) -> Result<bool, String> {
-        self.with_cf_handle(cf, |db, cf_handle| match db.get_cf(cf_handle, key) {
-            Ok(Some(_)) => Ok(true),
-            Ok(None) => Ok(false),
-            Err(e) => Err(e.to_string()),
-        })
-    }
-
-    pub fn batch_put(&self, cf: &str, batch: Vec<(&[u8], &[u8])>) -> Result<(), String> {
-        self.with_cf_handle(cf, |db, cf_handle| {
-            let mut write_batch = WriteBatch::default();
-            for (key, value) in batch {
-                write_batch.put_cf(cf_handle, key, value);
-            }
-            db.write(write_batch).map_err(|e| e.to_string())
-        })
+    pub fn new(backend: Arc<dyn KvStore>) -> Self {
+        Storage { backend }
+    }
+
+    pub fn init() -> Result<Storage, StorageError> {
+        Self::open(config::DB_PATH, Self::default_options())
+    }
+
+    pub fn default_options() -> Options {
+        RocksDbStore::default_options()
+    }
+
+    /// Opens an unencrypted database; fails with `Encrypted` if it was set up with a passphrase.
+    pub fn open<P: AsRef<Path>>(path: P, opts: Options) -> Result<Storage, StorageError> {
+        let backend = RocksDbStore::open(path, opts)?;
+        if EncryptedStore::is_encrypted(&backend)? {
+            return Err(StorageError::Encrypted);
+        }
+        Ok(Self::new(Arc::new(backend)))
+    }
+
+    /// Opens a database encrypted at rest, setting up encryption if the database is new.
+    /// `encrypt_keys` additionally hides record keys, at the cost of in-memory ordered scans.
+    pub fn open_encrypted<P: AsRef<Path>>(
+        path: P,
+        opts: Options,
+        passphrase: &str,
+        encrypt_keys: bool,
+    ) -> Result<Storage, StorageError> {
+        let backend = RocksDbStore::open(path, opts)?;
+        let encrypted = EncryptedStore::open(Arc::new(backend), passphrase, encrypt_keys)?;
+        Ok(Self::new(Arc::new(encrypted)))
+    }
+
+    pub fn memory() -> Storage {
+        Self::new(Arc::new(MemoryStore::new()))
+    }
+
+    pub fn put(
+        &self,
+        cf: &str,
+        key: &[u8],
+        value: &[u8],
+        check_exist: bool,
+    ) -> Result<(), StorageError> {
+        self.backend.put(cf, key, value, check_exist)
+    }
+
+    pub fn write(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        self.backend.write(batch)
+    }
+
+    pub fn get(&self, cf: &str, key: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.backend.get(cf, key)
+    }
+
+    pub fn exists(&self, cf: &str, key: &[u8]) -> Result<bool, StorageError> {
+        self.backend.exists(cf, key)
+    }
+
+    pub fn batch_put(&self, cf: &str, batch: Vec<(&[u8], &[u8])>) -> Result<(), StorageError> {
+        self.backend.batch_put(cf, batch)
     }
 
     pub fn batch_get(
@@ -92,64 +84,76 @@ impl Storage {
         cf: &str,
         start: usize,
         limit: usize,
-    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String> {
-        self.with_cf_handle(cf, |db, cf_handle| {
-            let mut iter = db.iterator_cf(cf_handle, IteratorMode::Start);
-
-            for _ in 0..start {
-                if iter.next().is_none() {
-                    break;
-                }
-            }
+    ) -> Result<Vec<KeyValue>, StorageError> {
+        self.backend.batch_get(cf, start, limit)
+    }
 
-            let result: Vec<(Vec<u8>, Vec<u8>)> = iter
-                .take(limit)
-                .filter_map(|item| item.ok())
-                .map(|(key, value)| (key.to_vec(), value.to_vec()))
-                .collect();
+    pub fn scan(&self, cf: &str, options: &ScanOptions) -> Result<KeyValuePage, StorageError> {
+        self.backend.scan(cf, options)
+    }
 
-            Ok(result)
-        })
+    pub fn create_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<(), StorageError> {
+        self.backend.create_checkpoint(path.as_ref())
     }
 
-    fn update_analytics(&self, key: &[u8]) -> Result<(), String> {
-        let cf = StorageKind::Analytics.name();
+    pub fn create_backup<P: AsRef<Path>>(&self, backup_dir: P) -> Result<BackupInfo, StorageError> {
+        self.backend.create_backup(backup_dir.as_ref())
+    }
 
-        let analytics = self.with_cf_handle(cf, |db, cf_handle| {
-            db.get_cf(cf_handle, key)
-                .map_err(|e| e.to_string())?
-                .ok_or_else(|| "Analytics doesn't exist".to_string())
-                .map(|data| data.to_vec())
-        });
+    pub fn get_analytics(&self, key: &[u8]) -> Result<i64, StorageError> {
+        self.backend.get_analytics(key)
+    }
+}
 
-        let value = match analytics {
-            Ok(data) => {
-                let mut parse_analytics: i64 =
-                    bincode::deserialize(&data).map_err(|e| e.to_string())?;
-                parse_analytics += 1;
-                bincode::serialize(&parse_analytics).map_err(|e| e.to_string())?
-            }
-            Err(_) => {
-                let value: i64 = 1;
-                bincode::serialize(&value).map_err(|e| e.to_string())?
+impl Storage {
+    /// Metric values for `bucket`s starting within `[from, to]` (unix seconds), oldest first.
+    pub fn get_analytics_series(
+        &self,
+        metric: Metric,
+        bucket: Bucket,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<MetricPoint>, StorageError> {
+        let start = analytics::metric_key(metric, bucket, bucket.start(from));
+        let end = match to.checked_add(1) {
+            Some(end) => analytics::metric_key(metric, bucket, end),
+            None => {
+                let mut end = analytics::metric_prefix(metric, bucket);
+                end.push(u8::MAX);
+                end
             }
         };
 
-        self.with_cf_handle(cf, |db, cf_handle| {
-            db.put_cf(cf_handle, key, value).map_err(|e| e.to_string())
-        })
-    }
+        let mut points = Vec::new();
+        let mut cursor = None;
+        loop {
+            let options = ScanOptions::new(1_000)
+                .range(Some(&start), Some(&end))
+                .after(cursor);
+            let page = self.scan(StorageKind::Analytics.name(), &options)?;
+            for (key, value) in page.items {
+                let bucket_start = analytics::bucket_start_from_key(&key)
+                    .ok_or_else(|| StorageError::Merge(hex::encode(&key)))?;
+                let value: f64 = bincode::deserialize(&value)?;
+                points.push(MetricPoint {
+                    bucket_start,
+                    value,
+                });
+            }
 
-    pub fn get_analytics(&self, key: &[u8]) -> Result<i64, String> {
-        let cf = StorageKind::Analytics.name();
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                return Ok(points);
+            }
+        }
+    }
 
-        self.with_cf_handle(cf, |db, cf_handle| {
-            let data = db
-                .get_cf(cf_handle, key)
-                .map_err(|e| e.to_string())?
-                .ok_or_else(|| "Analytics doesn't exist".to_string())?;
-            let analytics: i64 = bincode::deserialize(&data).map_err(|e| e.to_string())?;
-            Ok(analytics)
-        })
+    pub fn get_metric_total(&self, metric: Metric) -> Result<f64, StorageError> {
+        let key = analytics::metric_key(metric, Bucket::Total, 0);
+        match self.get(StorageKind::Analytics.name(), &key) {
+            Ok(value) => Ok(bincode::deserialize(&value)?),
+            Err(StorageError::NotFound) => Ok(0.0),
+            Err(e) => Err(e),
+        }
     }
 }
@@ -6,9 +6,26 @@ pub enum StorageKind {
     Chain,
     Analytics,
     Index,
+    Meta,
+    Peer,
+    Mempool,
 }
 
 impl StorageKind {
+    pub fn all() -> [StorageKind; 9] {
+        [
+            StorageKind::Account,
+            StorageKind::Transaction,
+            StorageKind::Contract,
+            StorageKind::Chain,
+            StorageKind::Index,
+            StorageKind::Analytics,
+            StorageKind::Meta,
+            StorageKind::Peer,
+            StorageKind::Mempool,
+        ]
+    }
+
     pub fn name(&self) -> &str {
         match self {
             StorageKind::Account => "accounts",
@@ -17,6 +34,9 @@ impl StorageKind {
             StorageKind::Chain => "blockchains",
             StorageKind::Analytics => "analytics",
             StorageKind::Index => "index",
+            StorageKind::Meta => "meta",
+            StorageKind::Peer => "peers",
+            StorageKind::Mempool => "mempool",
         }
     }
 }
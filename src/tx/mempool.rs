@@ -0,0 +1,276 @@
+use crate::account::Account;
+use crate::chain::validation::validate_transfer;
+use crate::chain::Block;
+use crate::config;
+use crate::events::{Event, EventBus};
+use crate::store::{ScanOptions, Storage, StorageBatch, StorageKind};
+use crate::tx::transaction::PlainTransaction;
+use crate::tx::Transaction;
+use crate::util::codec::{Decode, Encode};
+use crate::util::error::{BlockRejection, MempoolError, StorageError};
+use std::collections::{BTreeMap, HashMap};
+
+/// Signed transfers waiting for a block, indexed by id and by sender and nonce so a sender's
+/// transfers can be mined in order.
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    transactions: HashMap<String, PlainTransaction>,
+    by_sender: BTreeMap<(String, u64), String>,
+    max_transactions: usize,
+    events: EventBus,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new(config::MEMPOOL_MAX_TRANSACTIONS)
+    }
+}
+
+impl Mempool {
+    pub fn new(max_transactions: usize) -> Self {
+        Mempool {
+            transactions: HashMap::new(),
+            by_sender: BTreeMap::new(),
+            max_transactions,
+            events: EventBus::new(),
+        }
+    }
+
+    /// Publishes admissions on `events` instead of a bus of its own.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.transactions.contains_key(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Transaction> {
+        self.transactions.get(id).cloned().map(Transaction::Plain)
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.transactions.keys().cloned().collect()
+    }
+
+    /// Admits a transfer that could be mined on top of the current tip, possibly after the
+    /// sender's other pending transfers: its nonce isn't used yet and follows on from them
+    /// without a gap, and the sender can afford it together with everything it already has
+    /// pending.
+    pub fn add(&mut self, store: &Storage, tx: Transaction) -> Result<(), MempoolError> {
+        let tx = match tx {
+            Transaction::Plain(tx) => tx,
+            Transaction::Encrypted(_) => {
+                return Err(MempoolError::Invalid(BlockRejection::EncryptedTransaction(
+                    tx.id().to_string(),
+                )))
+            }
+            Transaction::Coinbase(_) => {
+                return Err(MempoolError::Invalid(BlockRejection::MisplacedCoinbase(
+                    tx.id().to_string(),
+                )))
+            }
+        };
+        if self.contains(tx.id()) {
+            return Err(MempoolError::Duplicate(tx.id().to_string()));
+        }
+        if self.transactions.len() >= self.max_transactions {
+            return Err(MempoolError::Full(self.max_transactions));
+        }
+        if let Some(reason) = validate_transfer(&tx)? {
+            return Err(MempoolError::Invalid(reason));
+        }
+
+        let sender = Account::state_of(store, tx.sender())?;
+        // Pending transfers fill the nonces from the account's on, so anything past the next
+        // one would leave a gap and couldn't be mined.
+        let next = sender.nonce + self.pending_count(tx.sender()) as u64;
+        if tx.nonce() < sender.nonce || tx.nonce() > next {
+            return Err(MempoolError::Invalid(BlockRejection::InvalidNonce {
+                id: tx.id().to_string(),
+                expected: next,
+                found: tx.nonce(),
+            }));
+        }
+        let slot = (tx.sender().to_string(), tx.nonce());
+        if self.by_sender.contains_key(&slot) {
+            return Err(MempoolError::NonceInUse {
+                sender: slot.0,
+                nonce: slot.1,
+            });
+        }
+        let required = self.pending_spend(tx.sender()) + tx.amount() + tx.fee();
+        if sender.balance < required {
+            return Err(MempoolError::Invalid(BlockRejection::InsufficientFunds {
+                id: tx.id().to_string(),
+                available: sender.balance,
+                required,
+            }));
+        }
+
+        self.by_sender.insert(slot, tx.id().to_string());
+        self.transactions.insert(tx.id().to_string(), tx.clone());
+        self.events
+            .publish(Event::TransactionAdmitted(Transaction::Plain(tx)));
+        Ok(())
+    }
+
+    /// Nonce for the next transfer of `sender`: the one after its last pending transfer, or
+    /// its account nonce when nothing is pending.
+    pub fn next_nonce(&self, store: &Storage, sender: &str) -> Result<u64, MempoolError> {
+        let pending = self
+            .by_sender
+            .range((sender.to_string(), 0)..=(sender.to_string(), u64::MAX))
+            .next_back()
+            .map(|((_, nonce), _)| nonce + 1);
+        match pending {
+            Some(nonce) => Ok(nonce),
+            None => Ok(Account::state_of(store, sender)?.nonce),
+        }
+    }
+
+    /// Readmits the transfers saved by `persist`, in nonce order per sender. The ones that
+    /// are no longer valid, usually because they were mined since, are left out. Returns how
+    /// many were admitted.
+    pub fn load(&mut self, store: &Storage) -> Result<usize, StorageError> {
+        let mut saved = Vec::new();
+        let mut cursor = None;
+        loop {
+            let options = ScanOptions::new(1_000).after(cursor);
+            let page = store.scan(StorageKind::Mempool.name(), &options)?;
+            for (_, value) in page.items {
+                if let Ok(Transaction::Plain(tx)) = Transaction::decode(&value) {
+                    saved.push(tx);
+                }
+            }
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        saved.sort_by(|a, b| (a.sender(), a.nonce()).cmp(&(b.sender(), b.nonce())));
+        let before = self.len();
+        for tx in saved {
+            let _ = self.add(store, Transaction::Plain(tx));
+        }
+        Ok(self.len() - before)
+    }
+
+    /// Saves the pending transfers for a later `load`, replacing the ones saved before, so
+    /// commands that don't run a node can hand transfers to the next one that does.
+    pub fn persist(&self, store: &Storage) -> Result<(), StorageError> {
+        let cf = StorageKind::Mempool.name();
+        let mut batch = StorageBatch::new();
+        let mut cursor = None;
+        loop {
+            let page = store.scan(cf, &ScanOptions::new(1_000).after(cursor))?;
+            for (key, _) in page.items {
+                if !self
+                    .transactions
+                    .contains_key(&*String::from_utf8_lossy(&key))
+                {
+                    batch.delete(cf, &key);
+                }
+            }
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        for (id, tx) in &self.transactions {
+            batch.put(cf, id.as_bytes(), &Transaction::Plain(tx.clone()).encode());
+        }
+        store.write(batch)
+    }
+
+    /// Transfers that can go into the next block, in nonce order per sender and stopping at
+    /// the first gap, up to `max_bytes` of encoded transactions.
+    pub fn select(&self, store: &Storage, max_bytes: u64) -> Vec<Transaction> {
+        let mut selected = Vec::new();
+        let mut size = 0;
+        let mut next_nonce: HashMap<&str, Option<u64>> = HashMap::new();
+        for ((sender, nonce), id) in &self.by_sender {
+            let expected = next_nonce.entry(sender).or_insert_with(|| {
+                Account::state_of(store, sender)
+                    .ok()
+                    .map(|state| state.nonce)
+            });
+            if *expected != Some(*nonce) {
+                continue;
+            }
+            let tx = Transaction::Plain(self.transactions[id].clone());
+            // Each transaction is stored with a u32 length prefix.
+            let tx_size = tx.encode().len() as u64 + 4;
+            if size + tx_size > max_bytes {
+                // Later nonces of this sender can't be mined without this one.
+                *expected = None;
+                continue;
+            }
+            size += tx_size;
+            *expected = Some(nonce + 1);
+            selected.push(tx);
+        }
+        selected
+    }
+
+    /// Drops transfers whose nonce the chain has moved past, either because they were mined
+    /// or because another transfer with the same nonce was, and the ones left behind a gap,
+    /// such as after a reorg rolls a sender's nonce back.
+    pub fn prune(&mut self, store: &Storage) {
+        let mut stale = Vec::new();
+        let mut expected: HashMap<&str, u64> = HashMap::new();
+        for ((sender, nonce), id) in &self.by_sender {
+            let next = expected.entry(sender).or_insert_with(|| {
+                Account::state_of(store, sender)
+                    .map(|state| state.nonce)
+                    .unwrap_or(u64::MAX)
+            });
+            if *nonce == *next {
+                *next = next.saturating_add(1);
+            } else {
+                stale.push(((sender.clone(), *nonce), id.clone()));
+            }
+        }
+        for (slot, id) in stale {
+            self.by_sender.remove(&slot);
+            self.transactions.remove(&id);
+        }
+    }
+
+    /// Returns the transfers of blocks dropped by a reorganization to the pool; the ones the
+    /// new branch already mined or invalidated are turned away.
+    pub fn restore(&mut self, store: &Storage, blocks: &[Block]) {
+        for block in blocks {
+            for tx in &block.transactions {
+                if let Transaction::Plain(_) = tx {
+                    let _ = self.add(store, tx.clone());
+                }
+            }
+        }
+    }
+
+    fn pending_count(&self, sender: &str) -> usize {
+        self.by_sender
+            .range((sender.to_string(), 0)..=(sender.to_string(), u64::MAX))
+            .count()
+    }
+
+    fn pending_spend(&self, sender: &str) -> f64 {
+        self.by_sender
+            .range((sender.to_string(), 0)..=(sender.to_string(), u64::MAX))
+            .map(|(_, id)| {
+                let tx = &self.transactions[id];
+                tx.amount() + tx.fee()
+            })
+            .sum()
+    }
+}
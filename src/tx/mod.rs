@@ -1,5 +1,7 @@
+pub mod mempool;
 pub mod transaction;
 pub mod transaction_status;
 
+pub use mempool::Mempool;
 pub use transaction::Transaction;
 pub use transaction_status::TransactionStatus;
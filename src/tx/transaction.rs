@@ -1,14 +1,24 @@
+use crate::account::wallet::Wallet;
 use crate::account::Account;
-use crate::store::{Storage, StorageKind};
+use crate::store::{schema, Metric, Storage, StorageBatch, StorageKind};
 use crate::tx::TransactionStatus;
+use crate::util::codec::{Decode, Decoder, Encode, Encoder};
 use crate::util::config;
-use crate::vault::Crypto;
+use crate::util::error::{CodecError, StorageError, TransactionError};
+use crate::vault::{Crypto, KeyPair};
 use blake3::Hasher;
 use chrono::Utc;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::mem;
 
+/// Layout version of the canonical transaction encoding.
+pub const TRANSACTION_VERSION: u8 = 1;
+
+const PLAIN_TAG: u8 = 0;
+const ENCRYPTED_TAG: u8 = 1;
+const COINBASE_TAG: u8 = 2;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum EncryptData {
     Plain(String),
@@ -40,6 +50,11 @@ pub struct PlainTransaction {
     narration: String,
     status: String,
     tx_key: Option<String>,
+    nonce: u64,
+    /// Public key and blinding that open `sender`, set when signing.
+    sender_key: String,
+    sender_blinding: String,
+    signature: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -54,39 +69,342 @@ pub struct EncryptedTransaction {
     status: String,
 }
 
+/// Pays the block subsidy plus the block's fees to the miner. It has no sender and no
+/// signature; the height keeps coinbase ids unique across blocks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CoinbaseTransaction {
+    id: String,
+    receiver: String,
+    amount: f64,
+    height: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Transaction {
     Plain(PlainTransaction),
     Encrypted(EncryptedTransaction),
+    Coinbase(CoinbaseTransaction),
+}
+
+impl CoinbaseTransaction {
+    pub fn new(receiver: String, amount: f64, height: u64) -> Self {
+        let mut hasher = Hasher::new();
+        hasher.update(b"coinbase");
+        hasher.update(&height.to_be_bytes());
+        hasher.update(receiver.as_bytes());
+        let id = hasher.finalize().to_hex().to_string();
+
+        CoinbaseTransaction {
+            id,
+            receiver,
+            amount,
+            height,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn receiver(&self) -> &str {
+        &self.receiver
+    }
+
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+}
+
+/// Status and the sender's local `tx_key` never leave the node, so they aren't encoded;
+/// decoded transactions come back pending and without a key.
+impl Encode for Transaction {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        encoder.u8(TRANSACTION_VERSION);
+        match self {
+            Transaction::Plain(tx) => {
+                encoder.u8(PLAIN_TAG).str(&tx.id);
+                tx.encode_unsigned(encoder);
+                encoder.option(tx.signature.as_deref(), Encoder::str);
+            }
+            Transaction::Encrypted(tx) => {
+                encoder
+                    .u8(ENCRYPTED_TAG)
+                    .str(&tx.id)
+                    .value(&tx.sender_data)
+                    .value(&tx.receiver_data)
+                    .f64(tx.fee)
+                    .f64(tx.size)
+                    .u64(tx.timestamp)
+                    .str(&tx.narration);
+            }
+            Transaction::Coinbase(tx) => {
+                encoder
+                    .u8(COINBASE_TAG)
+                    .str(&tx.id)
+                    .str(&tx.receiver)
+                    .f64(tx.amount)
+                    .u64(tx.height);
+            }
+        }
+    }
+}
+
+impl Decode for Transaction {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError> {
+        let version = decoder.u8()?;
+        if version != TRANSACTION_VERSION {
+            return Err(CodecError::UnsupportedVersion {
+                kind: "transaction",
+                version: version.into(),
+            });
+        }
+
+        let status = TransactionStatus::Pending.as_str().to_string();
+        match decoder.u8()? {
+            PLAIN_TAG => Ok(Transaction::Plain(PlainTransaction {
+                id: decoder.string()?,
+                sender: decoder.string()?,
+                receiver: decoder.string()?,
+                amount: decoder.f64()?,
+                fee: decoder.f64()?,
+                size: decoder.f64()?,
+                timestamp: decoder.u64()?,
+                narration: decoder.string()?,
+                nonce: decoder.u64()?,
+                sender_key: decoder.string()?,
+                sender_blinding: decoder.string()?,
+                signature: decoder.option(Decoder::string)?,
+                status,
+                tx_key: None,
+            })),
+            ENCRYPTED_TAG => Ok(Transaction::Encrypted(EncryptedTransaction {
+                id: decoder.string()?,
+                sender_data: decoder.value()?,
+                receiver_data: decoder.value()?,
+                fee: decoder.f64()?,
+                size: decoder.f64()?,
+                timestamp: decoder.u64()?,
+                narration: decoder.string()?,
+                status,
+            })),
+            COINBASE_TAG => Ok(Transaction::Coinbase(CoinbaseTransaction {
+                id: decoder.string()?,
+                receiver: decoder.string()?,
+                amount: decoder.f64()?,
+                height: decoder.u64()?,
+            })),
+            tag => Err(CodecError::InvalidTag {
+                kind: "transaction",
+                tag,
+            }),
+        }
+    }
+}
+
+impl Encode for TransactionPrimitive {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        match self {
+            TransactionPrimitive::Plain(data) => {
+                encoder
+                    .u8(0)
+                    .str(&data.sender)
+                    .str(&data.receiver)
+                    .f64(data.amount);
+            }
+            TransactionPrimitive::Encrypt(EncryptData::Plain(text)) => {
+                encoder.u8(1).str(text);
+            }
+            TransactionPrimitive::Encrypt(EncryptData::Vector(bytes)) => {
+                encoder.u8(2).bytes(bytes);
+            }
+        }
+    }
+}
+
+impl Decode for TransactionPrimitive {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError> {
+        match decoder.u8()? {
+            0 => Ok(TransactionPrimitive::Plain(TransactionData {
+                sender: decoder.string()?,
+                receiver: decoder.string()?,
+                amount: decoder.f64()?,
+            })),
+            1 => Ok(TransactionPrimitive::Encrypt(EncryptData::Plain(
+                decoder.string()?,
+            ))),
+            2 => Ok(TransactionPrimitive::Encrypt(EncryptData::Vector(
+                decoder.bytes()?.to_vec(),
+            ))),
+            tag => Err(CodecError::InvalidTag {
+                kind: "transaction data",
+                tag,
+            }),
+        }
+    }
+}
+
+impl EncryptedTransaction {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn fee(&self) -> f64 {
+        self.fee
+    }
+
+    pub fn size(&self) -> f64 {
+        self.size
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+}
+
+impl PlainTransaction {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn sender(&self) -> &str {
+        &self.sender
+    }
+
+    pub fn receiver(&self) -> &str {
+        &self.receiver
+    }
+
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    pub fn fee(&self) -> f64 {
+        self.fee
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn sender_key(&self) -> &str {
+        &self.sender_key
+    }
+
+    pub fn sender_blinding(&self) -> &str {
+        &self.sender_blinding
+    }
+
+    pub fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+
+    /// Everything the sender commits to: the canonical encoding without the id and the
+    /// signature.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.encode_unsigned(&mut encoder);
+        encoder.into_bytes()
+    }
+
+    /// The id a transfer must carry, the hash of its signing bytes, so the same id can't
+    /// name different contents.
+    pub fn content_id(&self) -> String {
+        blake3::hash(&self.signing_bytes()).to_hex().to_string()
+    }
+
+    fn encode_unsigned(&self, encoder: &mut Encoder) {
+        encoder
+            .str(&self.sender)
+            .str(&self.receiver)
+            .f64(self.amount)
+            .f64(self.fee)
+            .f64(self.size)
+            .u64(self.timestamp)
+            .str(&self.narration)
+            .u64(self.nonce)
+            .str(&self.sender_key)
+            .str(&self.sender_blinding);
+    }
+
+    /// Signs the transaction with the sender's private key as the sender's `nonce`-th transfer.
+    /// The public key and blinding that open the sender address go along, so any node can
+    /// check the signature without knowing the account.
+    pub fn sign(&mut self, private_key: &str, nonce: u64) -> Result<(), TransactionError> {
+        let (sender_key, sender_blinding) =
+            Wallet::opening(private_key).map_err(TransactionError::Signing)?;
+        self.nonce = nonce;
+        self.sender_key = sender_key;
+        self.sender_blinding = sender_blinding;
+        self.id = self.content_id();
+        let signature =
+            KeyPair::sign(private_key, &self.signing_bytes()).map_err(TransactionError::Signing)?;
+        self.signature = Some(signature);
+        Ok(())
+    }
+
+    pub fn verify_signature(&self, public_key: &str) -> Result<bool, TransactionError> {
+        let signature = match &self.signature {
+            Some(signature) => signature,
+            None => return Ok(false),
+        };
+        KeyPair::verify_signature(public_key, &self.signing_bytes(), signature)
+            .map_err(TransactionError::Signing)
+    }
 }
 
 impl Transaction {
-    fn ledger() -> Storage {
-        Storage::init().unwrap()
+    pub fn id(&self) -> &str {
+        match self {
+            Transaction::Plain(tx) => &tx.id,
+            Transaction::Encrypted(tx) => &tx.id,
+            Transaction::Coinbase(tx) => &tx.id,
+        }
+    }
+
+    pub fn coinbase(receiver: String, amount: f64, height: u64) -> Self {
+        Transaction::Coinbase(CoinbaseTransaction::new(receiver, amount, height))
+    }
+
+    /// Fee paid to the miner; only plain transfers carry one.
+    pub fn fee(&self) -> f64 {
+        match self {
+            Transaction::Plain(tx) => tx.fee,
+            Transaction::Encrypted(_) | Transaction::Coinbase(_) => 0.0,
+        }
+    }
+
+    pub fn sign(&mut self, private_key: &str, nonce: u64) -> Result<(), TransactionError> {
+        match self {
+            Transaction::Plain(tx) => tx.sign(private_key, nonce),
+            Transaction::Encrypted(_) | Transaction::Coinbase(_) => {
+                Err(TransactionError::Unsignable)
+            }
+        }
     }
 
     pub fn init(sender: String, receiver: String, amount: f64, narration: String) -> Self {
-        let mut hasher = Hasher::new();
         let timestamp = Utc::now().timestamp() as u64;
-        hasher.update(&timestamp.to_be_bytes());
-        let id = hasher.finalize().to_hex().to_string();
-        let id: String = hex::encode(id);
-
-        let size = Self::calculate_size_in_byte(
-            &id,
-            &sender,
-            &receiver,
-            amount,
-            narration.clone(),
-            timestamp,
-        );
+        let size =
+            Self::calculate_size_in_byte(&sender, &receiver, amount, narration.clone(), timestamp);
         let fee = Self::calculate_dynamic_fee(size);
 
         let status = TransactionStatus::as_str(&TransactionStatus::Pending);
         let status = status.to_string();
 
-        let transaction = PlainTransaction {
-            id,
+        let mut transaction = PlainTransaction {
+            id: String::new(),
             sender,
             receiver,
             amount,
@@ -96,13 +414,18 @@ impl Transaction {
             narration,
             status,
             tx_key: None,
+            nonce: 0,
+            sender_key: String::new(),
+            sender_blinding: String::new(),
+            signature: None,
         };
+        // Signing sets the nonce and recomputes the id.
+        transaction.id = transaction.content_id();
 
         Transaction::Plain(transaction)
     }
 
     fn calculate_size_in_byte(
-        id: &str,
         sender: &str,
         receiver: &str,
         amount: f64,
@@ -113,7 +436,7 @@ impl Transaction {
         let status = status.to_string();
 
         let temp_transaction = PlainTransaction {
-            id: id.to_string(),
+            id: String::new(),
             sender: sender.to_string(),
             receiver: receiver.to_string(),
             amount,
@@ -123,6 +446,10 @@ impl Transaction {
             timestamp,
             status,
             tx_key: None,
+            nonce: 0,
+            sender_key: String::new(),
+            sender_blinding: String::new(),
+            signature: None,
         };
 
         let size_bytes = mem::size_of_val(&temp_transaction);
@@ -132,11 +459,10 @@ impl Transaction {
     }
 
     fn calculate_dynamic_fee(size: f64) -> f64 {
-        let base_fee_per_byte = config::BASE_FEE_PER_BYTE as f64;
+        let base_fee_per_byte = config::BASE_FEE_PER_BYTE;
         let max_supply = config::MAX_SUPPLY as f64;
         let network_congestion_factor = Self::get_network_congestion_factor();
-        let fee = size * base_fee_per_byte * network_congestion_factor / max_supply;
-        fee
+        size * base_fee_per_byte * network_congestion_factor / max_supply
     }
 
     fn get_network_congestion_factor() -> f64 {
@@ -146,7 +472,7 @@ impl Transaction {
         let moderate_congestion = 1000;
         let high_congestion = 2000;
 
-        let congestion_factor = if recent_tx_count <= low_congestion {
+        if recent_tx_count <= low_congestion {
             config::LOW_CONGESTION
         } else if recent_tx_count <= moderate_congestion {
             config::MODERATE_CONGESTION
@@ -154,9 +480,7 @@ impl Transaction {
             config::HIGH_CONGESTION
         } else {
             config::NORMAL_CONGESTION
-        };
-
-        congestion_factor
+        }
     }
 
     fn get_recent_transaction_count() -> u64 {
@@ -164,11 +488,13 @@ impl Transaction {
         rng.gen_range(0..10000) // Simulated transaction count (0 to 10000)
     }
 
-    pub fn process_transaction(data: PlainTransaction) -> Result<Transaction, String> {
+    pub fn process_transaction(
+        store: &Storage,
+        data: PlainTransaction,
+    ) -> Result<Transaction, TransactionError> {
         let mut _data = data.to_owned();
 
-        let store = Self::ledger();
-        let key = bincode::serialize(&data.id).map_err(|e| e.to_string())?;
+        let key = bincode::serialize(&data.id)?;
         let tx_data = TransactionData {
             sender: data.sender,
             amount: data.amount,
@@ -177,16 +503,16 @@ impl Transaction {
 
         let _tx_data = tx_data.clone();
 
-        let receiver_key = Account::get_account_index(tx_data.sender)?;
-        let receiver_data = bincode::serialize(&_tx_data).map_err(|e| e.to_string())?;
+        let receiver_key = Account::get_account_index(store, tx_data.sender)?;
+        let receiver_data = bincode::serialize(&_tx_data)?;
         let receiver_data = Crypto::encrypt(receiver_data, Some(receiver_key))
-            .map_err(|e| format!("Encryption failed: {}", e))?;
+            .map_err(TransactionError::Encryption)?;
         let receiver_data: TransactionPrimitive =
             TransactionPrimitive::Encrypt(EncryptData::Vector(receiver_data.data));
 
-        let sender_data = bincode::serialize(&_tx_data).map_err(|e| e.to_string())?;
+        let sender_data = bincode::serialize(&_tx_data)?;
         let sender_data =
-            Crypto::encrypt(sender_data, None).map_err(|e| format!("Encryption failed: {}", e))?;
+            Crypto::encrypt(sender_data, None).map_err(TransactionError::Encryption)?;
         let tx_key = sender_data.key;
         let sender_data: TransactionPrimitive =
             TransactionPrimitive::Encrypt(EncryptData::Vector(sender_data.data));
@@ -202,24 +528,64 @@ impl Transaction {
             status: data.status,
         };
 
-        let value = bincode::serialize(&tx_serialize).map_err(|e| e.to_string())?;
+        let value = schema::encode_record(&tx_serialize)?;
         let cf = StorageKind::Transaction.name();
-        store.put(cf, &key, &value, true)?;
+        let mut batch = StorageBatch::new();
+        batch
+            .insert(cf, &key, &value)
+            .record_metric(Metric::Transactions, 1.0, data.timestamp)?
+            .record_metric(Metric::Volume, data.amount, data.timestamp)?
+            .record_metric(Metric::Fees, data.fee, data.timestamp)?;
+        store.write(batch)?;
 
         _data.tx_key = tx_key;
         let tx = Transaction::Plain(_data);
         Ok(tx)
     }
 
-    pub fn get_transaction(tx_id: String, tx_key: Option<String>) -> Result<Transaction, String> {
-        let store = Self::ledger();
+    /// Stages a status change for a stored transaction. Transactions that never went through
+    /// `process_transaction` have no record and are left alone.
+    pub fn stage_status(
+        store: &Storage,
+        batch: &mut StorageBatch,
+        tx_id: &str,
+        status: TransactionStatus,
+    ) -> Result<(), TransactionError> {
+        let key = bincode::serialize(tx_id)?;
+        let cf = StorageKind::Transaction.name();
+        let value = match store.get(cf, &key) {
+            Ok(value) => value,
+            Err(StorageError::NotFound) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
 
-        let key = bincode::serialize(&tx_id).map_err(|e| e.to_string())?;
+        let mut encrypted_tx: EncryptedTransaction = schema::decode_record(&value)?;
+        encrypted_tx.status = status.as_str().to_string();
+        batch.put(cf, &key, &schema::encode_record(&encrypted_tx)?);
+        Ok(())
+    }
+
+    /// The stored record of a transaction as it is kept, with its parties and amount still
+    /// encrypted.
+    pub fn get_record(
+        store: &Storage,
+        tx_id: &str,
+    ) -> Result<EncryptedTransaction, TransactionError> {
+        let key = bincode::serialize(tx_id)?;
+        let value = store.get(StorageKind::Transaction.name(), &key)?;
+        Ok(schema::decode_record(&value)?)
+    }
+
+    pub fn get_transaction(
+        store: &Storage,
+        tx_id: String,
+        tx_key: Option<String>,
+    ) -> Result<Transaction, TransactionError> {
+        let key = bincode::serialize(&tx_id)?;
         let cf = StorageKind::Transaction.name();
         let value = store.get(cf, &key)?;
 
-        let encrypted_tx: EncryptedTransaction =
-            bincode::deserialize(&value).map_err(|e| e.to_string())?;
+        let encrypted_tx: EncryptedTransaction = schema::decode_record(&value)?;
 
         let sender_data = match encrypted_tx.sender_data {
             TransactionPrimitive::Encrypt(EncryptData::Vector(ref encrypted_sender)) => {
@@ -227,11 +593,10 @@ impl Transaction {
                     encrypted_sender.clone(),
                     tx_key.as_deref().unwrap_or_default(),
                 )
-                .map_err(|e| format!("Sender decryption failed: {}", e))?;
-                bincode::deserialize::<TransactionData>(&decrypted_sender.data)
-                    .map_err(|e| e.to_string())?
+                .map_err(TransactionError::SenderDecryption)?;
+                bincode::deserialize::<TransactionData>(&decrypted_sender.data)?
             }
-            _ => return Err("Invalid sender data format".to_string()),
+            _ => return Err(TransactionError::InvalidSenderData),
         };
 
         let receiver_data = match encrypted_tx.receiver_data {
@@ -240,11 +605,10 @@ impl Transaction {
                     encrypted_receiver.clone(),
                     tx_key.as_deref().unwrap_or_default(),
                 )
-                .map_err(|e| format!("Receiver decryption failed: {}", e))?;
-                bincode::deserialize::<TransactionData>(&decrypted_receiver.data)
-                    .map_err(|e| e.to_string())?
+                .map_err(TransactionError::ReceiverDecryption)?;
+                bincode::deserialize::<TransactionData>(&decrypted_receiver.data)?
             }
-            _ => return Err("Invalid receiver data format".to_string()),
+            _ => return Err(TransactionError::InvalidReceiverData),
         };
 
         let tx = Transaction::Encrypted(EncryptedTransaction {
@@ -262,17 +626,15 @@ impl Transaction {
     }
 
     pub fn get_transaction_details(
+        store: &Storage,
         tx_id: String,
         tx_key: Option<String>,
-    ) -> Result<Transaction, String> {
-        let store = Self::ledger();
-
-        let key = bincode::serialize(&tx_id).map_err(|e| e.to_string())?;
+    ) -> Result<Transaction, TransactionError> {
+        let key = bincode::serialize(&tx_id)?;
         let cf = StorageKind::Transaction.name();
         let value = store.get(cf, &key)?;
 
-        let encrypted_tx: EncryptedTransaction =
-            bincode::deserialize(&value).map_err(|e| e.to_string())?;
+        let encrypted_tx: EncryptedTransaction = schema::decode_record(&value)?;
 
         let sender_data = match encrypted_tx.sender_data {
             TransactionPrimitive::Encrypt(EncryptData::Vector(ref encrypted_sender)) => {
@@ -280,11 +642,10 @@ impl Transaction {
                     encrypted_sender.clone(),
                     tx_key.as_deref().unwrap_or_default(),
                 )
-                .map_err(|e| format!("Sender decryption failed: {}", e))?;
-                bincode::deserialize::<TransactionData>(&decrypted_sender.data)
-                    .map_err(|e| e.to_string())?
+                .map_err(TransactionError::SenderDecryption)?;
+                bincode::deserialize::<TransactionData>(&decrypted_sender.data)?
             }
-            _ => return Err("Invalid sender data format".to_string()),
+            _ => return Err(TransactionError::InvalidSenderData),
         };
 
         let receiver_data = match encrypted_tx.receiver_data {
@@ -293,11 +654,10 @@ impl Transaction {
                     encrypted_receiver.clone(),
                     tx_key.as_deref().unwrap_or_default(),
                 )
-                .map_err(|e| format!("Receiver decryption failed: {}", e))?;
-                bincode::deserialize::<TransactionData>(&decrypted_receiver.data)
-                    .map_err(|e| e.to_string())?
+                .map_err(TransactionError::ReceiverDecryption)?;
+                bincode::deserialize::<TransactionData>(&decrypted_receiver.data)?
             }
-            _ => return Err("Invalid receiver data format".to_string()),
+            _ => return Err(TransactionError::InvalidReceiverData),
         };
 
         let tx = Transaction::Encrypted(EncryptedTransaction {
@@ -0,0 +1,196 @@
+use crate::util::error::CodecError;
+
+/// Canonical binary encoding for consensus data: blocks, headers and transactions hash,
+/// sign, persist and travel in this form.
+///
+/// Integers and floats are fixed-width big-endian (floats by their IEEE-754 bits), strings
+/// and byte strings are a `u32` big-endian length followed by the bytes, options are a `0`
+/// or `1` byte followed by the value, and enums start with a one-byte tag. Top-level types
+/// start with their own version so the layout can change without guessing.
+pub trait Encode {
+    fn encode_to(&self, encoder: &mut Encoder);
+
+    fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.encode_to(&mut encoder);
+        encoder.into_bytes()
+    }
+}
+
+pub trait Decode: Sized {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, CodecError>;
+
+    /// Decodes a value that must span all of `bytes`.
+    fn decode(bytes: &[u8]) -> Result<Self, CodecError> {
+        let mut decoder = Decoder::new(bytes);
+        let value = Self::decode_from(&mut decoder)?;
+        decoder.finish()?;
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes.push(value);
+        self
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn i64(&mut self, value: i64) -> &mut Self {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn f64(&mut self, value: f64) -> &mut Self {
+        self.u64(value.to_bits())
+    }
+
+    /// Length-prefixed bytes.
+    ///
+    /// # Panics
+    /// If `value` is longer than `u32::MAX`, far above any block size limit.
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        let len = u32::try_from(value.len()).expect("encoded field exceeds u32::MAX bytes");
+        self.u32(len);
+        self.bytes.extend_from_slice(value);
+        self
+    }
+
+    pub fn str(&mut self, value: &str) -> &mut Self {
+        self.bytes(value.as_bytes())
+    }
+
+    pub fn option<T>(
+        &mut self,
+        value: Option<T>,
+        encode: impl FnOnce(&mut Self, T) -> &mut Self,
+    ) -> &mut Self {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                encode(self, value)
+            }
+            None => self.u8(0),
+        }
+    }
+
+    pub fn value(&mut self, value: &impl Encode) -> &mut Self {
+        value.encode_to(self);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn finish(self) -> Result<(), CodecError> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            trailing => Err(CodecError::TrailingBytes(trailing)),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CodecError> {
+        if self.bytes.len() < len {
+            return Err(CodecError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, CodecError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, CodecError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, CodecError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, CodecError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, CodecError> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, CodecError> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], CodecError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn string(&mut self) -> Result<String, CodecError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| CodecError::InvalidUtf8)
+    }
+
+    pub fn option<T>(
+        &mut self,
+        decode: impl FnOnce(&mut Self) -> Result<T, CodecError>,
+    ) -> Result<Option<T>, CodecError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(decode(self)?)),
+            tag => Err(CodecError::InvalidTag {
+                kind: "option",
+                tag,
+            }),
+        }
+    }
+
+    pub fn value<T: Decode>(&mut self) -> Result<T, CodecError> {
+        T::decode_from(self)
+    }
+}
@@ -1,9 +1,31 @@
 pub const MAX_SUPPLY: u64 = 1_000_000_000;
+pub const INITIAL_BLOCK_REWARD: f64 = 475.0;
+pub const HALVING_INTERVAL: u64 = 1_051_200;
 pub const MAX_BLOCK_SIZE_BYTES: u64 = 1_000_000;
+/// Room left in a block template for the header and the coinbase.
+pub const BLOCK_OVERHEAD_BYTES: u64 = 4_096;
 pub const BLOCK_TIME_SECONDS: u64 = 120;
-pub const DIFFICULTY_WINDOW: usize = 720;
-pub const MIN_DIFFICULTY: usize = 1_000;
+pub const DIFFICULTY_WINDOW: usize = 90;
+pub const MIN_DIFFICULTY: u64 = 1_000;
+pub const MAX_DIFFICULTY_ADJUSTMENT: u64 = 2;
 pub const ADJUSTMENT_INTERVAL: usize = 10;
+pub const MAX_REORG_DEPTH: u64 = 100;
+pub const MEDIAN_TIME_SPAN: usize = 11;
+pub const MAX_FUTURE_DRIFT_SECONDS: i64 = 540;
+pub const MEMPOOL_MAX_TRANSACTIONS: usize = 5_000;
+pub const P2P_PORT: u16 = 7464;
+pub const MAX_ORPHAN_BLOCKS: usize = 100;
+pub const MAX_OUTBOUND_PEERS: usize = 8;
+pub const MAX_INBOUND_PEERS: usize = 32;
+pub const MAX_KNOWN_PEER_ADDRESSES: usize = 2_000;
+pub const BAN_SCORE_THRESHOLD: u32 = 100;
+pub const BAN_DURATION_SECONDS: i64 = 24 * 60 * 60;
+pub const RPC_PORT: u16 = 7465;
+pub const EXPLORER_PORT: u16 = 7466;
+pub const WS_PORT: u16 = 7467;
+pub const MAX_WS_CONNECTIONS: usize = 64;
+/// Events queued for a subscriber before it is dropped for falling behind.
+pub const EVENT_QUEUE_CAPACITY: usize = 1_024;
 pub const BASE_FEE_PER_BYTE: f64 = 1.0;
 pub const FEE_MULTIPLIER: f64 = 1.0;
 pub const LOW_CONGESTION: f64 = 0.8;
@@ -14,3 +36,8 @@ pub const CONTRACT_BASE_FEE: u64 = 2;
 pub const CONTRACT_MINIMUM_LIMIT: u64 = 1_000_000_000;
 pub const CONTRACT_MAXIMUM_SIZE: u64 = 5_000_000_000;
 pub const DB_PATH: &str = ".valtoria";
+pub const BACKUP_PATH: &str = ".valtoria-backups";
+pub const PASSPHRASE_ENV: &str = "VALTORIA_PASSPHRASE";
+pub const RPC_TOKEN_ENV: &str = "VALTORIA_RPC_TOKEN";
+/// File in the database directory holding the generated RPC token when none is configured.
+pub const RPC_COOKIE_FILE: &str = "rpc.cookie";
@@ -1,3 +1,329 @@
-use thiserror;
+use thiserror::Error;
 
-pub struct AccountError {}
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("DB lock error")]
+    Lock,
+    #[error("Column family {0} not found")]
+    ColumnFamilyNotFound(String),
+    #[error("Key not found")]
+    NotFound,
+    #[error("Record exists! Mutating value is not allowed.")]
+    RecordExists,
+    #[error("Invalid cursor")]
+    InvalidCursor,
+    #[error("Could not merge value for key {0}")]
+    Merge(String),
+    #[error("Invalid record envelope")]
+    InvalidRecord,
+    #[error("Unsupported record version {0}")]
+    UnsupportedRecordVersion(u16),
+    #[error("Database schema version {0} is newer than this node supports")]
+    SchemaTooNew(u32),
+    #[error("{0} are not supported by this storage backend")]
+    Unsupported(&'static str),
+    #[error("Invalid storage passphrase")]
+    InvalidPassphrase,
+    #[error("Database is encrypted; a passphrase is required to open it")]
+    Encrypted,
+    #[error("Database holds unencrypted records and can't be opened with a passphrase")]
+    Unencrypted,
+    #[error("Encryption error: {0}")]
+    Crypto(#[from] CryptoError),
+    #[error("Analytics doesn't exist")]
+    AnalyticsNotFound,
+    #[error("Database error: {0}")]
+    Database(#[from] rocksdb::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CodecError {
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+    #[error("{0} trailing bytes after the encoded value")]
+    TrailingBytes(usize),
+    #[error("Invalid UTF-8 in string field")]
+    InvalidUtf8,
+    #[error("Invalid {kind} tag {tag}")]
+    InvalidTag { kind: &'static str, tag: u8 },
+    #[error("Unsupported {kind} version {version}")]
+    UnsupportedVersion { kind: &'static str, version: u64 },
+}
+
+#[derive(Debug, Error)]
+pub enum CryptoError {
+    #[error("Invalid key encoding")]
+    InvalidKeyEncoding,
+    #[error("Key must be 32 bytes long for ChaCha20-Poly1305.")]
+    InvalidKeyLength,
+    #[error("Invalid private key encoding")]
+    InvalidPrivateKey,
+    #[error("Invalid encrypted data length.")]
+    InvalidDataLength,
+    #[error("Encryption failed")]
+    EncryptionFailed,
+    #[error("Decryption failed")]
+    DecryptionFailed,
+    #[error("Invalid signature encoding")]
+    InvalidSignature,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum AddressError {
+    #[error("Invalid Base58 encoding")]
+    Encoding,
+    #[error("Invalid address length")]
+    Length,
+    #[error("Invalid address checksum")]
+    Checksum,
+    #[error("Address is not a public key")]
+    NotAPoint,
+}
+
+#[derive(Debug, Error)]
+pub enum AccountError {
+    #[error("{0}")]
+    InvalidAddress(#[from] AddressError),
+    #[error("Balance type is not binary")]
+    InvalidBalanceType,
+    #[error("Invalid balance: {0}")]
+    InvalidBalance(String),
+    #[error("Account {0} not found")]
+    NotFound(String),
+    #[error("The private key does not belong to {0}")]
+    NotOwner(String),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum TransactionError {
+    #[error("Insufficient funds: available {available}, required {required}")]
+    InsufficientFunds { available: f64, required: f64 },
+    #[error("Invalid sender data format")]
+    InvalidSenderData,
+    #[error("Invalid receiver data format")]
+    InvalidReceiverData,
+    #[error("Encryption failed: {0}")]
+    Encryption(CryptoError),
+    #[error("Sender decryption failed: {0}")]
+    SenderDecryption(CryptoError),
+    #[error("Receiver decryption failed: {0}")]
+    ReceiverDecryption(CryptoError),
+    #[error("Signing failed: {0}")]
+    Signing(CryptoError),
+    #[error("Only plain transactions can be signed")]
+    Unsignable,
+    #[error("Transaction {0} not found")]
+    NotFound(String),
+    #[error(transparent)]
+    Account(#[from] AccountError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+}
+
+/// Why a block failed validation.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum BlockRejection {
+    #[error("height {found} does not follow parent height {parent}")]
+    InvalidHeight { parent: u64, found: u64 },
+    #[error("header hash does not match the header")]
+    HashMismatch,
+    #[error("hash does not meet difficulty {0}")]
+    InsufficientProofOfWork(u64),
+    #[error("timestamp {timestamp} is not after the median time past {median}")]
+    TimestampTooOld { timestamp: i64, median: i64 },
+    #[error("timestamp {timestamp} is more than {max_drift}s ahead of local time {now}")]
+    TimestampTooNew {
+        timestamp: i64,
+        now: i64,
+        max_drift: i64,
+    },
+    #[error("block is {size} bytes, above the {max} byte limit")]
+    TooLarge { size: u64, max: u64 },
+    #[error("declared size {declared} does not match the {actual} bytes of transactions")]
+    SizeMismatch { declared: u64, actual: u64 },
+    #[error("merkle root does not match the transactions")]
+    MerkleRootMismatch,
+    #[error("transaction {0} appears more than once")]
+    DuplicateTransaction(String),
+    #[error("transaction {0} does not match its content id")]
+    IdMismatch(String),
+    #[error("transaction {0} is encrypted and can't be validated")]
+    EncryptedTransaction(String),
+    #[error("transaction {0} has an invalid amount or fee")]
+    InvalidAmount(String),
+    #[error("transaction {0} has a missing or invalid signature")]
+    InvalidSignature(String),
+    #[error("transaction {0} is signed with a key that does not own the sender address")]
+    SenderKeyMismatch(String),
+    #[error("transaction {id} has nonce {found}, expected {expected}")]
+    InvalidNonce {
+        id: String,
+        expected: u64,
+        found: u64,
+    },
+    #[error("transaction {id} spends {required} but the sender only has {available}")]
+    InsufficientFunds {
+        id: String,
+        available: f64,
+        required: f64,
+    },
+    #[error("transaction {id} references unknown account {address}")]
+    UnknownAccount { id: String, address: String },
+    #[error("block has no coinbase transaction")]
+    MissingCoinbase,
+    #[error("coinbase transaction {0} is not the first transaction")]
+    MisplacedCoinbase(String),
+    #[error("coinbase is for height {found}, expected {expected}")]
+    CoinbaseHeightMismatch { expected: u64, found: u64 },
+    #[error("coinbase pays {claimed} but at most {allowed} is allowed")]
+    ExcessiveCoinbase { claimed: f64, allowed: f64 },
+    #[error("issued supply {supply} would exceed the maximum of {max}")]
+    SupplyExceeded { supply: f64, max: u64 },
+    #[error("difficulty is {found}, expected {expected}")]
+    UnexpectedDifficulty { expected: u64, found: u64 },
+    #[error("unsupported block version {0}")]
+    UnsupportedVersion(u64),
+    #[error("state root {found} does not match the computed {expected}")]
+    StateRootMismatch { expected: String, found: String },
+}
+
+#[derive(Debug, Error)]
+pub enum ChainError {
+    #[error("Invalid hash for block {0}")]
+    InvalidHash(u64),
+    #[error("Invalid previous hash for block {0}")]
+    InvalidPrevHash(u64),
+    #[error("Invalid proof of work")]
+    InvalidProofOfWork,
+    #[error("Block {hash} rejected: {reason}")]
+    InvalidBlock {
+        hash: String,
+        reason: BlockRejection,
+    },
+    #[error("Maximum supply reached")]
+    MaxSupplyReached,
+    #[error("Block {0} is already known")]
+    DuplicateBlock(String),
+    #[error("Unknown parent block {0}")]
+    UnknownParent(String),
+    #[error("Block {0} not found")]
+    UnknownBlock(String),
+    #[error("Reorganization of {depth} blocks exceeds the maximum depth of {max}")]
+    ReorgTooDeep { depth: u64, max: u64 },
+    #[error("Invalid genesis configuration: {0}")]
+    InvalidGenesis(String),
+    #[error("Stored genesis block {found} does not match the configured genesis {expected}")]
+    GenesisMismatch { expected: String, found: String },
+    #[error(transparent)]
+    Transaction(#[from] TransactionError),
+    #[error(transparent)]
+    Account(#[from] AccountError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("Encoding error: {0}")]
+    Codec(#[from] CodecError),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum MempoolError {
+    #[error("Transaction {0} is already in the mempool")]
+    Duplicate(String),
+    #[error("Sender {sender} already has a pending transaction with nonce {nonce}")]
+    NonceInUse { sender: String, nonce: u64 },
+    #[error("Mempool is full ({0} transactions)")]
+    Full(usize),
+    #[error("Transaction rejected: {0}")]
+    Invalid(BlockRejection),
+    #[error(transparent)]
+    Account(#[from] AccountError),
+}
+
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("Peer speaks protocol version {0}")]
+    UnsupportedProtocol(u32),
+    #[error("Peer is on network {found}, expected {expected}")]
+    WrongNetwork { expected: String, found: String },
+    #[error("Peer has genesis block {found}, expected {expected}")]
+    WrongGenesis { expected: String, found: String },
+    #[error("Unexpected {0} message")]
+    UnexpectedMessage(&'static str),
+    #[error("Message of {0} bytes exceeds the size limit")]
+    MessageTooLarge(usize),
+    #[error("Peer sent an invalid public key")]
+    InvalidPeerKey,
+    #[error("Malformed transport handshake message")]
+    MalformedHandshake,
+    #[error("Peer identity is {found}, expected {expected}")]
+    UnexpectedIdentity { expected: String, found: String },
+    #[error("Refusing to connect to ourselves")]
+    SelfConnection,
+    #[error("Already connected to {0}")]
+    AlreadyConnected(String),
+    #[error("Peer {0} is banned")]
+    Banned(std::net::IpAddr),
+    #[error("Already at the limit of {0} peers")]
+    TooManyPeers(usize),
+    #[error("Node is shut down")]
+    ShutDown,
+    #[error("Too many messages on one link")]
+    NonceExhausted,
+    #[error("Lock error")]
+    Lock,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Encoding error: {0}")]
+    Codec(#[from] CodecError),
+    #[error(transparent)]
+    Chain(#[from] ChainError),
+    #[error(transparent)]
+    Mempool(#[from] MempoolError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+    #[error(transparent)]
+    Account(#[from] AccountError),
+    #[error(transparent)]
+    Transaction(#[from] TransactionError),
+    #[error(transparent)]
+    Chain(#[from] ChainError),
+    #[error(transparent)]
+    Mempool(#[from] MempoolError),
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+    #[error(transparent)]
+    Api(#[from] ApiError),
+}
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("Refusing to serve the API on {0}, which is not a loopback address")]
+    NotLoopback(std::net::SocketAddr),
+    #[error("At least one auth token is required")]
+    NoTokens,
+    #[error("Could not start the HTTP server: {0}")]
+    Http(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
@@ -1,3 +1,4 @@
+use crate::util::error::CryptoError;
 use chacha20poly1305::{
     aead::{Aead, KeyInit},
     ChaCha20Poly1305, Key, Nonce,
@@ -12,14 +13,14 @@ pub struct Crypto {
 }
 
 impl Crypto {
-    pub fn encrypt(data: Vec<u8>, encryption_key: Option<String>) -> Result<Crypto, String> {
+    pub fn encrypt(data: Vec<u8>, encryption_key: Option<String>) -> Result<Crypto, CryptoError> {
         let mut rng = rand::thread_rng();
         let key = match encryption_key {
             Some(key_str) => {
                 let key_bytes =
-                    hex::decode(key_str).map_err(|_| "Invalid key encoding".to_string())?;
+                    hex::decode(key_str).map_err(|_| CryptoError::InvalidKeyEncoding)?;
                 if key_bytes.len() != 32 {
-                    return Err("Key must be 32 bytes long for ChaCha20-Poly1305.".into());
+                    return Err(CryptoError::InvalidKeyLength);
                 }
                 key_bytes
             }
@@ -37,7 +38,7 @@ impl Crypto {
         let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
         let ciphertext = cipher
             .encrypt(nonce, data.as_ref())
-            .map_err(|_| "Encryption failed".to_string())?;
+            .map_err(|_| CryptoError::EncryptionFailed)?;
 
         // Combine nonce with ciphertext
         let combined_data = [nonce.as_slice(), ciphertext.as_slice()].concat();
@@ -48,14 +49,14 @@ impl Crypto {
         })
     }
 
-    pub fn decrypt(encrypted_data: Vec<u8>, key: &str) -> Result<Crypto, String> {
-        let key_bytes = hex::decode(key).map_err(|_| "Invalid key encoding".to_string())?;
+    pub fn decrypt(encrypted_data: Vec<u8>, key: &str) -> Result<Crypto, CryptoError> {
+        let key_bytes = hex::decode(key).map_err(|_| CryptoError::InvalidKeyEncoding)?;
         if key_bytes.len() != 32 {
-            return Err("Key must be 32 bytes long for ChaCha20-Poly1305.".into());
+            return Err(CryptoError::InvalidKeyLength);
         }
 
         if encrypted_data.len() < 12 {
-            return Err("Invalid encrypted data length.".into());
+            return Err(CryptoError::InvalidDataLength);
         }
 
         let (nonce_bytes, ciphertext) = encrypted_data.split_at(12);
@@ -64,7 +65,7 @@ impl Crypto {
 
         let decrypted_data = cipher
             .decrypt(nonce, ciphertext)
-            .map_err(|_| "Decryption failed".to_string())?;
+            .map_err(|_| CryptoError::DecryptionFailed)?;
 
         Ok(Crypto {
             data: decrypted_data,
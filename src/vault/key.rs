@@ -1,9 +1,13 @@
+use crate::util::error::CryptoError;
 use arrayref::array_ref;
 use curve25519_dalek::constants;
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
 use rand::rngs::OsRng;
 use rand::RngCore;
+use sha2::{Digest, Sha512};
+
+const SIGNATURE_DOMAIN: &[u8] = b"valtoria-schnorr-v1";
 
 #[derive(Debug, Clone)]
 pub struct KeyPair {
@@ -18,7 +22,7 @@ impl KeyPair {
         csprng.fill_bytes(&mut random_bytes);
 
         let private_key = Scalar::from_bytes_mod_order(random_bytes);
-        let public_key = private_key * &constants::RISTRETTO_BASEPOINT_POINT;
+        let public_key = private_key * constants::RISTRETTO_BASEPOINT_POINT;
 
         KeyPair {
             public_key,
@@ -26,14 +30,95 @@ impl KeyPair {
         }
     }
 
-    pub fn verify(private_key: &str) -> Result<String, &'static str> {
-        let private_key_bytes =
-            hex::decode(private_key).map_err(|_| "Invalid private key encoding")?;
-        let private_key_array = array_ref![private_key_bytes, 0, 32];
-        let private_key_scalar = Scalar::from_bytes_mod_order(*private_key_array);
-        let public_key = &private_key_scalar * &constants::RISTRETTO_BASEPOINT_POINT;
+    /// Rebuilds a key pair from its hex encoded private key.
+    pub fn from_private_key(private_key: &str) -> Result<Self, CryptoError> {
+        let private_key = Self::parse_private_key(private_key)?;
+        Ok(KeyPair {
+            public_key: private_key * constants::RISTRETTO_BASEPOINT_POINT,
+            private_key,
+        })
+    }
+
+    pub fn verify(private_key: &str) -> Result<String, CryptoError> {
+        let private_key_scalar = Self::parse_private_key(private_key)?;
+        let public_key = private_key_scalar * constants::RISTRETTO_BASEPOINT_POINT;
         let public_key = hex::encode(public_key.compress().to_bytes());
 
         Ok(public_key)
     }
+
+    /// Schnorr signature over Ristretto, hex encoded as `R || s`. The nonce is derived from the
+    /// key and message, so signing the same message twice gives the same signature.
+    pub fn sign(private_key: &str, message: &[u8]) -> Result<String, CryptoError> {
+        let private_key_scalar = Self::parse_private_key(private_key)?;
+        let public_key = private_key_scalar * constants::RISTRETTO_BASEPOINT_POINT;
+
+        let nonce = hash_to_scalar(&[private_key_scalar.as_bytes(), message]);
+        let commitment = (nonce * constants::RISTRETTO_BASEPOINT_POINT).compress();
+        let challenge = hash_to_scalar(&[
+            commitment.as_bytes(),
+            public_key.compress().as_bytes(),
+            message,
+        ]);
+        let response = nonce + challenge * private_key_scalar;
+
+        let mut signature = commitment.to_bytes().to_vec();
+        signature.extend_from_slice(response.as_bytes());
+        Ok(hex::encode(signature))
+    }
+
+    /// Checks a signature made by `sign` against the hex encoded compressed public key.
+    pub fn verify_signature(
+        public_key: &str,
+        message: &[u8],
+        signature: &str,
+    ) -> Result<bool, CryptoError> {
+        let public_key_bytes =
+            hex::decode(public_key).map_err(|_| CryptoError::InvalidKeyEncoding)?;
+        let public_key = CompressedRistretto::from_slice(&public_key_bytes)
+            .map_err(|_| CryptoError::InvalidKeyLength)?;
+        let public_point = public_key
+            .decompress()
+            .ok_or(CryptoError::InvalidKeyEncoding)?;
+
+        let signature = hex::decode(signature).map_err(|_| CryptoError::InvalidSignature)?;
+        if signature.len() != 64 {
+            return Err(CryptoError::InvalidSignature);
+        }
+        let commitment = CompressedRistretto(*array_ref![signature, 0, 32]);
+        let response = match Option::<Scalar>::from(Scalar::from_canonical_bytes(*array_ref![
+            signature, 32, 32
+        ])) {
+            Some(response) => response,
+            None => return Ok(false),
+        };
+        let commitment_point = match commitment.decompress() {
+            Some(point) => point,
+            None => return Ok(false),
+        };
+
+        let challenge = hash_to_scalar(&[commitment.as_bytes(), public_key.as_bytes(), message]);
+        Ok(response * constants::RISTRETTO_BASEPOINT_POINT
+            == commitment_point + challenge * public_point)
+    }
+
+    fn parse_private_key(private_key: &str) -> Result<Scalar, CryptoError> {
+        let private_key_bytes =
+            hex::decode(private_key).map_err(|_| CryptoError::InvalidPrivateKey)?;
+        if private_key_bytes.len() != 32 {
+            return Err(CryptoError::InvalidPrivateKey);
+        }
+        let private_key_array = array_ref![private_key_bytes, 0, 32];
+        Ok(Scalar::from_bytes_mod_order(*private_key_array))
+    }
+}
+
+fn hash_to_scalar(parts: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha512::new();
+    hasher.update(SIGNATURE_DOMAIN);
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
 }
@@ -0,0 +1,111 @@
+use curve::account::account::BalanceType;
+use curve::account::wallet::Wallet;
+use curve::account::Account;
+use curve::store::Storage;
+use curve::util::error::{AccountError, AddressError, CryptoError, StorageError};
+use curve::vault::Crypto;
+
+#[test]
+fn malformed_addresses_report_why() {
+    let store = Storage::memory();
+    let mut corrupted = bs58::decode(Wallet::new().address).into_vec().unwrap();
+    corrupted[0] ^= 1;
+
+    let cases = [
+        ("0OIl".to_string(), AddressError::Encoding),
+        (bs58::encode([1u8; 12]).into_string(), AddressError::Length),
+        (
+            bs58::encode(corrupted).into_string(),
+            AddressError::Checksum,
+        ),
+    ];
+    for (address, reason) in cases {
+        let checked = Wallet::check_address(&address).unwrap_err();
+        assert!(
+            matches!(checked, AccountError::InvalidAddress(found) if found == reason),
+            "{address}: {checked}"
+        );
+        assert!(matches!(
+            Account::state_of(&store, &address),
+            Err(AccountError::InvalidAddress(found)) if found == reason
+        ));
+        assert!(matches!(
+            Account::get_account(&store, address.clone()),
+            Err(AccountError::InvalidAddress(found)) if found == reason
+        ));
+    }
+}
+
+#[test]
+fn key_and_cipher_failures_are_typed() {
+    assert!(matches!(
+        Wallet::verify("not hex"),
+        Err(CryptoError::InvalidPrivateKey)
+    ));
+    assert!(matches!(
+        Wallet::verify(&"00".repeat(31)),
+        Err(CryptoError::InvalidPrivateKey)
+    ));
+
+    let key = "11".repeat(32);
+    let sealed = Crypto::encrypt(b"balance".to_vec(), Some(key.clone())).unwrap();
+    assert!(matches!(
+        Crypto::encrypt(Vec::new(), Some("11".repeat(8))),
+        Err(CryptoError::InvalidKeyLength)
+    ));
+    assert!(matches!(
+        Crypto::decrypt(sealed.data.clone(), "zz"),
+        Err(CryptoError::InvalidKeyEncoding)
+    ));
+    assert!(matches!(
+        Crypto::decrypt(vec![0; 4], &key),
+        Err(CryptoError::InvalidDataLength)
+    ));
+    assert!(matches!(
+        Crypto::decrypt(sealed.data.clone(), &"22".repeat(32)),
+        Err(CryptoError::DecryptionFailed)
+    ));
+    assert_eq!(Crypto::decrypt(sealed.data, &key).unwrap().data, b"balance");
+}
+
+#[test]
+fn accounts_are_read_through_the_given_store() {
+    let dir = std::env::temp_dir().join(format!("curve-accounts-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = Storage::open(&dir, Storage::default_options()).unwrap();
+    let created = Account::new(&store).unwrap();
+
+    let account = Account::get_account(&store, created.address.clone()).unwrap();
+    assert!(matches!(account.balance, BalanceType::Text(_)));
+    let details = Account::get_account_details(&store, created.private_key).unwrap();
+    assert!(matches!(details.balance, BalanceType::Decimal(balance) if balance == 0.0));
+
+    assert!(matches!(
+        Account::get_account(&store, Wallet::new().address),
+        Err(AccountError::Storage(StorageError::NotFound))
+    ));
+    assert!(matches!(
+        Account::get_account_details(&store, "not hex".to_string()),
+        Err(AccountError::Crypto(CryptoError::InvalidPrivateKey))
+    ));
+    drop(store);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn account_pages_redact_balances() {
+    let store = Storage::memory();
+    for _ in 0..3 {
+        Account::new(&store).unwrap();
+    }
+
+    let first = Account::get_accounts(&store, None, 2).unwrap();
+    assert_eq!(first.items.len(), 2);
+    let rest = Account::get_accounts(&store, first.next_cursor, 2).unwrap();
+    assert_eq!(rest.items.len(), 1);
+    assert!(rest.next_cursor.is_none());
+    for account in first.items.iter().chain(&rest.items) {
+        assert!(matches!(account.balance, BalanceType::Text(_)));
+    }
+    assert_eq!(Account::total_accounts(&store).unwrap(), 3);
+}
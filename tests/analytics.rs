@@ -0,0 +1,74 @@
+use curve::store::{Bucket, Metric, MetricPoint, Storage, StorageBatch, StorageKind};
+
+// 2023-11-14 22:13:20 UTC, 800 seconds into its hour and 80,000 into its day.
+const NOW: u64 = 1_700_000_000;
+const HOUR: u64 = NOW - 800;
+const DAY: u64 = NOW - 80_000;
+
+fn point(bucket_start: u64, value: f64) -> MetricPoint {
+    MetricPoint {
+        bucket_start,
+        value,
+    }
+}
+
+#[test]
+fn metrics_sum_into_their_buckets() {
+    let store = Storage::memory();
+    for (value, timestamp) in [
+        (1.5, NOW),
+        (2.0, NOW + 10),
+        (4.0, NOW + 3_600),
+        (8.0, NOW + 7_200),
+    ] {
+        let mut batch = StorageBatch::new();
+        batch
+            .record_metric(Metric::Volume, value, timestamp)
+            .unwrap();
+        store.write(batch).unwrap();
+    }
+
+    assert_eq!(
+        store
+            .get_analytics_series(Metric::Volume, Bucket::Hour, NOW, NOW + 7_200)
+            .unwrap(),
+        vec![
+            point(HOUR, 3.5),
+            point(HOUR + 3_600, 4.0),
+            point(HOUR + 7_200, 8.0)
+        ]
+    );
+    assert_eq!(
+        store
+            .get_analytics_series(Metric::Volume, Bucket::Hour, NOW + 3_600, NOW + 3_600)
+            .unwrap(),
+        vec![point(HOUR + 3_600, 4.0)]
+    );
+    assert_eq!(
+        store
+            .get_analytics_series(Metric::Volume, Bucket::Day, 0, u64::MAX)
+            .unwrap(),
+        vec![point(DAY, 7.5), point(DAY + 86_400, 8.0)]
+    );
+    assert_eq!(store.get_metric_total(Metric::Volume).unwrap(), 15.5);
+    assert_eq!(store.get_metric_total(Metric::Fees).unwrap(), 0.0);
+}
+
+#[test]
+fn write_counters_count_only_inserts() {
+    let store = Storage::memory();
+    let cf = StorageKind::Transaction.name();
+
+    let mut batch = StorageBatch::new();
+    batch
+        .insert(cf, b"a", b"1")
+        .insert(cf, b"b", b"1")
+        .put(cf, b"c", b"1");
+    store.write(batch).unwrap();
+    store.put(cf, b"a", b"2", false).unwrap();
+    let mut batch = StorageBatch::new();
+    batch.delete(cf, b"b").insert(cf, b"d", b"1");
+    store.write(batch).unwrap();
+
+    assert_eq!(store.get_analytics(cf.as_bytes()).unwrap(), 3);
+}
@@ -0,0 +1,61 @@
+use curve::store::{backup, Storage, StorageKind};
+use std::path::{Path, PathBuf};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("curve-backup-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn value_in(dir: &Path) -> Vec<u8> {
+    let store = Storage::open(dir, Storage::default_options()).unwrap();
+    store.get(StorageKind::Meta.name(), b"key").unwrap()
+}
+
+#[test]
+fn backups_restore_the_state_they_captured() {
+    let (db, backups) = (temp_dir("db"), temp_dir("backups"));
+    let (first, latest, checkpoint) = (
+        temp_dir("first"),
+        temp_dir("latest"),
+        temp_dir("checkpoint"),
+    );
+    let cf = StorageKind::Meta.name();
+    {
+        let store = Storage::open(&db, Storage::default_options()).unwrap();
+        store.put(cf, b"key", b"first", false).unwrap();
+        assert_eq!(store.create_backup(&backups).unwrap().id, 1);
+        store.put(cf, b"key", b"second", false).unwrap();
+        assert_eq!(store.create_backup(&backups).unwrap().id, 2);
+        store.create_checkpoint(&checkpoint).unwrap();
+        // A checkpoint never overwrites an existing directory.
+        assert!(store.create_checkpoint(&checkpoint).is_err());
+    }
+
+    let ids: Vec<_> = backup::list_backups(&backups)
+        .unwrap()
+        .iter()
+        .map(|info| info.id)
+        .collect();
+    assert_eq!(ids, vec![1, 2]);
+    backup::verify_backup(&backups, 1).unwrap();
+    assert!(backup::verify_backup(&backups, 3).is_err());
+
+    backup::restore_backup(&backups, Some(1), &first).unwrap();
+    backup::restore_backup(&backups, None, &latest).unwrap();
+    assert_eq!(value_in(&first), b"first");
+    assert_eq!(value_in(&latest), b"second");
+    assert_eq!(value_in(&checkpoint), b"second");
+
+    backup::purge_backups(&backups, 1).unwrap();
+    let ids: Vec<_> = backup::list_backups(&backups)
+        .unwrap()
+        .iter()
+        .map(|info| info.id)
+        .collect();
+    assert_eq!(ids, vec![2]);
+
+    for dir in [db, backups, first, latest, checkpoint] {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
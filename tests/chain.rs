@@ -0,0 +1,168 @@
+mod common;
+
+use common::{funded_genesis, open};
+use curve::account::wallet::Wallet;
+use curve::account::Account;
+use curve::chain::emission::{block_subsidy, capped_subsidy};
+use curve::chain::{Block, BlockOutcome, Blockchain, GenesisConfig};
+use curve::config::{HALVING_INTERVAL, INITIAL_BLOCK_REWARD, MAX_SUPPLY};
+use curve::store::Storage;
+use curve::tx::Transaction;
+use curve::util::error::{BlockRejection, ChainError};
+
+/// Mines `count` blocks paying `miner`, spaced a minute apart.
+fn mine(chain: &mut Blockchain, miner: &Wallet, count: usize) {
+    for _ in 0..count {
+        let mut block = chain.next_block(&miner.address, vec![]).unwrap();
+        block.header.timestamp = chain.tip().header.timestamp + 60;
+        block.mine();
+        chain.add_block(block).unwrap();
+    }
+}
+
+#[test]
+fn heavier_branches_take_over_the_chain() {
+    let (honest, other) = (Wallet::new(), Wallet::new());
+    let genesis = funded_genesis(&[&honest, &other], 1.0);
+    let (mut chain, store) = open(&genesis);
+    mine(&mut chain, &honest, 2);
+    let (mut rival, _) = open(&genesis);
+    mine(&mut rival, &other, 3);
+
+    let branch = &rival.blocks[1..];
+    for block in &branch[..2] {
+        assert!(matches!(
+            chain.add_block(block.clone()).unwrap(),
+            BlockOutcome::SideChain
+        ));
+    }
+    assert_eq!(chain.height(), 2);
+    assert_eq!(
+        Account::balance_of(&store, &honest.address).unwrap(),
+        1.0 + 2.0 * INITIAL_BLOCK_REWARD
+    );
+    assert!(matches!(
+        chain.add_block(branch[0].clone()),
+        Err(ChainError::DuplicateBlock(_))
+    ));
+
+    match chain.add_block(branch[2].clone()).unwrap() {
+        BlockOutcome::Reorganized {
+            depth,
+            disconnected,
+        } => {
+            assert_eq!(depth, 2);
+            let heights: Vec<_> = disconnected.iter().map(|b| b.header.index).collect();
+            assert_eq!(heights, vec![2, 1]);
+        }
+        outcome => panic!("expected a reorganization, got {outcome:?}"),
+    }
+    assert_eq!(chain.tip().header.hash, rival.tip().header.hash);
+    assert_eq!(chain.supply(), rival.supply());
+    assert_eq!(Account::balance_of(&store, &honest.address).unwrap(), 1.0);
+    assert_eq!(
+        Account::balance_of(&store, &other.address).unwrap(),
+        1.0 + 3.0 * INITIAL_BLOCK_REWARD
+    );
+
+    // The switch is persisted along with the side chain it came from.
+    let reopened = Blockchain::open(&store, &genesis).unwrap();
+    assert_eq!(reopened.tip().header.hash, rival.tip().header.hash);
+    assert_eq!(reopened.blocks.len(), 4);
+}
+
+#[test]
+fn reorganizations_deeper_than_the_limit_are_refused() {
+    let (honest, other) = (Wallet::new(), Wallet::new());
+    let genesis = funded_genesis(&[&honest, &other], 1.0);
+    let (chain, store) = open(&genesis);
+    let mut chain = chain.max_reorg_depth(1);
+    mine(&mut chain, &honest, 2);
+    let (mut rival, _) = open(&genesis);
+    mine(&mut rival, &other, 4);
+
+    let tip = chain.tip().header.hash.clone();
+    for block in &rival.blocks[1..3] {
+        chain.add_block(block.clone()).unwrap();
+    }
+    assert!(matches!(
+        chain.add_block(rival.blocks[3].clone()),
+        Err(ChainError::ReorgTooDeep { depth: 2, max: 1 })
+    ));
+    assert_eq!(chain.tip().header.hash, tip);
+    assert_eq!(Account::balance_of(&store, &other.address).unwrap(), 1.0);
+    assert!(matches!(
+        chain.add_block(rival.blocks[4].clone()),
+        Err(ChainError::UnknownParent(_))
+    ));
+}
+
+#[test]
+fn subsidies_halve_and_stop_at_the_cap() {
+    assert_eq!(block_subsidy(0), 0.0);
+    assert_eq!(block_subsidy(1), INITIAL_BLOCK_REWARD);
+    assert_eq!(block_subsidy(HALVING_INTERVAL), INITIAL_BLOCK_REWARD);
+    assert_eq!(
+        block_subsidy(HALVING_INTERVAL + 1),
+        INITIAL_BLOCK_REWARD / 2.0
+    );
+    assert_eq!(block_subsidy(64 * HALVING_INTERVAL + 1), 0.0);
+
+    let scheduled: f64 = (0..64)
+        .map(|halving| block_subsidy(halving * HALVING_INTERVAL + 1) * HALVING_INTERVAL as f64)
+        .sum();
+    assert!(scheduled <= MAX_SUPPLY as f64);
+    let max = MAX_SUPPLY as f64;
+    assert_eq!(capped_subsidy(1, max - 10.0), 10.0);
+    assert_eq!(capped_subsidy(1, max), 0.0);
+}
+
+#[test]
+fn coinbases_pay_the_subsidy_plus_fees() {
+    let store = Storage::memory();
+    let mut chain = Blockchain::open(&store, &GenesisConfig::default()).unwrap();
+    let miner = Account::new(&store).unwrap();
+    let receiver = Account::new(&store).unwrap();
+    let mut block = chain.next_block(&miner.address, vec![]).unwrap();
+    block.mine();
+    chain.add_block(block).unwrap();
+
+    let mut tx = Transaction::init(
+        miner.address.clone(),
+        receiver.address.clone(),
+        1.0,
+        String::new(),
+    );
+    tx.sign(&miner.private_key, 0).unwrap();
+    let fee = tx.fee();
+    assert!(fee > 0.0);
+
+    let mut block = chain.next_block(&miner.address, vec![tx]).unwrap();
+    let Transaction::Coinbase(coinbase) = &block.transactions[0] else {
+        panic!("blocks start with their coinbase")
+    };
+    assert_eq!(coinbase.amount(), INITIAL_BLOCK_REWARD + fee);
+    block.mine();
+    chain.add_block(block).unwrap();
+    // Fees move existing coins, so only the subsidy adds to the supply.
+    assert_eq!(chain.supply(), 2.0 * INITIAL_BLOCK_REWARD);
+    let balance = Account::balance_of(&store, &miner.address).unwrap();
+    assert!((balance - (2.0 * INITIAL_BLOCK_REWARD - 1.0)).abs() < 1e-9);
+
+    let tip = chain.tip().clone();
+    let mut greedy = Block::new(
+        3,
+        tip.header.hash.clone(),
+        tip.header.difficulty,
+        vec![Transaction::coinbase(miner.address.clone(), 1_000.0, 3)],
+    );
+    greedy.header.timestamp = tip.header.timestamp + 60;
+    greedy.mine();
+    assert!(matches!(
+        chain.add_block(greedy),
+        Err(ChainError::InvalidBlock {
+            reason: BlockRejection::ExcessiveCoinbase { allowed, .. },
+            ..
+        }) if allowed == INITIAL_BLOCK_REWARD
+    ));
+}
@@ -0,0 +1,174 @@
+use clap::Parser;
+use curve::account::wallet::Wallet;
+use curve::account::Account;
+use curve::chain::{Blockchain, GenesisAllocation, GenesisConfig};
+use curve::cli::cli::{self, Cli};
+use curve::store::Storage;
+use curve::tx::Mempool;
+use curve::util::error::Error;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("curve-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) -> Result<(), Error> {
+    let cli = Cli::try_parse_from(
+        ["curve", "--data-dir", dir.to_str().unwrap()]
+            .iter()
+            .chain(args),
+    )
+    .unwrap();
+    cli::run(cli)
+}
+
+fn open(dir: &Path) -> Storage {
+    Storage::open(dir, Storage::default_options()).unwrap()
+}
+
+#[test]
+fn sent_transfers_wait_in_the_data_dir_until_mined() {
+    let dir = data_dir("mine");
+    let sender = Wallet::new();
+    let receiver = Wallet::new();
+    let mut genesis = GenesisConfig::default();
+    genesis.allocations.push(GenesisAllocation {
+        address: sender.address.clone(),
+        public_key: sender.public_key.clone(),
+        blinding: sender.blinding.clone(),
+        amount: 1_000.0,
+    });
+    let genesis_path = dir.join("genesis.json");
+    std::fs::write(&genesis_path, serde_json::to_string(&genesis).unwrap()).unwrap();
+    let genesis_arg = ["--genesis", genesis_path.to_str().unwrap()];
+
+    for amount in ["10", "5"] {
+        let send = [
+            "tx",
+            "send",
+            "--from",
+            &sender.address,
+            "--to",
+            &receiver.address,
+            "--amount",
+            amount,
+            "--private-key",
+            &sender.private_key,
+        ];
+        run(&dir, &[&genesis_arg[..], &send[..]].concat()).unwrap();
+    }
+    let ids = {
+        let store = open(&dir);
+        let mut mempool = Mempool::default();
+        assert_eq!(mempool.load(&store).unwrap(), 2);
+        mempool.ids()
+    };
+
+    let mine = ["mine", "--address", &receiver.address];
+    run(&dir, &[&genesis_arg[..], &mine[..]].concat()).unwrap();
+    {
+        let store = open(&dir);
+        let chain = Blockchain::open(&store, &genesis).unwrap();
+        assert_eq!(chain.height(), 1);
+        for id in &ids {
+            assert_eq!(chain.find_transaction(id).unwrap().0.header.index, 1);
+        }
+        assert_eq!(Mempool::default().load(&store).unwrap(), 0);
+        assert!(Account::balance_of(&store, &receiver.address).unwrap() > 15.0);
+    }
+
+    for id in &ids {
+        run(&dir, &[&genesis_arg[..], &["tx", "status", id]].concat()).unwrap();
+    }
+    run(&dir, &[&genesis_arg[..], &["chain", "validate"]].concat()).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn accounts_are_looked_up_by_address() {
+    let dir = data_dir("account");
+    let account = Account::new(&open(&dir)).unwrap();
+
+    run(&dir, &["account", "get", &account.address]).unwrap();
+    assert!(matches!(
+        run(&dir, &["account", "get", &account.private_key]),
+        Err(Error::Account(_))
+    ));
+    run(&dir, &["account", "list", "--limit", "1"]).unwrap();
+    assert!(Cli::try_parse_from(["curve", "account", "list", "--cursor", "0OIl"]).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unknown_transactions_and_blocks_are_errors() {
+    let dir = data_dir("unknown");
+    let missing_tx = run(&dir, &["tx", "get", "ff"]).unwrap_err();
+    assert!(matches!(missing_tx, Error::Transaction(_)));
+    assert_eq!(cli::exit_code(&missing_tx), cli::EXIT_NOT_FOUND);
+    let missing_block = run(&dir, &["chain", "block", "7"]).unwrap_err();
+    assert!(matches!(missing_block, Error::Chain(_)));
+    assert_eq!(cli::exit_code(&missing_block), cli::EXIT_NOT_FOUND);
+    run(&dir, &["chain", "block", "0"]).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn curve(dir: &Path, args: &[&str]) -> std::process::Output {
+    std::process::Command::new(env!("CARGO_BIN_EXE_curve"))
+        .arg("--data-dir")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn json(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes).unwrap()
+}
+
+#[test]
+fn json_output_follows_the_documented_schemas() {
+    let dir = data_dir("json");
+
+    let created = curve(&dir, &["--output", "json", "account", "create"]);
+    assert!(created.status.success());
+    let created = json(&created.stdout);
+    for field in ["address", "public_key", "private_key"] {
+        assert!(created[field].is_string());
+    }
+    assert!(created["timestamp"].is_u64());
+
+    let block = curve(&dir, &["chain", "block", "0", "--output", "json"]);
+    assert!(block.status.success());
+    let block = json(&block.stdout);
+    assert_eq!(block["height"], 0);
+    assert!(block["hash"].is_string());
+    assert!(block["transactions"].is_array());
+
+    let total = curve(&dir, &["account", "total", "--output", "plain"]);
+    assert_eq!(String::from_utf8(total.stdout).unwrap().trim(), "0");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn failures_exit_with_their_code() {
+    let dir = data_dir("exit");
+
+    let missing = curve(&dir, &["--output", "json", "tx", "get", "ff"]);
+    assert_eq!(missing.status.code(), Some(cli::EXIT_NOT_FOUND.into()));
+    assert!(missing.stdout.is_empty());
+    assert_eq!(json(&missing.stderr)["error"], "Transaction ff not found");
+
+    let invalid = curve(&dir, &["mine", "--address", "not-an-address"]);
+    assert_eq!(invalid.status.code(), Some(cli::EXIT_REJECTED.into()));
+    assert!(String::from_utf8(invalid.stderr)
+        .unwrap()
+        .starts_with("Error: "));
+
+    let usage = curve(&dir, &["--output", "yaml", "chain", "info"]);
+    assert_eq!(usage.status.code(), Some(2));
+    std::fs::remove_dir_all(dir).unwrap();
+}
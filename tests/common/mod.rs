@@ -0,0 +1,41 @@
+//! Helpers shared by the integration tests. Not every test crate uses all of them.
+#![allow(dead_code)]
+
+use curve::account::wallet::Wallet;
+use curve::chain::{Blockchain, GenesisAllocation, GenesisConfig};
+use curve::store::Storage;
+use curve::tx::Transaction;
+
+/// A genesis allocating `amount` to each of `wallets`, so chains opened from it in separate
+/// stores share their accounts.
+pub fn funded_genesis(wallets: &[&Wallet], amount: f64) -> GenesisConfig {
+    let mut genesis = GenesisConfig::default();
+    for wallet in wallets {
+        genesis.allocations.push(GenesisAllocation {
+            address: wallet.address.clone(),
+            public_key: wallet.public_key.clone(),
+            blinding: wallet.blinding.clone(),
+            amount,
+        });
+    }
+    genesis
+}
+
+/// A chain opened from `genesis` in a fresh in-memory store.
+pub fn open(genesis: &GenesisConfig) -> (Blockchain, Storage) {
+    let store = Storage::memory();
+    let chain = Blockchain::open(&store, genesis).unwrap();
+    (chain, store)
+}
+
+/// A fresh chain whose genesis gives `wallet` 1,000 coins.
+pub fn funded(wallet: &Wallet) -> (Blockchain, Storage) {
+    open(&funded_genesis(&[wallet], 1_000.0))
+}
+
+/// A plain transfer of `amount` from `from` to `to`, signed with `nonce`.
+pub fn transfer(from: &Wallet, to: &str, amount: f64, nonce: u64) -> Transaction {
+    let mut tx = Transaction::init(from.address.clone(), to.to_string(), amount, String::new());
+    tx.sign(&from.private_key, nonce).unwrap();
+    tx
+}
@@ -0,0 +1,98 @@
+use curve::config::{BLOCK_TIME_SECONDS, DIFFICULTY_WINDOW, MIN_DIFFICULTY};
+use curve::consensus::pow::{self, BlockSample};
+use curve::consensus::simulator::{self, Algorithm, Scenario};
+
+const BLOCK_TIME: i64 = BLOCK_TIME_SECONDS as i64;
+const DIFFICULTY: u64 = 100_000;
+
+/// A full window of blocks at `DIFFICULTY`, each `solvetime` seconds after the last.
+fn spaced(solvetime: i64) -> Vec<BlockSample> {
+    (0..=DIFFICULTY_WINDOW as i64)
+        .map(|i| BlockSample {
+            timestamp: 1_000_000 + i * solvetime,
+            difficulty: DIFFICULTY,
+        })
+        .collect()
+}
+
+#[test]
+fn targets_and_difficulties_are_inverse() {
+    for difficulty in [1, MIN_DIFFICULTY, DIFFICULTY, u64::MAX / 3] {
+        let roundtrip = pow::difficulty_of(pow::target(difficulty));
+        assert!(roundtrip.abs_diff(difficulty) <= 1, "{difficulty}");
+    }
+    assert!(pow::meets_target(&"0".repeat(64), u64::MAX));
+    assert!(!pow::meets_target(&"f".repeat(64), 2));
+    assert!(!pow::meets_target("not hex", 1));
+}
+
+#[test]
+fn steady_blocks_keep_the_difficulty() {
+    let next = pow::next_difficulty(&spaced(BLOCK_TIME));
+    assert!(next.abs_diff(DIFFICULTY) <= DIFFICULTY / 100, "{next}");
+
+    assert_eq!(pow::next_difficulty(&[]), MIN_DIFFICULTY);
+    assert_eq!(pow::next_difficulty(&spaced(BLOCK_TIME)[..1]), DIFFICULTY);
+}
+
+#[test]
+fn adjustments_are_clamped() {
+    assert_eq!(pow::next_difficulty(&spaced(1)), 2 * DIFFICULTY);
+    assert_eq!(
+        pow::next_difficulty(&spaced(1_000 * BLOCK_TIME)),
+        DIFFICULTY / 2
+    );
+
+    let mut floor = spaced(1_000 * BLOCK_TIME);
+    for sample in &mut floor {
+        sample.difficulty = MIN_DIFFICULTY;
+    }
+    assert_eq!(pow::next_difficulty(&floor), MIN_DIFFICULTY);
+}
+
+#[test]
+fn timestamps_cannot_drag_the_difficulty_far() {
+    // One timestamp far in the future counts for at most six block times.
+    let mut lying = spaced(BLOCK_TIME);
+    let last = lying.len() - 1;
+    lying[last].timestamp += 1_000_000;
+    let capped = {
+        let mut samples = spaced(BLOCK_TIME);
+        samples[last].timestamp += 5 * BLOCK_TIME;
+        pow::next_difficulty(&samples)
+    };
+    assert_eq!(pow::next_difficulty(&lying), capped);
+    assert!(capped > DIFFICULTY * 9 / 10, "{capped}");
+
+    // Timestamps going backwards neither underflow nor raise the difficulty past the clamp.
+    let backwards: Vec<_> = spaced(BLOCK_TIME).into_iter().rev().collect();
+    assert!(pow::next_difficulty(&backwards) <= 2 * DIFFICULTY);
+}
+
+#[test]
+fn simulated_difficulty_moves_within_the_clamp() {
+    let scenario = Scenario::hashrate_swings(1_000);
+    let report = simulator::simulate(Algorithm::Lwma, &scenario, 7);
+    let replay = simulator::simulate(Algorithm::Lwma, &scenario, 7);
+    assert_eq!(report.difficulties, replay.difficulties);
+
+    for pair in report.difficulties.windows(2) {
+        assert!(pair[1] <= 2 * pair[0] && pair[1] >= pair[0] / 2, "{pair:?}");
+        assert!(pair[1] >= MIN_DIFFICULTY);
+    }
+}
+
+#[test]
+fn steady_hashrate_settles_on_the_block_time() {
+    let scenario = Scenario {
+        blocks: 2_000,
+        base_hashrate: 1_000.0,
+        swings: Vec::new(),
+    };
+    for seed in 0..3 {
+        let report = simulator::simulate(Algorithm::Lwma, &scenario, seed);
+        let settled = &report.solvetimes[500..];
+        let mean = settled.iter().sum::<f64>() / settled.len() as f64;
+        assert!((mean - BLOCK_TIME_SECONDS as f64).abs() < BLOCK_TIME_SECONDS as f64 / 10.0);
+    }
+}
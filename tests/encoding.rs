@@ -0,0 +1,128 @@
+use curve::account::wallet::Wallet;
+use curve::chain::{Block, BlockHeader, GenesisConfig};
+use curve::tx::Transaction;
+use curve::util::codec::{Decode, Encode};
+use curve::util::error::CodecError;
+
+fn sample_block() -> Block {
+    let sender = Wallet::new();
+    let receiver = Wallet::new();
+    let mut transfer = Transaction::init(
+        sender.address.clone(),
+        receiver.address.clone(),
+        12.5,
+        "rent".to_string(),
+    );
+    transfer.sign(&sender.private_key, 3).unwrap();
+    let unsigned = Transaction::init(sender.address, receiver.address.clone(), 1.0, String::new());
+
+    let transactions = vec![
+        Transaction::coinbase(receiver.address, 475.0, 7),
+        transfer,
+        unsigned,
+    ];
+    let mut block = Block::new(7, "ab".repeat(32), 1, transactions);
+    block.mine();
+    block
+}
+
+#[test]
+fn transactions_round_trip() {
+    for tx in sample_block().transactions {
+        let encoded = tx.encode();
+        let decoded = Transaction::decode(&encoded).unwrap();
+        assert_eq!(decoded.id(), tx.id());
+        assert_eq!(decoded.encode(), encoded);
+    }
+}
+
+#[test]
+fn blocks_round_trip_with_the_same_hash() {
+    let block = sample_block();
+    let encoded = block.encode();
+    let decoded = Block::decode(&encoded).unwrap();
+
+    assert_eq!(decoded.encode(), encoded);
+    assert_eq!(decoded.header.hash, block.header.hash);
+    assert_eq!(decoded.calculate_hash(), block.header.hash);
+    assert_eq!(
+        BlockHeader::decode(&block.header.encode())
+            .unwrap()
+            .encode(),
+        block.header.encode()
+    );
+}
+
+#[test]
+fn genesis_encoding_is_deterministic() {
+    let first = GenesisConfig::default().block().unwrap();
+    let second = GenesisConfig::default().block().unwrap();
+    assert_eq!(first.encode(), second.encode());
+    assert_eq!(
+        Block::decode(&first.encode()).unwrap().encode(),
+        first.encode()
+    );
+}
+
+#[test]
+fn coinbase_layout_is_fixed() {
+    let tx = Transaction::coinbase("addr".to_string(), 1.5, 2);
+    let mut expected = vec![1, 2];
+    expected.extend_from_slice(&64u32.to_be_bytes());
+    expected.extend_from_slice(tx.id().as_bytes());
+    expected.extend_from_slice(&4u32.to_be_bytes());
+    expected.extend_from_slice(b"addr");
+    expected.extend_from_slice(&1.5f64.to_bits().to_be_bytes());
+    expected.extend_from_slice(&2u64.to_be_bytes());
+    assert_eq!(tx.encode(), expected);
+}
+
+#[test]
+fn header_hash_covers_the_preimage_only() {
+    let block = sample_block();
+    assert_eq!(
+        block.header.hash,
+        blake3::hash(&block.header.preimage()).to_hex().to_string()
+    );
+    assert!(block
+        .header
+        .encode()
+        .starts_with(&BlockHeader::VERSION.to_be_bytes()));
+}
+
+#[test]
+fn malformed_input_is_rejected() {
+    let encoded = sample_block().encode();
+
+    assert_eq!(
+        Block::decode(&encoded[..encoded.len() - 1]).unwrap_err(),
+        CodecError::UnexpectedEnd
+    );
+
+    let mut trailing = encoded.clone();
+    trailing.push(0);
+    assert_eq!(
+        Block::decode(&trailing).unwrap_err(),
+        CodecError::TrailingBytes(1)
+    );
+
+    let mut future = encoded;
+    future[..8].copy_from_slice(&(BlockHeader::VERSION + 1).to_be_bytes());
+    assert_eq!(
+        Block::decode(&future).unwrap_err(),
+        CodecError::UnsupportedVersion {
+            kind: "block header",
+            version: BlockHeader::VERSION + 1,
+        }
+    );
+
+    let mut unknown_tag = Transaction::coinbase("addr".to_string(), 1.0, 1).encode();
+    unknown_tag[1] = 9;
+    assert_eq!(
+        Transaction::decode(&unknown_tag).unwrap_err(),
+        CodecError::InvalidTag {
+            kind: "transaction",
+            tag: 9,
+        }
+    );
+}
@@ -0,0 +1,103 @@
+use curve::store::analytics::metric_key;
+use curve::store::{
+    Bucket, EncryptedStore, KvStore, MemoryStore, Metric, ScanOptions, Storage, StorageBatch,
+    StorageKind,
+};
+use curve::util::error::StorageError;
+use std::sync::Arc;
+
+const PASSPHRASE: &str = "correct horse battery staple";
+
+fn encrypted(inner: &Arc<MemoryStore>, passphrase: &str, encrypt_keys: bool) -> Storage {
+    let store = EncryptedStore::open(inner.clone(), passphrase, encrypt_keys).unwrap();
+    Storage::new(Arc::new(store))
+}
+
+#[test]
+fn values_are_sealed_and_need_the_passphrase() {
+    let inner = Arc::new(MemoryStore::new());
+    let cf = StorageKind::Account.name();
+    encrypted(&inner, PASSPHRASE, false)
+        .put(cf, b"key", b"secret", false)
+        .unwrap();
+
+    let stored = inner.get(cf, b"key").unwrap();
+    assert!(!stored.windows(6).any(|window| window == b"secret"));
+    assert!(matches!(
+        EncryptedStore::open(inner.clone(), "wrong passphrase", false),
+        Err(StorageError::InvalidPassphrase)
+    ));
+    assert_eq!(
+        encrypted(&inner, PASSPHRASE, false)
+            .get(cf, b"key")
+            .unwrap(),
+        b"secret"
+    );
+}
+
+#[test]
+fn hidden_keys_still_scan_in_order() {
+    let inner = Arc::new(MemoryStore::new());
+    let store = encrypted(&inner, PASSPHRASE, true);
+    let cf = StorageKind::Account.name();
+    for key in [&b"b2"[..], b"a", b"b1", b"c"] {
+        store.put(cf, key, key, true).unwrap();
+    }
+    let mut batch = StorageBatch::new();
+    batch.record_metric(Metric::Fees, 0.5, 0).unwrap();
+    batch.record_metric(Metric::Fees, 0.25, 0).unwrap();
+    store.write(batch).unwrap();
+
+    let page = store.scan(cf, &ScanOptions::new(10).prefix(b"b")).unwrap();
+    let keys: Vec<_> = page.items.iter().map(|(key, _)| key.as_slice()).collect();
+    assert_eq!(keys, vec![&b"b1"[..], b"b2"]);
+    assert!(!inner.exists(cf, b"a").unwrap());
+    assert_eq!(store.get_metric_total(Metric::Fees).unwrap(), 0.75);
+    // The write counters stay readable by the backend that merges them.
+    assert_eq!(store.get_analytics(cf.as_bytes()).unwrap(), 4);
+}
+
+#[test]
+fn resolved_merges_keep_their_place_in_the_batch() {
+    let inner = Arc::new(MemoryStore::new());
+    let store = encrypted(&inner, PASSPHRASE, true);
+    let cf = StorageKind::Analytics.name();
+    let key = metric_key(Metric::Fees, Bucket::Total, 0);
+
+    let mut batch = StorageBatch::new();
+    batch.record_metric(Metric::Fees, 0.5, 0).unwrap();
+    batch.delete(cf, &key);
+    store.write(batch).unwrap();
+    assert!(!store.exists(cf, &key).unwrap());
+
+    let mut batch = StorageBatch::new();
+    batch.put(cf, &key, &bincode::serialize(&2.0f64).unwrap());
+    batch.record_metric(Metric::Fees, 0.5, 0).unwrap();
+    store.write(batch).unwrap();
+    assert_eq!(store.get_metric_total(Metric::Fees).unwrap(), 2.5);
+}
+
+#[test]
+fn plain_and_encrypted_databases_are_not_mixed_up() {
+    let plain = Arc::new(MemoryStore::new());
+    plain
+        .put(StorageKind::Meta.name(), b"key", b"value", false)
+        .unwrap();
+    assert!(matches!(
+        EncryptedStore::open(plain, PASSPHRASE, false),
+        Err(StorageError::Unencrypted)
+    ));
+
+    let dir = std::env::temp_dir().join(format!("curve-encrypted-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    drop(Storage::open_encrypted(&dir, Storage::default_options(), PASSPHRASE, false).unwrap());
+    assert!(matches!(
+        Storage::open(&dir, Storage::default_options()),
+        Err(StorageError::Encrypted)
+    ));
+    assert!(matches!(
+        Storage::open_encrypted(&dir, Storage::default_options(), "wrong", false),
+        Err(StorageError::InvalidPassphrase)
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
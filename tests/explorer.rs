@@ -0,0 +1,154 @@
+use curve::account::wallet::Wallet;
+use curve::account::Account;
+use curve::api::{ExplorerConfig, ExplorerServer};
+use curve::chain::{GenesisAllocation, GenesisConfig};
+use curve::net::{Node, NodeConfig};
+use curve::store::Storage;
+use curve::tx::Transaction;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+fn setup() -> (Wallet, Node, ExplorerServer) {
+    let sender = Wallet::new();
+    let mut genesis = GenesisConfig::default();
+    genesis.allocations.push(GenesisAllocation {
+        address: sender.address.clone(),
+        public_key: sender.public_key.clone(),
+        blinding: sender.blinding.clone(),
+        amount: 1_000.0,
+    });
+    let config = NodeConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        discover: false,
+        ..NodeConfig::default()
+    };
+    let node = Node::start(Storage::memory(), &genesis, config).unwrap();
+    let server = ExplorerServer::start(
+        node.clone(),
+        ExplorerConfig {
+            listen: "127.0.0.1:0".parse().unwrap(),
+        },
+    )
+    .unwrap();
+    (sender, node, server)
+}
+
+/// Sends `method path` and returns the HTTP status and the parsed body.
+fn request(addr: SocketAddr, method: &str, path: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        method, path, addr
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+fn get(server: &ExplorerServer, path: &str) -> Value {
+    let (status, body) = request(server.local_addr(), "GET", path);
+    assert_eq!(status, 200, "GET {} answered {}", path, body);
+    body
+}
+
+#[test]
+fn blocks_and_transactions_only_show_public_metadata() {
+    let (sender, node, server) = setup();
+    let receiver = Wallet::new();
+    let mut tx = Transaction::init(
+        sender.address.clone(),
+        receiver.address.clone(),
+        10.0,
+        String::new(),
+    );
+    tx.sign(&sender.private_key, 0).unwrap();
+    node.submit_transaction(tx.clone()).unwrap();
+
+    let pending = get(&server, &format!("/transactions/{}", tx.id()));
+    assert_eq!(pending["status"], "Pending");
+    assert_eq!(pending["block_height"], Value::Null);
+
+    let block = node.mine_block(&Wallet::new().address).unwrap();
+    let confirmed = get(&server, &format!("/transactions/{}", tx.id()));
+    assert_eq!(confirmed["id"], tx.id());
+    assert_eq!(confirmed["fee"], tx.fee());
+    assert_eq!(confirmed["status"], "Completed");
+    assert_eq!(confirmed["block_height"], 1);
+    assert_eq!(confirmed["block_hash"], block.header.hash);
+    assert!(confirmed["size"].as_u64().unwrap() > 0);
+    for private in ["sender", "receiver", "amount", "signature"] {
+        assert!(confirmed.get(private).is_none(), "{} is disclosed", private);
+    }
+
+    let by_height = get(&server, "/blocks/1");
+    assert_eq!(by_height["hash"], block.header.hash);
+    assert_eq!(by_height["transaction_count"], 2);
+    assert_eq!(by_height["transactions"][1]["id"], tx.id());
+    assert!(by_height["transactions"][1].get("amount").is_none());
+    let by_hash = get(&server, &format!("/blocks/{}", block.header.hash));
+    assert_eq!(by_hash, by_height);
+    assert_eq!(get(&server, "/blocks/latest"), by_height);
+    assert_eq!(get(&server, "/blocks/0")["height"], 0);
+
+    let (status, body) = request(server.local_addr(), "GET", "/blocks/7");
+    assert_eq!(status, 404);
+    assert!(body["error"].is_string());
+    let (status, _) = request(server.local_addr(), "GET", "/transactions/nope");
+    assert_eq!(status, 404);
+}
+
+#[test]
+fn accounts_show_existence_and_creation_time_only() {
+    let (_, node, server) = setup();
+    let created = Account::new(node.store()).unwrap();
+
+    let account = get(&server, &format!("/accounts/{}", created.address));
+    assert_eq!(account["exists"], true);
+    assert_eq!(account["created_at"], created.timestamp);
+    assert!(account["balance"].as_str().unwrap().contains("Encrypted"));
+    assert!(account.get("private_key").is_none());
+
+    let unknown = get(&server, &format!("/accounts/{}", Wallet::new().address));
+    assert_eq!(unknown["exists"], false);
+    assert_eq!(unknown["created_at"], Value::Null);
+
+    let (status, _) = request(server.local_addr(), "GET", "/accounts/not-an-address");
+    assert_eq!(status, 400);
+}
+
+#[test]
+fn stats_come_from_the_chain_and_analytics() {
+    let (_, node, server) = setup();
+    Account::new(node.store()).unwrap();
+    Account::new(node.store()).unwrap();
+    // Genesis funds one account and the miner's is created by its first coinbase.
+    node.mine_block(&Wallet::new().address).unwrap();
+
+    let stats = get(&server, "/stats");
+    assert_eq!(stats["height"], 1);
+    assert_eq!(stats["supply"], node.chain().unwrap().supply());
+    assert_eq!(stats["max_supply"], curve::config::MAX_SUPPLY);
+    assert_eq!(stats["totals"]["accounts_created"], 4.0);
+    assert_eq!(stats["totals"]["fees"], 0.0);
+
+    let series = get(&server, "/stats/accounts_created?bucket=hour");
+    assert_eq!(series["bucket"], "hour");
+    let points = series["points"].as_array().unwrap();
+    let total: f64 = points.iter().map(|p| p["value"].as_f64().unwrap()).sum();
+    // The genesis allocation is bucketed at the genesis timestamp, outside the recent hours.
+    assert_eq!(total, 3.0);
+
+    let (status, _) = request(server.local_addr(), "GET", "/stats/unknown");
+    assert_eq!(status, 404);
+    let (status, _) = request(server.local_addr(), "GET", "/stats/fees?bucket=week");
+    assert_eq!(status, 400);
+    let (status, _) = request(server.local_addr(), "GET", "/stats/fees?from=9&to=1");
+    assert_eq!(status, 400);
+    let (status, _) = request(server.local_addr(), "POST", "/stats");
+    assert_eq!(status, 405);
+}
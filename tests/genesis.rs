@@ -0,0 +1,128 @@
+use curve::account::wallet::Wallet;
+use curve::account::Account;
+use curve::chain::{Blockchain, GenesisAllocation, GenesisConfig};
+use curve::config::{MAX_SUPPLY, MIN_DIFFICULTY};
+use curve::store::Storage;
+use curve::util::codec::Encode;
+use curve::util::error::ChainError;
+
+fn allocation(wallet: &Wallet, amount: f64) -> GenesisAllocation {
+    GenesisAllocation {
+        address: wallet.address.clone(),
+        public_key: wallet.public_key.clone(),
+        blinding: wallet.blinding.clone(),
+        amount,
+    }
+}
+
+fn with(allocations: Vec<GenesisAllocation>) -> Result<(), ChainError> {
+    let genesis = GenesisConfig {
+        allocations,
+        ..GenesisConfig::default()
+    };
+    genesis.validate()
+}
+
+#[test]
+fn allocation_keys_must_own_their_address() {
+    let owner = Wallet::new();
+    let other = Wallet::new();
+    with(vec![allocation(&owner, 10.0)]).unwrap();
+
+    let mut foreign = allocation(&owner, 10.0);
+    foreign.public_key = other.public_key.clone();
+    foreign.blinding = other.blinding.clone();
+    assert!(matches!(
+        with(vec![foreign]),
+        Err(ChainError::InvalidGenesis(_))
+    ));
+
+    let mut unblinded = allocation(&owner, 10.0);
+    unblinded.blinding = other.blinding;
+    assert!(matches!(
+        with(vec![unblinded]),
+        Err(ChainError::InvalidGenesis(_))
+    ));
+}
+
+#[test]
+fn every_node_builds_the_same_genesis() {
+    let holder = Wallet::new();
+    let genesis = GenesisConfig {
+        allocations: vec![allocation(&holder, 25.0)],
+        ..GenesisConfig::default()
+    };
+    let json = serde_json::to_string(&genesis).unwrap();
+    let loaded = GenesisConfig::parse(&json).unwrap();
+    assert_eq!(
+        loaded.block().unwrap().encode(),
+        genesis.block().unwrap().encode()
+    );
+
+    let (first, second) = (Storage::memory(), Storage::memory());
+    let chain = Blockchain::open(&first, &genesis).unwrap();
+    let other = Blockchain::open(&second, &loaded).unwrap();
+    assert_eq!(chain.tip().header.hash, other.tip().header.hash);
+    for store in [&first, &second] {
+        assert_eq!(
+            Account::state_of(store, &holder.address).unwrap().balance,
+            25.0
+        );
+    }
+
+    let renamed = GenesisConfig {
+        network_id: format!("{}-test", genesis.network_id),
+        ..genesis.clone()
+    };
+    assert_ne!(
+        renamed.block().unwrap().header.hash,
+        chain.tip().header.hash
+    );
+    drop(chain);
+    assert!(matches!(
+        Blockchain::open(&first, &renamed),
+        Err(ChainError::GenesisMismatch { .. })
+    ));
+}
+
+#[test]
+fn invalid_configurations_are_refused() {
+    let holder = Wallet::new();
+    let invalid = [
+        GenesisConfig {
+            network_id: String::new(),
+            ..GenesisConfig::default()
+        },
+        GenesisConfig {
+            difficulty: MIN_DIFFICULTY - 1,
+            ..GenesisConfig::default()
+        },
+        GenesisConfig {
+            timestamp: -1,
+            ..GenesisConfig::default()
+        },
+    ];
+    for genesis in invalid {
+        let json = serde_json::to_string(&genesis).unwrap();
+        assert!(matches!(
+            GenesisConfig::parse(&json),
+            Err(ChainError::InvalidGenesis(_))
+        ));
+    }
+
+    for allocations in [
+        vec![allocation(&holder, 0.0)],
+        vec![allocation(&holder, f64::NAN)],
+        vec![allocation(&holder, 1.0), allocation(&holder, 2.0)],
+        vec![
+            allocation(&holder, MAX_SUPPLY as f64),
+            allocation(&Wallet::new(), 1.0),
+        ],
+    ] {
+        assert!(matches!(
+            with(allocations),
+            Err(ChainError::InvalidGenesis(_))
+        ));
+    }
+    assert!(GenesisConfig::parse("{").is_err());
+}
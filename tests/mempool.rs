@@ -0,0 +1,44 @@
+mod common;
+
+use common::{funded, transfer};
+use curve::account::wallet::Wallet;
+use curve::tx::Mempool;
+use curve::util::error::{BlockRejection, MempoolError};
+
+#[test]
+fn nonces_must_follow_on_from_the_pending_ones() {
+    let sender = Wallet::new();
+    let (_, store) = funded(&sender);
+    let mut mempool = Mempool::default();
+
+    let gapped = mempool
+        .add(&store, transfer(&sender, &Wallet::new().address, 1.0, 5))
+        .unwrap_err();
+    assert!(matches!(
+        gapped,
+        MempoolError::Invalid(BlockRejection::InvalidNonce {
+            expected: 0,
+            found: 5,
+            ..
+        })
+    ));
+    mempool
+        .add(&store, transfer(&sender, &Wallet::new().address, 1.0, 0))
+        .unwrap();
+    mempool
+        .add(&store, transfer(&sender, &Wallet::new().address, 1.0, 1))
+        .unwrap();
+    assert!(matches!(
+        mempool.add(&store, transfer(&sender, &Wallet::new().address, 1.0, 3)),
+        Err(MempoolError::Invalid(BlockRejection::InvalidNonce {
+            expected: 2,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        mempool.add(&store, transfer(&sender, &Wallet::new().address, 1.0, 1)),
+        Err(MempoolError::NonceInUse { nonce: 1, .. })
+    ));
+    assert_eq!(mempool.len(), 2);
+    assert_eq!(mempool.next_nonce(&store, &sender.address).unwrap(), 2);
+}
@@ -0,0 +1,369 @@
+mod common;
+
+use common::{funded_genesis, transfer};
+use curve::account::wallet::Wallet;
+use curve::account::Account;
+use curve::chain::GenesisConfig;
+use curve::config::{BLOCK_TIME_SECONDS, MAX_INBOUND_PEERS};
+use curve::net::message::PROTOCOL_VERSION;
+use curve::net::transport::{self, SecureReader, SecureWriter};
+use curve::net::{Handshake, Message, Node, NodeConfig};
+use curve::store::Storage;
+use curve::util::error::NetworkError;
+use curve::vault::KeyPair;
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn start(genesis: &GenesisConfig, peers: Vec<SocketAddr>) -> Node {
+    let config = NodeConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        peers,
+        discover: false,
+        ..NodeConfig::default()
+    };
+    Node::start(Storage::memory(), genesis, config).unwrap()
+}
+
+fn wait_until(what: &str, condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !condition() {
+        assert!(
+            Instant::now() < deadline,
+            "timed out waiting until {}",
+            what
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Mines `count` blocks one block time apart, so difficulty stays put however fast they are
+/// found.
+fn mine_on_schedule(node: &Node, miner: &Wallet, count: usize) {
+    for _ in 0..count {
+        let mut block = {
+            let chain = node.chain().unwrap();
+            let mut block = chain.next_block(&miner.address, vec![]).unwrap();
+            block.header.timestamp = chain.tip().header.timestamp + BLOCK_TIME_SECONDS as i64;
+            block
+        };
+        block.mine();
+        node.submit_block(block).unwrap();
+    }
+}
+
+/// Three nodes in a line, each connected to the previous one.
+fn line(genesis: &GenesisConfig) -> Vec<Node> {
+    let first = start(genesis, vec![]);
+    let second = start(genesis, vec![first.local_addr()]);
+    let third = start(genesis, vec![second.local_addr()]);
+    wait_until("the line is connected", || {
+        first.peers().len() == 1 && second.peers().len() == 2 && third.peers().len() == 1
+    });
+    vec![first, second, third]
+}
+
+#[test]
+fn transactions_gossip_across_hops() {
+    let sender = Wallet::new();
+    let receiver = Wallet::new();
+    let nodes = line(&funded_genesis(&[&sender], 1_000.0));
+
+    let tx = transfer(&sender, &receiver.address, 10.0, 0);
+    let id = tx.id().to_string();
+    nodes[0].submit_transaction(tx.clone()).unwrap();
+
+    wait_until("the far node has the transaction", || {
+        nodes[2].mempool().unwrap().contains(&id)
+    });
+    assert!(matches!(
+        nodes[2].submit_transaction(tx),
+        Err(NetworkError::Mempool(_))
+    ));
+}
+
+#[test]
+fn mined_blocks_propagate_and_clear_mempools() {
+    let sender = Wallet::new();
+    let receiver = Wallet::new();
+    let miner = Wallet::new();
+    let nodes = line(&funded_genesis(&[&sender], 1_000.0));
+
+    let tx = transfer(&sender, &receiver.address, 10.0, 0);
+    let id = tx.id().to_string();
+    nodes[2].submit_transaction(tx).unwrap();
+    wait_until("the miner has the transaction", || {
+        nodes[0].mempool().unwrap().contains(&id)
+    });
+
+    let block = nodes[0].mine_block(&miner.address).unwrap();
+    assert_eq!(block.transactions.len(), 2);
+    for node in &nodes {
+        wait_until("every node has the block", || {
+            node.chain().unwrap().tip().header.hash == block.header.hash
+        });
+        assert!(node.mempool().unwrap().is_empty());
+        let received = Account::state_of(node.store(), &receiver.address).unwrap();
+        assert_eq!(received.balance, 10.0);
+        assert_eq!(received.nonce, 0);
+        assert_eq!(
+            Account::state_of(node.store(), &sender.address)
+                .unwrap()
+                .nonce,
+            1
+        );
+    }
+}
+
+#[test]
+fn peers_of_other_networks_are_refused() {
+    let sender = Wallet::new();
+    let genesis = funded_genesis(&[&sender], 1_000.0);
+    let node = start(&genesis, vec![]);
+
+    let mut other_network = genesis.clone();
+    other_network.network_id = "valtoria-othernet".to_string();
+    let stranger = start(&other_network, vec![]);
+    assert!(matches!(
+        stranger.connect(node.local_addr()),
+        Err(NetworkError::WrongNetwork { .. })
+    ));
+
+    let fork = start(&GenesisConfig::default(), vec![]);
+    assert!(matches!(
+        fork.connect(node.local_addr()),
+        Err(NetworkError::WrongGenesis { .. })
+    ));
+
+    thread::sleep(Duration::from_millis(200));
+    assert!(node.peers().is_empty());
+}
+
+#[test]
+fn late_joiners_catch_up() {
+    let sender = Wallet::new();
+    let miner = Wallet::new();
+    let genesis = funded_genesis(&[&sender], 1_000.0);
+    let node = start(&genesis, vec![]);
+    for _ in 0..3 {
+        node.mine_block(&miner.address).unwrap();
+    }
+
+    let joiner = start(&genesis, vec![node.local_addr()]);
+    wait_until("the joiner reaches the tip", || {
+        joiner.height().unwrap() == 3
+    });
+    assert_eq!(
+        joiner.chain().unwrap().tip().header.hash,
+        node.chain().unwrap().tip().header.hash
+    );
+    assert_eq!(
+        Account::state_of(joiner.store(), &miner.address)
+            .unwrap()
+            .balance,
+        Account::state_of(node.store(), &miner.address)
+            .unwrap()
+            .balance
+    );
+
+    node.shutdown();
+    joiner.shutdown();
+}
+
+#[test]
+fn new_nodes_sync_headers_first_from_several_peers() {
+    let sender = Wallet::new();
+    let miner = Wallet::new();
+    let genesis = funded_genesis(&[&sender], 1_000.0);
+    let source = start(&genesis, vec![]);
+    mine_on_schedule(&source, &miner, 150);
+
+    let mirror = start(&genesis, vec![source.local_addr()]);
+    wait_until("the mirror is synced", || mirror.height().unwrap() == 150);
+
+    let joiner = start(&genesis, vec![source.local_addr(), mirror.local_addr()]);
+    wait_until("the joiner is synced", || {
+        joiner.height().unwrap() == 150 && !joiner.is_syncing().unwrap()
+    });
+    let tip = source.chain().unwrap().tip().header.hash.clone();
+    assert_eq!(joiner.chain().unwrap().tip().header.hash, tip);
+    assert_eq!(joiner.chain().unwrap().best_header().hash, tip);
+    joiner.chain().unwrap().validate_chain().unwrap();
+
+    // Blocks mined after the sync arrive by ordinary gossip.
+    mine_on_schedule(&source, &miner, 1);
+    wait_until("the joiner has the new block", || {
+        joiner.height().unwrap() == 151
+    });
+}
+
+#[test]
+fn links_are_authenticated_with_persistent_identities() {
+    let genesis = funded_genesis(&[&Wallet::new()], 1_000.0);
+    let store = Storage::memory();
+    let config = NodeConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        discover: false,
+        ..NodeConfig::default()
+    };
+    let node = Node::start(store.clone(), &genesis, config.clone()).unwrap();
+    let identity = node.identity();
+    node.shutdown();
+    let restarted = Node::start(store, &genesis, config).unwrap();
+    assert_eq!(restarted.identity(), identity);
+
+    let other = start(&genesis, vec![]);
+    assert!(matches!(
+        other.connect_to(restarted.local_addr(), Some(&other.identity())),
+        Err(NetworkError::UnexpectedIdentity { .. })
+    ));
+    assert!(matches!(
+        restarted.connect(restarted.local_addr()),
+        Err(NetworkError::SelfConnection)
+    ));
+    other
+        .connect_to(restarted.local_addr(), Some(&identity))
+        .unwrap();
+    wait_until("both sides see the link", || {
+        restarted.peers().len() == 1 && other.peers().len() == 1
+    });
+}
+
+fn discovering(store: Storage, genesis: &GenesisConfig, seeds: Vec<String>) -> Node {
+    let config = NodeConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        seeds,
+        ..NodeConfig::default()
+    };
+    Node::start(store, genesis, config).unwrap()
+}
+
+#[test]
+fn peers_are_discovered_from_seeds_and_remembered() {
+    let genesis = funded_genesis(&[&Wallet::new()], 1_000.0);
+    let seed = start(&genesis, vec![]);
+    let seeds = vec![seed.local_addr().to_string()];
+    let first = discovering(Storage::memory(), &genesis, seeds.clone());
+    wait_until("the seed knows the first node", || seed.peers().len() == 1);
+
+    let store = Storage::memory();
+    let second = discovering(store.clone(), &genesis, seeds);
+    wait_until("the second node finds the first through the seed", || {
+        second.peers().len() == 2 && first.peers().len() == 2
+    });
+    let known = second.address_book().unwrap().records();
+    for node in [&seed, &first] {
+        let record = known
+            .iter()
+            .find(|record| record.addr == node.local_addr())
+            .expect("the address is in the peer table");
+        assert_eq!(record.failures, 0);
+    }
+
+    // Restarted without seeds, the node reconnects from its persisted peer table.
+    second.shutdown();
+    wait_until("the others see the second node leave", || {
+        seed.peers().len() == 1 && first.peers().len() == 1
+    });
+    let restarted = discovering(store, &genesis, vec![]);
+    wait_until("the restarted node reconnects", || {
+        restarted.peers().len() == 2
+    });
+}
+
+/// Completes the transport and version handshakes with `node` by hand, as a peer that may
+/// send anything.
+fn raw_peer(node: &Node, genesis: &GenesisConfig) -> (SecureReader, SecureWriter) {
+    let stream = TcpStream::connect(node.local_addr()).unwrap();
+    let (mut reader, mut writer, _) =
+        transport::handshake(stream, &KeyPair::generate(), true).unwrap();
+    let chain = node.chain().unwrap();
+    writer
+        .send(&Message::Version(Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            network_id: genesis.network_id.clone(),
+            genesis_hash: chain.blocks[0].header.hash.clone(),
+            best_height: 0,
+            listen_port: 1,
+        }))
+        .unwrap();
+    drop(chain);
+    assert!(matches!(reader.receive().unwrap(), Message::Version(_)));
+    writer.send(&Message::Verack).unwrap();
+    assert!(matches!(reader.receive().unwrap(), Message::Verack));
+    (reader, writer)
+}
+
+#[test]
+fn peers_sending_invalid_blocks_are_banned() {
+    let genesis = funded_genesis(&[&Wallet::new()], 1_000.0);
+    let miner = Wallet::new();
+    let store = Storage::memory();
+    let config = NodeConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        discover: false,
+        ..NodeConfig::default()
+    };
+    let node = Node::start(store.clone(), &genesis, config.clone()).unwrap();
+    let (mut reader, mut writer) = raw_peer(&node, &genesis);
+    wait_until("the raw peer is connected", || node.peers().len() == 1);
+
+    let mut forged = node
+        .chain()
+        .unwrap()
+        .next_block(&miner.address, vec![])
+        .unwrap();
+    forged.header.hash = "00".repeat(32);
+    writer.send(&Message::Block(forged.clone())).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(
+        node.peers().len(),
+        1,
+        "one invalid block is not enough for a ban"
+    );
+
+    forged.header.timestamp += 1;
+    writer.send(&Message::Block(forged)).unwrap();
+    wait_until("the peer is disconnected", || node.peers().is_empty());
+    assert!(loop {
+        match reader.receive() {
+            Ok(_) => continue,
+            Err(e) => break matches!(e, NetworkError::Io(_)),
+        }
+    });
+    assert_eq!(node.height().unwrap(), 0);
+    assert!(node
+        .address_book()
+        .unwrap()
+        .is_banned(&"127.0.0.1".parse().unwrap()));
+
+    // The ban outlives a restart.
+    node.shutdown();
+    let restarted = Node::start(store, &genesis, config).unwrap();
+    let stream = TcpStream::connect(restarted.local_addr()).unwrap();
+    assert!(transport::handshake(stream, &KeyPair::generate(), true).is_err());
+    assert!(start(&genesis, vec![])
+        .connect(restarted.local_addr())
+        .is_err());
+    assert!(restarted.peers().is_empty());
+}
+
+#[test]
+fn pending_handshakes_hold_inbound_slots() {
+    let genesis = GenesisConfig::default();
+    let node = start(&genesis, vec![]);
+    // Connections that never start the handshake still take a slot until it times out.
+    let stalled: Vec<TcpStream> = (0..MAX_INBOUND_PEERS)
+        .map(|_| TcpStream::connect(node.local_addr()).unwrap())
+        .collect();
+    thread::sleep(Duration::from_millis(200));
+    let late = start(&genesis, vec![]);
+    assert!(late.connect(node.local_addr()).is_err());
+    assert!(node.peers().is_empty());
+
+    drop(stalled);
+    wait_until("a slot is free again", || {
+        late.connect(node.local_addr()).is_ok()
+    });
+    wait_until("the late peer is connected", || node.peers().len() == 1);
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use common::{funded_genesis, transfer};
+use curve::account::wallet::Wallet;
+use curve::api::rpc::{
+    self, BLOCK_NOT_FOUND, INVALID_ADDRESS, MALFORMED_TRANSACTION, METHOD_NOT_FOUND,
+    TRANSACTION_NOT_FOUND, TRANSACTION_REJECTED, UNAUTHORIZED,
+};
+use curve::api::{RpcConfig, RpcServer};
+use curve::net::{Node, NodeConfig};
+use curve::store::Storage;
+use curve::util::codec::Encode;
+use curve::util::error::ApiError;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+const TOKEN: &str = "secret-token";
+
+fn setup() -> (Wallet, Node, RpcServer) {
+    let sender = Wallet::new();
+    let genesis = funded_genesis(&[&sender], 1_000.0);
+    let config = NodeConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        discover: false,
+        ..NodeConfig::default()
+    };
+    let node = Node::start(Storage::memory(), &genesis, config).unwrap();
+    let server = RpcServer::start(
+        node.clone(),
+        RpcConfig {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            tokens: vec![TOKEN.to_string()],
+        },
+    )
+    .unwrap();
+    (sender, node, server)
+}
+
+/// Posts `body` with `token` and returns the HTTP status and the parsed body, `Null` when
+/// empty.
+fn post(addr: SocketAddr, token: Option<&str>, body: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let auth = token
+        .map(|token| format!("Authorization: Bearer {}\r\n", token))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        addr,
+        auth,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(body).unwrap()
+    };
+    (status, body)
+}
+
+fn call(server: &RpcServer, method: &str, params: Value) -> Value {
+    let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+    let (status, body) = post(server.local_addr(), Some(TOKEN), &request.to_string());
+    assert_eq!(status, 200);
+    assert_eq!(body["jsonrpc"], "2.0");
+    assert_eq!(body["id"], 1);
+    body
+}
+
+fn error_code(response: &Value) -> i64 {
+    response["error"]["code"]
+        .as_i64()
+        .expect("an error response")
+}
+
+#[test]
+fn requests_need_a_known_token() {
+    let (_, _, server) = setup();
+    let request = json!({ "jsonrpc": "2.0", "method": "chain_info", "id": 1 }).to_string();
+    for token in [None, Some("wrong")] {
+        let (status, body) = post(server.local_addr(), token, &request);
+        assert_eq!(status, 401);
+        assert_eq!(error_code(&body), UNAUTHORIZED);
+    }
+    let (status, _) = post(server.local_addr(), Some(TOKEN), &request);
+    assert_eq!(status, 200);
+}
+
+#[test]
+fn the_server_only_listens_on_loopback_with_tokens() {
+    let (_, node, _) = setup();
+    let exposed = RpcConfig {
+        listen: "0.0.0.0:0".parse().unwrap(),
+        tokens: vec![TOKEN.to_string()],
+    };
+    assert!(matches!(
+        RpcServer::start(node.clone(), exposed),
+        Err(ApiError::NotLoopback(_))
+    ));
+    let open = RpcConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        tokens: vec![],
+    };
+    assert!(matches!(
+        RpcServer::start(node, open),
+        Err(ApiError::NoTokens)
+    ));
+
+    let dir = std::env::temp_dir().join(format!("rpc-cookie-{}", std::process::id()));
+    let token = rpc::write_cookie(&dir).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join(curve::config::RPC_COOKIE_FILE)).unwrap(),
+        token
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn accounts_are_created_and_queried() {
+    let (sender, _, server) = setup();
+    let created = call(&server, "account_create", json!({}));
+    let address = created["result"]["address"].as_str().unwrap();
+    assert!(created["result"]["private_key"].is_string());
+
+    let balance = call(&server, "account_balance", json!({ "address": address }));
+    assert_eq!(balance["result"]["balance"], 0.0);
+    let balance = call(&server, "account_balance", json!([sender.address]));
+    assert_eq!(balance["result"]["balance"], 1_000.0);
+    assert_eq!(balance["result"]["nonce"], 0);
+
+    let invalid = call(
+        &server,
+        "account_balance",
+        json!({ "address": "not-an-address" }),
+    );
+    assert_eq!(error_code(&invalid), INVALID_ADDRESS);
+}
+
+#[test]
+fn transactions_are_submitted_and_tracked_into_blocks() {
+    let (sender, node, server) = setup();
+    let receiver = Wallet::new();
+    let tx = transfer(&sender, &receiver.address, 10.0, 0);
+    let encoded = hex::encode(tx.encode());
+
+    let submitted = call(&server, "tx_submit", json!({ "transaction": encoded }));
+    assert_eq!(submitted["result"]["id"], tx.id());
+    assert_eq!(submitted["result"]["status"], "Pending");
+    let rejected = call(&server, "tx_submit", json!([encoded]));
+    assert_eq!(error_code(&rejected), TRANSACTION_REJECTED);
+    assert!(rejected["error"]["data"]["reason"].is_string());
+    let malformed = call(&server, "tx_submit", json!({ "transaction": "00ff" }));
+    assert_eq!(error_code(&malformed), MALFORMED_TRANSACTION);
+
+    let pending = call(&server, "tx_get", json!({ "id": tx.id() }));
+    assert_eq!(pending["result"]["status"], "Pending");
+    assert_eq!(pending["result"]["transaction"]["amount"], 10.0);
+    assert!(pending["result"]["transaction"].get("signature").is_none());
+
+    let block = node.mine_block(&Wallet::new().address).unwrap();
+    let confirmed = call(&server, "tx_get", json!({ "id": tx.id() }));
+    assert_eq!(confirmed["result"]["status"], "Completed");
+    assert_eq!(confirmed["result"]["height"], 1);
+    assert_eq!(confirmed["result"]["block_hash"], block.header.hash);
+    assert_eq!(confirmed["result"]["confirmations"], 1);
+    let missing = call(&server, "tx_get", json!({ "id": "nope" }));
+    assert_eq!(error_code(&missing), TRANSACTION_NOT_FOUND);
+
+    let by_height = call(&server, "block_get", json!({ "height": 1 }));
+    assert_eq!(by_height["result"]["hash"], block.header.hash);
+    assert_eq!(by_height["result"]["transactions"][1]["id"], tx.id());
+    let header = call(&server, "header_get", json!([block.header.hash]));
+    assert_eq!(header["result"]["height"], 1);
+    assert!(header["result"].get("transactions").is_none());
+    let missing = call(&server, "block_get", json!([5]));
+    assert_eq!(error_code(&missing), BLOCK_NOT_FOUND);
+
+    let info = call(&server, "chain_info", json!(null));
+    assert_eq!(info["result"]["height"], 1);
+    assert_eq!(info["result"]["tip_hash"], block.header.hash);
+    assert_eq!(info["result"]["network_id"], node.network_id());
+    assert_eq!(info["result"]["mempool_size"], 0);
+}
+
+#[test]
+fn protocol_errors_batches_and_notifications() {
+    let (_, _, server) = setup();
+    let addr = server.local_addr();
+
+    let (_, body) = post(addr, Some(TOKEN), "{not json");
+    assert_eq!(error_code(&body), -32700);
+    let unknown = call(&server, "no_such_method", json!({}));
+    assert_eq!(error_code(&unknown), METHOD_NOT_FOUND);
+    let (_, body) = post(addr, Some(TOKEN), r#"{"method": "chain_info", "id": 3}"#);
+    assert_eq!(error_code(&body), -32600);
+
+    let batch = json!([
+        { "jsonrpc": "2.0", "method": "chain_info", "id": "a" },
+        { "jsonrpc": "2.0", "method": "chain_info" },
+        { "jsonrpc": "2.0", "method": "tx_get", "params": ["nope"], "id": "b" },
+    ]);
+    let (status, body) = post(addr, Some(TOKEN), &batch.to_string());
+    assert_eq!(status, 200);
+    let replies = body.as_array().unwrap();
+    assert_eq!(replies.len(), 2);
+    assert_eq!(replies[0]["id"], "a");
+    assert!(replies[0]["result"].is_object());
+    assert_eq!(replies[1]["id"], "b");
+    assert_eq!(error_code(&replies[1]), TRANSACTION_NOT_FOUND);
+
+    let notification = json!({ "jsonrpc": "2.0", "method": "chain_info" });
+    let (status, body) = post(addr, Some(TOKEN), &notification.to_string());
+    assert_eq!(status, 204);
+    assert_eq!(body, Value::Null);
+}
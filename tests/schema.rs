@@ -0,0 +1,85 @@
+use curve::account::account::BalanceType;
+use curve::account::Account;
+use curve::store::schema::{self, SCHEMA_VERSION};
+use curve::store::{Storage, StorageKind};
+use curve::util::error::StorageError;
+
+/// An account record as written before versioning: bare bincode, without a nonce.
+fn legacy_store() -> (Storage, Vec<u8>) {
+    let store = Storage::memory();
+    let legacy = bincode::serialize(&(
+        "legacy-address".to_string(),
+        BalanceType::Binary(vec![1, 2, 3]),
+        42u64,
+    ))
+    .unwrap();
+    store
+        .put(StorageKind::Account.name(), b"legacy", &legacy, false)
+        .unwrap();
+    (store, legacy)
+}
+
+#[test]
+fn dry_runs_report_without_writing() {
+    let (store, legacy) = legacy_store();
+    let report = schema::migrate(&store, true).unwrap();
+    assert!(report.dry_run);
+    assert_eq!(
+        (report.from_version, report.to_version),
+        (0, SCHEMA_VERSION)
+    );
+    let applied: Vec<_> = report
+        .applied
+        .iter()
+        .map(|migration| (migration.version, migration.records))
+        .collect();
+    assert_eq!(applied, vec![(1, 1), (2, 1)]);
+
+    assert_eq!(schema::schema_version(&store).unwrap(), 0);
+    assert_eq!(
+        store.get(StorageKind::Account.name(), b"legacy").unwrap(),
+        legacy
+    );
+}
+
+#[test]
+fn migrations_upgrade_legacy_records_once() {
+    let (store, _) = legacy_store();
+    assert!(schema::decode_record::<Account>(
+        &store.get(StorageKind::Account.name(), b"legacy").unwrap()
+    )
+    .is_err());
+
+    schema::migrate(&store, false).unwrap();
+    assert_eq!(schema::schema_version(&store).unwrap(), SCHEMA_VERSION);
+    let data = store.get(StorageKind::Account.name(), b"legacy").unwrap();
+    let account: Account = schema::decode_record(&data).unwrap();
+    assert_eq!(
+        (account.address.as_str(), account.timestamp, account.nonce),
+        ("legacy-address", 42, 0)
+    );
+
+    let again = schema::migrate(&store, false).unwrap();
+    assert_eq!(again.from_version, SCHEMA_VERSION);
+    assert!(again.applied.is_empty());
+}
+
+#[test]
+fn new_databases_are_stamped_and_newer_ones_refused() {
+    let store = Storage::memory();
+    schema::migrate(&store, false).unwrap();
+    assert_eq!(schema::schema_version(&store).unwrap(), SCHEMA_VERSION);
+
+    let newer = bincode::serialize(&(SCHEMA_VERSION + 1)).unwrap();
+    store
+        .put(StorageKind::Meta.name(), b"schema_version", &newer, false)
+        .unwrap();
+    assert!(matches!(
+        schema::migrate(&store, false),
+        Err(StorageError::SchemaTooNew(version)) if version == SCHEMA_VERSION + 1
+    ));
+    assert!(matches!(
+        schema::migrate(&store, true),
+        Err(StorageError::SchemaTooNew(_))
+    ));
+}
@@ -0,0 +1,144 @@
+use curve::account::wallet::Wallet;
+use curve::account::{Account, AccountState};
+use curve::chain::state_tree::{self, StateTree};
+use curve::chain::{Blockchain, GenesisAllocation, GenesisConfig};
+use curve::store::Storage;
+use curve::tx::Transaction;
+use curve::util::error::ChainError;
+use std::collections::HashMap;
+
+fn state(balance: f64, nonce: u64) -> AccountState {
+    AccountState { balance, nonce }
+}
+
+fn accounts(range: std::ops::Range<u64>) -> HashMap<String, AccountState> {
+    range
+        .map(|i| (format!("account-{i}"), state(i as f64 + 1.0, i % 3)))
+        .collect()
+}
+
+#[test]
+fn cached_roots_match_a_full_rebuild() {
+    let mut committed = accounts(0..200);
+    let mut tree = StateTree::new(&committed);
+    assert_eq!(
+        tree.root_with(&HashMap::new()),
+        state_tree::root(&committed)
+    );
+
+    for round in 0..5u64 {
+        let mut changes = HashMap::new();
+        // Change some accounts, empty others and add new ones.
+        for i in (round..200).step_by(17) {
+            changes.insert(format!("account-{i}"), state(round as f64, i));
+        }
+        for i in (round..200).step_by(31) {
+            changes.insert(format!("account-{i}"), AccountState::default());
+        }
+        changes.extend(accounts(200 + round * 10..205 + round * 10));
+
+        let mut expected = committed.clone();
+        expected.extend(changes.clone());
+        let before = tree.root_with(&HashMap::new());
+        assert_eq!(tree.root_with(&changes), state_tree::root(&expected));
+        assert_eq!(tree.root_with(&HashMap::new()), before);
+
+        tree.apply(&changes);
+        committed = expected;
+        assert_eq!(
+            tree.root_with(&HashMap::new()),
+            state_tree::root(&committed)
+        );
+    }
+}
+
+#[test]
+fn emptying_every_account_leaves_the_empty_root() {
+    let committed = accounts(0..3);
+    let mut tree = StateTree::new(&committed);
+    let emptied = committed
+        .keys()
+        .map(|address| (address.clone(), AccountState::default()))
+        .collect();
+    tree.apply(&emptied);
+    assert_eq!(
+        tree.root_with(&HashMap::new()),
+        state_tree::root(&HashMap::new())
+    );
+}
+
+#[test]
+fn roots_commit_to_every_nonempty_account() {
+    let accounts = accounts(0..20);
+    let root = state_tree::root(&accounts);
+
+    let mut changed = accounts.clone();
+    changed.insert("account-3".to_string(), state(0.5, 0));
+    assert_ne!(state_tree::root(&changed), root);
+    let mut padded = accounts.clone();
+    padded.insert("account-empty".to_string(), AccountState::default());
+    assert_eq!(state_tree::root(&padded), root);
+
+    for address in accounts.keys() {
+        let proof = state_tree::prove(&accounts, address).unwrap();
+        assert_eq!(&proof.state, &accounts[address]);
+        assert!(proof.verify(&root));
+        assert!(!proof.verify(&state_tree::root(&changed)));
+    }
+    assert!(state_tree::prove(&accounts, "account-missing").is_none());
+}
+
+#[test]
+fn proofs_hold_against_the_root_of_their_height() {
+    let sender = Wallet::new();
+    let mut genesis = GenesisConfig::default();
+    genesis.allocations.push(GenesisAllocation {
+        address: sender.address.clone(),
+        public_key: sender.public_key.clone(),
+        blinding: sender.blinding.clone(),
+        amount: 1_000.0,
+    });
+    let store = Storage::memory();
+    let mut chain = Blockchain::open(&store, &genesis).unwrap();
+    let (receiver, miner) = (Account::new(&store).unwrap(), Account::new(&store).unwrap());
+    let mut tx = Transaction::init(
+        sender.address.clone(),
+        receiver.address.clone(),
+        10.0,
+        String::new(),
+    );
+    tx.sign(&sender.private_key, 0).unwrap();
+    for transactions in [vec![tx], vec![]] {
+        let mut block = chain.next_block(&miner.address, transactions).unwrap();
+        block.mine();
+        chain.add_block(block).unwrap();
+    }
+    let root = |height: usize| chain.blocks[height].header.state_root.clone();
+
+    let before = chain.prove_account(&sender.address, 0).unwrap().unwrap();
+    assert_eq!(before.state, state(1_000.0, 0));
+    assert!(before.verify(&root(0)));
+    assert!(!before.verify(&root(1)));
+
+    let spent = chain.prove_account(&sender.address, 1).unwrap().unwrap();
+    assert_eq!(spent.state.nonce, 1);
+    assert!(spent.state.balance < 990.0);
+    assert!(spent.verify(&root(1)));
+    // Later blocks change other accounts, so the same state has a new proof at the tip.
+    let after = chain.prove_account(&sender.address, 2).unwrap().unwrap();
+    assert_eq!(after.state, spent.state);
+    assert!(!spent.verify(&root(2)));
+    assert!(after.verify(&root(2)));
+    let mut forged = after.clone();
+    forged.state.balance = 1_000.0;
+    assert!(!forged.verify(&root(2)));
+
+    assert!(chain
+        .prove_account(&Wallet::new().address, 2)
+        .unwrap()
+        .is_none());
+    assert!(matches!(
+        chain.prove_account(&sender.address, 3),
+        Err(ChainError::UnknownBlock(_))
+    ));
+}
@@ -0,0 +1,260 @@
+use curve::store::{
+    Cursor, KvStore, MemoryStore, RocksDbStore, ScanOptions, Storage, StorageBatch, StorageKind,
+};
+use curve::util::error::StorageError;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn db_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("curve-storage-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn clones_share_one_open_database() {
+    let dir = db_dir("shared");
+    let cf = StorageKind::Account.name();
+    {
+        let store = Storage::open(&dir, Storage::default_options()).unwrap();
+        let handle = store.clone();
+        store.put(cf, b"key", b"value", false).unwrap();
+        assert_eq!(handle.get(cf, b"key").unwrap(), b"value");
+        // The database stays locked while any handle is alive.
+        drop(store);
+        assert!(Storage::open(&dir, Storage::default_options()).is_err());
+    }
+
+    let reopened = Storage::open(&dir, Storage::default_options()).unwrap();
+    assert_eq!(reopened.get(cf, b"key").unwrap(), b"value");
+    drop(reopened);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Every backend the storage layer can sit on.
+fn backends(dir: &Path) -> Vec<Storage> {
+    let rocksdb = RocksDbStore::open(dir, Storage::default_options()).unwrap();
+    let backends: Vec<Arc<dyn KvStore>> = vec![Arc::new(MemoryStore::new()), Arc::new(rocksdb)];
+    backends.into_iter().map(Storage::new).collect()
+}
+
+#[test]
+fn backends_behave_alike() {
+    let dir = db_dir("backends");
+    let cf = StorageKind::Account.name();
+    for store in backends(&dir) {
+        assert!(matches!(store.get(cf, b"a"), Err(StorageError::NotFound)));
+        assert!(!store.exists(cf, b"a").unwrap());
+
+        store
+            .batch_put(cf, vec![(b"b", b"2"), (b"a", b"1"), (b"c", b"3")])
+            .unwrap();
+        assert!(store.exists(cf, b"a").unwrap());
+        assert_eq!(store.get(cf, b"b").unwrap(), b"2");
+        let listed = store.batch_get(cf, 1, 5).unwrap();
+        assert_eq!(
+            listed,
+            vec![
+                (b"b".to_vec(), b"2".to_vec()),
+                (b"c".to_vec(), b"3".to_vec())
+            ]
+        );
+
+        assert!(matches!(
+            store.put(cf, b"a", b"again", true),
+            Err(StorageError::RecordExists)
+        ));
+        store.put(cf, b"a", b"again", false).unwrap();
+        assert_eq!(store.get(cf, b"a").unwrap(), b"again");
+        assert!(matches!(
+            store.get("missing", b"a"),
+            Err(StorageError::ColumnFamilyNotFound(_))
+        ));
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn memory_store_refuses_checkpoints() {
+    let store = Storage::memory();
+    let dir = db_dir("checkpoint");
+    assert!(matches!(
+        store.create_checkpoint(&dir),
+        Err(StorageError::Unsupported("checkpoints"))
+    ));
+    assert!(matches!(
+        store.create_backup(&dir),
+        Err(StorageError::Unsupported("backups"))
+    ));
+    assert!(!dir.exists());
+}
+
+#[test]
+fn failing_batches_write_nothing() {
+    let dir = db_dir("batches");
+    let cf = StorageKind::Account.name();
+    for store in backends(&dir) {
+        store.put(cf, b"taken", b"0", false).unwrap();
+
+        let mut batch = StorageBatch::new();
+        batch
+            .put(cf, b"a", b"1")
+            .insert(cf, b"b", b"2")
+            .insert(cf, b"taken", b"3");
+        assert!(matches!(
+            store.write(batch),
+            Err(StorageError::RecordExists)
+        ));
+        let mut twice = StorageBatch::new();
+        twice.insert(cf, b"c", b"1").insert(cf, b"c", b"2");
+        assert!(matches!(
+            store.write(twice),
+            Err(StorageError::RecordExists)
+        ));
+
+        for key in [&b"a"[..], b"b", b"c"] {
+            assert!(!store.exists(cf, key).unwrap());
+        }
+        assert_eq!(store.get(cf, b"taken").unwrap(), b"0");
+        assert!(matches!(
+            store.get_analytics(cf.as_bytes()),
+            Err(StorageError::AnalyticsNotFound)
+        ));
+
+        let mut batch = StorageBatch::new();
+        batch
+            .insert(cf, b"a", b"1")
+            .put(cf, b"taken", b"1")
+            .delete(cf, b"b");
+        store.write(batch).unwrap();
+        assert_eq!(store.get(cf, b"a").unwrap(), b"1");
+        assert_eq!(store.get(cf, b"taken").unwrap(), b"1");
+        assert_eq!(store.get_analytics(cf.as_bytes()).unwrap(), 1);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Collects the keys of every page of `options`, following the cursors.
+fn scan_keys(store: &Storage, cf: &str, options: ScanOptions) -> Vec<Vec<u8>> {
+    let mut keys = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = store.scan(cf, &options.clone().after(cursor)).unwrap();
+        assert!(page.items.len() <= options.limit);
+        keys.extend(page.items.into_iter().map(|(key, _)| key));
+        // Cursors survive a round trip through their text form.
+        cursor = page
+            .next_cursor
+            .map(|next| next.to_string().parse().unwrap());
+        if cursor.is_none() {
+            return keys;
+        }
+    }
+}
+
+#[test]
+fn inserts_see_earlier_ops_in_the_same_batch() {
+    let dir = db_dir("staged");
+    let cf = StorageKind::Account.name();
+    for store in backends(&dir) {
+        store.put(cf, b"deleted", b"0", false).unwrap();
+
+        let mut put_first = StorageBatch::new();
+        put_first.put(cf, b"put", b"1").insert(cf, b"put", b"2");
+        assert!(matches!(
+            store.write(put_first),
+            Err(StorageError::RecordExists)
+        ));
+        assert!(!store.exists(cf, b"put").unwrap());
+
+        let mut delete_first = StorageBatch::new();
+        delete_first
+            .delete(cf, b"deleted")
+            .insert(cf, b"deleted", b"1");
+        store.write(delete_first).unwrap();
+        assert_eq!(store.get(cf, b"deleted").unwrap(), b"1");
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn scans_page_within_their_bounds() {
+    let dir = db_dir("scans");
+    let cf = StorageKind::Account.name();
+    let stored: [&[u8]; 7] = [b"a", b"b1", b"b2", b"b3", b"b\xff", b"c", b"d"];
+    let keys = |wanted: &[&[u8]]| wanted.iter().map(|key| key.to_vec()).collect::<Vec<_>>();
+    for store in backends(&dir) {
+        for key in stored {
+            store.put(cf, key, b"", false).unwrap();
+        }
+
+        assert_eq!(scan_keys(&store, cf, ScanOptions::new(2)), keys(&stored));
+        assert_eq!(
+            scan_keys(&store, cf, ScanOptions::new(3).prefix(b"b")),
+            keys(&[b"b1", b"b2", b"b3", b"b\xff"])
+        );
+        assert_eq!(
+            scan_keys(&store, cf, ScanOptions::new(2).prefix(b"b").reverse()),
+            keys(&[b"b\xff", b"b3", b"b2", b"b1"])
+        );
+        assert_eq!(
+            scan_keys(
+                &store,
+                cf,
+                ScanOptions::new(2).range(Some(b"b2"), Some(b"c"))
+            ),
+            keys(&[b"b2", b"b3", b"b\xff"])
+        );
+        assert_eq!(
+            scan_keys(
+                &store,
+                cf,
+                ScanOptions::new(10).range(None, Some(b"b2")).reverse()
+            ),
+            keys(&[b"b1", b"a"])
+        );
+
+        let last = store.scan(cf, &ScanOptions::new(7)).unwrap();
+        assert_eq!(last.items.len(), 7);
+        assert!(last.next_cursor.is_none());
+    }
+    assert!(matches!(
+        "0OIl".parse::<Cursor>(),
+        Err(StorageError::InvalidCursor)
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cursors_only_narrow_the_scan() {
+    let dir = db_dir("cursors");
+    let cf = StorageKind::Account.name();
+    let cursor = |key: &[u8]| Some(bs58::encode(key).into_string().parse::<Cursor>().unwrap());
+    let page = |store: &Storage, options: ScanOptions| -> Vec<Vec<u8>> {
+        let page = store.scan(cf, &options).unwrap();
+        page.items.into_iter().map(|(key, _)| key).collect()
+    };
+    for store in backends(&dir) {
+        for key in [&b"a"[..], b"b1", b"b2", b"c"] {
+            store.put(cf, key, b"", false).unwrap();
+        }
+        let prefix = || ScanOptions::new(10).prefix(b"b");
+
+        // Cursors from outside the prefix leave its bounds in place.
+        assert_eq!(
+            page(&store, prefix().after(cursor(b"a"))),
+            vec![b"b1".to_vec(), b"b2".to_vec()]
+        );
+        assert_eq!(
+            page(&store, prefix().reverse().after(cursor(b"c"))),
+            vec![b"b2".to_vec(), b"b1".to_vec()]
+        );
+        assert!(page(&store, prefix().after(cursor(b"c"))).is_empty());
+        assert!(page(&store, prefix().reverse().after(cursor(b"a"))).is_empty());
+        assert_eq!(
+            page(&store, prefix().after(cursor(b"b1"))),
+            vec![b"b2".to_vec()]
+        );
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,234 @@
+mod common;
+
+use common::{funded_genesis, transfer};
+use curve::account::wallet::Wallet;
+use curve::api::rpc::{INVALID_ADDRESS, INVALID_PARAMS};
+use curve::api::{SubscriptionConfig, SubscriptionServer};
+use curve::config::EVENT_QUEUE_CAPACITY;
+use curve::events::{Event, EventBus};
+use curve::net::{Node, NodeConfig};
+use curve::store::Storage;
+use curve::tx::TransactionStatus;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::sync::mpsc::TryRecvError;
+use std::time::Duration;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+const TOKEN: &str = "secret-token";
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn setup() -> (Wallet, Node, SubscriptionServer) {
+    let sender = Wallet::new();
+    let genesis = funded_genesis(&[&sender], 1_000.0);
+    let config = NodeConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        discover: false,
+        ..NodeConfig::default()
+    };
+    let node = Node::start(Storage::memory(), &genesis, config).unwrap();
+    let server = SubscriptionServer::start(
+        node.clone(),
+        SubscriptionConfig {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            tokens: vec![TOKEN.to_string()],
+        },
+    )
+    .unwrap();
+    (sender, node, server)
+}
+
+fn connect(server: &SubscriptionServer) -> Socket {
+    let url = format!("ws://{}/?token={}", server.local_addr(), TOKEN);
+    let (socket, _) = tungstenite::connect(url).unwrap();
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+    }
+    socket
+}
+
+fn receive(socket: &mut Socket) -> Value {
+    loop {
+        if let Message::Text(text) = socket.read().unwrap() {
+            return serde_json::from_str(text.as_str()).unwrap();
+        }
+    }
+}
+
+fn call(socket: &mut Socket, method: &str, params: Value) -> Value {
+    let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+    socket.send(Message::text(request.to_string())).unwrap();
+    let reply = receive(socket);
+    assert_eq!(reply["id"], 1);
+    reply
+}
+
+fn subscribe(socket: &mut Socket, params: Value) -> String {
+    let reply = call(socket, "subscribe", params);
+    reply["result"]
+        .as_str()
+        .expect("a subscription id")
+        .to_string()
+}
+
+#[test]
+fn the_chain_and_mempool_publish_events() {
+    let (sender, node, _) = setup();
+    let events = node.events().subscribe();
+    let receiver = Wallet::new();
+    let miner = Wallet::new();
+    let tx = transfer(&sender, &receiver.address, 10.0, 0);
+
+    node.submit_transaction(tx.clone()).unwrap();
+    match events.try_recv().unwrap() {
+        Event::TransactionAdmitted(admitted) => assert_eq!(admitted.id(), tx.id()),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let block = node.mine_block(&miner.address).unwrap();
+    match events.try_recv().unwrap() {
+        Event::NewHeader(header) => assert_eq!(header.hash, block.header.hash),
+        other => panic!("unexpected {:?}", other),
+    }
+    let mut payments = HashMap::new();
+    let mut statuses = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        match event {
+            Event::Payment {
+                address,
+                amount,
+                height,
+                ..
+            } => {
+                assert_eq!(height, 1);
+                payments.insert(address, amount);
+            }
+            Event::StatusChanged { id, status, height } => statuses.push((id, status, height)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    assert_eq!(payments[&receiver.address], 10.0);
+    assert!(payments.contains_key(&miner.address));
+    assert_eq!(
+        statuses,
+        vec![(tx.id().to_string(), TransactionStatus::Completed, Some(1))]
+    );
+}
+
+#[test]
+fn subscribers_that_fall_behind_are_dropped() {
+    let bus = EventBus::new();
+    let events = bus.subscribe();
+    let status = |n: usize| Event::StatusChanged {
+        id: n.to_string(),
+        status: TransactionStatus::Pending,
+        height: None,
+    };
+    for n in 0..EVENT_QUEUE_CAPACITY {
+        bus.publish(status(n));
+    }
+    assert_eq!(bus.subscriber_count(), 1);
+    bus.publish(status(EVENT_QUEUE_CAPACITY));
+    assert_eq!(bus.subscriber_count(), 0);
+
+    for _ in 0..EVENT_QUEUE_CAPACITY {
+        events.try_recv().unwrap();
+    }
+    assert_eq!(events.try_recv().unwrap_err(), TryRecvError::Disconnected);
+}
+
+#[test]
+fn the_server_needs_a_token() {
+    let (_, _, server) = setup();
+    for url in [
+        format!("ws://{}/", server.local_addr()),
+        format!("ws://{}/?token=wrong", server.local_addr()),
+    ] {
+        match tungstenite::connect(url) {
+            Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 401),
+            other => panic!("expected a 401, got {:?}", other.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn subscriptions_stream_matching_events() {
+    let (sender, node, server) = setup();
+    let receiver = Wallet::new();
+    let tx = transfer(&sender, &receiver.address, 10.0, 0);
+    let mut socket = connect(&server);
+
+    let headers = subscribe(&mut socket, json!({ "topic": "headers" }));
+    let mempool = subscribe(&mut socket, json!(["mempool"]));
+    let statuses = subscribe(
+        &mut socket,
+        json!({ "topic": "statuses", "ids": [tx.id()] }),
+    );
+    let payments = subscribe(
+        &mut socket,
+        json!({ "topic": "payments", "addresses": [receiver.address] }),
+    );
+    let unwatched = subscribe(
+        &mut socket,
+        json!({ "topic": "payments", "addresses": [Wallet::new().address] }),
+    );
+    assert_eq!(
+        call(&mut socket, "unsubscribe", json!([headers]))["result"],
+        true
+    );
+
+    node.submit_transaction(tx.clone()).unwrap();
+    let block = node.mine_block(&Wallet::new().address).unwrap();
+
+    let mut results = HashMap::new();
+    while results.len() < 3 {
+        let notification = receive(&mut socket);
+        assert_eq!(notification["method"], "subscription");
+        let params = &notification["params"];
+        let subscription = params["subscription"].as_str().unwrap().to_string();
+        assert_ne!(subscription, headers);
+        assert_ne!(subscription, unwatched);
+        assert!(results
+            .insert(subscription, params["result"].clone())
+            .is_none());
+    }
+    assert_eq!(results[&mempool]["id"], tx.id());
+    assert_eq!(results[&mempool]["amount"], 10.0);
+    assert_eq!(results[&statuses]["status"], "Completed");
+    assert_eq!(results[&statuses]["height"], block.header.index);
+    assert_eq!(results[&payments]["address"], receiver.address);
+    assert_eq!(results[&payments]["id"], tx.id());
+    assert_eq!(results[&payments]["amount"], 10.0);
+
+    let headers = subscribe(&mut socket, json!({ "topic": "headers" }));
+    let block = node.mine_block(&Wallet::new().address).unwrap();
+    let notification = receive(&mut socket);
+    assert_eq!(notification["params"]["subscription"], headers);
+    assert_eq!(notification["params"]["topic"], "headers");
+    assert_eq!(notification["params"]["result"]["hash"], block.header.hash);
+}
+
+#[test]
+fn invalid_subscriptions_are_refused() {
+    let (_, _, server) = setup();
+    let mut socket = connect(&server);
+    let code = |reply: Value| reply["error"]["code"].as_i64().unwrap();
+
+    let unknown = call(&mut socket, "subscribe", json!({ "topic": "weather" }));
+    assert_eq!(code(unknown), INVALID_PARAMS);
+    let unwatched = call(&mut socket, "subscribe", json!({ "topic": "payments" }));
+    assert_eq!(code(unwatched), INVALID_PARAMS);
+    let invalid = call(
+        &mut socket,
+        "subscribe",
+        json!({ "topic": "payments", "addresses": ["not-an-address"] }),
+    );
+    assert_eq!(code(invalid), INVALID_ADDRESS);
+    let missing = call(&mut socket, "unsubscribe", json!({ "subscription": "ff" }));
+    assert_eq!(code(missing), INVALID_PARAMS);
+}
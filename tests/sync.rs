@@ -0,0 +1,71 @@
+use curve::account::wallet::Wallet;
+use curve::chain::{Blockchain, GenesisConfig};
+use curve::config::BLOCK_TIME_SECONDS;
+use curve::store::Storage;
+use curve::util::error::{BlockRejection, ChainError};
+
+/// A chain of `count` blocks spaced one block time apart.
+fn mined_chain(count: usize) -> Blockchain {
+    let miner = Wallet::new();
+    let mut chain = Blockchain::open(&Storage::memory(), &GenesisConfig::default()).unwrap();
+    for _ in 0..count {
+        let mut block = chain.next_block(&miner.address, vec![]).unwrap();
+        block.header.timestamp = chain.tip().header.timestamp + BLOCK_TIME_SECONDS as i64;
+        block.mine();
+        chain.add_block(block).unwrap();
+    }
+    chain
+}
+
+#[test]
+fn headers_are_validated_before_their_blocks() {
+    let source = mined_chain(40);
+    let mut syncing = mined_chain(0);
+
+    let headers = source.headers_after(&syncing.locator(), 25);
+    assert_eq!(headers.len(), 25);
+    assert_eq!(headers[0].index, 1);
+    assert_eq!(syncing.add_headers(&headers).unwrap(), 25);
+    assert_eq!(syncing.add_headers(&headers).unwrap(), 0);
+    assert_eq!(syncing.best_header().height, 25);
+    assert_eq!(syncing.height(), 0);
+
+    // The locator now starts at the best header, so the next batch picks up after it.
+    let rest = source.headers_after(&syncing.locator(), 2_000);
+    assert_eq!(rest.len(), 15);
+    assert_eq!(rest[0].index, 26);
+    syncing.add_headers(&rest).unwrap();
+
+    let missing = syncing.missing_blocks();
+    assert_eq!(missing.len(), 40);
+    for entry in &missing {
+        syncing
+            .add_block(source.get_block(&entry.hash).unwrap())
+            .unwrap();
+    }
+    assert_eq!(syncing.tip().header.hash, source.tip().header.hash);
+    assert!(syncing.missing_blocks().is_empty());
+}
+
+#[test]
+fn forged_headers_are_refused() {
+    let source = mined_chain(3);
+    let mut syncing = mined_chain(0);
+    let mut headers = source.headers_after(&syncing.locator(), 10);
+
+    headers[1].nonce += 1;
+    let err = syncing.add_headers(&headers).unwrap_err();
+    assert!(matches!(
+        err,
+        ChainError::InvalidBlock {
+            reason: BlockRejection::HashMismatch,
+            ..
+        }
+    ));
+    // The valid header before the forged one was kept.
+    assert_eq!(syncing.best_header().height, 1);
+
+    syncing.discard_header(&headers[0].hash);
+    assert_eq!(syncing.best_header().height, 0);
+    assert!(syncing.missing_blocks().is_empty());
+}
@@ -0,0 +1,88 @@
+use curve::net::transport::{self, SecureReader, SecureWriter};
+use curve::net::{Inventory, Message};
+use curve::util::error::{CryptoError, NetworkError};
+use curve::vault::KeyPair;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+type Link = (SecureReader, SecureWriter, RistrettoPoint);
+
+/// Runs the handshake between two fresh identities, returning both ends and a raw clone of
+/// each socket.
+fn link(initiator: &KeyPair, responder: &KeyPair) -> ((Link, TcpStream), (Link, TcpStream)) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let responder = responder.clone();
+    let accepting = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let raw = stream.try_clone().unwrap();
+        (
+            transport::handshake(stream, &responder, false).unwrap(),
+            raw,
+        )
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    let raw = stream.try_clone().unwrap();
+    let outbound = (transport::handshake(stream, initiator, true).unwrap(), raw);
+    (outbound, accepting.join().unwrap())
+}
+
+#[test]
+fn handshake_authenticates_both_identities() {
+    let alice = KeyPair::generate();
+    let bob = KeyPair::generate();
+    let (
+        ((mut alice_reader, mut alice_writer, seen_by_alice), _),
+        ((mut bob_reader, mut bob_writer, seen_by_bob), _),
+    ) = link(&alice, &bob);
+
+    assert_eq!(seen_by_alice, bob.public_key);
+    assert_eq!(seen_by_bob, alice.public_key);
+
+    let inv = Message::Inv(vec![Inventory::Block("ab".repeat(32))]);
+    alice_writer.send(&inv).unwrap();
+    alice_writer.send(&Message::Verack).unwrap();
+    assert!(matches!(bob_reader.receive().unwrap(), Message::Inv(items) if items.len() == 1));
+    assert!(matches!(bob_reader.receive().unwrap(), Message::Verack));
+
+    bob_writer
+        .send(&Message::GetHeaders(vec!["cd".repeat(32)]))
+        .unwrap();
+    assert!(
+        matches!(alice_reader.receive().unwrap(), Message::GetHeaders(locator) if locator == vec!["cd".repeat(32)])
+    );
+}
+
+#[test]
+fn traffic_is_encrypted() {
+    let (((_, mut writer, _), _), (_, mut raw)) = link(&KeyPair::generate(), &KeyPair::generate());
+    let marker = "plaintext-marker-".repeat(4);
+    writer
+        .send(&Message::GetHeaders(vec![marker.clone()]))
+        .unwrap();
+    writer.shutdown();
+
+    let mut wire = Vec::new();
+    raw.read_to_end(&mut wire).unwrap();
+    assert!(!wire.is_empty());
+    assert!(!wire
+        .windows(marker.len())
+        .any(|window| window == marker.as_bytes()));
+}
+
+#[test]
+fn forged_frames_are_rejected() {
+    let (((_, _, _), mut raw), ((mut reader, _, _), _)) =
+        link(&KeyPair::generate(), &KeyPair::generate());
+    let forged = [7u8; 40];
+    raw.write_all(&(forged.len() as u32).to_be_bytes()).unwrap();
+    raw.write_all(&forged).unwrap();
+
+    assert!(matches!(
+        reader.receive(),
+        Err(NetworkError::Crypto(CryptoError::DecryptionFailed))
+    ));
+}
@@ -0,0 +1,304 @@
+mod common;
+
+use chrono::Utc;
+use common::{funded, transfer};
+use curve::account::wallet::Wallet;
+use curve::account::Account;
+use curve::chain::{Block, Blockchain, GenesisConfig};
+use curve::config;
+use curve::store::Storage;
+use curve::tx::Transaction;
+use curve::util::error::{BlockRejection, ChainError};
+use serde_json::{json, Value};
+
+/// Whether a rejection is the one a case expects.
+type Expected = fn(&BlockRejection) -> bool;
+
+/// `tx` with its serialized transfer fields edited, bypassing signing.
+fn edited(tx: &Transaction, edit: impl FnOnce(&mut Value)) -> Transaction {
+    let mut value = serde_json::to_value(tx).unwrap();
+    edit(&mut value["Plain"]);
+    serde_json::from_value(value).unwrap()
+}
+
+fn coinbase(chain: &Blockchain, miner: &str, amount: f64) -> Transaction {
+    Transaction::coinbase(miner.to_string(), amount, chain.height() + 1)
+}
+
+/// A block on the tip over exactly `transactions`, with a valid header.
+fn block_of(chain: &Blockchain, transactions: Vec<Transaction>) -> Block {
+    let tip = &chain.tip().header;
+    let mut block = Block::new(
+        tip.index + 1,
+        tip.hash.clone(),
+        tip.difficulty,
+        transactions,
+    );
+    block.header.timestamp = tip.timestamp + 60;
+    block
+}
+
+fn rejection(chain: &mut Blockchain, mut block: Block, mine: bool) -> BlockRejection {
+    if mine {
+        block.mine();
+    }
+    match chain.add_block(block) {
+        Err(ChainError::InvalidBlock { reason, .. }) => reason,
+        other => panic!("expected a rejected block, got {other:?}"),
+    }
+}
+
+#[test]
+fn headers_are_checked_field_by_field() {
+    let store = Storage::memory();
+    let mut chain = Blockchain::open(&store, &GenesisConfig::default()).unwrap();
+    let miner = Account::new(&store).unwrap();
+    let template = chain.next_block(&miner.address, vec![]).unwrap();
+    let with = |edit: &dyn Fn(&mut Block)| {
+        let mut block = template.clone();
+        edit(&mut block);
+        block
+    };
+
+    let mut forged = template.clone();
+    forged.mine();
+    forged.header.data = "forged".to_string();
+    assert!(matches!(
+        rejection(&mut chain, forged, false),
+        BlockRejection::HashMismatch
+    ));
+    let mut unworked = template.clone();
+    while {
+        unworked.header.hash = unworked.calculate_hash();
+        unworked.meets_difficulty()
+    } {
+        unworked.header.nonce += 1;
+    }
+    assert!(matches!(
+        rejection(&mut chain, unworked, false),
+        BlockRejection::InsufficientProofOfWork(_)
+    ));
+
+    let cases: Vec<(Block, Expected)> = vec![
+        (with(&|b| b.header.index = 2), |r| {
+            matches!(
+                r,
+                BlockRejection::InvalidHeight {
+                    parent: 0,
+                    found: 2
+                }
+            )
+        }),
+        (
+            with(&|b| b.header.timestamp = chain.tip().header.timestamp),
+            |r| matches!(r, BlockRejection::TimestampTooOld { .. }),
+        ),
+        (
+            with(&|b| {
+                b.header.timestamp = Utc::now().timestamp() + config::MAX_FUTURE_DRIFT_SECONDS + 60
+            }),
+            |r| matches!(r, BlockRejection::TimestampTooNew { .. }),
+        ),
+    ];
+    for (block, expected) in cases {
+        let reason = rejection(&mut chain, block, true);
+        assert!(expected(&reason), "unexpected rejection: {reason}");
+    }
+    assert_eq!(chain.height(), 0);
+}
+
+#[test]
+fn bodies_must_commit_to_their_transactions() {
+    let sender = Wallet::new();
+    let (mut chain, store) = funded(&sender);
+    let receiver = Account::new(&store).unwrap();
+    let tx = transfer(&sender, &receiver.address, 1.0, 0);
+
+    let mut resized = chain.next_block(&sender.address, vec![tx.clone()]).unwrap();
+    resized.header.block_size += 1;
+    assert!(matches!(
+        rejection(&mut chain, resized, true),
+        BlockRejection::SizeMismatch { .. }
+    ));
+    let mut rerooted = chain.next_block(&sender.address, vec![tx]).unwrap();
+    rerooted.header.merkle_root = "f".repeat(64);
+    assert!(matches!(
+        rejection(&mut chain, rerooted, true),
+        BlockRejection::MerkleRootMismatch
+    ));
+
+    let mut oversized = Transaction::init(
+        sender.address.clone(),
+        receiver.address.clone(),
+        1.0,
+        "x".repeat(config::MAX_BLOCK_SIZE_BYTES as usize),
+    );
+    oversized.sign(&sender.private_key, 0).unwrap();
+    let oversized = chain.next_block(&sender.address, vec![oversized]).unwrap();
+    assert!(matches!(
+        rejection(&mut chain, oversized, true),
+        BlockRejection::TooLarge { .. }
+    ));
+}
+
+#[test]
+fn transactions_are_checked_before_state_is_touched() {
+    let sender = Wallet::new();
+    let (mut chain, store) = funded(&sender);
+    let receiver = Account::new(&store).unwrap();
+    let tx = transfer(&sender, &receiver.address, 1.0, 0);
+    let Transaction::Plain(plain) = &tx else {
+        unreachable!()
+    };
+    let fee = plain.fee();
+    let encrypted: Transaction = serde_json::from_value(json!({ "Encrypted": {
+        "id": tx.id(),
+        "sender_data": { "Plain": { "sender": sender.address, "receiver": receiver.address, "amount": 1.0 } },
+        "receiver_data": { "Plain": { "sender": sender.address, "receiver": receiver.address, "amount": 1.0 } },
+        "fee": 0.0,
+        "size": 0.0,
+        "timestamp": 0,
+        "narration": "",
+        "status": "pending",
+    }}))
+    .unwrap();
+    let tampered = edited(&tx, |plain| plain["amount"] = json!(500.0));
+    let signature = serde_json::to_value(transfer(&sender, &receiver.address, 2.0, 0)).unwrap()
+        ["Plain"]["signature"]
+        .clone();
+    let forged = edited(&tx, |plain| plain["signature"] = signature);
+    let mut stolen = tx.clone();
+    stolen
+        .sign(&Account::new(&store).unwrap().private_key, 0)
+        .unwrap();
+    let unaddressed = edited(&tx, |plain| plain["sender"] = json!("0OIl"));
+    let Transaction::Plain(plain) = &unaddressed else {
+        unreachable!()
+    };
+    let unaddressed = edited(&unaddressed, |fields| {
+        fields["id"] = json!(plain.content_id())
+    });
+
+    let miner = Account::new(&store).unwrap();
+    let miner = miner.address.as_str();
+    let cases: Vec<(Vec<Transaction>, Expected)> = vec![
+        (vec![tx.clone()], |r| {
+            matches!(r, BlockRejection::MissingCoinbase)
+        }),
+        (
+            vec![coinbase(&chain, miner, 1.0), tx.clone(), tx.clone()],
+            |r| matches!(r, BlockRejection::DuplicateTransaction(_)),
+        ),
+        (
+            vec![
+                coinbase(&chain, miner, 1.0),
+                coinbase(&chain, &receiver.address, 2.0),
+            ],
+            |r| matches!(r, BlockRejection::MisplacedCoinbase(_)),
+        ),
+        (
+            vec![Transaction::coinbase(miner.to_string(), 1.0, 5)],
+            |r| {
+                matches!(
+                    r,
+                    BlockRejection::CoinbaseHeightMismatch {
+                        expected: 1,
+                        found: 5
+                    }
+                )
+            },
+        ),
+        (vec![coinbase(&chain, miner, 1.0), encrypted], |r| {
+            matches!(r, BlockRejection::EncryptedTransaction(_))
+        }),
+        (
+            vec![
+                coinbase(&chain, miner, 1.0),
+                transfer(&sender, &receiver.address, -1.0, 0),
+            ],
+            |r| matches!(r, BlockRejection::InvalidAmount(_)),
+        ),
+        (vec![coinbase(&chain, miner, 1.0), unaddressed], |r| {
+            matches!(r, BlockRejection::UnknownAccount { .. })
+        }),
+        (vec![coinbase(&chain, miner, 1.0), tampered], |r| {
+            matches!(r, BlockRejection::IdMismatch(_))
+        }),
+        (vec![coinbase(&chain, miner, 1.0), forged], |r| {
+            matches!(r, BlockRejection::InvalidSignature(_))
+        }),
+        (vec![coinbase(&chain, miner, 1.0), stolen], |r| {
+            matches!(r, BlockRejection::SenderKeyMismatch(_))
+        }),
+        (
+            vec![
+                coinbase(&chain, miner, 1.0),
+                transfer(&sender, &receiver.address, 1.0, 1),
+            ],
+            |r| {
+                matches!(
+                    r,
+                    BlockRejection::InvalidNonce {
+                        expected: 0,
+                        found: 1,
+                        ..
+                    }
+                )
+            },
+        ),
+        (
+            vec![
+                coinbase(&chain, miner, 1.0),
+                transfer(&sender, &receiver.address, 5_000.0, 0),
+            ],
+            |r| matches!(r, BlockRejection::InsufficientFunds { .. }),
+        ),
+        (
+            vec![
+                coinbase(&chain, miner, 1.0),
+                tx.clone(),
+                transfer(&sender, &receiver.address, 2.0, 0),
+            ],
+            |r| matches!(r, BlockRejection::InvalidNonce { expected: 1, .. }),
+        ),
+        (vec![coinbase(&chain, miner, 1_000.0)], |r| {
+            matches!(r, BlockRejection::ExcessiveCoinbase { .. })
+        }),
+    ];
+    for (transactions, expected) in cases {
+        let block = block_of(&chain, transactions);
+        let reason = rejection(&mut chain, block, true);
+        assert!(expected(&reason), "unexpected rejection: {reason}");
+    }
+    assert_eq!(chain.height(), 0);
+    assert_eq!(Account::state_of(&store, &sender.address).unwrap().nonce, 0);
+
+    let mut block = chain.next_block(miner, vec![tx]).unwrap();
+    block.mine();
+    chain.add_block(block).unwrap();
+    let sent = Account::state_of(&store, &sender.address).unwrap();
+    assert_eq!(sent.nonce, 1);
+    assert_eq!(sent.balance, 1_000.0 - 1.0 - fee);
+    assert_eq!(Account::balance_of(&store, &receiver.address).unwrap(), 1.0);
+}
+
+#[test]
+fn transactions_on_chain_cannot_be_mined_again() {
+    let sender = Wallet::new();
+    let (mut chain, store) = funded(&sender);
+    let receiver = Account::new(&store).unwrap();
+    let tx = transfer(&sender, &receiver.address, 1.0, 0);
+    let mut block = chain.next_block(&sender.address, vec![tx.clone()]).unwrap();
+    block.mine();
+    chain.add_block(block).unwrap();
+
+    let replay = block_of(
+        &chain,
+        vec![coinbase(&chain, &sender.address, 1.0), tx.clone()],
+    );
+    assert!(matches!(
+        rejection(&mut chain, replay, true),
+        BlockRejection::DuplicateTransaction(id) if id == tx.id()
+    ));
+    assert_eq!(chain.height(), 1);
+}
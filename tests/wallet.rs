@@ -0,0 +1,101 @@
+use curve::account::account::BalanceType;
+use curve::account::wallet::Wallet;
+use curve::account::Account;
+use curve::chain::validation::validate_transfer;
+use curve::chain::{Blockchain, GenesisConfig};
+use curve::store::{schema, Storage, StorageKind};
+use curve::tx::Transaction;
+use curve::util::error::{AccountError, BlockRejection};
+use curve::vault::Crypto;
+
+/// The point an address encodes, as a hex key.
+fn address_point(address: &str) -> String {
+    let bytes = bs58::decode(address).into_vec().unwrap();
+    hex::encode(&bytes[..32])
+}
+
+fn encrypted_balance(store: &Storage, balance_key: &str) -> Vec<u8> {
+    let key = bincode::serialize(balance_key).unwrap();
+    let record = store.get(StorageKind::Account.name(), &key).unwrap();
+    match schema::decode_record::<Account>(&record).unwrap().balance {
+        BalanceType::Binary(data) => data,
+        _ => panic!("balances are stored encrypted"),
+    }
+}
+
+#[test]
+fn addresses_do_not_reveal_the_balance_key() {
+    let store = Storage::memory();
+    let account = Account::new(&store).unwrap();
+
+    assert_ne!(address_point(&account.address), account.public_key);
+    let balance = encrypted_balance(&store, &account.public_key);
+    assert!(Crypto::decrypt(balance.clone(), &address_point(&account.address)).is_err());
+    assert!(Crypto::decrypt(balance, &account.public_key).is_ok());
+}
+
+#[test]
+fn addresses_open_only_to_their_own_key() {
+    let wallet = Wallet::new();
+    let other = Wallet::new();
+    assert!(Wallet::open_address(&wallet.address, &wallet.public_key, &wallet.blinding).unwrap());
+    assert!(!Wallet::open_address(&wallet.address, &other.public_key, &other.blinding).unwrap());
+    assert!(!Wallet::open_address(&wallet.address, &wallet.public_key, &other.blinding).unwrap());
+    assert_eq!(
+        Wallet::opening(&wallet.private_key).unwrap(),
+        (wallet.public_key, wallet.blinding)
+    );
+}
+
+#[test]
+fn balances_need_the_owners_private_key() {
+    let store = Storage::memory();
+    let account = Account::new(&store).unwrap();
+    let other = Account::new(&store).unwrap();
+
+    let balance =
+        Account::get_balance(&store, account.address.clone(), account.private_key).unwrap();
+    assert_eq!(balance.balance, 0.0);
+    assert!(matches!(
+        Account::get_balance(&store, account.address, other.private_key),
+        Err(AccountError::NotOwner(_))
+    ));
+}
+
+#[test]
+fn accounts_first_paid_by_a_block_use_a_custody_key() {
+    let store = Storage::memory();
+    let mut chain = Blockchain::open(&store, &GenesisConfig::default()).unwrap();
+    let miner = Wallet::new();
+    let mut block = chain.next_block(&miner.address, Vec::new()).unwrap();
+    block.mine();
+    chain.add_block(block).unwrap();
+
+    assert!(Account::balance_of(&store, &miner.address).unwrap() > 0.0);
+    let balance_key = Account::balance_key_of(&store, &miner.address).unwrap();
+    assert_ne!(balance_key, miner.public_key);
+    let balance = encrypted_balance(&store, &balance_key);
+    assert!(Crypto::decrypt(balance, &address_point(&miner.address)).is_err());
+    let owned = Account::get_balance(&store, miner.address.clone(), miner.private_key).unwrap();
+    assert!(owned.balance > 0.0);
+}
+
+#[test]
+fn transfers_must_be_signed_by_the_sender_address_owner() {
+    let victim = Wallet::new();
+    let thief = Wallet::new();
+    let mut tx = Transaction::init(
+        victim.address.clone(),
+        thief.address.clone(),
+        1.0,
+        String::new(),
+    );
+    tx.sign(&thief.private_key, 0).unwrap();
+    let Transaction::Plain(tx) = tx else {
+        panic!("init builds plain transfers")
+    };
+    assert!(matches!(
+        validate_transfer(&tx).unwrap(),
+        Some(BlockRejection::SenderKeyMismatch(_))
+    ));
+}